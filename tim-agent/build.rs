@@ -17,7 +17,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_prost_build::configure()
         .build_client(true)
         .build_server(true)
-        .compile_protos(protos, &[&api_proto_root, &agent_proto_root])?;
+        .compile_protos(protos, &[api_proto_root, agent_proto_root])?;
 
     println!("cargo:rerun-if-changed={}", api_proto_root);
     println!("cargo:rerun-if-changed=tim/agent/db/g1/db.proto");
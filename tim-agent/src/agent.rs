@@ -1,19 +1,30 @@
 use std::time::Duration;
 
 use async_trait::async_trait;
+use futures::StreamExt;
+use tim_lib::sequence::GapDetector;
+use tim_lib::space_stream::ReconnectPolicy;
+use tim_lib::space_stream::ResilientSpaceStream;
+use tim_lib::space_stream::SpaceStreamItem;
 use tinytemplate::error::Error as TemplateError;
 use tokio::time::interval_at;
 use tokio::time::Instant;
 use tokio::time::MissedTickBehavior;
 use tracing::debug;
+use tracing::error;
 use tracing::info;
+use tracing::warn;
 
+use crate::tim_client::catch_up;
+use crate::tim_client::is_message_from;
 use crate::tim_client::SpaceEvent;
 use crate::tim_client::TimClient;
 use crate::tim_client::TimClientConf;
 use crate::tim_client::TimClientError;
 
 const MIN_LIVE_INTERVAL: Duration = Duration::from_secs(5);
+/// Reconnects tried in a row before the run fails and supervision takes over.
+const SUBSCRIBE_ATTEMPTS: u32 = 10;
 
 #[derive(Debug, thiserror::Error)]
 pub enum AgentError {
@@ -34,6 +45,25 @@ pub enum AgentError {
 
     #[error("memory error: {0}")]
     Memory(String),
+
+    #[error("space subscription lost after {attempts} reconnect attempts")]
+    SubscriptionLost { attempts: u32 },
+
+    #[error("agent build error: {0}")]
+    Build(Box<AgentError>),
+
+    #[error("agent gave up after {restarts} restarts: {last}")]
+    GaveUp {
+        restarts: u32,
+        last: Box<AgentError>,
+    },
+}
+
+impl AgentError {
+    /// Errors that restarting the agent cannot fix.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, AgentError::Build(_))
+    }
 }
 
 #[async_trait]
@@ -66,9 +96,24 @@ impl AgentRunner {
         }
     }
 
-    pub async fn start<A: Agent>(&mut self, mut agent: A) -> Result<(), AgentError> {
+    pub async fn start<A: Agent>(&mut self, agent: A) -> Result<(), AgentError> {
+        let res = self.run(agent).await;
+        // best effort, the server sweeps the subscriber anyway if this fails
+        if let Err(err) = self.client.disconnect().await {
+            debug!(error = %err, "agent disconnect failed");
+        }
+        res
+    }
+
+    async fn run<A: Agent>(&mut self, mut agent: A) -> Result<(), AgentError> {
         info!("starting agent: {:?}", self.client);
-        let mut stream = self.client.subscribe_to_space().await?;
+        let mut stream = ResilientSpaceStream::new(
+            self.client.clone(),
+            ReconnectPolicy {
+                max_attempts: Some(SUBSCRIBE_ATTEMPTS),
+                ..ReconnectPolicy::default()
+            },
+        );
         agent.on_start().await?;
         let mut live_timer = agent.live_interval().map(|period| {
             let safe_period = period.max(MIN_LIVE_INTERVAL);
@@ -77,32 +122,67 @@ impl AgentRunner {
             timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
             timer
         });
+        let mut gaps = GapDetector::new();
 
         loop {
-            if let Some(timer) = live_timer.as_mut() {
-                tokio::select! {
-                    maybe_update = stream.message() => {
-                        let maybe_update = maybe_update?;
-                        let Some(update) = maybe_update else {
-                            break;
-                        };
-                        agent.on_space_update(&update).await?;
-                    }
+            let item = match live_timer.as_mut() {
+                Some(timer) => tokio::select! {
+                    item = stream.next() => item,
                     _ = timer.tick() => {
                         debug!("agent live tick");
                         agent.on_live().await?;
+                        continue;
                     }
+                },
+                None => stream.next().await,
+            };
+            match item {
+                Some(SpaceStreamItem::Event(update)) => {
+                    self.deliver(&mut agent, &mut gaps, update).await?;
+                }
+                Some(SpaceStreamItem::Reconnecting { attempt, error }) => {
+                    warn!(attempt, %error, "space subscription lost, reconnecting");
+                }
+                Some(SpaceStreamItem::Reconnected) => info!("space subscription restored"),
+                None => {
+                    return Err(AgentError::SubscriptionLost {
+                        attempts: SUBSCRIBE_ATTEMPTS,
+                    })
                 }
-            } else {
-                let maybe_update = stream.message().await?;
-                let Some(update) = maybe_update else {
-                    break;
-                };
-                agent.on_space_update(&update).await?;
             }
         }
+    }
 
-        Ok(())
+    /// Hands `update` to the agent, preceded by whatever the subscription skipped.
+    async fn deliver<A: Agent>(
+        &mut self,
+        agent: &mut A,
+        gaps: &mut GapDetector,
+        update: SpaceEvent,
+    ) -> Result<(), AgentError> {
+        let own_id = self.client.timite_id();
+        let (id, prev_id) = update
+            .metadata
+            .as_ref()
+            .map_or((0, None), |meta| (meta.id, meta.prev_id));
+        if let Some(gap) = gaps.observe(id, prev_id) {
+            match catch_up(&mut self.client, gap.clone(), Some(own_id)).await {
+                Ok(missed) => {
+                    if !missed.is_empty() {
+                        warn!(?gap, missed = missed.len(), "caught up on skipped events");
+                    }
+                    for event in &missed {
+                        agent.on_space_update(event).await?;
+                    }
+                }
+                Err(err) => warn!(?gap, error = %err, "failed to catch up on skipped events"),
+            }
+        }
+        // own messages only come in to keep the stored events unbroken
+        if is_message_from(&update, Some(own_id)) {
+            return Ok(());
+        }
+        agent.on_space_update(&update).await
     }
 }
 
@@ -114,6 +194,75 @@ pub trait AgentBuilder {
 pub async fn spawn<B: AgentBuilder>(conf: TimClientConf, builder: B) -> Result<(), AgentError> {
     let client = TimClient::new(conf).await?;
     let mut runner = AgentRunner::new(&client).await;
-    let agent = builder.build(client)?;
+    let agent = builder
+        .build(client)
+        .map_err(|err| AgentError::Build(Box::new(err)))?;
     runner.start(agent).await
 }
+
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Consecutive failures tolerated before the agent is given up on.
+    pub max_restarts: u32,
+    /// A run lasting this long resets the failure count and backoff.
+    pub stable_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_restarts: 5,
+            stable_after: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Runs the agent and restarts it with backoff when it fails at runtime.
+/// Build errors are returned as is, a clean stop ends supervision.
+pub async fn supervise<B: AgentBuilder>(
+    conf: TimClientConf,
+    builder: B,
+    policy: RestartPolicy,
+) -> Result<(), AgentError> {
+    let nick = conf.nick.clone();
+    let mut restarts = 0;
+    let mut backoff = policy.initial_backoff;
+    loop {
+        let started = Instant::now();
+        let err = match spawn(conf.clone(), &builder).await {
+            Ok(()) => {
+                info!(agent = %nick, "agent stopped");
+                return Ok(());
+            }
+            Err(err) if err.is_fatal() => return Err(err),
+            Err(err) => err,
+        };
+        if started.elapsed() >= policy.stable_after {
+            restarts = 0;
+            backoff = policy.initial_backoff;
+        }
+        if restarts >= policy.max_restarts {
+            error!(agent = %nick, restarts, error = %err, "giving up on agent");
+            return Err(AgentError::GaveUp {
+                restarts,
+                last: Box::new(err),
+            });
+        }
+        restarts += 1;
+        warn!(agent = %nick, restarts, ?backoff, error = %err, "agent failed, restarting");
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(policy.max_backoff);
+    }
+}
+
+impl<B: AgentBuilder> AgentBuilder for &B {
+    type A = B::A;
+
+    fn build(&self, tim_client: TimClient) -> Result<Self::A, AgentError> {
+        (**self).build(tim_client)
+    }
+}
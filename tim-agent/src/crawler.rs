@@ -1,5 +1,12 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
 use async_trait::async_trait;
 use reqwest::Client;
+use reqwest::Url;
+use serde::Serialize;
+use tracing::warn;
 
 use crate::agent::Agent;
 use crate::agent::AgentBuilder;
@@ -7,6 +14,7 @@ use crate::agent::AgentError;
 use crate::tim_client::tim_api::Ability;
 use crate::tim_client::tim_api::CallAbility;
 use crate::tim_client::tim_api::CallAbilityOutcome;
+use crate::tim_client::tim_api::OutcomeStatus;
 use crate::tim_client::Event;
 use crate::tim_client::SpaceEvent;
 use crate::tim_client::TimClient;
@@ -14,8 +22,12 @@ use crate::tim_client::TimClient;
 #[derive(Clone)]
 pub struct CrawlerConf {
     pub ability_name: String,
+    /// Counted in characters, so multi-byte text gets as long a snippet as ASCII.
     pub max_snippet_chars: usize,
     pub user_agent: String,
+    /// Number of crawled pages kept in memory, 0 disables caching.
+    pub cache_capacity: usize,
+    pub cache_ttl: Duration,
 }
 
 impl Default for CrawlerConf {
@@ -24,14 +36,145 @@ impl Default for CrawlerConf {
             ability_name: "web.crawl".to_string(),
             max_snippet_chars: 480,
             user_agent: "tim-crawler/0.1".to_string(),
+            cache_capacity: 128,
+            cache_ttl: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CrawlResult {
+    url: String,
+    title: Option<String>,
+    snippet: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    cached: bool,
+}
+
+struct CacheEntry<V> {
+    value: V,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+/// LRU cache whose entries also expire `ttl` after insertion. Callers pass
+/// the current instant so expiry can be driven explicitly.
+pub struct CrawlCache<V> {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<String, CacheEntry<V>>,
+    tick: u64,
+}
+
+impl<V: Clone> CrawlCache<V> {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &str, now: Instant) -> Option<V> {
+        let expired = {
+            let entry = self.entries.get(key)?;
+            now.saturating_duration_since(entry.stored_at) >= self.ttl
+        };
+        if expired {
+            self.entries.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.tick;
+        Some(entry.value.clone())
+    }
+
+    pub fn insert(&mut self, key: String, value: V, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries
+            .retain(|_, entry| now.saturating_duration_since(entry.stored_at) < self.ttl);
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(
+            key,
+            CacheEntry {
+                value,
+                stored_at: now,
+                last_used: self.tick,
+            },
+        );
+    }
+}
+
+/// Whitespace-normalized text of `body`, cut after `max_chars` characters with an
+/// ellipsis. Cuts always land on a character boundary.
+pub fn render_snippet(body: &str, max_chars: usize) -> String {
+    let mut snippet = String::new();
+    let mut chars = 0;
+    for word in body.split_whitespace() {
+        if !snippet.is_empty() {
+            snippet.push(' ');
+            chars += 1;
+        }
+        snippet.push_str(word);
+        chars += word.chars().count();
+        if chars >= max_chars {
+            if let Some((cut, _)) = snippet.char_indices().nth(max_chars) {
+                snippet.truncate(cut);
+            }
+            snippet.push('…');
+            break;
         }
     }
+    if snippet.is_empty() {
+        "page returned no readable content".to_string()
+    } else {
+        snippet
+    }
+}
+
+/// Cache key for a crawl target. Drops the fragment and a trailing path
+/// slash and sorts query pairs; scheme and host case and default ports are
+/// normalized by the parser. Path case and query values are kept as is.
+pub fn normalize_url(raw: &str) -> Option<String> {
+    let mut url = Url::parse(raw.trim()).ok()?;
+    url.set_fragment(None);
+    let path = url.path();
+    if path.len() > 1 && path.ends_with('/') {
+        let trimmed = path.trim_end_matches('/').to_string();
+        url.set_path(if trimmed.is_empty() { "/" } else { &trimmed });
+    }
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if pairs.is_empty() {
+        url.set_query(None);
+    } else {
+        pairs.sort();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    Some(url.to_string())
 }
 
 pub struct WebCrawlerAgent {
     client: TimClient,
     conf: CrawlerConf,
     http: Client,
+    cache: CrawlCache<CrawlResult>,
 }
 
 impl WebCrawlerAgent {
@@ -45,10 +188,11 @@ impl WebCrawlerAgent {
             client,
             conf: conf.clone(),
             http,
+            cache: CrawlCache::new(conf.cache_capacity, conf.cache_ttl),
         })
     }
 
-    async fn crawl(&self, url: &str) -> Result<String, String> {
+    async fn crawl(&self, url: &str) -> Result<CrawlResult, String> {
         let parsed = reqwest::Url::parse(url).map_err(|err| format!("invalid url: {err}"))?;
         match parsed.scheme() {
             "http" | "https" => {}
@@ -74,27 +218,34 @@ impl WebCrawlerAgent {
             .await
             .map_err(|err| format!("failed to read body: {err}"))?;
 
-        Ok(self.render_snippet(&body))
+        Ok(CrawlResult {
+            url: url.to_string(),
+            title: extract_title(&body),
+            snippet: self.render_snippet(&body),
+            cached: false,
+        })
     }
 
-    fn render_snippet(&self, body: &str) -> String {
-        let mut snippet = String::new();
-        for word in body.split_whitespace() {
-            if !snippet.is_empty() {
-                snippet.push(' ');
-            }
-            snippet.push_str(word);
-            if snippet.len() >= self.conf.max_snippet_chars {
-                snippet.truncate(self.conf.max_snippet_chars);
-                snippet.push('…');
-                break;
-            }
+    async fn crawl_cached(&mut self, call_id: u64, url: &str) -> Result<CrawlResult, String> {
+        let Some(key) = normalize_url(url) else {
+            self.report_progress(call_id, url).await;
+            return self.crawl(url).await;
+        };
+        if let Some(mut hit) = self.cache.get(&key, Instant::now()) {
+            hit.url = url.to_string();
+            hit.cached = true;
+            return Ok(hit);
         }
-        if snippet.is_empty() {
-            "page returned no readable content".to_string()
-        } else {
-            snippet
+        self.report_progress(call_id, url).await;
+        let result = self.crawl(url).await;
+        if let Ok(crawled) = &result {
+            self.cache.insert(key, crawled.clone(), Instant::now());
         }
+        result
+    }
+
+    fn render_snippet(&self, body: &str) -> String {
+        render_snippet(body, self.conf.max_snippet_chars)
     }
 
     async fn declare(&mut self) -> Result<(), AgentError> {
@@ -103,26 +254,43 @@ impl WebCrawlerAgent {
                 name: self.conf.ability_name.clone(),
                 description: "Fetches a web page and returns a short text snippet.".to_string(),
                 params: Vec::new(),
+                allowed_caller_ids: Vec::new(),
             }])
             .await?;
         Ok(())
     }
 
+    /// Tells the caller a fetch started. Best effort: the final outcome follows
+    /// whether or not this one got through.
+    async fn report_progress(&mut self, call_id: u64, url: &str) {
+        if let Err(error) = self
+            .client
+            .send_call_ability_outcome(&progress_outcome(call_id, url))
+            .await
+        {
+            warn!(call_id, %error, "failed to report crawl progress");
+        }
+    }
+
     async fn respond_outcome(
         &mut self,
         call_id: u64,
-        result: Result<String, String>,
+        result: Result<CrawlResult, String>,
     ) -> Result<(), AgentError> {
         let outcome = match result {
-            Ok(payload) => CallAbilityOutcome {
+            Ok(crawled) => CallAbilityOutcome {
                 call_ability_id: call_id,
-                payload: Some(payload),
+                structured_payload: serde_json::to_string(&crawled).ok(),
+                payload: Some(crawled.snippet),
                 error: None,
+                status: OutcomeStatus::Final.into(),
             },
             Err(err) => CallAbilityOutcome {
                 call_ability_id: call_id,
                 payload: None,
                 error: Some(err),
+                structured_payload: None,
+                status: OutcomeStatus::Final.into(),
             },
         };
         self.client.send_call_ability_outcome(&outcome).await?;
@@ -145,12 +313,37 @@ impl WebCrawlerAgent {
                 .await?;
             return Ok(());
         }
-        let result = self.crawl(&payload).await;
+        let result = self.crawl_cached(call_id, &payload).await;
         self.respond_outcome(call_id, result).await?;
         Ok(())
     }
 }
 
+/// Non-final outcome sent when a fetch starts; the final outcome for `call_id`
+/// replaces it.
+pub fn progress_outcome(call_id: u64, url: &str) -> CallAbilityOutcome {
+    CallAbilityOutcome {
+        call_ability_id: call_id,
+        payload: Some(format!("fetching {url}")),
+        error: None,
+        structured_payload: None,
+        status: OutcomeStatus::InProgress.into(),
+    }
+}
+
+fn extract_title(body: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets valid for `body`
+    let lower = body.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = body[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
 #[async_trait]
 impl Agent for WebCrawlerAgent {
     async fn on_start(&mut self) -> Result<(), AgentError> {
@@ -1,9 +1,14 @@
 mod ability;
 pub mod agent;
 pub mod chatgpt;
+pub mod echo;
+pub mod fallback;
+pub mod live;
+#[allow(clippy::module_inception)]
 pub mod llm;
-mod memory;
+pub mod memory;
 mod prompt;
 
 pub use agent::AgentConf;
-pub use chatgpt::OPENAI_DEFAULT_ENDPOINT;
+pub use agent::LlmFallback;
+pub use llm::LlmProvider;
@@ -10,9 +10,16 @@ use tracing::trace;
 
 use super::ability;
 use super::chatgpt::ChatGpt;
+use super::echo::Echo;
+use super::echo::ECHO_PROVIDER;
+use super::fallback::FallbackLlm;
+use super::live::LiveTurnGate;
 use super::llm::Llm;
+use super::llm::LlmProvider;
 use super::llm::LlmReq;
 use super::llm::LlmRes;
+use super::llm::ToolInvocation;
+use super::memory::ContextFilter;
 use super::memory::Memory;
 use crate::agent::Agent as AgentTrait;
 use crate::agent::AgentBuilder;
@@ -20,19 +27,40 @@ use crate::agent::AgentError;
 use crate::llm::llm::LlmInputItem;
 use crate::llm::memory::MemoryError;
 use crate::llm::prompt::render;
+use crate::tim_client::Activity;
 use crate::tim_client::Event;
 use crate::tim_client::EventNewMessage;
 use crate::tim_client::SpaceEvent;
 use crate::tim_client::TimClient;
 
+const PROVIDER_ENV: &str = "TIM_ASSISTANT_PROVIDER";
+
 #[derive(Clone)]
 pub struct AgentConf {
+    pub provider: LlmProvider,
     pub sysp: String,
     pub api_key: String,
     pub endpoint: String,
     pub model: String,
     pub temperature: f32,
     pub live_interval: Option<Duration>,
+    /// Takes live turns on every tick, even when nothing new arrived since the last.
+    pub live_when_unchanged: bool,
+    /// Limits the history sent with each request, full history when unset.
+    pub context_filter: Option<ContextFilter>,
+    /// Most history items sent with each request, the newest are kept.
+    pub max_context_items: Option<usize>,
+    /// Tried in order when the primary endpoint fails with a transient error.
+    pub fallbacks: Vec<LlmFallback>,
+    /// Shows the agent as thinking to the space while a request is in flight.
+    pub thinking_indicator: bool,
+}
+
+#[derive(Clone)]
+pub struct LlmFallback {
+    pub endpoint: String,
+    pub model: String,
+    pub api_key: String,
 }
 
 pub struct Agent {
@@ -40,16 +68,29 @@ pub struct Agent {
     conf: AgentConf,
     llm: Arc<dyn Llm>,
     memory: Memory,
+    live_gate: LiveTurnGate,
 }
 
 impl Debug for AgentConf {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AgentConf")
+            .field("provider", &self.provider)
             .field("userp", &self.sysp)
             .field("endpoint", &self.endpoint)
             .field("model", &self.model)
             .field("temperature", &self.temperature)
             .field("live_interval", &self.live_interval)
+            .field("live_when_unchanged", &self.live_when_unchanged)
+            .field("max_context_items", &self.max_context_items)
+            .field("thinking_indicator", &self.thinking_indicator)
+            .field(
+                "fallbacks",
+                &self
+                    .fallbacks
+                    .iter()
+                    .map(|fallback| format!("{} {}", fallback.endpoint, fallback.model))
+                    .collect::<Vec<_>>(),
+            )
             .finish()
     }
 }
@@ -77,26 +118,75 @@ impl From<MemoryError> for AgentError {
 
 impl Agent {
     pub fn new(conf: &AgentConf, client: TimClient) -> Result<Self, AgentError> {
-        let llm: Arc<dyn Llm> = Arc::new(
-            ChatGpt::new(
-                conf.api_key.clone(),
-                conf.endpoint.clone(),
-                conf.model.clone(),
-                conf.temperature,
-            )
-            .map_err(|err| AgentError::Llm(err.to_string()))?,
-        );
-        let memory = Memory::new(client.clone());
+        let llm = Self::build_llm(conf)?;
+        let memory = Memory::new(client.clone(), conf.max_context_items);
+        let live_gate = LiveTurnGate::new(client.timite_id());
         Ok(Self {
             client,
             conf: conf.clone(),
             llm,
             memory,
+            live_gate,
         })
     }
 
+    fn build_llm(conf: &AgentConf) -> Result<Arc<dyn Llm>, AgentError> {
+        // the env override lets a configured agent run without a real LLM
+        let provider = match std::env::var(PROVIDER_ENV).as_deref() {
+            Ok(ECHO_PROVIDER) => LlmProvider::Echo,
+            _ => conf.provider,
+        };
+        if provider == LlmProvider::Echo {
+            return Ok(Arc::new(Echo));
+        }
+        let primary = LlmFallback {
+            endpoint: conf.endpoint.clone(),
+            model: conf.model.clone(),
+            api_key: conf.api_key.clone(),
+        };
+        let mut chain: Vec<(String, Arc<dyn Llm>)> = Vec::new();
+        for target in std::iter::once(&primary).chain(&conf.fallbacks) {
+            let chatgpt = ChatGpt::new(
+                target.api_key.clone(),
+                target.endpoint.clone(),
+                target.model.clone(),
+                conf.temperature,
+            )
+            .map_err(|err| AgentError::Llm(err.to_string()))?;
+            chain.push((
+                format!("{} {}", target.endpoint, target.model),
+                Arc::new(chatgpt),
+            ));
+        }
+        if chain.len() == 1 {
+            return Ok(chain.remove(0).1);
+        }
+        Ok(Arc::new(FallbackLlm::new(chain)))
+    }
+
     async fn ask_llm(&mut self) -> Result<(), AgentError> {
-        let history: Vec<LlmInputItem> = self.memory.context().await?;
+        if !self.conf.thinking_indicator {
+            return self.respond().await;
+        }
+        self.set_activity(Activity::Thinking).await;
+        let res = self.respond().await;
+        // cleared whatever the outcome; the server resets it too should this never run
+        self.set_activity(Activity::Idle).await;
+        res
+    }
+
+    // the indicator is cosmetic, failing to set it must not fail the reply
+    async fn set_activity(&mut self, activity: Activity) {
+        if let Err(err) = self.client.set_activity(activity).await {
+            debug!("failed to set activity {:?}: {}", activity, err);
+        }
+    }
+
+    async fn respond(&mut self) -> Result<(), AgentError> {
+        let history: Vec<LlmInputItem> = match &self.conf.context_filter {
+            Some(filter) => self.memory.context_filtered(filter).await?,
+            None => self.memory.context().await?,
+        };
         let nick = self.client.get_me().nick.clone();
         let ctx = AgentPromptContext {
             nick: nick.clone(),
@@ -131,6 +221,15 @@ impl Agent {
                 self.client.send_message(&message).await?;
                 Ok(())
             }
+            LlmRes::ToolCalls(calls) => {
+                log_tool_calls(&nick, &calls);
+                Ok(())
+            }
+            LlmRes::ReplyWithToolCalls(message, calls) => {
+                self.client.send_message(&message).await?;
+                log_tool_calls(&nick, &calls);
+                Ok(())
+            }
         }
     }
 
@@ -148,6 +247,7 @@ impl AgentTrait for Agent {
     }
 
     async fn on_space_update(&mut self, update: &SpaceEvent) -> Result<(), AgentError> {
+        self.live_gate.observe(update);
         match &update.data {
             Some(Event::EventNewMessage(EventNewMessage { message: Some(_) })) => Ok(()),
             _ => Ok(()),
@@ -155,6 +255,12 @@ impl AgentTrait for Agent {
     }
 
     async fn on_live(&mut self) -> Result<(), AgentError> {
+        // taken either way, so a forced turn doesn't leave a stale one pending
+        let changed = self.live_gate.take_turn();
+        if !changed && !self.conf.live_when_unchanged {
+            trace!("nothing new since the last live turn, skipping");
+            return Ok(());
+        }
         self.ask_llm().await?;
         Ok(())
     }
@@ -164,6 +270,13 @@ impl AgentTrait for Agent {
     }
 }
 
+// nothing dispatches tools yet, so just record what was asked for
+fn log_tool_calls(nick: &str, calls: &[ToolInvocation]) {
+    for call in calls {
+        debug!("{} requested tool {} with {}", nick, call.name, call.args);
+    }
+}
+
 impl AgentBuilder for AgentConf {
     type A = Agent;
 
@@ -1,16 +1,28 @@
 use std::fmt;
 use std::fmt::Debug;
+use std::time::Duration;
+use std::time::Instant;
 
 use async_trait::async_trait;
+use eventsource_stream::EventStreamError;
 use eventsource_stream::Eventsource;
+use futures::stream;
+use futures::Stream;
 use futures::StreamExt;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::HeaderValue;
 use reqwest::Client;
+use reqwest::RequestBuilder;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::json;
 use tokio::sync::mpsc;
-use tracing::debug;
+use tokio::time::timeout;
+use tracing::info;
+use tracing::info_span;
 use tracing::trace;
+use tracing::Instrument;
 
 use super::llm::Llm;
 use super::llm::LlmError;
@@ -23,6 +35,12 @@ pub const OPENAI_DEFAULT_MODEL: &str = "gpt-4.1";
 
 const STR_DBG_LEN: usize = 40;
 const VEC_DBG_LEN: usize = 3;
+const LOG_CONTENT_ENV: &str = "TIM_ASSISTANT_LOG_CONTENT";
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const API_VERSION_PARAM: &str = "api-version";
+pub const DEFAULT_USER_AGENT: &str = concat!("tim-agent/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Clone)]
 pub struct ChatGpt {
@@ -31,6 +49,11 @@ pub struct ChatGpt {
     endpoint: String,
     model: String,
     temperature: f32,
+    headers: HeaderMap,
+    query: Vec<(String, String)>,
+    log_content: bool,
+    timeout: Duration,
+    read_timeout: Duration,
 }
 
 impl fmt::Debug for ChatGpt {
@@ -50,30 +73,183 @@ impl ChatGpt {
         model: String,
         temperature: f32,
     ) -> Result<Self, LlmError> {
-        if api_key.trim().is_empty() {
+        ChatGpt::builder(api_key)
+            .endpoint(endpoint)
+            .model(model)
+            .temperature(temperature)
+            .build()
+    }
+
+    pub fn builder(api_key: impl Into<String>) -> ChatGptBuilder {
+        ChatGptBuilder {
+            api_key: api_key.into(),
+            endpoint: String::new(),
+            model: String::new(),
+            temperature: 0.0,
+            headers: Vec::new(),
+            query: Vec::new(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+        }
+    }
+
+    fn prepare_post(&self) -> RequestBuilder {
+        self.client
+            .post(&self.endpoint)
+            .headers(self.headers.clone())
+            .query(&self.query)
+            .bearer_auth(&self.api_key)
+    }
+}
+
+/// Builds a [`ChatGpt`] for OpenAI or any OpenAI-compatible gateway (Azure, local proxies).
+pub struct ChatGptBuilder {
+    api_key: String,
+    endpoint: String,
+    model: String,
+    temperature: f32,
+    headers: Vec<(String, String)>,
+    query: Vec<(String, String)>,
+    user_agent: String,
+    timeout: Duration,
+    read_timeout: Duration,
+}
+
+impl ChatGptBuilder {
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Adds an extra request header; repeated names accumulate rather than overwrite.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn query(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.query.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Bounds the wait for response headers; the streamed body is bounded by `read_timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Longest silence tolerated between stream chunks before the response is abandoned
+    /// with `LlmError::Stream`; any chunk, keep-alive comments included, resets it.
+    pub fn read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    /// Azure OpenAI selects the API revision via the `api-version` query parameter.
+    pub fn api_version(self, version: impl Into<String>) -> Self {
+        self.query(API_VERSION_PARAM, version)
+    }
+
+    pub fn build(self) -> Result<ChatGpt, LlmError> {
+        if self.api_key.trim().is_empty() {
             return Err(LlmError::MissingApiKey);
         }
-        let endpoint = if endpoint.trim().is_empty() {
+        let endpoint = if self.endpoint.trim().is_empty() {
             OPENAI_DEFAULT_ENDPOINT.to_string()
         } else {
-            endpoint
+            self.endpoint
         };
-        let model = if model.trim().is_empty() {
+        let model = if self.model.trim().is_empty() {
             OPENAI_DEFAULT_MODEL.to_string()
         } else {
-            model
+            self.model
         };
 
-        Ok(Self {
-            client: Client::new(),
-            api_key,
+        let client = Client::builder().user_agent(self.user_agent).build()?;
+
+        Ok(ChatGpt {
+            client,
+            api_key: self.api_key,
             endpoint,
             model,
-            temperature: temperature.max(0.0),
+            temperature: self.temperature.max(0.0),
+            headers: header_map(&self.headers)?,
+            query: self.query,
+            log_content: log_content_enabled(),
+            timeout: self.timeout,
+            read_timeout: self.read_timeout,
         })
     }
 }
 
+fn header_map(headers: &[(String, String)]) -> Result<HeaderMap, LlmError> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| LlmError::InvalidHeader(format!("{name}: {err}")))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|err| LlmError::InvalidHeader(format!("{name}: {err}")))?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+fn log_content_enabled() -> bool {
+    std::env::var(LOG_CONTENT_ENV)
+        .map(|value| matches!(value.trim(), "1" | "true" | "on"))
+        .unwrap_or(false)
+}
+
+/// One structured record per LLM request, emitted once the request settles.
+struct RequestLog {
+    model: String,
+    input_chars: usize,
+    content: Option<String>,
+    provider_request_id: Option<String>,
+    started: Instant,
+}
+
+impl RequestLog {
+    fn emit(&self, status: u16, usage: Option<&Usage>, error: Option<&str>) {
+        info!(
+            model = %self.model,
+            input_chars = self.input_chars,
+            latency_ms = self.started.elapsed().as_millis() as u64,
+            status,
+            input_tokens = usage.map(|u| u.input_tokens),
+            output_tokens = usage.map(|u| u.output_tokens),
+            provider_request_id = self.provider_request_id.as_deref().unwrap_or("-"),
+            content = self.content.as_deref().unwrap_or("[redacted]"),
+            error,
+            "llm request"
+        );
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Usage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
 #[derive(Serialize)]
 struct ResponsesReq {
     model: String,
@@ -164,7 +340,7 @@ struct SseEvent {
     kind: String,
     delta: Option<String>,
     item: Option<serde_json::Value>,
-    _response: Option<serde_json::Value>,
+    response: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize)]
@@ -218,43 +394,121 @@ impl Llm for ChatGpt {
             tools: Some(vec![silence_tool()]),
         };
 
-        debug!("chatgpt req: {:?}", payload);
+        let span = info_span!("llm_request", model = %self.model);
+        let mut log = RequestLog {
+            model: self.model.clone(),
+            input_chars: req.sysp.chars().count()
+                + req
+                    .inputs
+                    .iter()
+                    .map(|item| item.content.chars().count())
+                    .sum::<usize>(),
+            content: req
+                .inputs
+                .last()
+                .filter(|_| self.log_content)
+                .map(|item| item.content.clone()),
+            provider_request_id: None,
+            started: Instant::now(),
+        };
 
-        let response = self
-            .client
-            .post(&self.endpoint)
-            .bearer_auth(&self.api_key)
-            .json(&payload)
-            .send()
-            .await?;
+        let sent = timeout(
+            self.timeout,
+            self.prepare_post()
+                .json(&payload)
+                .send()
+                .instrument(span.clone()),
+        )
+        .await;
+        let response = match sent {
+            Ok(Ok(response)) => response,
+            Ok(Err(err)) => {
+                span.in_scope(|| log.emit(0, None, Some(&err.to_string())));
+                return Err(err.into());
+            }
+            Err(_) => {
+                let err = LlmError::Stream(format!("no response within {:?}", self.timeout));
+                span.in_scope(|| log.emit(0, None, Some(&err.to_string())));
+                return Err(err);
+            }
+        };
 
         let status = response.status();
+        log.provider_request_id = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
         if !status.is_success() {
             let body = response.text().await.unwrap_or_default();
-            return Err(LlmError::Api(format!("status {status}: {body}")));
+            span.in_scope(|| log.emit(status.as_u16(), None, Some(&body)));
+            return Err(LlmError::from_status(status.as_u16(), &body));
         }
 
         let (tx, rx) = mpsc::channel(32);
-        let mut events = response.bytes_stream().eventsource();
-        tokio::spawn(async move {
-            while let Some(next) = events.next().await {
-                let results = match next {
-                    Ok(ev) => map_sse_event(ev),
-                    Err(err) => vec![Err(LlmError::Stream(err.to_string()))],
-                };
-                for item in results {
-                    if tx.send(item).await.is_err() {
-                        return;
+        // the idle window is applied to raw chunks so keep-alive comments, which the
+        // SSE parser drops, still count as progress
+        let mut events =
+            Box::pin(idle_bounded(response.bytes_stream(), self.read_timeout).eventsource());
+        tokio::spawn(
+            async move {
+                let mut usage = None;
+                let mut error = None;
+                while let Some(next) = events.next().await {
+                    let results = match next {
+                        Ok(ev) => map_sse_event(ev, &mut usage),
+                        Err(EventStreamError::Transport(ReadError::Idle)) => {
+                            vec![Err(LlmError::Stream("stream idle timeout".to_string()))]
+                        }
+                        Err(err) => vec![Err(LlmError::Stream(err.to_string()))],
+                    };
+                    for item in results {
+                        if let Err(err) = &item {
+                            error = Some(err.to_string());
+                        }
+                        if tx.send(item).await.is_err() {
+                            log.emit(status.as_u16(), usage.as_ref(), error.as_deref());
+                            return;
+                        }
                     }
                 }
+                log.emit(status.as_u16(), usage.as_ref(), error.as_deref());
             }
-        });
+            .instrument(span),
+        );
 
         Ok(ResponseStream { rx_event: rx })
     }
 }
 
-fn map_sse_event(event: eventsource_stream::Event) -> Vec<Result<LlmStreamEvent, LlmError>> {
+#[derive(Debug, thiserror::Error)]
+enum ReadError {
+    #[error("{0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("stream idle timeout")]
+    Idle,
+}
+
+/// Ends `chunks` with [`ReadError::Idle`] once nothing arrives for `idle`.
+fn idle_bounded<S, B>(chunks: S, idle: Duration) -> impl Stream<Item = Result<B, ReadError>>
+where
+    S: Stream<Item = Result<B, reqwest::Error>>,
+    B: AsRef<[u8]>,
+{
+    stream::unfold(Some(Box::pin(chunks)), move |state| async move {
+        let mut chunks = state?;
+        match timeout(idle, chunks.next()).await {
+            Ok(Some(chunk)) => Some((chunk.map_err(ReadError::from), Some(chunks))),
+            Ok(None) => None,
+            Err(_) => Some((Err(ReadError::Idle), None)),
+        }
+    })
+}
+
+fn map_sse_event(
+    event: eventsource_stream::Event,
+    usage: &mut Option<Usage>,
+) -> Vec<Result<LlmStreamEvent, LlmError>> {
     trace!("sse event: {}", event.data);
     let parsed = match serde_json::from_str::<SseEvent>(&event.data) {
         Ok(val) => val,
@@ -290,7 +544,13 @@ fn map_sse_event(event: eventsource_stream::Event) -> Vec<Result<LlmStreamEvent,
                 Vec::new()
             }
         }
-        "response.completed" => vec![Ok(LlmStreamEvent::Completed)],
+        "response.completed" => {
+            *usage = parsed
+                .response
+                .and_then(|response| response.get("usage").cloned())
+                .and_then(|value| serde_json::from_value(value).ok());
+            vec![Ok(LlmStreamEvent::Completed)]
+        }
         _ => Vec::new(),
     }
 }
@@ -0,0 +1,44 @@
+use async_trait::async_trait;
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use super::llm::Llm;
+use super::llm::LlmError;
+use super::llm::LlmReq;
+use super::llm::LlmStreamEvent;
+use super::llm::ResponseStream;
+
+pub const ECHO_PROVIDER: &str = "echo";
+
+const ECHO_PREFIX: &str = "echo:";
+
+/// Offline stand-in for a real provider: answers the latest user input with a
+/// canned transformation and never touches the network.
+#[derive(Debug, Clone, Default)]
+pub struct Echo;
+
+#[async_trait]
+impl Llm for Echo {
+    async fn chat_stream(&self, req: &LlmReq<'_>) -> Result<ResponseStream, LlmError> {
+        if req.inputs.is_empty() && req.sysp.trim().is_empty() {
+            return Err(LlmError::EmptyPrompt);
+        }
+
+        let (tx, rx) = mpsc::channel(2);
+        let reply = match req.inputs.last() {
+            Some(last) if last.role == "user" => {
+                LlmStreamEvent::ContentDelta(format!("{ECHO_PREFIX} {}", last.content.trim()))
+            }
+            _ => LlmStreamEvent::ToolCallDelta {
+                id: ECHO_PROVIDER.to_string(),
+                name: Some("TIM-LLM-SILENCE".to_string()),
+                arguments_delta: json!({ "reason": "nothing new to echo" }).to_string(),
+                finished: true,
+            },
+        };
+        let _ = tx.try_send(Ok(reply));
+        let _ = tx.try_send(Ok(LlmStreamEvent::Completed));
+
+        Ok(ResponseStream { rx_event: rx })
+    }
+}
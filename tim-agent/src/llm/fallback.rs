@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::warn;
+
+use super::llm::Llm;
+use super::llm::LlmError;
+use super::llm::LlmReq;
+use super::llm::LlmRes;
+use super::llm::ResponseStream;
+
+type Labelled = (String, Arc<dyn Llm>);
+
+/// Asks the primary LLM first and moves down the list only on transient errors.
+/// Every request starts over at the primary, so a recovered primary is used again
+/// right away.
+pub struct FallbackLlm {
+    chain: Vec<Labelled>,
+}
+
+impl FallbackLlm {
+    /// `chain` holds labelled LLMs, primary first. The labels only show up in logs.
+    pub fn new(chain: Vec<Labelled>) -> Self {
+        Self { chain }
+    }
+
+    /// The last LLM, whose error is final, and the ones tried before it.
+    fn split(&self) -> Result<(&Arc<dyn Llm>, &[Labelled]), LlmError> {
+        let ((_, last), earlier) = self
+            .chain
+            .split_last()
+            .ok_or_else(|| LlmError::Api("no llm configured".to_string()))?;
+        Ok((last, earlier))
+    }
+}
+
+fn log_fallback(label: &str, err: &LlmError) {
+    warn!(llm = %label, error = %err, "llm failed, trying the next one");
+}
+
+#[async_trait]
+impl Llm for FallbackLlm {
+    async fn chat_stream(&self, req: &LlmReq<'_>) -> Result<ResponseStream, LlmError> {
+        let (last, earlier) = self.split()?;
+        for (label, llm) in earlier {
+            match llm.chat_stream(req).await {
+                Err(err) if err.is_transient() => log_fallback(label, &err),
+                res => return res,
+            }
+        }
+        last.chat_stream(req).await
+    }
+
+    // overridden so that a stream failing half way also moves on to the fallback
+    async fn chat(&self, req: &LlmReq<'_>) -> Result<LlmRes, LlmError> {
+        let (last, earlier) = self.split()?;
+        for (label, llm) in earlier {
+            match llm.chat(req).await {
+                Err(err) if err.is_transient() => log_fallback(label, &err),
+                res => return res,
+            }
+        }
+        last.chat(req).await
+    }
+}
@@ -0,0 +1,56 @@
+use crate::tim_client::Event;
+use crate::tim_client::SpaceEvent;
+
+/// Tracks whether anything worth answering arrived since the agent's last live
+/// turn, so idle ticks don't send the same context to the LLM again.
+#[derive(Debug)]
+pub struct LiveTurnGate {
+    self_id: u64,
+    /// Set by new context, cleared when a turn is taken. Starts set so the first
+    /// tick answers the history already there.
+    pending: bool,
+}
+
+impl LiveTurnGate {
+    pub fn new(self_id: u64) -> Self {
+        Self {
+            self_id,
+            pending: true,
+        }
+    }
+
+    /// Records an event the agent received. The agent's own messages and calls
+    /// don't count, or it would keep taking turns to answer itself.
+    pub fn observe(&mut self, event: &SpaceEvent) {
+        if self.adds_context(event) {
+            self.pending = true;
+        }
+    }
+
+    /// Whether a live tick should ask the LLM; a `true` answer counts as the turn
+    /// being taken. Events arriving while the turn runs make the next one fire.
+    pub fn take_turn(&mut self) -> bool {
+        std::mem::take(&mut self.pending)
+    }
+
+    fn adds_context(&self, event: &SpaceEvent) -> bool {
+        match &event.data {
+            Some(Event::EventNewMessage(payload)) => payload
+                .message
+                .as_ref()
+                .is_some_and(|message| message.sender_id != self.self_id),
+            Some(Event::EventCallAbility(payload)) => payload
+                .call_ability
+                .as_ref()
+                .is_some_and(|call| call.sender_id != self.self_id),
+            Some(Event::EventCallAbilityOutcome(_)) => true,
+            // presence, activity and deletions give the agent nothing new to answer
+            Some(Event::EventTimiteConnected(_))
+            | Some(Event::EventTimiteDisconnected(_))
+            | Some(Event::EventAbilitiesChanged(_))
+            | Some(Event::EventTimiteActivity(_))
+            | Some(Event::EventMessageDeleted(_))
+            | None => false,
+        }
+    }
+}
@@ -6,12 +6,40 @@ use std::task::Poll;
 use async_trait::async_trait;
 use futures::Stream;
 use futures::StreamExt;
+use serde::Deserialize;
 use thiserror::Error;
 use tokio::sync::mpsc;
 use tracing::debug;
 use tracing::trace;
 use tracing::warn;
 
+use super::chatgpt::OPENAI_DEFAULT_ENDPOINT;
+
+/// LLM implementation selected by the agent's `provider`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmProvider {
+    OpenAi,
+    Echo,
+}
+
+impl LlmProvider {
+    /// Reads the LLM part of a `<llm>:<label>` provider, e.g. `openai` from `openai:jarvis`.
+    pub fn from_provider(provider: &str) -> Option<Self> {
+        match provider.split(':').next().unwrap_or_default() {
+            "openai" => Some(Self::OpenAi),
+            "echo" => Some(Self::Echo),
+            _ => None,
+        }
+    }
+
+    pub fn default_endpoint(self) -> &'static str {
+        match self {
+            Self::OpenAi => OPENAI_DEFAULT_ENDPOINT,
+            Self::Echo => "",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct LlmInputItem {
     pub role: &'static str,
@@ -24,9 +52,20 @@ pub struct LlmReq<'a> {
     pub inputs: &'a [LlmInputItem],
 }
 
+#[derive(Debug)]
 pub enum LlmRes {
     Reply(String),
     NoResponse(String), // Contains the reason for silence
+    ToolCalls(Vec<ToolInvocation>),
+    /// Content the model wrote alongside the tool calls it requested.
+    ReplyWithToolCalls(String, Vec<ToolInvocation>),
+}
+
+/// A finished tool call with its arguments parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolInvocation {
+    pub name: String,
+    pub args: serde_json::Value,
 }
 
 #[derive(Debug, Error)]
@@ -35,16 +74,77 @@ pub enum LlmError {
     EmptyPrompt,
     #[error("missing OpenAI API key (set OPENAI_API_KEY)")]
     MissingApiKey,
+    #[error("invalid request header: {0}")]
+    InvalidHeader(String),
     #[error("http error while contacting LLM: {0}")]
     Http(#[from] reqwest::Error),
     #[error("failed to decode LLM response: {0}")]
     Response(#[from] serde_json::Error),
     #[error("LLM reported an error: {0}")]
     Api(String),
+    #[error("LLM rate limited the request ({status}): {message}")]
+    RateLimited { status: u16, message: String },
+    #[error("LLM server error ({status}): {message}")]
+    ServerError { status: u16, message: String },
+    #[error("LLM rejected the request ({status}): {message}")]
+    ClientError { status: u16, message: String },
+    #[error("LLM refused the credentials ({status}): {message}")]
+    Auth { status: u16, message: String },
     #[error("LLM response missing message content")]
     MissingContent,
     #[error("llm stream error: {0}")]
     Stream(String),
+    #[error("malformed arguments for tool {name}: {source}")]
+    ToolArguments {
+        name: String,
+        source: serde_json::Error,
+    },
+}
+
+impl LlmError {
+    /// Categorizes a non-success response. The message is taken from the provider's
+    /// error envelope when there is one, else the raw body. Statuses outside 4xx are
+    /// treated as server errors so they get retried rather than given up on.
+    pub fn from_status(status: u16, body: &str) -> Self {
+        let message = error_message(body);
+        match status {
+            401 | 403 => LlmError::Auth { status, message },
+            429 => LlmError::RateLimited { status, message },
+            408 => LlmError::ServerError { status, message },
+            400..=499 => LlmError::ClientError { status, message },
+            _ => LlmError::ServerError { status, message },
+        }
+    }
+
+    /// Failures another attempt or endpoint may not share: transport errors,
+    /// timeouts, rate limits and server errors. Bad requests and auth errors would
+    /// fail the same way anywhere.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            LlmError::Http(_)
+                | LlmError::Stream(_)
+                | LlmError::RateLimited { .. }
+                | LlmError::ServerError { .. }
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+fn error_message(body: &str) -> String {
+    match serde_json::from_str::<ErrorEnvelope>(body) {
+        Ok(envelope) if !envelope.error.message.is_empty() => envelope.error.message,
+        _ => body.trim().to_string(),
+    }
 }
 
 #[derive(Debug)]
@@ -65,7 +165,7 @@ pub struct ResponseStream {
 
 #[derive(Debug)]
 struct ToolCall {
-    _id: String,
+    id: String,
     name: Option<String>,
     arguments: String,
 }
@@ -73,11 +173,24 @@ struct ToolCall {
 impl ToolCall {
     fn new(id: String) -> Self {
         Self {
-            _id: id,
+            id,
             name: None,
             arguments: String::new(),
         }
     }
+
+    // Arguments stream in fragments, so this only makes sense once the call is finished.
+    fn into_invocation(self) -> Result<ToolInvocation, LlmError> {
+        let name = self.name.unwrap_or(self.id);
+        let args = match self.arguments.trim() {
+            "" => serde_json::Value::Object(Default::default()),
+            raw => serde_json::from_str(raw).map_err(|source| LlmError::ToolArguments {
+                name: name.clone(),
+                source,
+            })?,
+        };
+        Ok(ToolInvocation { name, args })
+    }
 }
 
 impl Stream for ResponseStream {
@@ -91,7 +204,7 @@ impl Stream for ResponseStream {
 #[derive(Debug)]
 struct CollectedStream {
     message: String,
-    tool_calls: Vec<ToolCall>,
+    tool_calls: Vec<ToolInvocation>,
 }
 
 #[async_trait]
@@ -106,9 +219,16 @@ pub trait Llm: Send + Sync {
             return Ok(LlmRes::NoResponse(reason));
         }
 
-        match collected.message.trim() {
-            "" => Err(LlmError::MissingContent),
-            content => Ok(LlmRes::Reply(content.to_string())),
+        // tool calls are passed on even when no tool by that name is known, the
+        // caller decides what to do with them
+        match (collected.message.trim(), collected.tool_calls.is_empty()) {
+            ("", true) => Err(LlmError::MissingContent),
+            ("", false) => Ok(LlmRes::ToolCalls(collected.tool_calls)),
+            (content, true) => Ok(LlmRes::Reply(content.to_string())),
+            (content, false) => Ok(LlmRes::ReplyWithToolCalls(
+                content.to_string(),
+                collected.tool_calls,
+            )),
         }
     }
 }
@@ -117,7 +237,8 @@ async fn collect_message_and_tool_calls(
     stream: &mut ResponseStream,
 ) -> Result<CollectedStream, LlmError> {
     let mut message = String::new();
-    let mut tool_calls: HashMap<String, ToolCall> = HashMap::new();
+    let mut pending: HashMap<String, ToolCall> = HashMap::new();
+    let mut tool_calls = Vec::new();
 
     while let Some(item) = stream.next().await {
         match item? {
@@ -138,13 +259,18 @@ async fn collect_message_and_tool_calls(
                     arguments_delta,
                     finished
                 );
-                let entry = tool_calls
+                let entry = pending
                     .entry(id.clone())
                     .or_insert_with(|| ToolCall::new(id.clone()));
                 if let Some(name) = name {
                     entry.name = Some(name);
                 }
                 entry.arguments.push_str(&arguments_delta);
+                if finished {
+                    if let Some(call) = pending.remove(&id) {
+                        tool_calls.push(call.into_invocation()?);
+                    }
+                }
             }
             LlmStreamEvent::Completed => {
                 debug!("LLM stream completed");
@@ -153,40 +279,29 @@ async fn collect_message_and_tool_calls(
         }
     }
 
+    for call in pending.into_values() {
+        warn!(
+            "dropping unfinished tool call {:?} ({})",
+            call.name, call.id
+        );
+    }
+
     Ok(CollectedStream {
         message,
-        tool_calls: tool_calls.into_values().collect(),
+        tool_calls,
     })
 }
 
-fn silence_reason(tool_calls: &[ToolCall]) -> Option<String> {
+fn silence_reason(tool_calls: &[ToolInvocation]) -> Option<String> {
     let call = tool_calls
         .iter()
-        .find(|call| call.name.as_deref() == Some("TIM-LLM-SILENCE"))?;
-
-    Some(parse_silence_reason(&call.arguments))
-}
-
-fn parse_silence_reason(args: &str) -> String {
-    if args.trim().is_empty() {
-        debug!("TIM-LLM-SILENCE called without arguments, defaulting reason");
-        return "No reason provided".to_string();
-    }
+        .find(|call| call.name == "TIM-LLM-SILENCE")?;
 
-    match serde_json::from_str::<serde_json::Value>(args) {
-        Ok(parsed) => parsed["reason"]
-            .as_str()
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| {
-                warn!("TIM-LLM-SILENCE missing 'reason' field. Args: {}", args);
-                "No reason provided".to_string()
-            }),
-        Err(e) => {
-            warn!(
-                "Failed to parse TIM-LLM-SILENCE arguments: {}. Args: {}",
-                e, args
-            );
-            "No reason provided".to_string()
+    match call.args["reason"].as_str() {
+        Some(reason) => Some(reason.to_string()),
+        None => {
+            debug!("TIM-LLM-SILENCE called without a reason, defaulting");
+            Some("No reason provided".to_string())
         }
     }
 }
@@ -1,24 +1,83 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use chrono::SecondsFormat;
 use chrono::TimeZone;
 use chrono::Utc;
+use futures::stream;
+use futures::stream::BoxStream;
 use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
 use tokio_stream::StreamExt;
+use tracing::warn;
 
 use crate::llm::llm::LlmInputItem;
 use crate::tim_client::tim_api::EventCallAbility;
 use crate::tim_client::tim_api::EventCallAbilityOutcome;
 use crate::tim_client::tim_api::EventNewMessage;
+use crate::tim_client::tim_api::GetTimelineRes;
+use crate::tim_client::tim_api::OutcomeStatus;
 use crate::tim_client::tim_api::Timite;
 use crate::tim_client::Event;
+use crate::tim_client::SpaceEvent;
 use crate::tim_client::TimClient;
 use crate::tim_client::TimClientError;
+use crate::tim_client::TimelinePaging;
+use crate::tim_client::TimelineSource;
 
-const TIMELINE_PAGE_SIZE: u32 = 128;
+/// How hard a single timeline page is retried before the context is built without it.
+#[derive(Debug, Clone)]
+pub struct PageRetry {
+    /// Tries per page, the first one included.
+    pub attempts: u32,
+    /// Pause before the first retry, doubled for each one after.
+    pub backoff: Duration,
+}
+
+impl Default for PageRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+/// Narrows the history handed to the LLM. Empty lists match everything;
+/// when both are set an event has to pass both.
+#[derive(Debug, Clone, Default)]
+pub struct ContextFilter {
+    /// Sender nicks, compared case-insensitively.
+    pub senders: Vec<String>,
+    /// Substrings looked up case-insensitively in the rendered event.
+    pub keywords: Vec<String>,
+}
+
+impl ContextFilter {
+    fn matches(&self, sender: Option<&str>, content: &str) -> bool {
+        let sender_ok = self.senders.is_empty()
+            || sender.is_some_and(|sender| {
+                self.senders
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(sender))
+            });
+        let content = content.to_lowercase();
+        let keyword_ok = self.keywords.is_empty()
+            || self
+                .keywords
+                .iter()
+                .any(|keyword| content.contains(&keyword.to_lowercase()));
+        sender_ok && keyword_ok
+    }
+}
 
 pub(super) struct Memory {
     client: TimClient,
+    retry: PageRetry,
+    /// Renders only the most recent items, full history when unset.
+    max_items: Option<usize>,
 }
 
 #[derive(Debug, Error)]
@@ -28,25 +87,45 @@ pub(super) enum MemoryError {
 }
 
 impl Memory {
-    pub(super) fn new(client: TimClient) -> Self {
-        Self { client }
+    pub(super) fn new(client: TimClient, max_items: Option<usize>) -> Self {
+        Self {
+            client,
+            retry: PageRetry::default(),
+            max_items,
+        }
     }
 
     pub(super) async fn context(&mut self) -> Result<Vec<LlmInputItem>, MemoryError> {
+        self.context_filtered(&ContextFilter::default()).await
+    }
+
+    pub(super) async fn context_filtered(
+        &mut self,
+        filter: &ContextFilter,
+    ) -> Result<Vec<LlmInputItem>, MemoryError> {
         let self_id = self.client.timite_id();
-        let mut messages = Vec::new();
-        let mut names = HashMap::new();
-        let mut stream = Box::pin(self.client.timeline_stream(TIMELINE_PAGE_SIZE));
-        while let Some(page) = stream.next().await {
-            let page = page?;
-            Self::collect_nicks(&mut names, &page.timites);
-            for event in &page.events {
-                if let Some(message) = Self::render_event(event, &names, self_id) {
-                    messages.push(message);
-                }
-            }
+        let paging = self.client.paging();
+        if let Some(max_items) = self.max_items {
+            return Ok(recent_context(
+                &mut self.client,
+                self_id,
+                filter,
+                paging.page_size,
+                max_items,
+                &self.retry,
+            )
+            .await?);
+        }
+        let pages = timeline_stream(self.client.clone(), paging, self.retry.clone());
+        Ok(collect_context(pages, self_id, filter).await?)
+    }
+
+    fn event_sender(event: &SpaceEvent) -> Option<u64> {
+        match &event.data {
+            Some(Event::EventNewMessage(msg)) => msg.message.as_ref().map(|m| m.sender_id),
+            Some(Event::EventCallAbility(call)) => call.call_ability.as_ref().map(|c| c.sender_id),
+            _ => None,
         }
-        Ok(messages)
     }
 
     fn collect_nicks(names: &mut HashMap<u64, String>, timites: &[Timite]) {
@@ -80,6 +159,10 @@ impl Memory {
             }
             Some(Event::EventTimiteConnected(_)) => None,
             Some(Event::EventTimiteDisconnected(_)) => None,
+            Some(Event::EventAbilitiesChanged(_)) => None,
+            Some(Event::EventTimiteActivity(_)) => None,
+            // deleted messages are tombstones in the timeline, which render to nothing
+            Some(Event::EventMessageDeleted(_)) => None,
             None => None,
         }
     }
@@ -92,7 +175,8 @@ impl Memory {
     ) -> Option<LlmInputItem> {
         let message = new_message.message.as_ref()?;
         let content = message.content.trim();
-        if content.is_empty() {
+        // tombstones of expired and erased messages
+        if message.deleted || content.is_empty() {
             return None;
         }
         let timestamp = Self::format_emitted_at(emitted_at).unwrap_or_else(|| "-".to_string());
@@ -144,6 +228,9 @@ impl Memory {
             parts.push(format!("error={err}"));
         }
         let mut line = format!("CallAbilityOutcome:id={}", payload.call_ability_id);
+        if payload.status() == OutcomeStatus::InProgress {
+            line.push_str(" status=in_progress");
+        }
         if !parts.is_empty() {
             line.push(' ');
             line.push_str(&parts.join(" "));
@@ -180,3 +267,243 @@ impl Memory {
         }
     }
 }
+
+/// Pages through the timeline, retrying a failing page as `retry` allows. A page
+/// that keeps failing ends the walk: the pages before it are returned and nothing
+/// after it, so the history has no holes.
+pub async fn fetch_pages<S: TimelineSource + ?Sized>(
+    source: &mut S,
+    page_size: u32,
+    retry: &PageRetry,
+) -> Vec<GetTimelineRes> {
+    let mut pages = Vec::new();
+    if page_size == 0 {
+        return pages;
+    }
+    let mut walk = PageWalk::default();
+    while let Some(page) = walk.next(source, page_size, retry).await {
+        pages.push(page);
+    }
+    pages
+}
+
+/// The pages `fetch_pages` walks through, as a stream. `paging` is checked first:
+/// a zero page size yields an error instead of an empty history. With a prefetch
+/// depth a task reads up to that many pages ahead of the consumer.
+pub fn timeline_stream<S: TimelineSource + 'static>(
+    source: S,
+    paging: TimelinePaging,
+    retry: PageRetry,
+) -> BoxStream<'static, Result<GetTimelineRes, TimClientError>> {
+    let paging = match paging.checked() {
+        Ok(paging) => paging,
+        Err(err) => return Box::pin(stream::once(async move { Err(err) })),
+    };
+    let page_size = paging.page_size;
+    if paging.prefetch == 0 {
+        let state = (source, PageWalk::default(), retry);
+        return Box::pin(stream::unfold(
+            state,
+            move |(mut source, mut walk, retry)| async move {
+                let page = walk.next(&mut source, page_size, &retry).await?;
+                Some((Ok(page), (source, walk, retry)))
+            },
+        ));
+    }
+    let (tx, rx) = mpsc::channel(paging.prefetch);
+    tokio::spawn(async move {
+        let mut source = source;
+        let mut walk = PageWalk::default();
+        while let Some(page) = walk.next(&mut source, page_size, &retry).await {
+            if tx.send(Ok(page)).await.is_err() {
+                break;
+            }
+        }
+    });
+    Box::pin(ReceiverStream::new(rx))
+}
+
+/// Where a walk through the timeline from its start stands.
+#[derive(Default)]
+struct PageWalk {
+    offset: u64,
+    done: bool,
+}
+
+impl PageWalk {
+    async fn next<S: TimelineSource + ?Sized>(
+        &mut self,
+        source: &mut S,
+        page_size: u32,
+        retry: &PageRetry,
+    ) -> Option<GetTimelineRes> {
+        if self.done {
+            return None;
+        }
+        let page = fetch_page(source, self.offset, page_size, retry)
+            .await
+            .filter(|page| !page.events.is_empty());
+        let Some(page) = page else {
+            self.done = true;
+            return None;
+        };
+        let len = page.events.len() as u64;
+        // servers that report `total_known` also say whether more follows,
+        // older ones leave only the short page to go by
+        self.done = match page.total_known {
+            Some(_) => !page.has_more,
+            None => len < page_size as u64,
+        };
+        self.offset = self.offset.saturating_add(len);
+        Some(page)
+    }
+}
+
+/// Pages back from the newest event until `max_items` rendered items matching
+/// `filter` are collected, and returns the pages oldest first. A page that keeps
+/// failing ends the walk like in `fetch_pages`.
+pub async fn fetch_recent_pages<S: TimelineSource + ?Sized>(
+    source: &mut S,
+    self_id: u64,
+    filter: &ContextFilter,
+    page_size: u32,
+    max_items: usize,
+    retry: &PageRetry,
+) -> Vec<GetTimelineRes> {
+    let mut pages: Vec<GetTimelineRes> = Vec::new();
+    if page_size == 0 || max_items == 0 {
+        return pages;
+    }
+    let mut rendered = 0;
+    // offset 0 asks for the newest page, any other offset is the id to start from
+    let mut offset = 0u64;
+    loop {
+        let Some(mut page) = fetch_page(source, offset, page_size, retry).await else {
+            break;
+        };
+        // a page read from a start id may run into the newer page already fetched
+        if let Some(newer) = pages.last().and_then(first_event_id) {
+            page.events
+                .retain(|event| event_id(event).is_some_and(|id| id < newer));
+        }
+        if page.events.is_empty() {
+            break;
+        }
+        rendered += rendered_items(&page, self_id, filter);
+        let first = first_event_id(&page);
+        pages.push(page);
+        match first {
+            // id 0 is only reachable through the newest page
+            Some(first) if first > 1 && rendered < max_items => {
+                offset = first.saturating_sub(page_size as u64).max(1);
+            }
+            _ => break,
+        }
+    }
+    pages.reverse();
+    pages
+}
+
+/// The most recent `max_items` items of the timeline, rendered as `collect_context`
+/// does, without reading further back than needed.
+pub async fn recent_context<S: TimelineSource + ?Sized>(
+    source: &mut S,
+    self_id: u64,
+    filter: &ContextFilter,
+    page_size: u32,
+    max_items: usize,
+    retry: &PageRetry,
+) -> Result<Vec<LlmInputItem>, TimClientError> {
+    let pages = fetch_recent_pages(source, self_id, filter, page_size, max_items, retry).await;
+    let mut items =
+        collect_context(stream::iter(pages.into_iter().map(Ok)), self_id, filter).await?;
+    // the oldest page is usually only partly needed
+    let excess = items.len().saturating_sub(max_items);
+    items.drain(..excess);
+    Ok(items)
+}
+
+fn event_id(event: &SpaceEvent) -> Option<u64> {
+    event.metadata.as_ref().map(|meta| meta.id)
+}
+
+fn first_event_id(page: &GetTimelineRes) -> Option<u64> {
+    page.events.first().and_then(event_id)
+}
+
+fn rendered_items(page: &GetTimelineRes, self_id: u64, filter: &ContextFilter) -> usize {
+    let mut names = HashMap::new();
+    Memory::collect_nicks(&mut names, &page.timites);
+    page.events
+        .iter()
+        .filter(|event| {
+            Memory::render_event(event, &names, self_id).is_some_and(|item| {
+                let sender = Memory::event_sender(event).and_then(|id| names.get(&id));
+                filter.matches(sender.map(String::as_str), &item.content)
+            })
+        })
+        .count()
+}
+
+async fn fetch_page<S: TimelineSource + ?Sized>(
+    source: &mut S,
+    offset: u64,
+    page_size: u32,
+    retry: &PageRetry,
+) -> Option<GetTimelineRes> {
+    let attempts = retry.attempts.max(1);
+    let mut backoff = retry.backoff;
+    for attempt in 1..=attempts {
+        match source.timeline_page(offset, page_size).await {
+            Ok(page) => return Some(page),
+            Err(err) if attempt < attempts => {
+                warn!(offset, attempt, error = %err, "timeline page failed, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(err) => {
+                warn!(offset, attempts, error = %err, "timeline page failed, building context without the rest");
+            }
+        }
+    }
+    None
+}
+
+/// Renders timeline pages into LLM input in chronological order, keeping only
+/// events that match `filter`. The latest message from another timite is kept
+/// regardless, it is what the agent is about to answer.
+pub async fn collect_context<S>(
+    stream: S,
+    self_id: u64,
+    filter: &ContextFilter,
+) -> Result<Vec<LlmInputItem>, TimClientError>
+where
+    S: Stream<Item = Result<GetTimelineRes, TimClientError>>,
+{
+    let mut rendered = Vec::new();
+    let mut names = HashMap::new();
+    let mut trigger = None;
+    let mut stream = Box::pin(stream);
+    while let Some(page) = stream.next().await {
+        let page = page?;
+        Memory::collect_nicks(&mut names, &page.timites);
+        for event in &page.events {
+            let Some(item) = Memory::render_event(event, &names, self_id) else {
+                continue;
+            };
+            let sender = Memory::event_sender(event);
+            if matches!(event.data, Some(Event::EventNewMessage(_))) && sender != Some(self_id) {
+                trigger = Some(rendered.len());
+            }
+            rendered.push((sender.and_then(|id| names.get(&id).cloned()), item));
+        }
+    }
+    Ok(rendered
+        .into_iter()
+        .enumerate()
+        .filter(|(index, (sender, item))| {
+            Some(*index) == trigger || filter.matches(sender.as_deref(), &item.content)
+        })
+        .map(|(_, (_, item))| item)
+        .collect())
+}
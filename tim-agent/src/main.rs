@@ -1,10 +1,7 @@
-mod agent;
-mod crawler;
-mod llm;
-mod tim_client;
-
 use std::fs;
+use std::io;
 use std::path::Path;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use config::Config;
@@ -12,25 +9,35 @@ use config::Environment;
 use config::File;
 use config::FileFormat;
 use dotenvy::dotenv;
-use futures::future::try_join_all;
 use futures::future::BoxFuture;
 use serde::Deserialize;
 use shellexpand::env as expand_env;
+use tim_agent::agent;
+use tim_agent::agent::RestartPolicy;
+use tim_agent::crawler::CrawlerConf;
+use tim_agent::llm::memory::ContextFilter;
+use tim_agent::llm::AgentConf;
+use tim_agent::llm::LlmFallback;
+use tim_agent::llm::LlmProvider;
+use tim_agent::tim_client::TimClient;
+use tim_agent::tim_client::TimClientConf;
+use tim_agent::tim_client::TimelinePaging;
+use tim_agent::tim_client::DEFAULT_CONNECT_TIMEOUT;
+use tokio::task::JoinSet;
 use toml_edit::value;
 use toml_edit::DocumentMut;
+use tracing::error;
 use tracing::warn;
 use tracing_subscriber::fmt;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
-use crate::crawler::CrawlerConf;
-use crate::llm::AgentConf;
-use crate::llm::OPENAI_DEFAULT_ENDPOINT;
-use crate::tim_client::TimClient;
-use crate::tim_client::TimClientConf;
-
 const CONFIG_PATH: &str = "agents.toml";
+const PROMPTS_DIR_FLAG: &str = "--prompts-dir";
+const PROMPTS_DIR_ENV: &str = "TIM_AGENT_PROMPTS_DIR";
+const DEFAULT_TIM_ENDPOINT: &str = "http://127.0.0.1:8787";
+const DEFAULT_TEMPERATURE: f32 = 1.0;
 
 struct LoadedConfig {
     config: AppConfig,
@@ -51,33 +58,245 @@ enum AgentConfig {
     Crawler(CrawlerAgentConfig),
 }
 
+/// A config value that deserialized but makes no sense, named by agent and field.
+#[derive(Debug, thiserror::Error)]
+#[error("agent {nick:?}: {field} {problem}")]
+struct InvalidAgentConfig {
+    nick: String,
+    field: &'static str,
+    problem: &'static str,
+}
+
 #[derive(Deserialize)]
 struct LlmAgentConfig {
     nick: String,
     provider: String,
+    #[serde(default = "default_tim_endpoint")]
     endpoint: String,
     prompt: String,
     model: String,
+    #[serde(default = "default_temperature")]
     temperature: f32,
     live_interval_secs: Option<u64>,
+    #[serde(default)]
+    live_when_unchanged: bool,
+    #[serde(default)]
+    thinking_indicator: bool,
+    context_senders: Option<Vec<String>>,
+    context_keywords: Option<Vec<String>>,
+    max_context_items: Option<usize>,
+    #[serde(default)]
+    fallbacks: Vec<LlmFallbackConfig>,
     api_key: String,
     timite_id: Option<u64>,
+    session_key: Option<String>,
+    connect_timeout_secs: Option<u64>,
+}
+
+/// Endpoint and key default to the primary's, so a fallback may only swap the model.
+#[derive(Deserialize)]
+struct LlmFallbackConfig {
+    endpoint: Option<String>,
+    model: String,
+    api_key: Option<String>,
 }
 
 #[derive(Deserialize)]
 struct CrawlerAgentConfig {
     nick: String,
     provider: String,
+    #[serde(default = "default_tim_endpoint")]
     endpoint: String,
+    #[serde(default = "default_ability_name")]
     ability_name: String,
+    #[serde(default = "default_max_snippet_chars")]
     max_snippet_chars: usize,
+    #[serde(default = "default_user_agent")]
     user_agent: String,
+    cache_capacity: Option<usize>,
+    cache_ttl_secs: Option<u64>,
     timite_id: Option<u64>,
+    session_key: Option<String>,
+    connect_timeout_secs: Option<u64>,
+}
+
+fn default_tim_endpoint() -> String {
+    DEFAULT_TIM_ENDPOINT.to_string()
+}
+
+fn default_temperature() -> f32 {
+    DEFAULT_TEMPERATURE
+}
+
+fn default_ability_name() -> String {
+    CrawlerConf::default().ability_name
+}
+
+fn default_max_snippet_chars() -> usize {
+    CrawlerConf::default().max_snippet_chars
+}
+
+fn default_user_agent() -> String {
+    CrawlerConf::default().user_agent
+}
+
+impl AgentConfig {
+    /// Range checks serde can't express. Runs on the merged config, so values
+    /// coming from `TIM_AGENT__...` overrides are checked as well.
+    fn validate(&self) -> Result<(), InvalidAgentConfig> {
+        let (nick, checks) = match self {
+            AgentConfig::Llm(conf) => (
+                &conf.nick,
+                vec![
+                    (conf.nick.trim().is_empty(), "nick", "must not be empty"),
+                    (
+                        conf.endpoint.trim().is_empty(),
+                        "endpoint",
+                        "must not be empty",
+                    ),
+                    (conf.prompt.trim().is_empty(), "prompt", "must not be empty"),
+                    (conf.model.trim().is_empty(), "model", "must not be empty"),
+                    (
+                        conf.fallbacks
+                            .iter()
+                            .any(|fallback| fallback.model.trim().is_empty()),
+                        "fallbacks.model",
+                        "must not be empty",
+                    ),
+                    (
+                        !(0.0..=2.0).contains(&conf.temperature),
+                        "temperature",
+                        "must be between 0 and 2",
+                    ),
+                    (
+                        conf.live_interval_secs == Some(0),
+                        "live_interval_secs",
+                        "must be positive",
+                    ),
+                    (
+                        conf.connect_timeout_secs == Some(0),
+                        "connect_timeout_secs",
+                        "must be positive",
+                    ),
+                ],
+            ),
+            AgentConfig::Crawler(conf) => (
+                &conf.nick,
+                vec![
+                    (conf.nick.trim().is_empty(), "nick", "must not be empty"),
+                    (
+                        conf.endpoint.trim().is_empty(),
+                        "endpoint",
+                        "must not be empty",
+                    ),
+                    (
+                        conf.ability_name.trim().is_empty(),
+                        "ability_name",
+                        "must not be empty",
+                    ),
+                    (
+                        conf.max_snippet_chars == 0,
+                        "max_snippet_chars",
+                        "must be positive",
+                    ),
+                    (
+                        conf.connect_timeout_secs == Some(0),
+                        "connect_timeout_secs",
+                        "must be positive",
+                    ),
+                ],
+            ),
+        };
+        match checks.into_iter().find(|(failed, _, _)| *failed) {
+            Some((_, field, problem)) => Err(InvalidAgentConfig {
+                nick: nick.clone(),
+                field,
+                problem,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn connect_timeout(secs: Option<u64>) -> Duration {
+    secs.map(Duration::from_secs)
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT)
+}
+
+// shared by all agents, a server requiring tokens takes the same one from each
+fn auth_token() -> Option<String> {
+    std::env::var("TIM_AUTH_TOKEN").ok()
+}
+
+fn context_filter(
+    senders: Option<Vec<String>>,
+    keywords: Option<Vec<String>>,
+) -> Option<ContextFilter> {
+    if senders.is_none() && keywords.is_none() {
+        return None;
+    }
+    Some(ContextFilter {
+        senders: senders.unwrap_or_default(),
+        keywords: keywords.unwrap_or_default(),
+    })
 }
 
-fn load_prompt(prompts_dir: &Path, name: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let prompt_path = prompts_dir.join(name);
-    Ok(fs::read_to_string(prompt_path)?)
+/// Directories searched for prompts, in order: `--prompts-dir`, then
+/// `TIM_AGENT_PROMPTS_DIR`, then `prompts` next to the executable, then the
+/// source tree for `cargo run`.
+fn prompt_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = flag_value(std::env::args().skip(1), PROMPTS_DIR_FLAG) {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Ok(dir) = std::env::var(PROMPTS_DIR_ENV) {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        dirs.push(exe_dir.join("prompts"));
+    }
+    dirs.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("prompts"));
+    dirs
+}
+
+/// Value of `--flag value` or `--flag=value`.
+fn flag_value(mut args: impl Iterator<Item = String>, flag: &str) -> Option<String> {
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg
+            .strip_prefix(flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+fn load_prompt(prompt_dirs: &[PathBuf], name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    for dir in prompt_dirs {
+        let prompt_path = dir.join(name);
+        match fs::read_to_string(&prompt_path) {
+            Ok(prompt) => return Ok(prompt),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(
+                    format!("failed to read prompt {}: {}", prompt_path.display(), err).into(),
+                )
+            }
+        }
+    }
+    let tried = prompt_dirs
+        .iter()
+        .map(|dir| dir.join(name).display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(format!("prompt {name:?} not found, tried: {tried}").into())
 }
 
 fn load_config() -> Result<LoadedConfig, Box<dyn std::error::Error>> {
@@ -87,50 +306,80 @@ fn load_config() -> Result<LoadedConfig, Box<dyn std::error::Error>> {
     let doc: DocumentMut = raw.parse()?;
     let expanded = expand_env(&raw)?.into_owned();
 
-    let config = Config::builder()
+    let config: AppConfig = Config::builder()
         .add_source(File::from_str(&expanded, FileFormat::Toml))
         .add_source(Environment::with_prefix("TIM_AGENT").separator("__"))
         .build()?
         .try_deserialize()?;
+    for agent in &config.agents {
+        agent.validate()?;
+    }
 
     Ok(LoadedConfig { config, doc })
 }
 
 fn spawn_agent(
     config: AgentConfig,
-    prompts_dir: &Path,
+    prompt_dirs: &[PathBuf],
 ) -> Result<BoxFuture<'static, Result<(), agent::AgentError>>, Box<dyn std::error::Error>> {
     match config {
-        AgentConfig::Llm(conf) => spawn_llm_agent(conf, prompts_dir),
+        AgentConfig::Llm(conf) => spawn_llm_agent(conf, prompt_dirs),
         AgentConfig::Crawler(conf) => spawn_crawler_agent(conf),
     }
 }
 
 fn spawn_llm_agent(
     conf: LlmAgentConfig,
-    prompts_dir: &Path,
+    prompt_dirs: &[PathBuf],
 ) -> Result<BoxFuture<'static, Result<(), agent::AgentError>>, Box<dyn std::error::Error>> {
-    let sysp = load_prompt(prompts_dir, &conf.prompt)?;
+    let llm_provider = LlmProvider::from_provider(&conf.provider).ok_or_else(|| {
+        format!(
+            "agent {}: unknown llm provider {:?}",
+            conf.nick, conf.provider
+        )
+    })?;
+    let sysp = load_prompt(prompt_dirs, &conf.prompt)
+        .map_err(|err| format!("agent {}: {}", conf.nick, err))?;
 
     let tim_conf = TimClientConf {
         nick: conf.nick,
         provider: conf.provider,
         endpoint: conf.endpoint,
         timite_id: conf.timite_id,
+        session_key: conf.session_key,
+        connect_timeout: connect_timeout(conf.connect_timeout_secs),
+        auth_token: auth_token(),
+        paging: TimelinePaging::default(),
     };
 
+    let endpoint = llm_provider.default_endpoint().to_string();
+    let fallbacks = conf
+        .fallbacks
+        .into_iter()
+        .map(|fallback| LlmFallback {
+            endpoint: fallback.endpoint.unwrap_or_else(|| endpoint.clone()),
+            model: fallback.model,
+            api_key: fallback.api_key.unwrap_or_else(|| conf.api_key.clone()),
+        })
+        .collect();
     let llm_conf = AgentConf {
+        provider: llm_provider,
         sysp,
         api_key: conf.api_key,
-        endpoint: OPENAI_DEFAULT_ENDPOINT.to_string(),
+        endpoint,
         model: conf.model,
         temperature: conf.temperature,
         live_interval: conf.live_interval_secs.map(Duration::from_secs),
+        live_when_unchanged: conf.live_when_unchanged,
+        context_filter: context_filter(conf.context_senders, conf.context_keywords),
+        max_context_items: conf.max_context_items,
+        fallbacks,
+        thinking_indicator: conf.thinking_indicator,
     };
 
-    Ok(Box::pin(
-        async move { agent::spawn(tim_conf, llm_conf).await },
-    ))
+    Ok(Box::pin(async move {
+        agent::supervise(tim_conf, llm_conf, RestartPolicy::default()).await
+    }))
 }
 
 fn spawn_crawler_agent(
@@ -141,16 +390,26 @@ fn spawn_crawler_agent(
         provider: conf.provider,
         endpoint: conf.endpoint,
         timite_id: conf.timite_id,
+        session_key: conf.session_key,
+        connect_timeout: connect_timeout(conf.connect_timeout_secs),
+        auth_token: auth_token(),
+        paging: TimelinePaging::default(),
     };
 
+    let defaults = CrawlerConf::default();
     let crawler_conf = CrawlerConf {
         ability_name: conf.ability_name,
         max_snippet_chars: conf.max_snippet_chars,
         user_agent: conf.user_agent,
+        cache_capacity: conf.cache_capacity.unwrap_or(defaults.cache_capacity),
+        cache_ttl: conf
+            .cache_ttl_secs
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.cache_ttl),
     };
 
     Ok(Box::pin(async move {
-        agent::spawn(tim_conf, crawler_conf).await
+        agent::supervise(tim_conf, crawler_conf, RestartPolicy::default()).await
     }))
 }
 
@@ -179,12 +438,17 @@ async fn register_timite(
     endpoint: &str,
     nick: &str,
     provider: &str,
+    connect_timeout: Duration,
 ) -> Result<u64, Box<dyn std::error::Error>> {
     let client = TimClient::new(TimClientConf {
         endpoint: endpoint.to_string(),
         nick: nick.to_string(),
         provider: provider.to_string(),
         timite_id: None,
+        session_key: None,
+        connect_timeout,
+        auth_token: auth_token(),
+        paging: TimelinePaging::default(),
     })
     .await?;
     Ok(client.timite_id())
@@ -194,18 +458,22 @@ async fn ensure_timite_ids(loaded: &mut LoadedConfig) -> Result<(), Box<dyn std:
     let mut updated = false;
 
     for (index, agent) in loaded.config.agents.iter_mut().enumerate() {
-        let (timite_slot, endpoint, nick, provider) = match agent {
+        let (timite_slot, endpoint, nick, provider, session_key, timeout) = match agent {
             AgentConfig::Llm(conf) => (
                 &mut conf.timite_id,
                 conf.endpoint.as_str(),
                 conf.nick.as_str(),
                 conf.provider.as_str(),
+                conf.session_key.clone(),
+                connect_timeout(conf.connect_timeout_secs),
             ),
             AgentConfig::Crawler(conf) => (
                 &mut conf.timite_id,
                 conf.endpoint.as_str(),
                 conf.nick.as_str(),
                 conf.provider.as_str(),
+                conf.session_key.clone(),
+                connect_timeout(conf.connect_timeout_secs),
             ),
         };
 
@@ -215,6 +483,10 @@ async fn ensure_timite_ids(loaded: &mut LoadedConfig) -> Result<(), Box<dyn std:
                 nick: nick.to_string(),
                 provider: provider.to_string(),
                 timite_id: Some(*timite_id),
+                session_key,
+                connect_timeout: timeout,
+                auth_token: auth_token(),
+                paging: TimelinePaging::default(),
             };
             if TimClient::new(probe_conf.clone()).await.is_ok() {
                 continue;
@@ -225,7 +497,7 @@ async fn ensure_timite_ids(loaded: &mut LoadedConfig) -> Result<(), Box<dyn std:
             );
         }
 
-        let timite_id = register_timite(endpoint, nick, provider).await?;
+        let timite_id = register_timite(endpoint, nick, provider, timeout).await?;
         *timite_slot = Some(timite_id);
         update_timite_in_doc(&mut loaded.doc, index, timite_id)?;
         updated = true;
@@ -248,19 +520,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut loaded_config = load_config()?;
     ensure_timite_ids(&mut loaded_config).await?;
 
-    let prompts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("prompts");
+    let prompt_dirs = prompt_dirs();
 
     let agents = loaded_config
         .config
         .agents
         .into_iter()
-        .map(|agent| spawn_agent(agent, &prompts_dir))
+        .map(|agent| spawn_agent(agent, &prompt_dirs))
         .collect::<Result<Vec<_>, _>>()?;
 
-    try_join_all(agents)
-        .await
-        .map(|_| ())
-        .map_err(|err| Box::new(err) as Box<dyn std::error::Error>)?;
+    // each agent is supervised on its own task, so one failing agent leaves
+    // the rest running; only build errors abort the whole process
+    let mut tasks = JoinSet::new();
+    for agent in agents {
+        tasks.spawn(agent);
+    }
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) if err.is_fatal() => return Err(err.into()),
+            Ok(Err(err)) => error!(error = %err, "agent stopped for good"),
+            Err(err) => error!(error = %err, "agent task panicked"),
+        }
+    }
 
     Ok(())
 }
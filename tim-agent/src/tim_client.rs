@@ -1,38 +1,72 @@
+use std::error::Error as StdError;
 use std::fmt::Debug;
+use std::future::Future;
+use std::io;
+use std::ops::Range;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::time::Duration;
+use std::time::Instant;
 
 pub mod tim_api {
     tonic::include_proto!("tim.api.g1");
 }
 
-use futures::stream;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use futures::StreamExt;
 pub use tim_api::space_event::Data as Event;
 use tim_api::tim_grpc_api_client::TimGrpcApiClient;
 use tim_api::Ability;
+pub use tim_api::Activity;
 use tim_api::CallAbilityOutcome;
 use tim_api::ClientInfo;
 use tim_api::DeclareAbilitiesReq;
+use tim_api::DisconnectReq;
 pub use tim_api::EventNewMessage;
+use tim_api::GetMeReq;
 use tim_api::GetTimelineReq;
 use tim_api::GetTimelineRes;
 use tim_api::ListAbilitiesReq;
 use tim_api::SendCallAbilityOutcomeReq;
 use tim_api::SendMessageReq;
+use tim_api::SetActivityReq;
 pub use tim_api::SpaceEvent;
 use tim_api::SubscribeToSpaceReq;
 use tim_api::TimiteAbilities;
 use tim_api::TrustedConnectReq;
 use tim_api::TrustedRegisterReq;
-use tokio_stream::Stream;
+use tim_lib::space_stream::SpaceSource;
+use tim_lib::space_stream::Subscribing;
+use tokio::sync::broadcast;
+use tonic::codec::CompressionEncoding;
 use tonic::metadata::errors::InvalidMetadataValue;
 use tonic::metadata::Ascii;
 use tonic::metadata::MetadataValue;
+use tonic::transport::Channel;
 use tonic::transport::Endpoint;
+use tracing::info;
+use tracing::warn;
 
 use crate::tim_client::tim_api::ErrorCode;
 use crate::tim_client::tim_api::Timite;
+use crate::tim_client::tim_api::TimiteRole;
 
 pub const SESSION_METADATA_KEY: &str = "tim-session-key";
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_BACKOFF_MIN: Duration = Duration::from_millis(100);
+const CONNECT_BACKOFF_MAX: Duration = Duration::from_secs(2);
+/// Largest gap fetched back; older missed events are left to the timeline.
+const MAX_CATCH_UP: u64 = 1000;
+pub const DEFAULT_TIMELINE_PAGE_SIZE: u32 = 128;
+/// The server never returns more events per timeline page than this.
+pub const MAX_TIMELINE_PAGE_SIZE: u32 = 1000;
+/// Renewals kept for a `session_renewed` receiver that fell behind.
+const RENEWAL_BACKLOG: usize = 8;
 
 #[derive(Clone)]
 pub struct TimClientConf {
@@ -40,6 +74,47 @@ pub struct TimClientConf {
     pub nick: String,
     pub provider: String,
     pub timite_id: Option<u64>,
+    /// Previously issued session key, reused when it still works for `timite_id`
+    pub session_key: Option<String>,
+    /// Overall deadline for reaching the server; refused connections are retried until then
+    pub connect_timeout: Duration,
+    /// Sent with registration and connect to servers that require one
+    pub auth_token: Option<String>,
+    pub paging: TimelinePaging,
+}
+
+/// How the timeline is paged through when building history.
+#[derive(Debug, Clone, Copy)]
+pub struct TimelinePaging {
+    /// Events asked for per page, clamped to `MAX_TIMELINE_PAGE_SIZE`.
+    pub page_size: u32,
+    /// Pages read ahead while earlier ones are consumed, none when 0.
+    pub prefetch: usize,
+}
+
+impl Default for TimelinePaging {
+    fn default() -> Self {
+        Self {
+            page_size: DEFAULT_TIMELINE_PAGE_SIZE,
+            prefetch: 0,
+        }
+    }
+}
+
+impl TimelinePaging {
+    /// Rejects a zero page size, which would page through nothing, and clamps a
+    /// page size the server would cut short anyway.
+    pub fn checked(self) -> Result<Self, TimClientError> {
+        if self.page_size == 0 {
+            return Err(TimClientError::InvalidPaging(
+                "timeline page size must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            page_size: self.page_size.min(MAX_TIMELINE_PAGE_SIZE),
+            ..self
+        })
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -47,6 +122,13 @@ pub enum TimClientError {
     #[error("tim connect error: {0}")]
     TimConnect(#[from] tonic::transport::Error),
 
+    #[error("tim server at {endpoint} not reachable within {timeout:?}: {source}")]
+    ConnectTimeout {
+        endpoint: String,
+        timeout: Duration,
+        source: tonic::transport::Error,
+    },
+
     #[error("tim gprc error: {0}")]
     TimGrpc(#[from] tonic::Status),
 
@@ -55,100 +137,95 @@ pub enum TimClientError {
 
     #[error("invalid session metadata value: {0}")]
     SessionMetadata(#[from] InvalidMetadataValue),
+
+    #[error("invalid timeline paging: {0}")]
+    InvalidPaging(String),
 }
 
-#[derive(Clone)]
-pub struct TimClient {
-    client: TimGrpcApiClient<tonic::transport::Channel>,
+/// Sent when the client logged in again because the server stopped accepting its
+/// session, e.g. after a restart that lost the session store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionRenewed {
+    pub previous_timite_id: u64,
+    /// Differs from `previous_timite_id` when the server no longer knew the timite and
+    /// a new one was registered; abilities declared for the old one are gone with it.
+    pub new_timite_id: u64,
+}
+
+struct Login {
     token: MetadataValue<Ascii>,
     timite_id: u64,
     nick: String,
 }
 
+/// Shared by all clones, so a session renewed through one is used by every other.
+struct SessionState {
+    conf: TimClientConf,
+    login: RwLock<Login>,
+    // one renewal at a time, the others wait for it and reuse its session
+    renewing: tokio::sync::Mutex<()>,
+    renewed: broadcast::Sender<SessionRenewed>,
+}
+
+#[derive(Clone)]
+pub struct TimClient {
+    client: TimGrpcApiClient<tonic::transport::Channel>,
+    session: Arc<SessionState>,
+    paging: TimelinePaging,
+}
+
 impl Debug for TimClient {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let login = self.login();
         f.debug_struct("tim")
-            .field("nick", &self.nick)
-            .field("timite_id", &self.timite_id)
+            .field("nick", &login.nick)
+            .field("timite_id", &login.timite_id)
             .finish()
     }
 }
 
 impl TimClient {
     pub async fn new(conf: TimClientConf) -> Result<Self, TimClientError> {
-        let endpoint = Endpoint::from_str(&conf.endpoint)?;
-        let channel = endpoint.connect().await?;
-        let mut client = TimGrpcApiClient::new(channel);
-
-        let session = match conf.timite_id {
-            Some(timite_id) => {
-                let connect_req = TrustedConnectReq {
-                    timite: Some(Timite {
-                        id: timite_id,
-                        nick: conf.nick.clone(),
-                    }),
-                    client_info: Some(ClientInfo {
-                        platform: conf.provider.to_string(),
-                    }),
-                };
-                let connect_res = client
-                    .trusted_connect(tonic::Request::new(connect_req))
-                    .await?
-                    .into_inner();
-
-                if let Some(session) = connect_res.session {
-                    session
-                } else {
-                    let err_code =
-                        ErrorCode::try_from(connect_res.error).unwrap_or(ErrorCode::Unspecified);
-                    if err_code == ErrorCode::TimiteNotFound {
-                        let register_req = TrustedRegisterReq {
-                            nick: conf.nick.to_string(),
-                            client_info: Some(ClientInfo {
-                                platform: conf.provider.to_string(),
-                            }),
-                        };
-                        client
-                            .trusted_register(tonic::Request::new(register_req))
-                            .await?
-                            .into_inner()
-                            .session
-                            .ok_or(TimClientError::MissingSession)?
-                    } else {
-                        return Err(TimClientError::MissingSession);
-                    }
-                }
-            }
-            None => {
-                let register_req = TrustedRegisterReq {
-                    nick: conf.nick.to_string(),
-                    client_info: Some(ClientInfo {
-                        platform: conf.provider.to_string(),
-                    }),
-                };
-                client
-                    .trusted_register(tonic::Request::new(register_req))
-                    .await?
-                    .into_inner()
-                    .session
-                    .ok_or(TimClientError::MissingSession)?
-            }
-        };
+        let paging = conf.paging.checked()?;
+        let channel = connect_with_retry(&conf).await?;
+        // advertise gzip, the server decides whether to compress
+        let mut client =
+            TimGrpcApiClient::new(channel).accept_compressed(CompressionEncoding::Gzip);
 
-        let token = MetadataValue::try_from(session.key.clone())?;
+        let login = match resume_session(&mut client, &conf).await {
+            Some(login) => login,
+            None => log_in(&mut client, &conf, &conf.nick, conf.timite_id).await?,
+        };
+        let (renewed, _) = broadcast::channel(RENEWAL_BACKLOG);
 
         Ok(TimClient {
             client,
-            token,
-            timite_id: session.timite_id,
-            nick: conf.nick,
+            session: Arc::new(SessionState {
+                conf,
+                login: RwLock::new(login),
+                renewing: tokio::sync::Mutex::new(()),
+                renewed,
+            }),
+            paging,
         })
     }
 
+    pub fn paging(&self) -> TimelinePaging {
+        self.paging
+    }
+
+    /// Renewals of the session from now on, see `SessionRenewed`.
+    pub fn session_renewed(&self) -> broadcast::Receiver<SessionRenewed> {
+        self.session.renewed.subscribe()
+    }
+
     pub fn get_me(&self) -> Timite {
+        let login = self.login();
         Timite {
-            id: self.timite_id,
-            nick: self.nick.clone(),
+            id: login.timite_id,
+            nick: login.nick.clone(),
+            avatar_seed: 0,
+            role: TimiteRole::Agent.into(),
         }
     }
 
@@ -157,12 +234,31 @@ impl TimClient {
         if trimmed.is_empty() {
             return Ok(());
         }
-        let mut req = tonic::Request::new(SendMessageReq {
+        let req = SendMessageReq {
             content: trimmed.to_string(),
-        });
-        req.metadata_mut()
-            .insert(SESSION_METADATA_KEY, self.token.clone());
-        self.client.send_message(req).await?;
+            reply_to_message_id: None,
+            metadata: Default::default(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: String::new(),
+        };
+        self.with_session(|mut client, token| {
+            let req = authorized(req.clone(), token);
+            async move { client.send_message(req).await }
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_activity(&mut self, activity: Activity) -> Result<(), TimClientError> {
+        let req = SetActivityReq {
+            activity: activity.into(),
+        };
+        self.with_session(|mut client, token| {
+            let req = authorized(req, token);
+            async move { client.set_activity(req).await }
+        })
+        .await?;
         Ok(())
     }
 
@@ -170,10 +266,12 @@ impl TimClient {
         &mut self,
         abilities: Vec<Ability>,
     ) -> Result<(), TimClientError> {
-        let mut req = tonic::Request::new(DeclareAbilitiesReq { abilities });
-        req.metadata_mut()
-            .insert(SESSION_METADATA_KEY, self.token.clone());
-        self.client.declare_abilities(req).await?;
+        let req = DeclareAbilitiesReq { abilities };
+        self.with_session(|mut client, token| {
+            let req = authorized(req.clone(), token);
+            async move { client.declare_abilities(req).await }
+        })
+        .await?;
         Ok(())
     }
 
@@ -181,38 +279,52 @@ impl TimClient {
         &mut self,
         outcome: &CallAbilityOutcome,
     ) -> Result<(), TimClientError> {
-        let mut req = tonic::Request::new(SendCallAbilityOutcomeReq {
+        let req = SendCallAbilityOutcomeReq {
             outcome: Some(outcome.clone()),
-        });
-        req.metadata_mut()
-            .insert(SESSION_METADATA_KEY, self.token.clone());
-        self.client.send_call_ability_outcome(req).await?;
+        };
+        self.with_session(|mut client, token| {
+            let req = authorized(req.clone(), token);
+            async move { client.send_call_ability_outcome(req).await }
+        })
+        .await?;
         Ok(())
     }
 
     pub async fn list_abilities(&mut self) -> Result<Vec<TimiteAbilities>, TimClientError> {
-        let mut req = tonic::Request::new(ListAbilitiesReq { timite_id: None });
-        req.metadata_mut()
-            .insert(SESSION_METADATA_KEY, self.token.clone());
-        let res = self.client.list_abilities(req).await?.into_inner();
+        let res = self
+            .with_session(|mut client, token| {
+                let req = authorized(ListAbilitiesReq { timite_id: None }, token);
+                async move { client.list_abilities(req).await }
+            })
+            .await?;
         Ok(res.abilities)
     }
 
+    /// Not renewed: a session the server dropped is as good as disconnected.
+    pub async fn disconnect(&mut self) -> Result<(), TimClientError> {
+        let req = authorized(DisconnectReq {}, self.token());
+        self.client.disconnect(req).await?;
+        Ok(())
+    }
+
     pub fn timite_id(&self) -> u64 {
-        self.timite_id
+        self.login().timite_id
     }
 
     pub async fn subscribe_to_space(
         &mut self,
     ) -> Result<tonic::Streaming<SpaceEvent>, TimClientError> {
-        let sub_req = SubscribeToSpaceReq {
-            receive_own_messages: false,
+        // own messages are stored like any other, leaving them out would look like
+        // missed events; the agent runner drops them
+        let req = SubscribeToSpaceReq {
+            receive_own_messages: true,
+            room_id: String::new(),
         };
-        let mut sub_req = tonic::Request::new(sub_req);
-        sub_req
-            .metadata_mut()
-            .insert(SESSION_METADATA_KEY, self.token.clone());
-        Ok(self.client.subscribe_to_space(sub_req).await?.into_inner())
+        self.with_session(|mut client, token| {
+            let req = authorized(req.clone(), token);
+            async move { client.subscribe_to_space(req).await }
+        })
+        .await
     }
 
     pub async fn get_timeline(
@@ -220,57 +332,309 @@ impl TimClient {
         offset: u64,
         size: u32,
     ) -> Result<GetTimelineRes, TimClientError> {
-        let mut req = tonic::Request::new(GetTimelineReq { offset, size });
-        req.metadata_mut()
-            .insert(SESSION_METADATA_KEY, self.token.clone());
-        let res = self.client.get_timeline(req).await?.into_inner();
-        Ok(res)
+        let req = GetTimelineReq {
+            offset,
+            size,
+            room_id: String::new(),
+        };
+        self.with_session(|mut client, token| {
+            let req = authorized(req.clone(), token);
+            async move { client.get_timeline(req).await }
+        })
+        .await
     }
 
-    pub fn timeline_stream(
-        &mut self,
-        page_size: u32,
-    ) -> impl Stream<Item = Result<GetTimelineRes, TimClientError>> + '_ {
-        struct State<'a> {
-            client: &'a mut TimClient,
-            offset: u64,
-            page_size: u32,
-            finished: bool,
+    fn login(&self) -> RwLockReadGuard<'_, Login> {
+        self.session
+            .login
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn token(&self) -> MetadataValue<Ascii> {
+        self.login().token.clone()
+    }
+
+    /// Runs `call` with the session key. When the server turns the session down as
+    /// unauthenticated, the client logs in again and retries once; a second rejection
+    /// is returned as is instead of renewing in a loop.
+    async fn with_session<T, F, Fut>(&self, call: F) -> Result<T, TimClientError>
+    where
+        F: Fn(TimGrpcApiClient<Channel>, MetadataValue<Ascii>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<T>, tonic::Status>>,
+    {
+        let token = self.token();
+        match call(self.client.clone(), token.clone()).await {
+            Err(status) if status.code() == tonic::Code::Unauthenticated => {
+                warn!(%status, "session rejected, logging in again");
+                let token = self.renew(&token).await?;
+                Ok(call(self.client.clone(), token).await?.into_inner())
+            }
+            res => Ok(res?.into_inner()),
         }
-        stream::unfold(
-            State {
-                client: self,
-                offset: 0,
-                page_size,
-                finished: false,
-            },
-            |mut state| async move {
-                if state.finished || state.page_size == 0 {
-                    return None;
-                }
-                let res = state
-                    .client
-                    .get_timeline(state.offset, state.page_size)
-                    .await;
-                match res {
-                    Ok(res) => {
-                        if res.events.is_empty() {
-                            state.finished = true;
-                            return None;
-                        }
-                        let len = res.events.len() as u64;
-                        state.offset = state.offset.saturating_add(len);
-                        if len < state.page_size as u64 {
-                            state.finished = true;
-                        }
-                        Some((Ok(res), state))
-                    }
-                    Err(err) => {
-                        state.finished = true;
-                        Some((Err(err), state))
-                    }
-                }
-            },
+    }
+
+    /// Logs in again unless another clone already replaced the `stale` session, and
+    /// returns the key to use from now on.
+    async fn renew(
+        &self,
+        stale: &MetadataValue<Ascii>,
+    ) -> Result<MetadataValue<Ascii>, TimClientError> {
+        let _renewing = self.session.renewing.lock().await;
+        let current = self.token();
+        if current != *stale {
+            return Ok(current);
+        }
+        let previous_timite_id = self.timite_id();
+        // keep the nick we logged in under, the server may have assigned it
+        let nick = self.login().nick.clone();
+        let mut client = self.client.clone();
+        let login = log_in(
+            &mut client,
+            &self.session.conf,
+            &nick,
+            Some(previous_timite_id),
         )
+        .await?;
+        let renewed = SessionRenewed {
+            previous_timite_id,
+            new_timite_id: login.timite_id,
+        };
+        let token = login.token.clone();
+        *self
+            .session
+            .login
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = login;
+        info!(?renewed, "session renewed");
+        // nobody listening is fine, the new session is in place either way
+        let _ = self.session.renewed.send(renewed);
+        Ok(token)
+    }
+}
+
+fn authorized<R>(message: R, token: MetadataValue<Ascii>) -> tonic::Request<R> {
+    let mut req = tonic::Request::new(message);
+    req.metadata_mut().insert(SESSION_METADATA_KEY, token);
+    req
+}
+
+/// Connects as `timite_id` under `nick`, or registers a new timite when there is none
+/// or the server doesn't know it.
+async fn log_in(
+    client: &mut TimGrpcApiClient<Channel>,
+    conf: &TimClientConf,
+    nick: &str,
+    timite_id: Option<u64>,
+) -> Result<Login, TimClientError> {
+    let client_info = ClientInfo {
+        platform: conf.provider.to_string(),
+        auth_token: conf.auth_token.clone().unwrap_or_default(),
+    };
+    if let Some(timite_id) = timite_id {
+        let connect_req = TrustedConnectReq {
+            timite: Some(Timite {
+                id: timite_id,
+                nick: nick.to_string(),
+                avatar_seed: 0,
+                role: Default::default(),
+            }),
+            client_info: Some(client_info.clone()),
+        };
+        let connect_res = client
+            .trusted_connect(tonic::Request::new(connect_req))
+            .await?
+            .into_inner();
+        if let Some(session) = connect_res.session {
+            return Ok(Login {
+                token: MetadataValue::try_from(session.key.clone())?,
+                timite_id: session.timite_id,
+                nick: nick.to_string(),
+            });
+        }
+        let err_code = ErrorCode::try_from(connect_res.error).unwrap_or(ErrorCode::Unspecified);
+        if err_code != ErrorCode::TimiteNotFound {
+            return Err(TimClientError::MissingSession);
+        }
+    }
+
+    let register_req = TrustedRegisterReq {
+        nick: nick.to_string(),
+        client_info: Some(client_info),
+        role: TimiteRole::Agent.into(),
+        force: false,
+    };
+    let register_res = client
+        .trusted_register(tonic::Request::new(register_req))
+        .await?
+        .into_inner();
+    let session = register_res.session.ok_or(TimClientError::MissingSession)?;
+    Ok(Login {
+        token: MetadataValue::try_from(session.key.clone())?,
+        timite_id: session.timite_id,
+        // the server may have picked another nick when ours was empty or taken
+        nick: if register_res.nick.is_empty() {
+            nick.to_string()
+        } else {
+            register_res.nick
+        },
+    })
+}
+
+/// Paged timeline reads, the seam memory building is tested through.
+#[async_trait]
+pub trait TimelineSource: Send {
+    async fn timeline_page(
+        &mut self,
+        offset: u64,
+        size: u32,
+    ) -> Result<GetTimelineRes, TimClientError>;
+}
+
+#[async_trait]
+impl TimelineSource for TimClient {
+    async fn timeline_page(
+        &mut self,
+        offset: u64,
+        size: u32,
+    ) -> Result<GetTimelineRes, TimClientError> {
+        self.get_timeline(offset, size).await
+    }
+}
+
+impl SpaceSource for TimClient {
+    type Event = SpaceEvent;
+    type Error = TimClientError;
+
+    fn subscribe(&mut self) -> Subscribing<'_, SpaceEvent, TimClientError> {
+        async move {
+            let stream = self.subscribe_to_space().await?;
+            Ok(stream
+                .map(|item| item.map_err(TimClientError::from))
+                .boxed())
+        }
+        .boxed()
+    }
+
+    fn replay(
+        &mut self,
+        from_id: u64,
+        limit: u32,
+    ) -> BoxFuture<'_, Result<Vec<SpaceEvent>, TimClientError>> {
+        async move { Ok(self.get_timeline(from_id, limit).await?.events) }.boxed()
+    }
+
+    fn event_id(event: &SpaceEvent) -> u64 {
+        event.metadata.as_ref().map_or(0, |meta| meta.id)
+    }
+}
+
+/// Fetches the events of `gap` that a subscription never delivered. New messages
+/// from `skip_sender` are left out, a client doesn't need its own messages back.
+pub async fn catch_up<S: TimelineSource + ?Sized>(
+    source: &mut S,
+    gap: Range<u64>,
+    skip_sender: Option<u64>,
+) -> Result<Vec<SpaceEvent>, TimClientError> {
+    let start = gap.start.max(gap.end.saturating_sub(MAX_CATCH_UP));
+    let size = (gap.end - start) as u32;
+    let res = source.timeline_page(start, size).await?;
+    Ok(res
+        .events
+        .into_iter()
+        .filter(|event| {
+            let id = event.metadata.as_ref().map_or(0, |meta| meta.id);
+            gap.contains(&id) && !is_message_from(event, skip_sender)
+        })
+        .collect())
+}
+
+/// Whether `event` is a new message sent by `sender`.
+pub fn is_message_from(event: &SpaceEvent, sender: Option<u64>) -> bool {
+    match &event.data {
+        Some(Event::EventNewMessage(EventNewMessage {
+            message: Some(message),
+        })) => Some(message.sender_id) == sender,
+        _ => false,
+    }
+}
+
+async fn resume_session(
+    client: &mut TimGrpcApiClient<Channel>,
+    conf: &TimClientConf,
+) -> Option<Login> {
+    let (Some(key), Some(timite_id)) = (conf.session_key.as_deref(), conf.timite_id) else {
+        return None;
+    };
+    let token = MetadataValue::try_from(key).ok()?;
+    let mut req = tonic::Request::new(GetMeReq {});
+    req.metadata_mut()
+        .insert(SESSION_METADATA_KEY, token.clone());
+    let timite = match client.get_me(req).await {
+        Ok(res) => res.into_inner().timite?,
+        Err(status) => {
+            warn!(timite_id, %status, "session key rejected, connecting again");
+            return None;
+        }
+    };
+    // a valid key of someone else must not run under the configured identity
+    if timite.id != timite_id {
+        warn!(
+            timite_id,
+            session_timite_id = timite.id,
+            "session key belongs to another timite, connecting again"
+        );
+        return None;
+    }
+    Some(Login {
+        token,
+        timite_id,
+        nick: timite.nick,
+    })
+}
+
+async fn connect_with_retry(conf: &TimClientConf) -> Result<Channel, TimClientError> {
+    // an invalid endpoint fails here, without retries
+    let endpoint = Endpoint::from_str(&conf.endpoint)?.connect_timeout(conf.connect_timeout);
+    let started = Instant::now();
+    let mut backoff = CONNECT_BACKOFF_MIN;
+    loop {
+        let err = match endpoint.connect().await {
+            Ok(channel) => return Ok(channel),
+            Err(err) => err,
+        };
+        if !is_retryable(&err) {
+            return Err(err.into());
+        }
+        if started.elapsed() + backoff >= conf.connect_timeout {
+            return Err(TimClientError::ConnectTimeout {
+                endpoint: conf.endpoint.clone(),
+                timeout: conf.connect_timeout,
+                source: err,
+            });
+        }
+        warn!(
+            endpoint = %conf.endpoint,
+            "tim server not ready, retrying in {:?}: {}", backoff, err
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(CONNECT_BACKOFF_MAX);
+    }
+}
+
+fn is_retryable(err: &tonic::transport::Error) -> bool {
+    let mut source = err.source();
+    while let Some(cause) = source {
+        if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+            return matches!(
+                io_err.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::TimedOut
+            );
+        }
+        source = cause.source();
     }
+    false
 }
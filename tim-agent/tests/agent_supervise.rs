@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use tim_agent::agent::supervise;
+use tim_agent::agent::AgentError;
+use tim_agent::agent::RestartPolicy;
+use tim_agent::crawler::CrawlerConf;
+use tim_agent::tim_client::TimClientConf;
+use tim_agent::tim_client::TimelinePaging;
+use tokio::net::TcpListener;
+
+// Binds and immediately releases a port so connections to it are refused.
+async fn closed_endpoint() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind failed");
+    let addr = listener.local_addr().expect("missing local addr");
+    drop(listener);
+    format!("http://{addr}")
+}
+
+#[tokio::test]
+async fn supervise_gives_up_after_max_restarts() {
+    let conf = TimClientConf {
+        endpoint: closed_endpoint().await,
+        nick: "crawler".into(),
+        provider: "crawler:web".into(),
+        timite_id: None,
+        session_key: None,
+        connect_timeout: Duration::from_millis(50),
+        auth_token: None,
+        paging: TimelinePaging::default(),
+    };
+    let policy = RestartPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+        max_restarts: 2,
+        stable_after: Duration::from_secs(60),
+    };
+
+    let res = tokio::time::timeout(
+        Duration::from_secs(10),
+        supervise(conf, CrawlerConf::default(), policy),
+    )
+    .await
+    .expect("supervisor should give up in time");
+
+    match res {
+        Err(err @ AgentError::GaveUp { restarts: 2, .. }) => assert!(!err.is_fatal()),
+        other => panic!("expected the agent to be given up on, got {other:?}"),
+    }
+}
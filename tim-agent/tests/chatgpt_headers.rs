@@ -0,0 +1,85 @@
+use tim_agent::llm::chatgpt::ChatGpt;
+use tim_agent::llm::llm::Llm;
+use tim_agent::llm::llm::LlmInputItem;
+use tim_agent::llm::llm::LlmReq;
+use tim_agent::llm::llm::LlmRes;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+
+const SSE_BODY: &str = concat!(
+    "data: {\"type\":\"response.output_text.delta\",\"delta\":\"pong\"}\n\n",
+    "data: {\"type\":\"response.completed\"}\n\n",
+);
+
+// Accepts a single request, returns its raw head and replies with a minimal SSE stream.
+async fn serve_once(listener: TcpListener) -> String {
+    let (mut socket, _) = listener.accept().await.expect("accept failed");
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !raw.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = socket.read(&mut buf).await.expect("read failed");
+        if read == 0 {
+            break;
+        }
+        raw.extend_from_slice(&buf[..read]);
+    }
+    let response = format!(
+        "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        SSE_BODY.len(),
+        SSE_BODY
+    );
+    socket
+        .write_all(response.as_bytes())
+        .await
+        .expect("write failed");
+    let head = String::from_utf8_lossy(&raw).to_string();
+    head.split("\r\n\r\n")
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[tokio::test]
+async fn chatgpt_sends_custom_headers_and_query() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}/openai/deployments/tim", listener.local_addr()?);
+    let server = tokio::spawn(serve_once(listener));
+
+    let chatgpt = ChatGpt::builder("test-key")
+        .endpoint(endpoint)
+        .model("gpt-test")
+        .header("x-gateway", "one")
+        .header("x-gateway", "two")
+        .api_version("2024-10-21")
+        .build()?;
+
+    let history = vec![LlmInputItem {
+        role: "user",
+        content: "ping".to_string(),
+    }];
+    let answer = chatgpt
+        .chat(&LlmReq {
+            sysp: "test",
+            inputs: &history,
+        })
+        .await?;
+
+    let head = server.await?.to_lowercase();
+    assert!(head.starts_with("post /openai/deployments/tim?api-version=2024-10-21 "));
+    assert!(head.contains("x-gateway: one"));
+    assert!(head.contains("x-gateway: two"));
+    assert!(head.contains("authorization: bearer test-key"));
+    assert!(head.contains("user-agent: tim-agent/"));
+    assert!(matches!(answer, LlmRes::Reply(content) if content == "pong"));
+
+    Ok(())
+}
+
+#[test]
+fn chatgpt_rejects_invalid_header_at_build() {
+    let res = ChatGpt::builder("test-key")
+        .header("bad header", "value")
+        .build();
+    assert!(res.is_err());
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use tim_agent::llm::chatgpt::ChatGpt;
+use tim_agent::llm::llm::Llm;
+use tim_agent::llm::llm::LlmError;
+use tim_agent::llm::llm::LlmInputItem;
+use tim_agent::llm::llm::LlmReq;
+use tim_agent::llm::llm::LlmRes;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+const FIRST_CHUNK: &str = "data: {\"type\":\"response.output_text.delta\",\"delta\":\"po\"}\n\n";
+const LAST_CHUNK: &str = concat!(
+    "data: {\"type\":\"response.output_text.delta\",\"delta\":\"ng\"}\n\n",
+    "data: {\"type\":\"response.completed\"}\n\n",
+);
+const KEEP_ALIVE: &str = ": keep-alive\n\n";
+
+fn chunk(data: &str) -> String {
+    format!("{:x}\r\n{}\r\n", data.len(), data)
+}
+
+// Reads the request head and answers with a chunked SSE response head plus `first`.
+async fn accept_stream(listener: TcpListener, first: &str) -> Option<TcpStream> {
+    let (mut socket, _) = listener.accept().await.expect("accept failed");
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !raw.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = socket.read(&mut buf).await.expect("read failed");
+        if read == 0 {
+            return None;
+        }
+        raw.extend_from_slice(&buf[..read]);
+    }
+    let head =
+        "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n";
+    socket
+        .write_all(format!("{}{}", head, chunk(first)).as_bytes())
+        .await
+        .expect("write failed");
+    Some(socket)
+}
+
+// Sends one event, then keeps the connection open without writing.
+async fn serve_stalled(listener: TcpListener) {
+    // holding the socket keeps the connection open, dropping it would end the body
+    let Some(_socket) = accept_stream(listener, FIRST_CHUNK).await else {
+        return;
+    };
+    tokio::time::sleep(Duration::from_secs(10)).await;
+}
+
+// Slow but alive: keep-alive comments fill the gaps longer than the idle window.
+async fn serve_keep_alive(listener: TcpListener) {
+    let Some(mut socket) = accept_stream(listener, FIRST_CHUNK).await else {
+        return;
+    };
+    for _ in 0..5 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        socket
+            .write_all(chunk(KEEP_ALIVE).as_bytes())
+            .await
+            .expect("write failed");
+    }
+    socket
+        .write_all(format!("{}0\r\n\r\n", chunk(LAST_CHUNK)).as_bytes())
+        .await
+        .expect("write failed");
+}
+
+fn history() -> Vec<LlmInputItem> {
+    vec![LlmInputItem {
+        role: "user",
+        content: "ping".to_string(),
+    }]
+}
+
+#[tokio::test]
+async fn chatgpt_stalled_stream_times_out() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}/v1/responses", listener.local_addr()?);
+    let server = tokio::spawn(serve_stalled(listener));
+
+    let chatgpt = ChatGpt::builder("test-key")
+        .endpoint(endpoint)
+        .read_timeout(Duration::from_millis(200))
+        .build()?;
+
+    let history = history();
+    let res = tokio::time::timeout(
+        Duration::from_secs(5),
+        chatgpt.chat(&LlmReq {
+            sysp: "test",
+            inputs: &history,
+        }),
+    )
+    .await
+    .expect("read timeout should end the request");
+
+    assert!(matches!(res, Err(LlmError::Stream(reason)) if reason == "stream idle timeout"));
+    server.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn chatgpt_keep_alive_resets_idle_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}/v1/responses", listener.local_addr()?);
+    let server = tokio::spawn(serve_keep_alive(listener));
+
+    let chatgpt = ChatGpt::builder("test-key")
+        .endpoint(endpoint)
+        .read_timeout(Duration::from_millis(300))
+        .build()?;
+
+    let history = history();
+    let answer = chatgpt
+        .chat(&LlmReq {
+            sysp: "test",
+            inputs: &history,
+        })
+        .await?;
+
+    assert!(matches!(answer, LlmRes::Reply(content) if content == "pong"));
+    server.await?;
+
+    Ok(())
+}
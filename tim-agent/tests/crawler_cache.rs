@@ -0,0 +1,68 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use tim_agent::crawler::normalize_url;
+use tim_agent::crawler::CrawlCache;
+
+#[test]
+fn cache_hit_and_miss() {
+    let now = Instant::now();
+    let mut cache = CrawlCache::new(2, Duration::from_secs(60));
+
+    assert_eq!(cache.get("https://example.com/", now), None);
+    cache.insert("https://example.com/".into(), "snippet", now);
+    assert_eq!(cache.get("https://example.com/", now), Some("snippet"));
+    assert_eq!(cache.get("https://example.org/", now), None);
+}
+
+#[test]
+fn cache_entries_expire_by_ttl() {
+    let start = Instant::now();
+    let mut cache = CrawlCache::new(8, Duration::from_secs(60));
+    cache.insert("https://example.com/".into(), "snippet", start);
+
+    let before = start + Duration::from_secs(59);
+    assert_eq!(cache.get("https://example.com/", before), Some("snippet"));
+    let after = start + Duration::from_secs(60);
+    assert_eq!(cache.get("https://example.com/", after), None);
+}
+
+#[test]
+fn cache_evicts_least_recently_used() {
+    let now = Instant::now();
+    let mut cache = CrawlCache::new(2, Duration::from_secs(60));
+    cache.insert("a".into(), 1, now);
+    cache.insert("b".into(), 2, now);
+    assert_eq!(cache.get("a", now), Some(1));
+
+    cache.insert("c".into(), 3, now);
+    assert_eq!(cache.get("b", now), None);
+    assert_eq!(cache.get("a", now), Some(1));
+    assert_eq!(cache.get("c", now), Some(3));
+}
+
+#[test]
+fn normalize_url_merges_equivalent_urls_only() {
+    let key = |raw: &str| normalize_url(raw).expect("url should parse");
+
+    assert_eq!(
+        key("HTTPS://Example.com/docs/"),
+        key("https://example.com/docs")
+    );
+    assert_eq!(
+        key("https://example.com/search?b=2&a=1"),
+        key("https://example.com/search?a=1&b=2#results")
+    );
+    assert_eq!(key("https://example.com:443"), key("https://example.com/"));
+
+    assert_ne!(
+        key("https://example.com/Docs"),
+        key("https://example.com/docs")
+    );
+    assert_ne!(
+        key("https://example.com/search?a=1"),
+        key("https://example.com/search?a=2")
+    );
+    assert_ne!(key("http://example.com/"), key("https://example.com/"));
+    assert_eq!(normalize_url("not a url"), None);
+}
@@ -0,0 +1,16 @@
+use tim_agent::crawler::progress_outcome;
+use tim_agent::tim_client::tim_api::OutcomeStatus;
+
+#[test]
+fn progress_outcome_is_not_final() {
+    let outcome = progress_outcome(7, "https://example.com");
+
+    assert_eq!(outcome.call_ability_id, 7);
+    assert_eq!(outcome.status(), OutcomeStatus::InProgress);
+    // an error would make it terminal
+    assert!(outcome.error.is_none());
+    assert_eq!(
+        outcome.payload.as_deref(),
+        Some("fetching https://example.com")
+    );
+}
@@ -0,0 +1,23 @@
+use tim_agent::crawler::render_snippet;
+
+#[test]
+fn snippet_is_cut_after_max_chars() {
+    assert_eq!(render_snippet("one  two\nthree", 100), "one two three");
+    assert_eq!(render_snippet("one two three", 5), "one t…");
+    assert_eq!(
+        render_snippet("   ", 5),
+        "page returned no readable content"
+    );
+}
+
+#[test]
+fn multi_byte_snippet_is_cut_on_a_char_boundary() {
+    // three bytes per char, so a byte limit of 10 used to land inside a char
+    let body = "日本語のテキストです 文字化けしないこと";
+    let snippet = render_snippet(body, 10);
+    assert_eq!(snippet, "日本語のテキストです…");
+    assert_eq!(snippet.chars().count(), 11);
+
+    let mixed = render_snippet("héllo wörld ünïcode", 8);
+    assert_eq!(mixed, "héllo wö…");
+}
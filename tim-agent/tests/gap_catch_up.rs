@@ -0,0 +1,181 @@
+use async_trait::async_trait;
+use tim_agent::tim_client::catch_up;
+use tim_agent::tim_client::is_message_from;
+use tim_agent::tim_client::tim_api::space_event::Data;
+use tim_agent::tim_client::tim_api::space_event::Metadata;
+use tim_agent::tim_client::tim_api::EventNewMessage;
+use tim_agent::tim_client::tim_api::GetTimelineRes;
+use tim_agent::tim_client::tim_api::Message;
+use tim_agent::tim_client::tim_api::SpaceEvent;
+use tim_agent::tim_client::TimClientError;
+use tim_agent::tim_client::TimelineSource;
+use tim_lib::sequence::GapDetector;
+
+const ME: u64 = 7;
+const OTHER: u64 = 2;
+
+// Holds the whole timeline and answers pages like the server does.
+struct FakeTimeline {
+    events: Vec<SpaceEvent>,
+    requested: Vec<(u64, u32)>,
+}
+
+#[async_trait]
+impl TimelineSource for FakeTimeline {
+    async fn timeline_page(
+        &mut self,
+        offset: u64,
+        size: u32,
+    ) -> Result<GetTimelineRes, TimClientError> {
+        self.requested.push((offset, size));
+        Ok(GetTimelineRes {
+            offset,
+            size,
+            events: self
+                .events
+                .iter()
+                .filter(|event| id_of(event) >= offset)
+                .take(size as usize)
+                .cloned()
+                .collect(),
+            timites: Vec::new(),
+            has_more: false,
+            total_known: None,
+        })
+    }
+}
+
+// A stored message following the stored event `prev_id`.
+fn message(id: u64, prev_id: u64, sender_id: u64) -> SpaceEvent {
+    SpaceEvent {
+        metadata: Some(Metadata {
+            id,
+            emitted_at: None,
+            room_id: String::new(),
+            prev_id: Some(prev_id),
+        }),
+        data: Some(Data::EventNewMessage(EventNewMessage {
+            message: Some(Message {
+                id,
+                sender_id,
+                content: format!("message {id}"),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                expires_at: None,
+                deleted: false,
+                priority: Default::default(),
+            }),
+        })),
+    }
+}
+
+fn id_of(event: &SpaceEvent) -> u64 {
+    event.metadata.as_ref().map_or(0, |meta| meta.id)
+}
+
+fn chain(ids: &[u64], sender_id: u64) -> Vec<SpaceEvent> {
+    let mut prev_id = 0;
+    ids.iter()
+        .map(|&id| {
+            let event = message(id, prev_id, sender_id);
+            prev_id = id;
+            event
+        })
+        .collect()
+}
+
+// Replays `live` like the agent runner does, catching up on every gap and
+// dropping own messages.
+async fn replay(
+    timeline: &mut FakeTimeline,
+    live: &[SpaceEvent],
+) -> Result<Vec<u64>, TimClientError> {
+    let mut gaps = GapDetector::new();
+    let mut delivered = Vec::new();
+    for event in live {
+        let prev_id = event.metadata.as_ref().and_then(|meta| meta.prev_id);
+        if let Some(gap) = gaps.observe(id_of(event), prev_id) {
+            let missed = catch_up(timeline, gap, Some(ME)).await?;
+            delivered.extend(missed.iter().map(id_of));
+        }
+        if !is_message_from(event, Some(ME)) {
+            delivered.push(id_of(event));
+        }
+    }
+    Ok(delivered)
+}
+
+#[tokio::test]
+async fn missed_events_are_fetched_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let all = chain(&[1, 2, 3, 4, 5, 6], OTHER);
+    let mut timeline = FakeTimeline {
+        events: all.clone(),
+        requested: Vec::new(),
+    };
+    let live = [
+        all[0].clone(),
+        all[1].clone(),
+        all[4].clone(),
+        all[5].clone(),
+    ];
+
+    let delivered = replay(&mut timeline, &live).await?;
+
+    assert_eq!(delivered, vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(timeline.requested, vec![(3, 2)]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn own_messages_are_not_treated_as_missed() -> Result<(), Box<dyn std::error::Error>> {
+    let all = vec![
+        message(1, 0, OTHER),
+        message(2, 1, ME),
+        message(3, 2, OTHER),
+    ];
+    let mut timeline = FakeTimeline {
+        events: all.clone(),
+        requested: Vec::new(),
+    };
+
+    let delivered = replay(&mut timeline, &all).await?;
+
+    assert_eq!(delivered, vec![1, 3]);
+    assert!(timeline.requested.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ids_of_unstored_events_are_not_fetched() -> Result<(), Box<dyn std::error::Error>> {
+    // 2 and 3 went to another room or were never stored
+    let all = chain(&[1, 4, 5], OTHER);
+    let mut timeline = FakeTimeline {
+        events: all.clone(),
+        requested: Vec::new(),
+    };
+
+    let delivered = replay(&mut timeline, &all).await?;
+
+    assert_eq!(delivered, vec![1, 4, 5]);
+    assert!(timeline.requested.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn catch_up_stays_inside_the_gap() -> Result<(), Box<dyn std::error::Error>> {
+    // ids 3 and 4 were never stored, the page would run into the live event
+    let mut timeline = FakeTimeline {
+        events: chain(&[2, 5, 6], OTHER),
+        requested: Vec::new(),
+    };
+
+    let missed = catch_up(&mut timeline, 2..5, Some(ME)).await?;
+
+    assert_eq!(missed.iter().map(id_of).collect::<Vec<_>>(), vec![2]);
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use tim_agent::llm::live::LiveTurnGate;
+use tim_agent::tim_client::tim_api::space_event::Data;
+use tim_agent::tim_client::tim_api::Activity;
+use tim_agent::tim_client::tim_api::EventNewMessage;
+use tim_agent::tim_client::tim_api::EventTimiteActivity;
+use tim_agent::tim_client::tim_api::Message;
+use tim_agent::tim_client::tim_api::SpaceEvent;
+
+const AGENT_ID: u64 = 1;
+const ALICE_ID: u64 = 2;
+
+fn message(id: u64, sender_id: u64) -> SpaceEvent {
+    SpaceEvent {
+        metadata: None,
+        data: Some(Data::EventNewMessage(EventNewMessage {
+            message: Some(Message {
+                id,
+                sender_id,
+                content: format!("message {id}"),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                expires_at: None,
+                deleted: false,
+                priority: Default::default(),
+            }),
+        })),
+    }
+}
+
+fn activity(timite_id: u64) -> SpaceEvent {
+    SpaceEvent {
+        metadata: None,
+        data: Some(Data::EventTimiteActivity(EventTimiteActivity {
+            timite_id,
+            activity: Activity::Typing.into(),
+        })),
+    }
+}
+
+#[test]
+fn first_tick_answers_the_existing_history() {
+    let mut gate = LiveTurnGate::new(AGENT_ID);
+
+    assert!(gate.take_turn());
+    assert!(!gate.take_turn());
+}
+
+#[test]
+fn ticks_without_new_messages_are_skipped() {
+    let mut gate = LiveTurnGate::new(AGENT_ID);
+    assert!(gate.take_turn());
+
+    gate.observe(&activity(ALICE_ID));
+    assert!(!gate.take_turn());
+
+    // the agent's own reply is no reason to speak again
+    gate.observe(&message(1, AGENT_ID));
+    assert!(!gate.take_turn());
+}
+
+#[test]
+fn new_message_fires_the_next_tick_once() {
+    let mut gate = LiveTurnGate::new(AGENT_ID);
+    assert!(gate.take_turn());
+
+    gate.observe(&message(1, ALICE_ID));
+    gate.observe(&message(2, ALICE_ID));
+    assert!(gate.take_turn());
+    assert!(!gate.take_turn());
+
+    // a message arriving after the turn was taken, e.g. while the LLM answers
+    gate.observe(&message(3, ALICE_ID));
+    assert!(gate.take_turn());
+}
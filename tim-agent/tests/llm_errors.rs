@@ -0,0 +1,71 @@
+use tim_agent::llm::llm::LlmError;
+
+const ENVELOPE: &str = r#"{"error":{"message":"Rate limit reached for gpt-4o","type":"requests","code":"rate_limit_exceeded"}}"#;
+
+#[test]
+fn statuses_map_to_categories() {
+    assert!(matches!(
+        LlmError::from_status(401, ""),
+        LlmError::Auth { status: 401, .. }
+    ));
+    assert!(matches!(
+        LlmError::from_status(403, ""),
+        LlmError::Auth { status: 403, .. }
+    ));
+    assert!(matches!(
+        LlmError::from_status(429, ""),
+        LlmError::RateLimited { status: 429, .. }
+    ));
+    assert!(matches!(
+        LlmError::from_status(400, ""),
+        LlmError::ClientError { status: 400, .. }
+    ));
+    assert!(matches!(
+        LlmError::from_status(404, ""),
+        LlmError::ClientError { status: 404, .. }
+    ));
+    assert!(matches!(
+        LlmError::from_status(408, ""),
+        LlmError::ServerError { status: 408, .. }
+    ));
+    assert!(matches!(
+        LlmError::from_status(503, ""),
+        LlmError::ServerError { status: 503, .. }
+    ));
+}
+
+#[test]
+fn unknown_statuses_are_retryable() {
+    let err = LlmError::from_status(302, "moved");
+
+    assert!(matches!(err, LlmError::ServerError { status: 302, .. }));
+    assert!(err.is_transient());
+}
+
+#[test]
+fn only_rate_limits_and_server_errors_are_transient() {
+    assert!(LlmError::from_status(429, "").is_transient());
+    assert!(LlmError::from_status(500, "").is_transient());
+    assert!(!LlmError::from_status(400, "").is_transient());
+    assert!(!LlmError::from_status(401, "").is_transient());
+}
+
+#[test]
+fn message_comes_from_the_error_envelope() {
+    let err = LlmError::from_status(429, ENVELOPE);
+
+    assert!(matches!(
+        err,
+        LlmError::RateLimited { ref message, .. } if message == "Rate limit reached for gpt-4o"
+    ));
+}
+
+#[test]
+fn raw_body_is_kept_without_an_envelope() {
+    let err = LlmError::from_status(502, "  bad gateway\n");
+
+    assert!(matches!(
+        err,
+        LlmError::ServerError { ref message, .. } if message == "bad gateway"
+    ));
+}
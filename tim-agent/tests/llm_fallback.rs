@@ -0,0 +1,120 @@
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tim_agent::llm::fallback::FallbackLlm;
+use tim_agent::llm::llm::Llm;
+use tim_agent::llm::llm::LlmError;
+use tim_agent::llm::llm::LlmInputItem;
+use tim_agent::llm::llm::LlmReq;
+use tim_agent::llm::llm::LlmRes;
+use tim_agent::llm::llm::ResponseStream;
+
+// Answers every chat with a fixed outcome and counts the calls.
+struct Stub {
+    outcome: fn() -> Result<LlmRes, LlmError>,
+    calls: AtomicUsize,
+}
+
+impl Stub {
+    fn new(outcome: fn() -> Result<LlmRes, LlmError>) -> Arc<Self> {
+        Arc::new(Self {
+            outcome,
+            calls: AtomicUsize::new(0),
+        })
+    }
+
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Llm for Stub {
+    async fn chat_stream(&self, _req: &LlmReq<'_>) -> Result<ResponseStream, LlmError> {
+        unimplemented!("the stub only answers whole chats")
+    }
+
+    async fn chat(&self, _req: &LlmReq<'_>) -> Result<LlmRes, LlmError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        (self.outcome)()
+    }
+}
+
+fn reply() -> Result<LlmRes, LlmError> {
+    Ok(LlmRes::Reply("pong".to_string()))
+}
+
+fn unavailable() -> Result<LlmRes, LlmError> {
+    Err(LlmError::ServerError {
+        status: 503,
+        message: "overloaded".to_string(),
+    })
+}
+
+fn unauthorized() -> Result<LlmRes, LlmError> {
+    Err(LlmError::Auth {
+        status: 401,
+        message: "bad key".to_string(),
+    })
+}
+
+fn chain(primary: &Arc<Stub>, fallback: &Arc<Stub>) -> FallbackLlm {
+    FallbackLlm::new(vec![
+        ("primary".to_string(), primary.clone() as Arc<dyn Llm>),
+        ("fallback".to_string(), fallback.clone() as Arc<dyn Llm>),
+    ])
+}
+
+async fn ask(llm: &FallbackLlm) -> Result<LlmRes, LlmError> {
+    let inputs = vec![LlmInputItem {
+        role: "user",
+        content: "ping".to_string(),
+    }];
+    llm.chat(&LlmReq {
+        sysp: "test",
+        inputs: &inputs,
+    })
+    .await
+}
+
+#[tokio::test]
+async fn server_error_falls_back_and_next_turn_starts_at_primary() {
+    let primary = Stub::new(unavailable);
+    let fallback = Stub::new(reply);
+    let llm = chain(&primary, &fallback);
+
+    assert!(matches!(ask(&llm).await, Ok(LlmRes::Reply(content)) if content == "pong"));
+    assert!(matches!(ask(&llm).await, Ok(LlmRes::Reply(_))));
+
+    assert_eq!(primary.calls(), 2);
+    assert_eq!(fallback.calls(), 2);
+}
+
+#[tokio::test]
+async fn client_error_does_not_fall_back() {
+    let primary = Stub::new(unauthorized);
+    let fallback = Stub::new(reply);
+    let llm = chain(&primary, &fallback);
+
+    assert!(matches!(
+        ask(&llm).await,
+        Err(LlmError::Auth { status: 401, .. })
+    ));
+    assert_eq!(fallback.calls(), 0);
+}
+
+#[tokio::test]
+async fn last_error_is_returned_when_every_llm_fails() {
+    let primary = Stub::new(unavailable);
+    let fallback = Stub::new(unavailable);
+    let llm = chain(&primary, &fallback);
+
+    assert!(matches!(
+        ask(&llm).await,
+        Err(LlmError::ServerError { status: 503, .. })
+    ));
+    assert_eq!(primary.calls(), 1);
+    assert_eq!(fallback.calls(), 1);
+}
@@ -0,0 +1,167 @@
+use tim_agent::llm::chatgpt::ChatGpt;
+use tim_agent::llm::llm::Llm;
+use tim_agent::llm::llm::LlmError;
+use tim_agent::llm::llm::LlmInputItem;
+use tim_agent::llm::llm::LlmReq;
+use tim_agent::llm::llm::LlmRes;
+use tim_agent::llm::llm::ToolInvocation;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+
+fn function_call(call_id: &str, name: &str, arguments: &str) -> String {
+    let event = serde_json::json!({
+        "type": "response.output_item.done",
+        "item": {
+            "type": "function_call",
+            "call_id": call_id,
+            "name": name,
+            "arguments": arguments,
+        },
+    });
+    format!("data: {}\n\n", event)
+}
+
+// Answers a single request with the given SSE body.
+async fn serve_once(listener: TcpListener, body: String) {
+    let (mut socket, _) = listener.accept().await.expect("accept failed");
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !raw.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = socket.read(&mut buf).await.expect("read failed");
+        if read == 0 {
+            return;
+        }
+        raw.extend_from_slice(&buf[..read]);
+    }
+    let head = format!(
+        "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\n\r\n",
+        body.len()
+    );
+    socket
+        .write_all(format!("{}{}", head, body).as_bytes())
+        .await
+        .expect("write failed");
+}
+
+async fn chat_with(body: String) -> Result<LlmRes, Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}/v1/responses", listener.local_addr()?);
+    let server = tokio::spawn(serve_once(listener, body));
+
+    let chatgpt = ChatGpt::builder("test-key").endpoint(endpoint).build()?;
+    let history = vec![LlmInputItem {
+        role: "user",
+        content: "ping".to_string(),
+    }];
+    let answer = chatgpt
+        .chat(&LlmReq {
+            sysp: "test",
+            inputs: &history,
+        })
+        .await;
+    server.await?;
+
+    Ok(answer?)
+}
+
+#[tokio::test]
+async fn finished_tool_calls_are_parsed() -> Result<(), Box<dyn std::error::Error>> {
+    let body = [
+        function_call("call-1", "web_search", r#"{"query":"rust","limit":3}"#),
+        function_call("call-2", "list_abilities", ""),
+        "data: {\"type\":\"response.completed\"}\n\n".to_string(),
+    ]
+    .concat();
+
+    let LlmRes::ToolCalls(calls) = chat_with(body).await? else {
+        panic!("expected tool calls");
+    };
+
+    assert_eq!(
+        calls,
+        vec![
+            ToolInvocation {
+                name: "web_search".to_string(),
+                args: serde_json::json!({ "query": "rust", "limit": 3 }),
+            },
+            ToolInvocation {
+                name: "list_abilities".to_string(),
+                args: serde_json::json!({}),
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn silence_reason_comes_from_parsed_arguments() -> Result<(), Box<dyn std::error::Error>> {
+    let body = [
+        function_call("call-1", "TIM-LLM-SILENCE", r#"{"reason":"not my turn"}"#),
+        "data: {\"type\":\"response.completed\"}\n\n".to_string(),
+    ]
+    .concat();
+
+    let answer = chat_with(body).await?;
+
+    assert!(matches!(answer, LlmRes::NoResponse(reason) if reason == "not my turn"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn malformed_tool_arguments_are_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let body = [
+        function_call("call-1", "web_search", r#"{"query":"ru"#),
+        "data: {\"type\":\"response.completed\"}\n\n".to_string(),
+    ]
+    .concat();
+
+    let err = chat_with(body)
+        .await
+        .expect_err("truncated arguments must fail");
+    let err = err.downcast::<LlmError>()?;
+
+    assert!(matches!(*err, LlmError::ToolArguments { ref name, .. } if name == "web_search"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn content_and_tool_calls_are_both_returned() -> Result<(), Box<dyn std::error::Error>> {
+    let body = [
+        "data: {\"type\":\"response.output_text.delta\",\"delta\":\"looking it up\"}\n\n"
+            .to_string(),
+        function_call("call-1", "web_search", r#"{"query":"rust"}"#),
+        "data: {\"type\":\"response.completed\"}\n\n".to_string(),
+    ]
+    .concat();
+
+    let LlmRes::ReplyWithToolCalls(content, calls) = chat_with(body).await? else {
+        panic!("expected content with tool calls");
+    };
+
+    assert_eq!(content, "looking it up");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].name, "web_search");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_tool_without_content_is_not_an_error() -> Result<(), Box<dyn std::error::Error>> {
+    let body = [
+        function_call("call-1", "no_such_tool", ""),
+        "data: {\"type\":\"response.completed\"}\n\n".to_string(),
+    ]
+    .concat();
+
+    let LlmRes::ToolCalls(calls) = chat_with(body).await? else {
+        panic!("expected tool calls");
+    };
+
+    assert_eq!(calls[0].name, "no_such_tool");
+
+    Ok(())
+}
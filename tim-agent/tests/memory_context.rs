@@ -0,0 +1,130 @@
+use futures::stream;
+use tim_agent::llm::memory::collect_context;
+use tim_agent::llm::memory::ContextFilter;
+use tim_agent::tim_client::tim_api::space_event::Data;
+use tim_agent::tim_client::tim_api::EventNewMessage;
+use tim_agent::tim_client::tim_api::GetTimelineRes;
+use tim_agent::tim_client::tim_api::Message;
+use tim_agent::tim_client::tim_api::SpaceEvent;
+use tim_agent::tim_client::tim_api::Timite;
+use tim_agent::tim_client::TimClientError;
+
+const AGENT_ID: u64 = 1;
+const ALICE_ID: u64 = 2;
+const BOB_ID: u64 = 3;
+
+fn timite(id: u64, nick: &str) -> Timite {
+    Timite {
+        id,
+        nick: nick.into(),
+        avatar_seed: 0,
+        role: Default::default(),
+    }
+}
+
+fn message(id: u64, sender_id: u64, content: &str) -> SpaceEvent {
+    SpaceEvent {
+        metadata: None,
+        data: Some(Data::EventNewMessage(EventNewMessage {
+            message: Some(Message {
+                id,
+                sender_id,
+                content: content.into(),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                expires_at: None,
+                deleted: false,
+                priority: Default::default(),
+            }),
+        })),
+    }
+}
+
+// Two pages, the way the client pages through the timeline.
+fn timeline() -> Vec<Result<GetTimelineRes, TimClientError>> {
+    let timites = vec![
+        timite(AGENT_ID, "jarvis"),
+        timite(ALICE_ID, "alice"),
+        timite(BOB_ID, "bob"),
+    ];
+    vec![
+        Ok(GetTimelineRes {
+            offset: 0,
+            size: 3,
+            events: vec![
+                message(1, ALICE_ID, "the deploy is failing"),
+                message(2, BOB_ID, "lunch anyone?"),
+                message(3, AGENT_ID, "looking into the Deploy logs"),
+            ],
+            timites: timites.clone(),
+            has_more: false,
+            total_known: None,
+        }),
+        Ok(GetTimelineRes {
+            offset: 3,
+            size: 3,
+            events: vec![
+                message(4, ALICE_ID, "still red"),
+                message(5, BOB_ID, "ping"),
+            ],
+            timites,
+            has_more: false,
+            total_known: None,
+        }),
+    ]
+}
+
+async fn contents(filter: ContextFilter) -> Vec<String> {
+    collect_context(stream::iter(timeline()), AGENT_ID, &filter)
+        .await
+        .expect("context should build")
+        .into_iter()
+        .map(|item| item.content)
+        .collect()
+}
+
+fn ends_with(items: &[String], suffixes: &[&str]) -> bool {
+    items.len() == suffixes.len()
+        && items
+            .iter()
+            .zip(suffixes)
+            .all(|(item, suffix)| item.ends_with(suffix))
+}
+
+#[tokio::test]
+async fn empty_filter_keeps_everything() {
+    let items = contents(ContextFilter::default()).await;
+    assert_eq!(items.len(), 5);
+}
+
+#[tokio::test]
+async fn filters_by_sender_nick() {
+    let items = contents(ContextFilter {
+        senders: vec!["Alice".into()],
+        keywords: Vec::new(),
+    })
+    .await;
+    // bob's "ping" is the latest message and stays as the trigger
+    assert!(ends_with(
+        &items,
+        &["the deploy is failing", "still red", "ping"]
+    ));
+}
+
+#[tokio::test]
+async fn filters_by_keyword_in_order() {
+    let items = contents(ContextFilter {
+        senders: Vec::new(),
+        keywords: vec!["deploy".into()],
+    })
+    .await;
+    assert!(ends_with(
+        &items,
+        &[
+            "the deploy is failing",
+            "looking into the Deploy logs",
+            "ping"
+        ]
+    ));
+}
@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tim_agent::llm::memory::recent_context;
+use tim_agent::llm::memory::ContextFilter;
+use tim_agent::llm::memory::PageRetry;
+use tim_agent::tim_client::tim_api::space_event::Data;
+use tim_agent::tim_client::tim_api::space_event::Metadata;
+use tim_agent::tim_client::tim_api::EventNewMessage;
+use tim_agent::tim_client::tim_api::GetTimelineRes;
+use tim_agent::tim_client::tim_api::Message;
+use tim_agent::tim_client::tim_api::SpaceEvent;
+use tim_agent::tim_client::tim_api::Timite;
+use tim_agent::tim_client::TimClientError;
+use tim_agent::tim_client::TimelineSource;
+
+const AGENT_ID: u64 = 1;
+const ALICE_ID: u64 = 2;
+const PAGE_SIZE: u32 = 50;
+
+// Answers like the server: offset 0 is the newest page, any other offset is the
+// id to read forward from.
+struct FakeTimeline {
+    events: Vec<SpaceEvent>,
+    requested: Vec<u64>,
+}
+
+impl FakeTimeline {
+    fn new(len: u64) -> Self {
+        Self {
+            events: (0..len).map(message).collect(),
+            requested: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl TimelineSource for FakeTimeline {
+    async fn timeline_page(
+        &mut self,
+        offset: u64,
+        size: u32,
+    ) -> Result<GetTimelineRes, TimClientError> {
+        self.requested.push(offset);
+        let size = size as usize;
+        let start = if offset == 0 {
+            self.events.len().saturating_sub(size)
+        } else {
+            (offset as usize).min(self.events.len())
+        };
+        let end = (start + size).min(self.events.len());
+        Ok(GetTimelineRes {
+            offset,
+            size: size as u32,
+            events: self.events[start..end].to_vec(),
+            timites: vec![Timite {
+                id: ALICE_ID,
+                nick: "alice".into(),
+                avatar_seed: 0,
+                role: Default::default(),
+            }],
+            has_more: false,
+            total_known: None,
+        })
+    }
+}
+
+fn message(id: u64) -> SpaceEvent {
+    SpaceEvent {
+        metadata: Some(Metadata {
+            id,
+            emitted_at: None,
+            room_id: String::new(),
+            prev_id: None,
+        }),
+        data: Some(Data::EventNewMessage(EventNewMessage {
+            message: Some(Message {
+                id,
+                sender_id: ALICE_ID,
+                content: format!("message {id}"),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                expires_at: None,
+                deleted: false,
+                priority: Default::default(),
+            }),
+        })),
+    }
+}
+
+fn retry() -> PageRetry {
+    PageRetry {
+        attempts: 1,
+        backoff: Duration::ZERO,
+    }
+}
+
+fn assert_messages(items: &[tim_agent::llm::llm::LlmInputItem], ids: std::ops::Range<u64>) {
+    assert_eq!(items.len(), ids.clone().count());
+    for (item, id) in items.iter().zip(ids) {
+        assert!(
+            item.content.ends_with(&format!("message {id}")),
+            "expected message {id}, got {:?}",
+            item.content
+        );
+    }
+}
+
+#[tokio::test]
+async fn newest_page_is_enough_for_a_small_limit() {
+    let mut timeline = FakeTimeline::new(1000);
+
+    let items = recent_context(
+        &mut timeline,
+        AGENT_ID,
+        &ContextFilter::default(),
+        PAGE_SIZE,
+        30,
+        &retry(),
+    )
+    .await
+    .unwrap();
+
+    assert_messages(&items, 970..1000);
+    assert_eq!(timeline.requested, [0]);
+}
+
+#[tokio::test]
+async fn only_the_most_recent_items_are_returned() {
+    let mut timeline = FakeTimeline::new(1000);
+
+    let items = recent_context(
+        &mut timeline,
+        AGENT_ID,
+        &ContextFilter::default(),
+        PAGE_SIZE,
+        120,
+        &retry(),
+    )
+    .await
+    .unwrap();
+
+    assert_messages(&items, 880..1000);
+    // pages are walked back from the newest and stop once the limit is reached
+    assert_eq!(timeline.requested, [0, 900, 850]);
+}
+
+#[tokio::test]
+async fn short_timeline_is_returned_whole() {
+    let mut timeline = FakeTimeline::new(70);
+
+    let items = recent_context(
+        &mut timeline,
+        AGENT_ID,
+        &ContextFilter::default(),
+        PAGE_SIZE,
+        500,
+        &retry(),
+    )
+    .await
+    .unwrap();
+
+    // id 0 only shows up in the newest page, older pages start from 1
+    assert_messages(&items, 1..70);
+    assert_eq!(timeline.requested, [0, 1]);
+}
@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tim_agent::llm::memory::fetch_pages;
+use tim_agent::llm::memory::PageRetry;
+use tim_agent::tim_client::tim_api::space_event::Data;
+use tim_agent::tim_client::tim_api::EventNewMessage;
+use tim_agent::tim_client::tim_api::GetTimelineRes;
+use tim_agent::tim_client::tim_api::Message;
+use tim_agent::tim_client::tim_api::SpaceEvent;
+use tim_agent::tim_client::TimClientError;
+use tim_agent::tim_client::TimelineSource;
+
+const PAGE_SIZE: u32 = 2;
+
+// Answers page requests from a script and records the offsets asked for.
+struct FakeSource {
+    script: VecDeque<Result<GetTimelineRes, TimClientError>>,
+    requested: Vec<u64>,
+}
+
+impl FakeSource {
+    fn new(script: Vec<Result<GetTimelineRes, TimClientError>>) -> Self {
+        Self {
+            script: script.into(),
+            requested: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl TimelineSource for FakeSource {
+    async fn timeline_page(
+        &mut self,
+        offset: u64,
+        _size: u32,
+    ) -> Result<GetTimelineRes, TimClientError> {
+        self.requested.push(offset);
+        self.script
+            .pop_front()
+            .unwrap_or_else(|| Ok(page(offset, &[])))
+    }
+}
+
+fn page(offset: u64, ids: &[u64]) -> GetTimelineRes {
+    GetTimelineRes {
+        offset,
+        size: PAGE_SIZE,
+        events: ids
+            .iter()
+            .map(|id| SpaceEvent {
+                metadata: None,
+                data: Some(Data::EventNewMessage(EventNewMessage {
+                    message: Some(Message {
+                        id: *id,
+                        sender_id: 2,
+                        content: format!("message {id}"),
+                        reply_to_message_id: None,
+                        metadata: Default::default(),
+                        parts: Vec::new(),
+                        expires_at: None,
+                        deleted: false,
+                        priority: Default::default(),
+                    }),
+                })),
+            })
+            .collect(),
+        timites: Vec::new(),
+        has_more: false,
+        total_known: None,
+    }
+}
+
+fn unavailable() -> Result<GetTimelineRes, TimClientError> {
+    Err(TimClientError::TimGrpc(tonic::Status::unavailable(
+        "connection reset",
+    )))
+}
+
+fn retry() -> PageRetry {
+    PageRetry {
+        attempts: 3,
+        backoff: Duration::ZERO,
+    }
+}
+
+fn offsets(pages: &[GetTimelineRes]) -> Vec<u64> {
+    pages.iter().map(|page| page.offset).collect()
+}
+
+#[tokio::test]
+async fn failing_page_is_retried_until_it_loads() {
+    let mut source = FakeSource::new(vec![
+        Ok(page(0, &[1, 2])),
+        unavailable(),
+        unavailable(),
+        Ok(page(2, &[3, 4])),
+        Ok(page(4, &[5])),
+    ]);
+
+    let pages = fetch_pages(&mut source, PAGE_SIZE, &retry()).await;
+
+    assert_eq!(offsets(&pages), [0, 2, 4]);
+    assert_eq!(source.requested, [0, 2, 2, 2, 4]);
+}
+
+#[tokio::test]
+async fn page_that_keeps_failing_ends_the_history() {
+    let mut source = FakeSource::new(vec![
+        Ok(page(0, &[1, 2])),
+        unavailable(),
+        unavailable(),
+        unavailable(),
+        Ok(page(4, &[5, 6])),
+    ]);
+
+    let pages = fetch_pages(&mut source, PAGE_SIZE, &retry()).await;
+
+    // the page after the lost one is not fetched, the history stays gapless
+    assert_eq!(offsets(&pages), [0]);
+    assert_eq!(source.requested, [0, 2, 2, 2]);
+}
+
+#[tokio::test]
+async fn has_more_decides_when_paging_stops() {
+    let reported = |offset, ids: &[u64], has_more| GetTimelineRes {
+        has_more,
+        total_known: Some(5),
+        ..page(offset, ids)
+    };
+    let mut source = FakeSource::new(vec![
+        Ok(reported(0, &[1], true)),
+        Ok(reported(1, &[2, 3], false)),
+        Ok(page(3, &[4, 5])),
+    ]);
+
+    let pages = fetch_pages(&mut source, PAGE_SIZE, &retry()).await;
+
+    // a short page with more to come goes on, a full last page stops
+    assert_eq!(offsets(&pages), [0, 1]);
+    assert_eq!(source.requested, [0, 1]);
+}
@@ -0,0 +1,323 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::Stream;
+use tim_agent::tim_client::tim_api::tim_grpc_api_server::TimGrpcApi;
+use tim_agent::tim_client::tim_api::tim_grpc_api_server::TimGrpcApiServer;
+use tim_agent::tim_client::tim_api::AnnounceReq;
+use tim_agent::tim_client::tim_api::AnnounceRes;
+use tim_agent::tim_client::tim_api::DeclareAbilitiesReq;
+use tim_agent::tim_client::tim_api::DeclareAbilitiesRes;
+use tim_agent::tim_client::tim_api::DisconnectReq;
+use tim_agent::tim_client::tim_api::DisconnectRes;
+use tim_agent::tim_client::tim_api::EraseTimiteReq;
+use tim_agent::tim_client::tim_api::EraseTimiteRes;
+use tim_agent::tim_client::tim_api::ErrorCode;
+use tim_agent::tim_client::tim_api::GetMeReq;
+use tim_agent::tim_client::tim_api::GetMeRes;
+use tim_agent::tim_client::tim_api::GetTimelineReq;
+use tim_agent::tim_client::tim_api::GetTimelineRes;
+use tim_agent::tim_client::tim_api::GetTimelineSinceReq;
+use tim_agent::tim_client::tim_api::HealthReq;
+use tim_agent::tim_client::tim_api::HealthRes;
+use tim_agent::tim_client::tim_api::KickReq;
+use tim_agent::tim_client::tim_api::KickRes;
+use tim_agent::tim_client::tim_api::ListAbilitiesReq;
+use tim_agent::tim_client::tim_api::ListAbilitiesRes;
+use tim_agent::tim_client::tim_api::ListSubscribersReq;
+use tim_agent::tim_client::tim_api::ListSubscribersRes;
+use tim_agent::tim_client::tim_api::SendCallAbilityOutcomeReq;
+use tim_agent::tim_client::tim_api::SendCallAbilityOutcomeRes;
+use tim_agent::tim_client::tim_api::SendCallAbilityReq;
+use tim_agent::tim_client::tim_api::SendCallAbilityRes;
+use tim_agent::tim_client::tim_api::SendMessageReq;
+use tim_agent::tim_client::tim_api::SendMessageRes;
+use tim_agent::tim_client::tim_api::Session;
+use tim_agent::tim_client::tim_api::SetActivityReq;
+use tim_agent::tim_client::tim_api::SetActivityRes;
+use tim_agent::tim_client::tim_api::SpaceEvent;
+use tim_agent::tim_client::tim_api::StreamTimelineReq;
+use tim_agent::tim_client::tim_api::SubscribeToSpaceReq;
+use tim_agent::tim_client::tim_api::TrustedConnectReq;
+use tim_agent::tim_client::tim_api::TrustedConnectRes;
+use tim_agent::tim_client::tim_api::TrustedRegisterReq;
+use tim_agent::tim_client::tim_api::TrustedRegisterRes;
+use tim_agent::tim_client::TimClient;
+use tim_agent::tim_client::TimClientConf;
+use tim_agent::tim_client::TimClientError;
+use tim_agent::tim_client::SESSION_METADATA_KEY;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+
+type EventStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+// Hands out sessions like the server would. With `reject_all` no session is accepted,
+// not even a fresh one.
+#[derive(Default)]
+struct FakeState {
+    sessions: Vec<(String, u64)>,
+    timites: Vec<u64>,
+    next_id: u64,
+    reject_all: bool,
+    sent: Vec<String>,
+    logins: usize,
+}
+
+#[derive(Clone, Default)]
+struct FakeServer {
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeServer {
+    fn reject_all(&self) {
+        self.state.lock().unwrap().reject_all = true;
+    }
+
+    fn sent(&self) -> Vec<String> {
+        self.state.lock().unwrap().sent.clone()
+    }
+
+    fn logins(&self) -> usize {
+        self.state.lock().unwrap().logins
+    }
+
+    fn open_session(state: &mut FakeState, timite_id: u64) -> Session {
+        state.logins += 1;
+        let key = format!("key-{}", state.logins);
+        state.sessions.push((key.clone(), timite_id));
+        Session {
+            key,
+            timite_id,
+            created_at: None,
+            client_info: None,
+        }
+    }
+
+    fn authorize<T>(&self, req: &Request<T>) -> Result<(), Status> {
+        let state = self.state.lock().unwrap();
+        let key = req
+            .metadata()
+            .get(SESSION_METADATA_KEY)
+            .and_then(|key| key.to_str().ok());
+        match key {
+            Some(key) if !state.reject_all && state.sessions.iter().any(|(k, _)| k == key) => {
+                Ok(())
+            }
+            _ => Err(Status::unauthenticated("No session")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl TimGrpcApi for FakeServer {
+    type SubscribeToSpaceStream = EventStream<SpaceEvent>;
+    type StreamTimelineStream = EventStream<GetTimelineRes>;
+
+    async fn trusted_register(
+        &self,
+        _req: Request<TrustedRegisterReq>,
+    ) -> Result<Response<TrustedRegisterRes>, Status> {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let timite_id = state.next_id;
+        state.timites.push(timite_id);
+        let session = Self::open_session(&mut state, timite_id);
+        Ok(Response::new(TrustedRegisterRes {
+            session: Some(session),
+            nick: String::new(),
+        }))
+    }
+
+    async fn trusted_connect(
+        &self,
+        req: Request<TrustedConnectReq>,
+    ) -> Result<Response<TrustedConnectRes>, Status> {
+        let timite_id = req.into_inner().timite.map(|t| t.id).unwrap_or_default();
+        let mut state = self.state.lock().unwrap();
+        if !state.timites.contains(&timite_id) {
+            return Ok(Response::new(TrustedConnectRes {
+                session: None,
+                error: ErrorCode::TimiteNotFound.into(),
+            }));
+        }
+        let session = Self::open_session(&mut state, timite_id);
+        Ok(Response::new(TrustedConnectRes {
+            session: Some(session),
+            error: Default::default(),
+        }))
+    }
+
+    async fn send_message(
+        &self,
+        req: Request<SendMessageReq>,
+    ) -> Result<Response<SendMessageRes>, Status> {
+        self.authorize(&req)?;
+        let mut state = self.state.lock().unwrap();
+        state.sent.push(req.into_inner().content);
+        Ok(Response::new(SendMessageRes {
+            error: None,
+            message_id: state.sent.len() as u64,
+        }))
+    }
+
+    async fn get_timeline(
+        &self,
+        req: Request<GetTimelineReq>,
+    ) -> Result<Response<GetTimelineRes>, Status> {
+        self.authorize(&req)?;
+        let req = req.into_inner();
+        Ok(Response::new(GetTimelineRes {
+            offset: req.offset,
+            size: req.size,
+            events: Vec::new(),
+            timites: Vec::new(),
+            has_more: false,
+            total_known: None,
+        }))
+    }
+
+    async fn subscribe_to_space(
+        &self,
+        req: Request<SubscribeToSpaceReq>,
+    ) -> Result<Response<Self::SubscribeToSpaceStream>, Status> {
+        self.authorize(&req)?;
+        Ok(Response::new(Box::pin(futures::stream::pending())))
+    }
+
+    async fn send_call_ability(
+        &self,
+        _req: Request<SendCallAbilityReq>,
+    ) -> Result<Response<SendCallAbilityRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn send_call_ability_outcome(
+        &self,
+        _req: Request<SendCallAbilityOutcomeReq>,
+    ) -> Result<Response<SendCallAbilityOutcomeRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn declare_abilities(
+        &self,
+        _req: Request<DeclareAbilitiesReq>,
+    ) -> Result<Response<DeclareAbilitiesRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn list_abilities(
+        &self,
+        _req: Request<ListAbilitiesReq>,
+    ) -> Result<Response<ListAbilitiesRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn get_timeline_since(
+        &self,
+        _req: Request<GetTimelineSinceReq>,
+    ) -> Result<Response<GetTimelineRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn stream_timeline(
+        &self,
+        _req: Request<StreamTimelineReq>,
+    ) -> Result<Response<Self::StreamTimelineStream>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn disconnect(
+        &self,
+        _req: Request<DisconnectReq>,
+    ) -> Result<Response<DisconnectRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn set_activity(
+        &self,
+        _req: Request<SetActivityReq>,
+    ) -> Result<Response<SetActivityRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn get_me(&self, _req: Request<GetMeReq>) -> Result<Response<GetMeRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn health(&self, _req: Request<HealthReq>) -> Result<Response<HealthRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn list_subscribers(
+        &self,
+        _req: Request<ListSubscribersReq>,
+    ) -> Result<Response<ListSubscribersRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn kick(&self, _req: Request<KickReq>) -> Result<Response<KickRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn erase_timite(
+        &self,
+        _req: Request<EraseTimiteReq>,
+    ) -> Result<Response<EraseTimiteRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn announce(&self, _req: Request<AnnounceReq>) -> Result<Response<AnnounceRes>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+}
+
+async fn serve(server: FakeServer) -> Result<SocketAddr, Box<dyn std::error::Error>> {
+    let incoming = TcpIncoming::bind("127.0.0.1:0".parse()?)?;
+    let addr = incoming.local_addr()?;
+    tokio::spawn(
+        Server::builder()
+            .add_service(TimGrpcApiServer::new(server))
+            .serve_with_incoming(incoming),
+    );
+    Ok(addr)
+}
+
+async fn client(addr: SocketAddr) -> Result<TimClient, TimClientError> {
+    TimClient::new(TimClientConf {
+        endpoint: format!("http://{addr}"),
+        nick: "agent".into(),
+        provider: "session-renewal-test".into(),
+        timite_id: None,
+        session_key: None,
+        connect_timeout: Duration::from_secs(5),
+        auth_token: None,
+        paging: Default::default(),
+    })
+    .await
+}
+
+#[tokio::test]
+async fn persistent_rejection_fails_after_one_renewal() -> Result<(), Box<dyn std::error::Error>> {
+    let server = FakeServer::default();
+    let addr = serve(server.clone()).await?;
+    let mut client = client(addr).await?;
+
+    server.reject_all();
+    let res = client.send_message("hello").await;
+
+    assert!(matches!(
+        res,
+        Err(TimClientError::TimGrpc(status)) if status.code() == tonic::Code::Unauthenticated
+    ));
+    // the initial login and a single renewal, no loop
+    assert_eq!(server.logins(), 2);
+    assert!(server.sent().is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use tim_agent::llm::memory::timeline_stream;
+use tim_agent::llm::memory::PageRetry;
+use tim_agent::tim_client::tim_api::space_event::Data;
+use tim_agent::tim_client::tim_api::space_event::Metadata;
+use tim_agent::tim_client::tim_api::EventNewMessage;
+use tim_agent::tim_client::tim_api::GetTimelineRes;
+use tim_agent::tim_client::tim_api::Message;
+use tim_agent::tim_client::tim_api::SpaceEvent;
+use tim_agent::tim_client::TimClientError;
+use tim_agent::tim_client::TimelinePaging;
+use tim_agent::tim_client::TimelineSource;
+use tim_agent::tim_client::MAX_TIMELINE_PAGE_SIZE;
+
+// A timeline of `len` messages with ids from 0, recording each (offset, size) asked for.
+#[derive(Clone)]
+struct FakeTimeline {
+    len: u64,
+    requests: Arc<Mutex<Vec<(u64, u32)>>>,
+}
+
+impl FakeTimeline {
+    fn new(len: u64) -> Self {
+        Self {
+            len,
+            requests: Arc::default(),
+        }
+    }
+
+    fn requests(&self) -> Vec<(u64, u32)> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl TimelineSource for FakeTimeline {
+    async fn timeline_page(
+        &mut self,
+        offset: u64,
+        size: u32,
+    ) -> Result<GetTimelineRes, TimClientError> {
+        self.requests.lock().unwrap().push((offset, size));
+        let end = offset.saturating_add(size as u64).min(self.len);
+        Ok(GetTimelineRes {
+            offset,
+            size,
+            events: (offset..end).map(message).collect(),
+            timites: Vec::new(),
+            has_more: end < self.len,
+            total_known: self.len.checked_sub(1),
+        })
+    }
+}
+
+fn message(id: u64) -> SpaceEvent {
+    SpaceEvent {
+        metadata: Some(Metadata {
+            id,
+            emitted_at: None,
+            room_id: String::new(),
+            prev_id: None,
+        }),
+        data: Some(Data::EventNewMessage(EventNewMessage {
+            message: Some(Message {
+                id,
+                sender_id: 2,
+                content: format!("message {id}"),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                expires_at: None,
+                deleted: false,
+                priority: Default::default(),
+            }),
+        })),
+    }
+}
+
+fn paging(page_size: u32, prefetch: usize) -> TimelinePaging {
+    TimelinePaging {
+        page_size,
+        prefetch,
+    }
+}
+
+#[tokio::test]
+async fn zero_page_size_is_an_error_not_an_empty_history() {
+    assert!(matches!(
+        paging(0, 0).checked(),
+        Err(TimClientError::InvalidPaging(_))
+    ));
+
+    let timeline = FakeTimeline::new(3);
+    let pages: Vec<_> = timeline_stream(timeline.clone(), paging(0, 2), PageRetry::default())
+        .collect()
+        .await;
+
+    assert_eq!(pages.len(), 1);
+    assert!(matches!(pages[0], Err(TimClientError::InvalidPaging(_))));
+    assert!(timeline.requests().is_empty());
+}
+
+#[tokio::test]
+async fn page_size_is_clamped_to_the_server_max() {
+    let checked = paging(u32::MAX, 0).checked().expect("valid paging");
+    assert_eq!(checked.page_size, MAX_TIMELINE_PAGE_SIZE);
+
+    let timeline = FakeTimeline::new(1500);
+    let pages: Vec<_> = timeline_stream(timeline.clone(), paging(5000, 0), PageRetry::default())
+        .collect()
+        .await;
+
+    assert_eq!(pages.len(), 2);
+    assert_eq!(
+        timeline.requests(),
+        [(0, MAX_TIMELINE_PAGE_SIZE), (1000, MAX_TIMELINE_PAGE_SIZE)]
+    );
+}
+
+#[tokio::test]
+async fn prefetch_reads_ahead_and_keeps_page_order() {
+    let timeline = FakeTimeline::new(7);
+    let mut pages = timeline_stream(timeline.clone(), paging(2, 2), PageRetry::default());
+
+    let first = pages.next().await.expect("first page").expect("page loads");
+    assert_eq!(first.offset, 0);
+    // the reader runs ahead of what was consumed
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while timeline.requests().len() < 3 {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .expect("pages were not prefetched");
+
+    let mut offsets = vec![first.offset];
+    while let Some(page) = pages.next().await {
+        offsets.push(page.expect("page loads").offset);
+    }
+    assert_eq!(offsets, [0, 2, 4, 6]);
+}
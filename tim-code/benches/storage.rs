@@ -0,0 +1,170 @@
+//! Baselines for the storage paths the timeline and startup rely on.
+//!
+//! Run with `cargo bench -p tim-code --bench storage`. Every size gets its own
+//! RocksDB in a temp dir that is removed once the benchmarks for that size ran.
+
+use std::hint::black_box;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BatchSize;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use prost_types::Timestamp;
+use tempfile::TempDir;
+use tim_code::api::space_event;
+use tim_code::api::Ability;
+use tim_code::api::EventNewMessage;
+use tim_code::api::Message;
+use tim_code::api::SpaceEvent;
+use tim_code::api::Timite;
+use tim_code::tim_storage::TimStorage;
+
+const EVENT_COUNTS: [u64; 3] = [1_000, 10_000, 100_000];
+const TIMITE_COUNTS: [u64; 3] = [10, 100, 1_000];
+const PAGE_SIZE: u32 = 100;
+
+fn event(id: u64) -> SpaceEvent {
+    SpaceEvent {
+        metadata: Some(space_event::Metadata {
+            id,
+            emitted_at: Some(Timestamp {
+                seconds: 1_700_000_000 + id as i64,
+                nanos: 0,
+            }),
+            room_id: String::new(),
+            prev_id: None,
+        }),
+        data: Some(space_event::Data::EventNewMessage(EventNewMessage {
+            message: Some(message(id)),
+        })),
+    }
+}
+
+fn message(id: u64) -> Message {
+    Message {
+        id,
+        sender_id: 1 + id % 8,
+        content: format!("benchmark message {id}"),
+        reply_to_message_id: None,
+        metadata: Default::default(),
+        parts: Vec::new(),
+        expires_at: None,
+        deleted: false,
+        priority: Default::default(),
+    }
+}
+
+/// A fresh on-disk storage holding events and messages `1..=count`. The dir goes away with
+/// the returned `TempDir`, so keep it alive as long as the storage.
+fn seeded(count: u64) -> (TempDir, TimStorage) {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().to_str().expect("utf-8 temp dir");
+    let storage = TimStorage::new(path, Default::default()).expect("open storage");
+    for id in 1..=count {
+        storage.store_space_event(&event(id)).expect("seed event");
+        storage
+            .store_message(id, &message(id))
+            .expect("seed message");
+    }
+    (dir, storage)
+}
+
+fn seeded_abilities(timites: u64) -> (TempDir, TimStorage) {
+    let (dir, storage) = seeded(0);
+    for id in 1..=timites {
+        storage
+            .store_timite(&Timite {
+                id,
+                nick: format!("timite-{id}"),
+                avatar_seed: id,
+                role: Default::default(),
+            })
+            .expect("seed timite");
+        let abilities = vec![Ability {
+            name: format!("ability-{id}"),
+            description: "benchmark ability".into(),
+            params: Vec::new(),
+            allowed_caller_ids: Vec::new(),
+        }];
+        storage
+            .store_timite_abilities(id, &abilities)
+            .expect("seed abilities");
+    }
+    (dir, storage)
+}
+
+fn store_space_event(c: &mut Criterion) {
+    let mut group = c.benchmark_group("store_space_event");
+    for count in EVENT_COUNTS {
+        let (_dir, storage) = seeded(count);
+        let next_id = AtomicU64::new(count + 1);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter_batched(
+                || event(next_id.fetch_add(1, Ordering::Relaxed)),
+                |event| storage.store_space_event(black_box(&event)),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn timeline(c: &mut Criterion) {
+    let mut group = c.benchmark_group("timeline");
+    for count in EVENT_COUNTS {
+        let (_dir, storage) = seeded(count);
+        group.bench_with_input(BenchmarkId::new("forward", count), &count, |b, &count| {
+            b.iter(|| storage.timeline("", black_box(count / 2), PAGE_SIZE))
+        });
+        // offset 0 reads the newest page, which starts from the max id
+        group.bench_with_input(BenchmarkId::new("latest", count), &count, |b, _| {
+            b.iter(|| storage.timeline("", 0, black_box(PAGE_SIZE)))
+        });
+    }
+    group.finish();
+}
+
+fn list_abilities(c: &mut Criterion) {
+    let mut group = c.benchmark_group("list_abilities");
+    for timites in TIMITE_COUNTS {
+        let (_dir, storage) = seeded_abilities(timites);
+        group.bench_with_input(BenchmarkId::from_parameter(timites), &timites, |b, _| {
+            b.iter(|| storage.list_abilities())
+        });
+    }
+    group.finish();
+}
+
+fn fetch_max(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fetch_max");
+    for count in EVENT_COUNTS {
+        let (_dir, storage) = seeded(count);
+        group.bench_with_input(BenchmarkId::new("event_id", count), &count, |b, _| {
+            b.iter(|| storage.fetch_max_event_id())
+        });
+        group.bench_with_input(BenchmarkId::new("message_id", count), &count, |b, _| {
+            b.iter(|| storage.fetch_max_message_id())
+        });
+        group.bench_with_input(BenchmarkId::new("timite_id", count), &count, |b, _| {
+            b.iter(|| storage.fetch_max_timite_id())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("call_ability_id", count),
+            &count,
+            |b, _| b.iter(|| storage.fetch_max_call_ability_id()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    store_space_event,
+    timeline,
+    list_abilities,
+    fetch_max
+);
+criterion_main!(benches);
@@ -1,3 +1,5 @@
+use std::env;
+use std::path::PathBuf;
 use std::process::Command;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -10,7 +12,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .expect("failed to run buf build");
     assert!(status.success(), "buf build failed");
 
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
     tonic_prost_build::configure()
+        .file_descriptor_set_path(out_dir.join("tim_descriptor.bin"))
         .build_client(true)
         .build_server(true)
         .compile_protos(protos, &[proto_root, "."])?;
@@ -14,14 +14,22 @@ pub mod tim {
     }
 }
 
+/// Encoded descriptors of the compiled protos, served by gRPC reflection.
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("tim_descriptor");
+
 pub use tim::api::g1 as api;
 pub use tim::code::db::g1 as storage;
 
 pub mod tim_ability;
 pub mod tim_api;
+pub mod tim_auth;
+pub mod tim_clock;
+pub mod tim_config;
+pub mod tim_filter;
 pub mod tim_grpc_api;
 pub mod tim_message;
 pub mod tim_session;
 pub mod tim_space;
 pub mod tim_storage;
 pub mod tim_timite;
+pub mod tim_web;
@@ -1,9 +1,12 @@
-use std::net::SocketAddr;
 use std::sync::Arc;
 
 use tim_code::api::tim_grpc_api_server::TimGrpcApiServer;
 use tim_code::tim_ability::TimAbility;
 use tim_code::tim_api::TimApi;
+use tim_code::tim_auth::TokenAuthorizer;
+use tim_code::tim_config::LogFormat;
+use tim_code::tim_config::ServerConfig;
+use tim_code::tim_filter::WordFilter;
 use tim_code::tim_grpc_api::TimGrpcApiService;
 use tim_code::tim_message::TimMessage;
 use tim_code::tim_session::SessionLayer;
@@ -11,82 +14,182 @@ use tim_code::tim_session::TimSession;
 use tim_code::tim_space::TimSpace;
 use tim_code::tim_storage::TimStorage;
 use tim_code::tim_timite::TimTimite;
+use tim_code::tim_web;
+use tokio_util::sync::CancellationToken;
+use tonic::codec::CompressionEncoding;
+use tonic::service::Routes;
 use tonic::transport::Server;
 use tonic_web::GrpcWebLayer;
 use tower_http::cors::Any;
 use tower_http::cors::CorsLayer;
-use tracing::{info, warn};
+use tracing::info;
+use tracing::warn;
 use tracing_subscriber::fmt::format::FmtSpan;
 
-fn init_tracing() {
+fn init_tracing(format: LogFormat) {
     let default_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
-    tracing_subscriber::fmt()
+    let builder = tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::new(default_filter))
         .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
-        .with_ansi(true)
         .with_level(true)
         .with_thread_ids(true)
         .with_target(false)
-        .with_line_number(true)
-        .init();
+        .with_line_number(true);
+    match format {
+        LogFormat::Pretty => builder.with_ansi(true).init(),
+        // escape codes would end up inside the JSON strings
+        LogFormat::Json => builder
+            .with_ansi(false)
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    init_tracing();
-
-    let port: u16 = std::env::var("TIM_CODE_PORT")
-        .ok()
-        .and_then(|value| value.parse().ok())
-        .unwrap_or(8787);
-    let host = std::env::var("TIM_CODE_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
+    let config = ServerConfig::from_env()?;
+    match std::env::args().nth(1).as_deref() {
+        None => {}
+        // prints what the environment resolves to, secrets redacted, and exits
+        Some("--print-config") | Some("config") => {
+            print!("{config}");
+            return Ok(());
+        }
+        Some(arg) => return Err(format!("unknown argument {arg}, try --print-config").into()),
+    }
 
-    let addr: SocketAddr = format!("{host}:{port}")
-        .parse()
-        .expect("invalid TIM_CODE_HOST or TIM_CODE_PORT");
+    init_tracing(config.log_format);
 
-    let data_dir = std::env::var("TIM_DATA_DIR").unwrap_or_else(|_| "./.tim".to_string());
+    let ServerConfig {
+        addr,
+        data_dir,
+        family_paths,
+        storage: storage_conf,
+        space: space_conf,
+        api: api_conf,
+        filter_timeout,
+        registration_tokens,
+        admin_token,
+        grpc_gzip,
+        reflection,
+        web_root,
+        web_spa,
+        ephemeral_sweep,
+        ..
+    } = config.clone();
 
-    let storage_svc = Arc::new(TimStorage::new(&data_dir)?);
+    let storage_svc = Arc::new(TimStorage::with_family_paths(
+        &data_dir,
+        &family_paths,
+        storage_conf,
+    )?);
     let session_svc = Arc::new(TimSession::new(storage_svc.clone()));
-    let space_svc = Arc::new(TimSpace::new(storage_svc.clone())?);
+    let space_svc = Arc::new(TimSpace::new(storage_svc.clone(), space_conf)?);
     let timite_svc = Arc::new(TimTimite::new(storage_svc.clone())?);
     let ability_svc = Arc::new(TimAbility::new(storage_svc.clone(), space_svc.clone())?);
-    let message_svc = Arc::new(TimMessage::new(storage_svc.clone(), space_svc.clone())?);
+    let mut message_svc = TimMessage::new(storage_svc.clone(), space_svc.clone())?;
+    if config.filters_content() {
+        info!("Filtering message content");
+        message_svc = message_svc.with_filter(
+            Arc::new(WordFilter::from_lists(
+                &config.blocked_words,
+                &config.flagged_words,
+            )),
+            filter_timeout,
+        );
+    }
+    let message_svc = Arc::new(message_svc);
 
-    let api_svc = Arc::new(TimApi::new(
+    let mut api_svc = TimApi::new(
         session_svc.clone(),
         space_svc.clone(),
         timite_svc.clone(),
         ability_svc.clone(),
         message_svc.clone(),
-    ));
+        api_conf,
+    );
+    if let Some(tokens) = registration_tokens {
+        info!("Registration requires an auth token");
+        api_svc = api_svc.with_authorizer(Arc::new(TokenAuthorizer::from_list(&tokens)));
+    }
+    let api_svc = Arc::new(api_svc);
 
     let api_svc = TimGrpcApiService::new(api_svc.clone());
-    let server = TimGrpcApiServer::new(api_svc);
+    let mut server = TimGrpcApiServer::new(api_svc).accept_compressed(CompressionEncoding::Gzip);
+    // Gzip pays off for timelines and long payloads but costs CPU and can grow tiny
+    // messages, so responses are compressed only on request and only for clients that
+    // advertise gzip; others keep getting plain responses.
+    if grpc_gzip {
+        server = server.send_compressed(CompressionEncoding::Gzip);
+    }
+
+    let reflection = if reflection {
+        Some(
+            tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(tim_code::FILE_DESCRIPTOR_SET)
+                .build_v1()?,
+        )
+    } else {
+        None
+    };
+    let mut routes = Routes::new(server);
+    if let Some(reflection) = reflection {
+        routes = routes.add_service(reflection);
+    }
+    if let Some(web_root) = web_root {
+        info!("Serving web client from {web_root}");
+        routes = tim_web::with_web_root(routes, web_root, web_spa);
+    }
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_headers(Any)
         .allow_origin(Any);
 
-    // Spawn periodic cleanup task for disconnected subscribers
+    let shutdown = CancellationToken::new();
     tokio::spawn({
-        let space = space_svc.clone();
+        let shutdown = shutdown.clone();
         async move {
-            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
-            loop {
-                interval.tick().await;
-                match space.cleanup_disconnected().await {
-                    Ok(removed) if removed > 0 => {
-                        info!("Cleaned up {removed} disconnected subscriber(s)");
+            if let Err(error) = tokio::signal::ctrl_c().await {
+                warn!("Failed to listen for shutdown signal: {error}");
+                return;
+            }
+            info!("Shutdown signal received");
+            shutdown.cancel();
+        }
+    });
+
+    let expiry = tokio::spawn({
+        let message = message_svc.clone();
+        let shutdown = shutdown.clone();
+        async move { message.run_expiry(ephemeral_sweep, shutdown).await }
+    });
+
+    // Periodic cleanup of disconnected subscribers, stopped with the server
+    let cleanup = tokio::spawn({
+        let space = space_svc.clone();
+        let shutdown = shutdown.clone();
+        async move { space.run_cleanup(shutdown).await }
+    });
+
+    let flush = storage_conf.batches_events().then(|| {
+        tokio::spawn({
+            let storage = storage_svc.clone();
+            let shutdown = shutdown.clone();
+            async move {
+                let mut interval = tokio::time::interval(storage_conf.event_flush_interval);
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        _ = shutdown.cancelled() => break,
                     }
-                    Ok(_) => {}
-                    Err(error) => {
-                        warn!("Failed to cleanup disconnected subscribers: {error}");
+                    if let Err(error) = storage.flush_space_events() {
+                        warn!("Failed to flush buffered space events: {error}");
                     }
                 }
             }
-        }
+        })
     });
 
     info!("Starting tim-code gRPC backend on {addr}");
@@ -94,11 +197,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Server::builder()
         .accept_http1(true)
         .layer(cors)
-        .layer(SessionLayer::new(session_svc.clone()))
+        .layer(SessionLayer::new(session_svc.clone(), admin_token))
         .layer(GrpcWebLayer::new())
-        .add_service(server)
-        .serve(addr)
+        .add_routes(routes)
+        .serve_with_shutdown(addr, shutdown.clone().cancelled_owned())
         .await?;
 
+    // covers the server stopping on its own as well
+    shutdown.cancel();
+    if let Err(error) = cleanup.await {
+        warn!("Cleanup task failed: {error}");
+    }
+    if let Err(error) = expiry.await {
+        warn!("Message expiry task failed: {error}");
+    }
+    if let Some(flush) = flush {
+        if let Err(error) = flush.await {
+            warn!("Event flush task failed: {error}");
+        }
+        // after the other tasks, so the events of their last runs are written too
+        if let Err(error) = storage_svc.flush_space_events() {
+            warn!("Failed to flush buffered space events: {error}");
+        }
+    }
+
     Ok(())
 }
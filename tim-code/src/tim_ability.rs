@@ -20,6 +20,9 @@ pub enum TimAbilityError {
 
     #[error("Call ability {0} not found")]
     CallAbilityMissing(u64),
+
+    #[error("Timite {caller_id} is not allowed to call ability {ability}")]
+    PermissionDenied { ability: String, caller_id: u64 },
 }
 
 pub struct TimAbility {
@@ -47,6 +50,7 @@ impl TimAbility {
         call_ability: &CallAbility,
         session: &Session,
     ) -> Result<u64, TimAbilityError> {
+        self.check_access(call_ability, session.timite_id)?;
         let call_ability_id = self.call_ability_cnt.fetch_add(1, Ordering::Relaxed) + 1;
         self.t_store
             .store_call_ability(call_ability_id, call_ability)?;
@@ -59,6 +63,30 @@ impl TimAbility {
         Ok(call_ability_id)
     }
 
+    fn check_access(
+        &self,
+        call_ability: &CallAbility,
+        caller_id: u64,
+    ) -> Result<(), TimAbilityError> {
+        if call_ability.timite_id == caller_id {
+            return Ok(());
+        }
+        let abilities = self
+            .t_store
+            .fetch_timite_abilities(call_ability.timite_id)?;
+        let Some(ability) = abilities.iter().find(|a| a.name == call_ability.name) else {
+            return Ok(());
+        };
+        if ability.allowed_caller_ids.is_empty() || ability.allowed_caller_ids.contains(&caller_id)
+        {
+            return Ok(());
+        }
+        Err(TimAbilityError::PermissionDenied {
+            ability: call_ability.name.clone(),
+            caller_id,
+        })
+    }
+
     pub fn find_call_ability(&self, call_ability_id: u64) -> Result<CallAbility, TimAbilityError> {
         self.t_store
             .fetch_call_ability(call_ability_id)?
@@ -1,20 +1,41 @@
 use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Instant;
 
 use tokio::sync::mpsc;
 use tracing::debug;
 use tracing::error;
 use tracing::field;
 use tracing::instrument;
+use tracing::warn;
 use tracing::Span;
 
+use crate::api::message_content::Part;
 use crate::api::space_event::Data as SpaceEventData;
+use crate::api::Ability;
+use crate::api::Activity;
+use crate::api::AnnounceReq;
+use crate::api::AnnounceRes;
 use crate::api::DeclareAbilitiesReq;
 use crate::api::DeclareAbilitiesRes;
+use crate::api::DisconnectReq;
+use crate::api::DisconnectRes;
+use crate::api::EraseTimiteReq;
+use crate::api::EraseTimiteRes;
 use crate::api::ErrorCode;
+use crate::api::GetMeRes;
 use crate::api::GetTimelineReq;
 use crate::api::GetTimelineRes;
+use crate::api::GetTimelineSinceReq;
+use crate::api::HealthRes;
+use crate::api::KickReq;
+use crate::api::KickRes;
 use crate::api::ListAbilitiesRes;
+use crate::api::ListSubscribersRes;
+use crate::api::MessageContent;
+use crate::api::OutcomeStatus;
 use crate::api::SendCallAbilityOutcomeReq;
 use crate::api::SendCallAbilityOutcomeRes;
 use crate::api::SendCallAbilityReq;
@@ -22,21 +43,31 @@ use crate::api::SendCallAbilityRes;
 use crate::api::SendMessageReq;
 use crate::api::SendMessageRes;
 use crate::api::Session;
+use crate::api::SetActivityReq;
+use crate::api::SetActivityRes;
 use crate::api::SpaceEvent;
+use crate::api::StreamTimelineReq;
 use crate::api::SubscribeToSpaceReq;
 use crate::api::Timite;
+use crate::api::TimiteRole;
 use crate::api::TrustedConnectReq;
 use crate::api::TrustedConnectRes;
 use crate::api::TrustedRegisterReq;
 use crate::api::TrustedRegisterRes;
 use crate::tim_ability::TimAbility;
 use crate::tim_ability::TimAbilityError;
+use crate::tim_auth::AllowAll;
+use crate::tim_auth::RegistrationAuthorizer;
+use crate::tim_auth::RegistrationDenied;
+use crate::tim_message::plain_text;
 use crate::tim_message::TimMessage;
 use crate::tim_message::TimMessageError;
 use crate::tim_session::TimSession;
 use crate::tim_session::TimSessionError;
 use crate::tim_space::TimSpace;
 use crate::tim_space::TimSpaceError;
+use crate::tim_timite::avatar_seed;
+use crate::tim_timite::NickFallback;
 use crate::tim_timite::TimTimite;
 use crate::tim_timite::TimTimiteError;
 
@@ -67,6 +98,60 @@ pub enum TimApiError {
 
     #[error("Invalid args error: {0}")]
     InvalidArgError(String),
+
+    #[error("Timite {0} not found")]
+    TimiteNotFound(u64),
+
+    #[error(transparent)]
+    RegistrationDenied(#[from] RegistrationDenied),
+
+    #[error("{field} exceeds {limit} bytes (got {actual} bytes)")]
+    PayloadTooLarge {
+        field: &'static str,
+        limit: usize,
+        actual: usize,
+    },
+}
+
+const DEFAULT_MAX_MESSAGE_BYTES: usize = 64 * 1024;
+const DEFAULT_MAX_METADATA_ENTRIES: usize = 16;
+const DEFAULT_MAX_METADATA_BYTES: usize = 4 * 1024;
+const DEFAULT_MAX_ABILITIES_PER_TIMITE: usize = 64;
+const DEFAULT_MAX_ABILITIES_BYTES: usize = 32 * 1024;
+const DEFAULT_TIMELINE_PAGE_SIZE: u32 = 100;
+const MAX_TIMELINE_PAGE_SIZE: u32 = 1000;
+/// Metadata keys with this prefix are set by the server only.
+pub const RESERVED_METADATA_PREFIX: &str = "tim.";
+/// Room ids are at most this long; the empty id is the default room.
+pub const MAX_ROOM_ID_LEN: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct TimApiConf {
+    /// Upper bound for message content and call ability payloads, in UTF-8 bytes.
+    pub max_message_bytes: usize,
+    pub max_metadata_entries: usize,
+    /// Upper bound for all metadata keys and values together, in UTF-8 bytes.
+    pub max_metadata_bytes: usize,
+    /// Abilities a timite may have declared at once.
+    pub max_abilities_per_timite: usize,
+    /// Upper bound for the names, descriptions and params of all declared abilities
+    /// together, in UTF-8 bytes.
+    pub max_abilities_bytes: usize,
+    /// Applied by `trusted_register` to empty and taken nicks unless the request forces its nick.
+    pub nick_fallback: NickFallback,
+}
+
+impl Default for TimApiConf {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            max_metadata_entries: DEFAULT_MAX_METADATA_ENTRIES,
+            max_metadata_bytes: DEFAULT_MAX_METADATA_BYTES,
+            max_abilities_per_timite: DEFAULT_MAX_ABILITIES_PER_TIMITE,
+            max_abilities_bytes: DEFAULT_MAX_ABILITIES_BYTES,
+            nick_fallback: NickFallback::default(),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -76,6 +161,9 @@ pub struct TimApi {
     t_timite: Arc<TimTimite>,
     t_ability: Arc<TimAbility>,
     t_message: Arc<TimMessage>,
+    conf: TimApiConf,
+    started_at: Instant,
+    authorizer: Arc<dyn RegistrationAuthorizer>,
 }
 
 impl TimApi {
@@ -85,6 +173,7 @@ impl TimApi {
         t_timite: Arc<TimTimite>,
         t_ability: Arc<TimAbility>,
         t_message: Arc<TimMessage>,
+        conf: TimApiConf,
     ) -> Self {
         Self {
             t_session,
@@ -92,6 +181,23 @@ impl TimApi {
             t_timite,
             t_ability,
             t_message,
+            conf,
+            started_at: Instant::now(),
+            authorizer: Arc::new(AllowAll),
+        }
+    }
+
+    /// Replaces the default of letting every client register and connect.
+    pub fn with_authorizer(mut self, authorizer: Arc<dyn RegistrationAuthorizer>) -> Self {
+        self.authorizer = authorizer;
+        self
+    }
+
+    pub fn health(&self) -> HealthRes {
+        HealthRes {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            subscribers: self.t_space.subscriber_count() as u64,
         }
     }
 
@@ -100,17 +206,25 @@ impl TimApi {
         &self,
         req: &TrustedRegisterReq,
     ) -> Result<TrustedRegisterRes, TimApiError> {
-        let timite = self.t_timite.create(&req.nick)?;
-
         let info = req
             .client_info
             .as_ref()
             .ok_or_else(|| TimApiError::InvalidArgError("client info required".into()))?;
+        self.authorizer.authorize(info, &req.nick).await?;
+        // a new timite subscribes next, no point registering it into a full space
+        self.t_space.check_capacity()?;
+        let timite =
+            self.t_timite
+                .register(&req.nick, req.role(), &self.conf.nick_fallback, req.force)?;
+        if timite.nick != req.nick {
+            debug!(assigned = %timite.nick, "registered under a fallback nick");
+        }
 
         let session = self.t_session.create(&timite, info)?;
 
         Ok(TrustedRegisterRes {
             session: Some(session),
+            nick: timite.nick,
         })
     }
 
@@ -128,6 +242,11 @@ impl TimApi {
             .as_ref()
             .ok_or_else(|| TimApiError::InvalidArgError("timite required".into()))?;
         Span::current().record("timite_id", timite.id);
+        let info = req
+            .client_info
+            .as_ref()
+            .ok_or_else(|| TimApiError::InvalidArgError("client info required".into()))?;
+        self.authorizer.authorize(info, &timite.nick).await?;
 
         let stored_timite = self.t_timite.get(timite.id)?;
         if stored_timite.is_none() {
@@ -138,12 +257,7 @@ impl TimApi {
             });
         }
 
-        let info = req
-            .client_info
-            .as_ref()
-            .ok_or_else(|| TimApiError::InvalidArgError("client info required".into()))?;
-
-        let session = self.t_session.create(&timite, info)?;
+        let session = self.t_session.create(timite, info)?;
 
         Ok(TrustedConnectRes {
             session: Some(session),
@@ -161,8 +275,19 @@ impl TimApi {
         req: &DeclareAbilitiesReq,
         session: &Session,
     ) -> Result<DeclareAbilitiesRes, TimApiError> {
+        let abilities = normalize_abilities(&req.abilities);
+        // a declaration replaces the previous set, so it is the resulting set
+        self.check_abilities(&abilities)?;
         self.t_timite
-            .declare_abilities(session.timite_id, &req.abilities)?;
+            .declare_abilities(session.timite_id, &abilities)?;
+        // announced in the background so the coalescing delay doesn't hold the reply
+        let space = self.t_space.clone();
+        let timite_id = session.timite_id;
+        tokio::spawn(async move {
+            if let Err(error) = space.publish_abilities_changed(timite_id).await {
+                warn!("Failed to announce abilities change of {timite_id}: {error}");
+            }
+        });
         Ok(DeclareAbilitiesRes {})
     }
 
@@ -186,8 +311,20 @@ impl TimApi {
             "message received from timite {}: {}",
             session.timite_id, &req.content
         );
-        self.t_message.process_message(req, session).await?;
-        Ok(SendMessageRes { error: None })
+        self.check_size("message content", &req.content)?;
+        self.check_parts(&req.parts)?;
+        self.check_metadata(&req.metadata)?;
+        check_room(&req.room_id)?;
+        if req.ephemeral_ttl_secs == Some(0) {
+            return Err(TimApiError::InvalidArgError(
+                "ephemeral_ttl_secs must be positive".into(),
+            ));
+        }
+        let message_id = self.t_message.process_message(req, session).await?;
+        Ok(SendMessageRes {
+            error: None,
+            message_id,
+        })
     }
 
     #[instrument(
@@ -200,11 +337,14 @@ impl TimApi {
         req: &SubscribeToSpaceReq,
         session: &Session,
     ) -> Result<mpsc::Receiver<SpaceEvent>, TimApiError> {
+        check_room(&req.room_id)?;
         let timite = self.t_timite.get(session.timite_id)?.unwrap_or(Timite {
             id: session.timite_id,
             nick: String::new(),
+            avatar_seed: 0,
+            role: TimiteRole::Unspecified.into(),
         });
-        Ok(self.t_space.subscribe(req, &session, timite).await?)
+        Ok(self.t_space.subscribe(req, session, timite).await?)
     }
 
     #[instrument(
@@ -217,18 +357,119 @@ impl TimApi {
         req: &GetTimelineReq,
         session: &Session,
     ) -> Result<GetTimelineRes, TimApiError> {
-        let events = self.t_space.timeline(req.offset, req.size)?;
+        check_room(&req.room_id)?;
+        let events = self.t_space.timeline(&req.room_id, req.offset, req.size)?;
+        self.timeline_res(&req.room_id, req.offset, req.size, events)
+    }
+
+    /// Events emitted at or after `req.since`. The response offset is the id of the
+    /// first event returned, or 0 when there is none.
+    #[instrument(
+        skip(self, req, session),
+        level = "debug",
+        fields(service = "api", timite_id = session.timite_id)
+    )]
+    pub fn get_timeline_since(
+        &self,
+        req: &GetTimelineSinceReq,
+        session: &Session,
+    ) -> Result<GetTimelineRes, TimApiError> {
+        let since = req
+            .since
+            .as_ref()
+            .ok_or_else(|| TimApiError::InvalidArgError("since is required".into()))?;
+        check_room(&req.room_id)?;
+        let events = self.t_space.timeline_since(&req.room_id, since, req.size)?;
+        let offset = events
+            .first()
+            .and_then(|event| event.metadata.as_ref())
+            .map(|meta| meta.id)
+            .unwrap_or(0);
+        self.timeline_res(&req.room_id, offset, req.size, events)
+    }
+
+    /// Streams the timeline from `req.offset` on in chunks of `req.page_size`
+    /// events. The producer stops once the receiver is dropped.
+    #[instrument(
+        skip(self, req, session),
+        level = "debug",
+        fields(service = "api", timite_id = session.timite_id)
+    )]
+    pub fn stream_timeline(
+        &self,
+        req: &StreamTimelineReq,
+        session: &Session,
+    ) -> mpsc::Receiver<Result<GetTimelineRes, TimApiError>> {
+        let page_size = match req.page_size {
+            0 => DEFAULT_TIMELINE_PAGE_SIZE,
+            size => size.min(MAX_TIMELINE_PAGE_SIZE),
+        };
+        let (tx, rx) = mpsc::channel(1);
+        if let Err(error) = check_room(&req.room_id) {
+            let _ = tx.try_send(Err(error));
+            return rx;
+        }
+        let api = self.clone();
+        let room = req.room_id.clone();
+        let mut offset = req.offset;
+        tokio::spawn(async move {
+            while !tx.is_closed() {
+                let chunk = api
+                    .t_space
+                    .timeline_from(&room, offset, page_size)
+                    .map_err(TimApiError::from)
+                    .and_then(|events| api.timeline_res(&room, offset, page_size, events));
+                let next = match &chunk {
+                    Ok(res) => match res.events.last().and_then(|ev| ev.metadata.as_ref()) {
+                        Some(meta) if res.has_more => Some(meta.id + 1),
+                        Some(_) => None,
+                        None => break,
+                    },
+                    Err(_) => None,
+                };
+                if tx.send(chunk).await.is_err() {
+                    debug!("timeline stream receiver dropped");
+                    break;
+                }
+                match next {
+                    Some(next) => offset = next,
+                    None => break,
+                }
+            }
+        });
+        rx
+    }
+
+    /// Wraps a page of `room`. `has_more` is checked against the newest event still
+    /// stored, so a page past the end of what is kept never reports more.
+    fn timeline_res(
+        &self,
+        room: &str,
+        offset: u64,
+        size: u32,
+        events: Vec<SpaceEvent>,
+    ) -> Result<GetTimelineRes, TimApiError> {
         let mut timites: Vec<Timite> = Vec::new();
         for timite_id in collect_timite_ids(&events) {
             if let Some(timite) = self.t_timite.get(timite_id)? {
                 timites.push(timite);
             }
         }
+        let last_returned = events
+            .last()
+            .and_then(|event| event.metadata.as_ref())
+            .map(|meta| meta.id);
+        let has_more = match (last_returned, self.t_space.last_event_id(room)?) {
+            (Some(returned), Some(stored)) => stored > returned,
+            _ => false,
+        };
         Ok(GetTimelineRes {
-            offset: req.offset,
-            size: req.size,
+            offset,
+            size,
             events,
             timites,
+            has_more,
+            total_known: Some(self.t_space.max_event_id()?),
         })
     }
 
@@ -246,6 +487,7 @@ impl TimApi {
             .call_ability
             .as_ref()
             .ok_or_else(|| TimApiError::InvalidArgError("call ability required".into()))?;
+        self.check_size("call ability payload", &call_ability.payload)?;
         let call_ability_id = self
             .t_ability
             .process_call_ability(call_ability, session)
@@ -267,6 +509,17 @@ impl TimApi {
             .outcome
             .as_ref()
             .ok_or_else(|| TimApiError::InvalidArgError("outcome payload required".into()))?;
+        if outcome.status() == OutcomeStatus::InProgress && outcome.error.is_some() {
+            return Err(TimApiError::InvalidArgError(
+                "an outcome with an error must be final".into(),
+            ));
+        }
+        if let Some(structured) = outcome.structured_payload.as_deref() {
+            self.check_size("call ability outcome structured payload", structured)?;
+            serde_json::from_str::<serde_json::Value>(structured).map_err(|err| {
+                TimApiError::InvalidArgError(format!("structured payload is not valid JSON: {err}"))
+            })?;
+        }
         let call_ability = self.t_ability.find_call_ability(outcome.call_ability_id)?;
         if call_ability.timite_id != session.timite_id {
             return Err(TimApiError::CallAbilityTargetMismatch {
@@ -279,6 +532,262 @@ impl TimApi {
             .await?;
         Ok(SendCallAbilityOutcomeRes {})
     }
+
+    #[instrument(skip(self), level = "debug", fields(service = "api"))]
+    pub fn list_subscribers(&self) -> Result<ListSubscribersRes, TimApiError> {
+        Ok(ListSubscribersRes {
+            subscribers: self.t_space.list_subscribers(),
+        })
+    }
+
+    #[instrument(skip(self, req), level = "debug", fields(service = "api"))]
+    pub async fn kick(&self, req: &KickReq) -> Result<KickRes, TimApiError> {
+        self.t_space.kick(&req.session_key).await?;
+        self.t_session.revoke(&req.session_key)?;
+        Ok(KickRes {})
+    }
+
+    /// Erases a timite on request, e.g. for a right-to-erasure claim. Its sessions
+    /// are kicked and revoked first so nothing new slips in, then its messages are
+    /// tombstoned and announced as deleted, its abilities dropped and, with
+    /// `anonymize`, its nick replaced. Copies outside the store, such as exports,
+    /// client caches and audit logs, are out of reach.
+    #[instrument(skip(self, req), level = "debug", fields(service = "api"))]
+    pub async fn erase_timite(&self, req: &EraseTimiteReq) -> Result<EraseTimiteRes, TimApiError> {
+        let timite = self
+            .t_timite
+            .get(req.timite_id)?
+            .ok_or(TimApiError::TimiteNotFound(req.timite_id))?;
+        for session_key in self.t_space.kick_timite(timite.id).await? {
+            self.t_session.revoke(&session_key)?;
+        }
+        let alias = req.anonymize.then(|| anonymized(&timite));
+        let erased = self
+            .t_message
+            .erase_timite(timite.id, alias.as_ref())
+            .await?;
+        let space = self.t_space.clone();
+        tokio::spawn(async move {
+            if let Err(error) = space.publish_abilities_changed(timite.id).await {
+                warn!(
+                    "Failed to announce abilities change of {}: {error}",
+                    timite.id
+                );
+            }
+        });
+        Ok(EraseTimiteRes {
+            erased_messages: erased as u64,
+        })
+    }
+
+    /// Posts an operator announcement to a room, see `TimMessage::announce`.
+    #[instrument(skip(self, req), level = "debug", fields(service = "api"))]
+    pub async fn announce(&self, req: &AnnounceReq) -> Result<AnnounceRes, TimApiError> {
+        if req.content.trim().is_empty() {
+            return Err(TimApiError::InvalidArgError(
+                "announcement content required".into(),
+            ));
+        }
+        self.check_size("announcement content", &req.content)?;
+        check_room(&req.room_id)?;
+        let message_id = self.t_message.announce(req).await?;
+        Ok(AnnounceRes { message_id })
+    }
+
+    /// Graceful counterpart of the disconnect sweep; the session stays valid for reconnects.
+    #[instrument(
+        skip(self, _req, session),
+        level = "debug",
+        fields(service = "api", timite_id = session.timite_id)
+    )]
+    pub async fn disconnect(
+        &self,
+        _req: &DisconnectReq,
+        session: &Session,
+    ) -> Result<DisconnectRes, TimApiError> {
+        self.t_space.disconnect(session).await?;
+        Ok(DisconnectRes {})
+    }
+
+    #[instrument(
+        skip(self, req, session),
+        level = "debug",
+        fields(service = "api", timite_id = session.timite_id)
+    )]
+    pub async fn set_activity(
+        &self,
+        req: &SetActivityReq,
+        session: &Session,
+    ) -> Result<SetActivityRes, TimApiError> {
+        let activity = req.activity();
+        let upd_id = self
+            .t_space
+            .publish_activity(session.timite_id, activity)
+            .await?;
+        if activity != Activity::Idle {
+            let space = self.t_space.clone();
+            let timite_id = session.timite_id;
+            tokio::spawn(async move {
+                if let Err(error) = space.expire_activity(timite_id, upd_id).await {
+                    warn!("Failed to expire activity of {timite_id}: {error}");
+                }
+            });
+        }
+        Ok(SetActivityRes {})
+    }
+
+    pub async fn get_me(&self, session: &Session) -> Result<GetMeRes, TimApiError> {
+        let timite = self
+            .t_timite
+            .get(session.timite_id)?
+            .ok_or(TimApiError::TimiteNotFound(session.timite_id))?;
+        Ok(GetMeRes {
+            timite: Some(timite),
+        })
+    }
+
+    fn check_parts(&self, parts: &[MessageContent]) -> Result<(), TimApiError> {
+        for content in parts {
+            match &content.part {
+                None => {
+                    return Err(TimApiError::InvalidArgError(
+                        "message part without content".into(),
+                    ))
+                }
+                Some(Part::Link(link)) if link.url.trim().is_empty() => {
+                    return Err(TimApiError::InvalidArgError("link part without url".into()))
+                }
+                Some(_) => {}
+            }
+        }
+        self.check_size("message parts", &plain_text(parts))
+    }
+
+    fn check_metadata(&self, metadata: &HashMap<String, String>) -> Result<(), TimApiError> {
+        if metadata.len() > self.conf.max_metadata_entries {
+            return Err(TimApiError::InvalidArgError(format!(
+                "message metadata has {} entries, at most {} allowed",
+                metadata.len(),
+                self.conf.max_metadata_entries
+            )));
+        }
+        if let Some(key) = metadata
+            .keys()
+            .find(|key| key.starts_with(RESERVED_METADATA_PREFIX))
+        {
+            return Err(TimApiError::InvalidArgError(format!(
+                "message metadata key {key} is reserved"
+            )));
+        }
+        let size: usize = metadata
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum();
+        if size > self.conf.max_metadata_bytes {
+            return Err(TimApiError::PayloadTooLarge {
+                field: "message metadata",
+                limit: self.conf.max_metadata_bytes,
+                actual: size,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_abilities(&self, abilities: &[Ability]) -> Result<(), TimApiError> {
+        if abilities.len() > self.conf.max_abilities_per_timite {
+            return Err(TimApiError::InvalidArgError(format!(
+                "{} abilities declared, at most {} allowed",
+                abilities.len(),
+                self.conf.max_abilities_per_timite
+            )));
+        }
+        let mut names = HashSet::new();
+        for (index, ability) in abilities.iter().enumerate() {
+            if ability.name.is_empty() {
+                return Err(TimApiError::InvalidArgError(format!(
+                    "ability #{index} has an empty name"
+                )));
+            }
+            if !names.insert(ability.name.as_str()) {
+                return Err(TimApiError::InvalidArgError(format!(
+                    "ability {} is declared more than once",
+                    ability.name
+                )));
+            }
+            let mut params = HashSet::new();
+            for (param_index, param) in ability.params.iter().enumerate() {
+                if param.name.is_empty() {
+                    return Err(TimApiError::InvalidArgError(format!(
+                        "parameter #{param_index} of ability {} has an empty name",
+                        ability.name
+                    )));
+                }
+                if !params.insert(param.name.as_str()) {
+                    return Err(TimApiError::InvalidArgError(format!(
+                        "parameter {} of ability {} is declared more than once",
+                        param.name, ability.name
+                    )));
+                }
+            }
+        }
+        let size: usize = abilities
+            .iter()
+            .map(|ability| {
+                let params: usize = ability
+                    .params
+                    .iter()
+                    .map(|param| param.name.len() + param.description.len())
+                    .sum();
+                ability.name.len() + ability.description.len() + params
+            })
+            .sum();
+        if size > self.conf.max_abilities_bytes {
+            return Err(TimApiError::PayloadTooLarge {
+                field: "abilities",
+                limit: self.conf.max_abilities_bytes,
+                actual: size,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_size(&self, field: &'static str, value: &str) -> Result<(), TimApiError> {
+        let limit = self.conf.max_message_bytes;
+        if value.len() > limit {
+            return Err(TimApiError::PayloadTooLarge {
+                field,
+                limit,
+                actual: value.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The timite as shown after an anonymizing erasure.
+fn anonymized(timite: &Timite) -> Timite {
+    let nick = format!("erased-{}", timite.id);
+    Timite {
+        avatar_seed: avatar_seed(&nick),
+        nick,
+        ..timite.clone()
+    }
+}
+
+/// Room ids go into storage keys, so they are kept to a small alphabet without ':'.
+fn check_room(room_id: &str) -> Result<(), TimApiError> {
+    if room_id.len() > MAX_ROOM_ID_LEN {
+        return Err(TimApiError::InvalidArgError(format!(
+            "room id is longer than {MAX_ROOM_ID_LEN} bytes"
+        )));
+    }
+    let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+    if !room_id.chars().all(valid) {
+        return Err(TimApiError::InvalidArgError(format!(
+            "room id {room_id} may only contain a-z, 0-9, '-' and '_'"
+        )));
+    }
+    Ok(())
 }
 
 fn collect_timite_ids(events: &[SpaceEvent]) -> BTreeSet<u64> {
@@ -310,7 +819,29 @@ fn collect_timite_ids(events: &[SpaceEvent]) -> BTreeSet<u64> {
                     ids.insert(timite.id);
                 }
             }
+            SpaceEventData::EventAbilitiesChanged(payload) => {
+                ids.insert(payload.timite_id);
+            }
+            SpaceEventData::EventTimiteActivity(payload) => {
+                ids.insert(payload.timite_id);
+            }
+            SpaceEventData::EventMessageDeleted(_) => {}
         }
     }
     ids
 }
+
+/// Trims ability and parameter names, so `" search"` and `"search"` are one ability.
+fn normalize_abilities(abilities: &[Ability]) -> Vec<Ability> {
+    abilities
+        .iter()
+        .cloned()
+        .map(|mut ability| {
+            ability.name = ability.name.trim().to_string();
+            for param in &mut ability.params {
+                param.name = param.name.trim().to_string();
+            }
+            ability
+        })
+        .collect()
+}
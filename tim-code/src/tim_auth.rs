@@ -0,0 +1,73 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+
+use crate::api::ClientInfo;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistrationDenied {
+    #[error("Registration requires credentials: {0}")]
+    Unauthenticated(String),
+
+    #[error("Registration not allowed: {0}")]
+    PermissionDenied(String),
+}
+
+/// Decides who may register or connect a timite. Consulted before anything is
+/// written, so a rejected client leaves no timite or session behind.
+#[async_trait]
+pub trait RegistrationAuthorizer: Send + Sync {
+    async fn authorize(&self, info: &ClientInfo, nick: &str) -> Result<(), RegistrationDenied>;
+}
+
+/// Lets everyone in, the default.
+#[derive(Debug, Default)]
+pub struct AllowAll;
+
+#[async_trait]
+impl RegistrationAuthorizer for AllowAll {
+    async fn authorize(&self, _info: &ClientInfo, _nick: &str) -> Result<(), RegistrationDenied> {
+        Ok(())
+    }
+}
+
+/// Admits clients presenting one of the configured tokens in `ClientInfo.auth_token`.
+#[derive(Debug)]
+pub struct TokenAuthorizer {
+    tokens: HashSet<String>,
+}
+
+impl TokenAuthorizer {
+    pub fn new(tokens: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            tokens: tokens.into_iter().collect(),
+        }
+    }
+
+    /// Parses a comma separated token list, blanks are skipped.
+    pub fn from_list(list: &str) -> Self {
+        Self::new(
+            list.split(',')
+                .map(str::trim)
+                .filter(|token| !token.is_empty())
+                .map(str::to_string),
+        )
+    }
+}
+
+#[async_trait]
+impl RegistrationAuthorizer for TokenAuthorizer {
+    async fn authorize(&self, info: &ClientInfo, _nick: &str) -> Result<(), RegistrationDenied> {
+        if info.auth_token.is_empty() {
+            return Err(RegistrationDenied::Unauthenticated(
+                "auth token missing".into(),
+            ));
+        }
+        if !self.tokens.contains(&info.auth_token) {
+            return Err(RegistrationDenied::PermissionDenied(
+                "auth token not recognized".into(),
+            ));
+        }
+        Ok(())
+    }
+}
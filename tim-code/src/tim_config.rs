@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tim_lib::kvstore::Durability;
+use tim_lib::kvstore::FamilyPaths;
+use tim_lib::kvstore::KvStoreConf;
+
+use crate::tim_api::TimApiConf;
+use crate::tim_filter::DEFAULT_FILTER_TIMEOUT;
+use crate::tim_space::PersistPolicy;
+use crate::tim_space::TimSpaceConf;
+use crate::tim_storage::TimStorageConf;
+use crate::tim_timite::NickFallback;
+
+/// Environment variables the server reads, in the order they are printed.
+pub const SERVER_VARS: &[&str] = &[
+    "TIM_CODE_HOST",
+    "TIM_CODE_PORT",
+    "TIM_LOG_FORMAT",
+    "TIM_DATA_DIR",
+    "TIM_FAMILY_PATHS",
+    "TIM_DURABILITY",
+    "TIM_EVENT_BATCH_SIZE",
+    "TIM_AUTO_MIGRATE",
+    "TIM_TRANSIENT_EVENTS",
+    "TIM_MAX_MESSAGE_BYTES",
+    "TIM_MAX_ABILITIES_PER_TIMITE",
+    "TIM_MAX_ABILITIES_BYTES",
+    "TIM_NICK_FALLBACK",
+    "TIM_MOTD",
+    "TIM_SUBSCRIBER_IDLE_SECS",
+    "TIM_MAX_SUBSCRIPTIONS_PER_TIMITE",
+    "TIM_MAX_SUBSCRIBERS",
+    "TIM_SUBSCRIBERS_HIGH_WATER",
+    "TIM_REPLAY_ON_SUBSCRIBE",
+    "TIM_REPLAY_MAX_AGE_SECS",
+    "TIM_ACTIVITY_TTL_SECS",
+    "TIM_CLEANUP_INTERVAL_SECS",
+    "TIM_SUBSCRIBER_BACKLOG",
+    "TIM_FANOUT_CONCURRENCY",
+    "TIM_BLOCKED_WORDS",
+    "TIM_FLAGGED_WORDS",
+    "TIM_FILTER_TIMEOUT_MS",
+    "TIM_REGISTRATION_TOKENS",
+    "TIM_ADMIN_TOKEN",
+    "TIM_GRPC_COMPRESSION",
+    "TIM_ENABLE_REFLECTION",
+    "TIM_WEB_ROOT",
+    "TIM_WEB_SPA",
+    "TIM_EPHEMERAL_SWEEP_SECS",
+];
+
+const REDACTED: &str = "<redacted>";
+const UNSET: &str = "<unset>";
+
+/// How the server writes its logs; levels stay with `RUST_LOG` either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines with ANSI colors, for interactive use.
+    #[default]
+    Pretty,
+    /// One JSON object per line without colors, span fields as attributes, for log
+    /// aggregators.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!(
+                "unknown log format {other}, expected pretty or json"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Pretty => write!(f, "pretty"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TimConfigError {
+    #[error("Invalid {name}: {reason}")]
+    Invalid { name: &'static str, reason: String },
+}
+
+/// Everything the server takes from its environment, resolved with defaults applied.
+/// Numbers that don't parse fall back to their default; malformed lists and
+/// addresses are errors.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+    pub log_format: LogFormat,
+    pub data_dir: String,
+    /// Moves families off `data_dir`, e.g. `log=/mnt/big/tim-log`; changing it for an
+    /// existing store requires moving the family data first.
+    pub family_paths: FamilyPaths,
+    pub storage: TimStorageConf,
+    pub space: TimSpaceConf,
+    pub api: TimApiConf,
+    /// Comma separated; blocked words reject messages, flagged words only mark them.
+    pub blocked_words: String,
+    pub flagged_words: String,
+    pub filter_timeout: Duration,
+    /// Comma separated, closes registration to clients without one.
+    pub registration_tokens: Option<String>,
+    pub admin_token: Option<String>,
+    /// Compresses responses for clients that advertise gzip.
+    pub grpc_gzip: bool,
+    /// Off by default, reflection exposes the whole API schema.
+    pub reflection: bool,
+    /// Serves a built front-end next to the API; gRPC paths still go to tonic.
+    pub web_root: Option<String>,
+    pub web_spa: bool,
+    /// How often expired ephemeral messages are deleted.
+    pub ephemeral_sweep: Duration,
+    /// Set variables that look meant for tim but aren't read by the server,
+    /// a misspelled name or another component's setting.
+    pub unused: Vec<(String, String)>,
+}
+
+struct Vars(HashMap<String, String>);
+
+impl Vars {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    fn parsed<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.get(name).and_then(|value| value.parse().ok())
+    }
+
+    fn secs(&self, name: &str) -> Option<Duration> {
+        self.parsed(name)
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        matches!(self.get(name), Some("1") | Some("true"))
+    }
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Result<Self, TimConfigError> {
+        // variables that aren't UTF-8 can't be any of ours
+        Self::from_vars(std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }))
+    }
+
+    pub fn from_vars(
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, TimConfigError> {
+        let vars = Vars(vars.into_iter().collect());
+
+        let port: u16 = vars.parsed("TIM_CODE_PORT").unwrap_or(8787);
+        let host = vars.get("TIM_CODE_HOST").unwrap_or("0.0.0.0");
+        let addr = format!("{host}:{port}")
+            .parse()
+            .map_err(|error| invalid("TIM_CODE_HOST", error))?;
+        let log_format = vars
+            .get("TIM_LOG_FORMAT")
+            .map(str::parse::<LogFormat>)
+            .transpose()
+            .map_err(|error| invalid("TIM_LOG_FORMAT", error))?
+            .unwrap_or_default();
+
+        // TIM_DURABILITY applies to every family; secrets keep synced writes when unset
+        let kv = match vars.get("TIM_DURABILITY") {
+            Some(value) => KvStoreConf::uniform(
+                value
+                    .parse::<Durability>()
+                    .map_err(|error| invalid("TIM_DURABILITY", error))?,
+            ),
+            None => KvStoreConf::default(),
+        };
+        let mut storage = TimStorageConf {
+            kv,
+            ..TimStorageConf::default()
+        };
+        if let Some(batch_size) = vars.parsed("TIM_EVENT_BATCH_SIZE") {
+            storage.event_batch_size = batch_size;
+        }
+        // stores from older versions are upgraded on open unless TIM_AUTO_MIGRATE=false
+        if let Some(auto_migrate) = vars.parsed("TIM_AUTO_MIGRATE") {
+            storage.auto_migrate = auto_migrate;
+        }
+        let family_paths = vars
+            .get("TIM_FAMILY_PATHS")
+            .map(str::parse::<FamilyPaths>)
+            .transpose()
+            .map_err(|error| invalid("TIM_FAMILY_PATHS", error))?
+            .unwrap_or_default();
+
+        let mut api = TimApiConf::default();
+        if let Some(max_message_bytes) = vars.parsed("TIM_MAX_MESSAGE_BYTES") {
+            api.max_message_bytes = max_message_bytes;
+        }
+        if let Some(max_abilities) = vars.parsed("TIM_MAX_ABILITIES_PER_TIMITE") {
+            api.max_abilities_per_timite = max_abilities;
+        }
+        if let Some(max_abilities_bytes) = vars.parsed("TIM_MAX_ABILITIES_BYTES") {
+            api.max_abilities_bytes = max_abilities_bytes;
+        }
+        // TIM_NICK_FALLBACK is `off`, `suffix` or `suffix:<base for empty nicks>`
+        if let Some(fallback) = vars.get("TIM_NICK_FALLBACK") {
+            api.nick_fallback = fallback
+                .parse::<NickFallback>()
+                .map_err(|error| invalid("TIM_NICK_FALLBACK", error))?;
+        }
+
+        let transient = vars.get("TIM_TRANSIENT_EVENTS").unwrap_or_default();
+        let mut space = TimSpaceConf {
+            persist: PersistPolicy::from_transient_list(transient)
+                .map_err(|error| invalid("TIM_TRANSIENT_EVENTS", error))?,
+            ..TimSpaceConf::default()
+        };
+        // TIM_MOTD is either a path to a file or the text itself
+        space.motd = vars
+            .get("TIM_MOTD")
+            .map(|motd| std::fs::read_to_string(motd).unwrap_or_else(|_| motd.to_string()))
+            .filter(|motd| !motd.trim().is_empty());
+        if let Some(secs) = vars.parsed("TIM_SUBSCRIBER_IDLE_SECS") {
+            space.idle_timeout = Duration::from_secs(secs);
+        }
+        if let Some(limit) = vars.parsed("TIM_MAX_SUBSCRIPTIONS_PER_TIMITE") {
+            space.max_subscriptions_per_timite = limit;
+        }
+        if let Some(limit) = vars.parsed("TIM_MAX_SUBSCRIBERS") {
+            space.max_subscribers = limit;
+        }
+        if let Some(mark) = vars.parsed("TIM_SUBSCRIBERS_HIGH_WATER") {
+            space.subscribers_high_water = mark;
+        }
+        // TIM_REPLAY_ON_SUBSCRIBE backfills new subscriptions with recent events,
+        // optionally only those from the last TIM_REPLAY_MAX_AGE_SECS
+        if let Some(size) = vars.parsed("TIM_REPLAY_ON_SUBSCRIBE") {
+            space.replay_on_subscribe = size;
+        }
+        if let Some(secs) = vars.parsed("TIM_REPLAY_MAX_AGE_SECS") {
+            space.replay_max_age = Some(Duration::from_secs(secs));
+        }
+        if let Some(ttl) = vars.secs("TIM_ACTIVITY_TTL_SECS") {
+            space.activity_ttl = ttl;
+        }
+        if let Some(interval) = vars.secs("TIM_CLEANUP_INTERVAL_SECS") {
+            space.cleanup_interval = interval;
+        }
+        if let Some(backlog) = vars.parsed("TIM_SUBSCRIBER_BACKLOG") {
+            space.subscriber_backlog = backlog;
+        }
+        if let Some(concurrency) = vars.parsed("TIM_FANOUT_CONCURRENCY") {
+            space.fanout_concurrency = concurrency;
+        }
+
+        let mut unused: Vec<(String, String)> = vars
+            .0
+            .iter()
+            .filter(|(name, _)| name.contains("TIM") && !SERVER_VARS.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        unused.sort();
+
+        Ok(Self {
+            addr,
+            log_format,
+            data_dir: vars.get("TIM_DATA_DIR").unwrap_or("./.tim").to_string(),
+            family_paths,
+            storage,
+            space,
+            api,
+            blocked_words: vars
+                .get("TIM_BLOCKED_WORDS")
+                .unwrap_or_default()
+                .to_string(),
+            flagged_words: vars
+                .get("TIM_FLAGGED_WORDS")
+                .unwrap_or_default()
+                .to_string(),
+            filter_timeout: vars
+                .parsed("TIM_FILTER_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_FILTER_TIMEOUT),
+            registration_tokens: vars.get("TIM_REGISTRATION_TOKENS").map(str::to_string),
+            admin_token: vars.get("TIM_ADMIN_TOKEN").map(str::to_string),
+            grpc_gzip: vars.get("TIM_GRPC_COMPRESSION") == Some("gzip"),
+            reflection: vars.flag("TIM_ENABLE_REFLECTION"),
+            web_root: vars.get("TIM_WEB_ROOT").map(str::to_string),
+            web_spa: vars.flag("TIM_WEB_SPA"),
+            ephemeral_sweep: vars
+                .secs("TIM_EPHEMERAL_SWEEP_SECS")
+                .unwrap_or(Duration::from_secs(5)),
+            unused,
+        })
+    }
+
+    /// Whether message content goes through the word filter.
+    pub fn filters_content(&self) -> bool {
+        !self.blocked_words.trim().is_empty() || !self.flagged_words.trim().is_empty()
+    }
+
+    /// The resolved value behind each of `SERVER_VARS`, secrets redacted.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let kv = &self.storage.kv;
+        let space = &self.space;
+        let api = &self.api;
+        let values = [
+            self.addr.ip().to_string(),
+            self.addr.port().to_string(),
+            self.log_format.to_string(),
+            self.data_dir.clone(),
+            family_paths(&self.family_paths),
+            format!(
+                "secrets={:?},data={:?},log={:?}",
+                kv.secrets, kv.data, kv.log
+            ),
+            self.storage.event_batch_size.to_string(),
+            self.storage.auto_migrate.to_string(),
+            space
+                .persist
+                .transient_kinds()
+                .iter()
+                .map(|kind| kind.name())
+                .collect::<Vec<_>>()
+                .join(","),
+            api.max_message_bytes.to_string(),
+            api.max_abilities_per_timite.to_string(),
+            api.max_abilities_bytes.to_string(),
+            api.nick_fallback.to_string(),
+            space.motd.as_ref().map_or(UNSET.to_string(), |motd| {
+                format!("{} chars", motd.chars().count())
+            }),
+            space.idle_timeout.as_secs().to_string(),
+            space.max_subscriptions_per_timite.to_string(),
+            space.max_subscribers.to_string(),
+            space.subscribers_high_water.to_string(),
+            space.replay_on_subscribe.to_string(),
+            space
+                .replay_max_age
+                .map_or(UNSET.to_string(), |age| age.as_secs().to_string()),
+            space.activity_ttl.as_secs().to_string(),
+            space.cleanup_interval.as_secs().to_string(),
+            space.subscriber_backlog.to_string(),
+            space.fanout_concurrency.to_string(),
+            self.blocked_words.clone(),
+            self.flagged_words.clone(),
+            self.filter_timeout.as_millis().to_string(),
+            self.registration_tokens
+                .clone()
+                .unwrap_or(UNSET.to_string()),
+            self.admin_token.clone().unwrap_or(UNSET.to_string()),
+            if self.grpc_gzip { "gzip" } else { "none" }.to_string(),
+            self.reflection.to_string(),
+            self.web_root.clone().unwrap_or(UNSET.to_string()),
+            self.web_spa.to_string(),
+            self.ephemeral_sweep.as_secs().to_string(),
+        ];
+        SERVER_VARS
+            .iter()
+            .zip(values)
+            .map(|(name, value)| (*name, redact(name, value)))
+            .collect()
+    }
+}
+
+/// Prints `NAME=value` lines, followed by the unused variables as comments.
+impl fmt::Display for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.entries() {
+            writeln!(f, "{name}={value}")?;
+        }
+        if !self.unused.is_empty() {
+            writeln!(f, "# set but not used by tim-code")?;
+        }
+        for (name, value) in &self.unused {
+            writeln!(f, "# {name}={}", redact(name, value.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Names that hold credentials, e.g. `TIM_ADMIN_TOKEN` or `OPENAI_TIM_API_KEY`.
+pub fn is_secret(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    ["KEY", "TOKEN", "SECRET", "PASSWORD"]
+        .iter()
+        .any(|marker| name.contains(marker))
+}
+
+fn redact(name: &str, value: String) -> String {
+    if is_secret(name) && value != UNSET {
+        REDACTED.to_string()
+    } else {
+        value
+    }
+}
+
+fn invalid(name: &'static str, error: impl fmt::Display) -> TimConfigError {
+    TimConfigError::Invalid {
+        name,
+        reason: error.to_string(),
+    }
+}
+
+fn family_paths(paths: &FamilyPaths) -> String {
+    if paths.is_empty() {
+        return UNSET.to_string();
+    }
+    [
+        ("secrets", &paths.secrets),
+        ("data", &paths.data),
+        ("log", &paths.log),
+    ]
+    .iter()
+    .filter_map(|(name, path)| {
+        path.as_ref()
+            .map(|path| format!("{name}={}", path.display()))
+    })
+    .collect::<Vec<_>>()
+    .join(",")
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+/// Metadata key set on stored messages a filter flagged.
+pub const FLAGGED_METADATA_KEY: &str = "tim.flagged";
+
+/// How long a filter may take before the message goes through unchecked.
+pub const DEFAULT_FILTER_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterVerdict {
+    Allow,
+    /// Refused, the sender gets the reason back and nothing is stored.
+    Reject(String),
+    /// Delivered, but stored with `FLAGGED_METADATA_KEY` set.
+    Flag,
+}
+
+/// Checks message content before it is stored or broadcast. Runs on the send path,
+/// so implementations should answer quickly; slow ones are cut off after a timeout.
+#[async_trait]
+pub trait ContentFilter: Send + Sync {
+    async fn check(&self, content: &str) -> FilterVerdict;
+}
+
+/// Allows everything, the default.
+#[derive(Debug, Default)]
+pub struct NoFilter;
+
+#[async_trait]
+impl ContentFilter for NoFilter {
+    async fn check(&self, _content: &str) -> FilterVerdict {
+        FilterVerdict::Allow
+    }
+}
+
+/// Rejects or flags messages containing any of the configured words,
+/// compared case-insensitively. Blocked words win over flagged ones.
+#[derive(Debug, Default)]
+pub struct WordFilter {
+    blocked: Vec<String>,
+    flagged: Vec<String>,
+}
+
+impl WordFilter {
+    /// Takes comma separated word lists, blanks are skipped.
+    pub fn from_lists(blocked: &str, flagged: &str) -> Self {
+        Self {
+            blocked: parse_list(blocked),
+            flagged: parse_list(flagged),
+        }
+    }
+}
+
+fn parse_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[async_trait]
+impl ContentFilter for WordFilter {
+    async fn check(&self, content: &str) -> FilterVerdict {
+        let content = content.to_lowercase();
+        if self
+            .blocked
+            .iter()
+            .any(|word| content.contains(word.as_str()))
+        {
+            return FilterVerdict::Reject("contains a blocked word".into());
+        }
+        if self
+            .flagged
+            .iter()
+            .any(|word| content.contains(word.as_str()))
+        {
+            return FilterVerdict::Flag;
+        }
+        FilterVerdict::Allow
+    }
+}
@@ -8,12 +8,27 @@ use tonic::Response;
 use tonic::Status;
 
 use crate::api::tim_grpc_api_server::TimGrpcApi;
+use crate::api::AnnounceReq;
+use crate::api::AnnounceRes;
 use crate::api::DeclareAbilitiesReq;
 use crate::api::DeclareAbilitiesRes;
+use crate::api::DisconnectReq;
+use crate::api::DisconnectRes;
+use crate::api::EraseTimiteReq;
+use crate::api::EraseTimiteRes;
+use crate::api::GetMeReq;
+use crate::api::GetMeRes;
 use crate::api::GetTimelineReq;
 use crate::api::GetTimelineRes;
+use crate::api::GetTimelineSinceReq;
+use crate::api::HealthReq;
+use crate::api::HealthRes;
+use crate::api::KickReq;
+use crate::api::KickRes;
 use crate::api::ListAbilitiesReq;
 use crate::api::ListAbilitiesRes;
+use crate::api::ListSubscribersReq;
+use crate::api::ListSubscribersRes;
 use crate::api::SendCallAbilityOutcomeReq;
 use crate::api::SendCallAbilityOutcomeRes;
 use crate::api::SendCallAbilityReq;
@@ -21,13 +36,21 @@ use crate::api::SendCallAbilityRes;
 use crate::api::SendMessageReq;
 use crate::api::SendMessageRes;
 use crate::api::Session;
+use crate::api::SetActivityReq;
+use crate::api::SetActivityRes;
 use crate::api::SpaceEvent;
+use crate::api::StreamTimelineReq;
 use crate::api::SubscribeToSpaceReq;
 use crate::api::TrustedConnectReq;
 use crate::api::TrustedConnectRes;
 use crate::api::TrustedRegisterReq;
 use crate::api::TrustedRegisterRes;
+use crate::tim_ability::TimAbilityError;
 use crate::tim_api::TimApi;
+use crate::tim_api::TimApiError;
+use crate::tim_auth::RegistrationDenied;
+use crate::tim_message::TimMessageError;
+use crate::tim_space::TimSpaceError;
 
 #[derive(Clone)]
 pub struct TimGrpcApiService {
@@ -38,6 +61,8 @@ pub struct TimGrpcApiService {
 impl TimGrpcApi for TimGrpcApiService {
     type SubscribeToSpaceStream =
         Pin<Box<dyn tokio_stream::Stream<Item = Result<SpaceEvent, Status>> + Send>>;
+    type StreamTimelineStream =
+        Pin<Box<dyn tokio_stream::Stream<Item = Result<GetTimelineRes, Status>> + Send>>;
 
     async fn trusted_register(
         &self,
@@ -47,8 +72,8 @@ impl TimGrpcApi for TimGrpcApiService {
             .api
             .trusted_register(&req.into_inner())
             .await
-            .map(|r| Response::new(r));
-        res.map_err(|e| Status::internal(e.to_string()))
+            .map(Response::new);
+        res.map_err(to_status)
     }
 
     async fn trusted_connect(
@@ -59,8 +84,8 @@ impl TimGrpcApi for TimGrpcApiService {
             .api
             .trusted_connect(&req.into_inner())
             .await
-            .map(|r| Response::new(r));
-        res.map_err(|e| Status::internal(e.to_string()))
+            .map(Response::new);
+        res.map_err(to_status)
     }
 
     async fn declare_abilities(
@@ -72,8 +97,8 @@ impl TimGrpcApi for TimGrpcApiService {
             .api
             .declare_abilities(&req.into_inner(), &session)
             .await
-            .map(|r| Response::new(r));
-        res.map_err(|e| Status::internal(e.to_string()))
+            .map(Response::new);
+        res.map_err(to_status)
     }
 
     async fn list_abilities(
@@ -81,8 +106,8 @@ impl TimGrpcApi for TimGrpcApiService {
         req: Request<ListAbilitiesReq>,
     ) -> Result<Response<ListAbilitiesRes>, Status> {
         self.require_session(&req)?;
-        let res = self.api.list_abilities().await.map(|r| Response::new(r));
-        res.map_err(|e| Status::internal(e.to_string()))
+        let res = self.api.list_abilities().await.map(Response::new);
+        res.map_err(to_status)
     }
 
     async fn get_timeline(
@@ -94,7 +119,19 @@ impl TimGrpcApi for TimGrpcApiService {
             .api
             .get_timeline(&req.into_inner(), &session)
             .map(Response::new);
-        res.map_err(|e| Status::internal(e.to_string()))
+        res.map_err(to_status)
+    }
+
+    async fn get_timeline_since(
+        &self,
+        req: Request<GetTimelineSinceReq>,
+    ) -> Result<Response<GetTimelineRes>, Status> {
+        let session = self.require_session(&req)?;
+        let res = self
+            .api
+            .get_timeline_since(&req.into_inner(), &session)
+            .map(Response::new);
+        res.map_err(to_status)
     }
 
     async fn send_message(
@@ -106,8 +143,8 @@ impl TimGrpcApi for TimGrpcApiService {
             .api
             .send_message(&req.into_inner(), &session)
             .await
-            .map(|r| Response::new(r));
-        res.map_err(|e| Status::internal(e.to_string()))
+            .map(Response::new);
+        res.map_err(to_status)
     }
 
     async fn subscribe_to_space(
@@ -119,13 +156,25 @@ impl TimGrpcApi for TimGrpcApiService {
             .api
             .subscribe(&req.into_inner(), &session)
             .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+            .map_err(to_status)?;
         Ok(Response::new(
             Box::pin(ReceiverStream::new(stream).map(Ok::<SpaceEvent, Status>))
                 as Self::SubscribeToSpaceStream,
         ))
     }
 
+    async fn stream_timeline(
+        &self,
+        req: Request<StreamTimelineReq>,
+    ) -> Result<Response<Self::StreamTimelineStream>, Status> {
+        let session = self.require_session(&req)?;
+        let chunks = self.api.stream_timeline(&req.into_inner(), &session);
+        Ok(Response::new(
+            Box::pin(ReceiverStream::new(chunks).map(|chunk| chunk.map_err(to_status)))
+                as Self::StreamTimelineStream,
+        ))
+    }
+
     async fn send_call_ability(
         &self,
         req: Request<SendCallAbilityReq>,
@@ -136,7 +185,7 @@ impl TimGrpcApi for TimGrpcApiService {
             .send_call_ability(&req.into_inner(), &session)
             .await
             .map(Response::new);
-        res.map_err(|e| Status::internal(e.to_string()))
+        res.map_err(to_status)
     }
 
     async fn send_call_ability_outcome(
@@ -149,7 +198,78 @@ impl TimGrpcApi for TimGrpcApiService {
             .send_call_ability_outcome(&req.into_inner(), &session)
             .await
             .map(Response::new);
-        res.map_err(|e| Status::internal(e.to_string()))
+        res.map_err(to_status)
+    }
+
+    // admin token is checked by the session middleware
+    async fn list_subscribers(
+        &self,
+        _req: Request<ListSubscribersReq>,
+    ) -> Result<Response<ListSubscribersRes>, Status> {
+        let res = self.api.list_subscribers().map(Response::new);
+        res.map_err(to_status)
+    }
+
+    async fn kick(&self, req: Request<KickReq>) -> Result<Response<KickRes>, Status> {
+        let res = self.api.kick(&req.into_inner()).await.map(Response::new);
+        res.map_err(to_status)
+    }
+
+    async fn erase_timite(
+        &self,
+        req: Request<EraseTimiteReq>,
+    ) -> Result<Response<EraseTimiteRes>, Status> {
+        let res = self
+            .api
+            .erase_timite(&req.into_inner())
+            .await
+            .map(Response::new);
+        res.map_err(to_status)
+    }
+
+    async fn announce(&self, req: Request<AnnounceReq>) -> Result<Response<AnnounceRes>, Status> {
+        let res = self
+            .api
+            .announce(&req.into_inner())
+            .await
+            .map(Response::new);
+        res.map_err(to_status)
+    }
+
+    async fn disconnect(
+        &self,
+        req: Request<DisconnectReq>,
+    ) -> Result<Response<DisconnectRes>, Status> {
+        let session = self.require_session(&req)?;
+        let res = self
+            .api
+            .disconnect(&req.into_inner(), &session)
+            .await
+            .map(Response::new);
+        res.map_err(to_status)
+    }
+
+    async fn set_activity(
+        &self,
+        req: Request<SetActivityReq>,
+    ) -> Result<Response<SetActivityRes>, Status> {
+        let session = self.require_session(&req)?;
+        let res = self
+            .api
+            .set_activity(&req.into_inner(), &session)
+            .await
+            .map(Response::new);
+        res.map_err(to_status)
+    }
+
+    async fn get_me(&self, req: Request<GetMeReq>) -> Result<Response<GetMeRes>, Status> {
+        let session = self.require_session(&req)?;
+        let res = self.api.get_me(&session).await.map(Response::new);
+        res.map_err(to_status)
+    }
+
+    async fn health(&self, _req: Request<HealthReq>) -> Result<Response<HealthRes>, Status> {
+        Ok(Response::new(self.api.health()))
     }
 }
 
@@ -165,3 +285,31 @@ impl TimGrpcApiService {
             .ok_or_else(|| Status::unauthenticated("No session"))
     }
 }
+
+fn to_status(err: TimApiError) -> Status {
+    match err {
+        TimApiError::InvalidArgError(_) | TimApiError::PayloadTooLarge { .. } => {
+            Status::invalid_argument(err.to_string())
+        }
+        TimApiError::TimiteNotFound(_) => Status::not_found(err.to_string()),
+        TimApiError::MessageError(TimMessageError::ReplyTargetMissing(_)) => {
+            Status::invalid_argument(err.to_string())
+        }
+        TimApiError::MessageError(TimMessageError::Rejected(_)) => {
+            Status::permission_denied(err.to_string())
+        }
+        TimApiError::RegistrationDenied(RegistrationDenied::Unauthenticated(_)) => {
+            Status::unauthenticated(err.to_string())
+        }
+        TimApiError::RegistrationDenied(RegistrationDenied::PermissionDenied(_)) => {
+            Status::permission_denied(err.to_string())
+        }
+        TimApiError::AbilityError(TimAbilityError::PermissionDenied { .. }) => {
+            Status::permission_denied(err.to_string())
+        }
+        TimApiError::SpaceError(
+            TimSpaceError::TooManySubscriptions { .. } | TimSpaceError::SpaceFull { .. },
+        ) => Status::resource_exhausted(err.to_string()),
+        _ => Status::internal(err.to_string()),
+    }
+}
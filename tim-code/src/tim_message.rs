@@ -1,15 +1,38 @@
+use std::collections::HashMap;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use tracing::warn;
+
+use crate::api::message_content::Part;
+use crate::api::AnnounceReq;
 use crate::api::Message;
+use crate::api::MessageContent;
+use crate::api::MessagePriority;
 use crate::api::SendMessageReq;
 use crate::api::Session;
+use crate::api::Timite;
+use crate::tim_clock::to_timestamp;
+use crate::tim_filter::ContentFilter;
+use crate::tim_filter::FilterVerdict;
+use crate::tim_filter::NoFilter;
+use crate::tim_filter::DEFAULT_FILTER_TIMEOUT;
+use crate::tim_filter::FLAGGED_METADATA_KEY;
 use crate::tim_space::TimSpace;
 use crate::tim_space::TimSpaceError;
+use crate::tim_space::SYSTEM_SENDER_ID;
 use crate::tim_storage::TimStorage;
 use crate::tim_storage::TimStorageError;
 
+/// Expired messages deleted per sweep, the rest wait for the next one.
+const EXPIRY_BATCH: usize = 256;
+/// Set on announcements pinned to their room.
+pub const PINNED_METADATA_KEY: &str = "tim.pinned";
+
 #[derive(Debug, thiserror::Error)]
 pub enum TimMessageError {
     #[error("Storage error")]
@@ -20,12 +43,20 @@ pub enum TimMessageError {
 
     #[error("Message {0} not found")]
     MessageMissing(u64),
+
+    #[error("Reply target message {0} not found")]
+    ReplyTargetMissing(u64),
+
+    #[error("Message rejected: {0}")]
+    Rejected(String),
 }
 
 pub struct TimMessage {
     t_store: Arc<TimStorage>,
     t_space: Arc<TimSpace>,
     msg_counter: AtomicU64,
+    filter: Arc<dyn ContentFilter>,
+    filter_timeout: Duration,
 }
 
 impl TimMessage {
@@ -35,28 +66,179 @@ impl TimMessage {
             t_store,
             t_space,
             msg_counter: AtomicU64::new(max_msg_id),
+            filter: Arc::new(NoFilter),
+            filter_timeout: DEFAULT_FILTER_TIMEOUT,
         })
     }
 
+    /// Checks every message with `filter` before it is stored. A filter that takes
+    /// longer than `timeout` lets the message through, so it can't stall sending.
+    pub fn with_filter(mut self, filter: Arc<dyn ContentFilter>, timeout: Duration) -> Self {
+        self.filter = filter;
+        self.filter_timeout = timeout;
+        self
+    }
+
     pub async fn process_message(
         &self,
         req: &SendMessageReq,
         session: &Session,
     ) -> Result<u64, TimMessageError> {
+        // Replies are rejected unless the target is already stored. Targets are therefore
+        // always older than the reply, so reply chains cannot loop.
+        if let Some(reply_to) = req.reply_to_message_id {
+            if self.t_store.fetch_message(reply_to)?.is_none() {
+                return Err(TimMessageError::ReplyTargetMissing(reply_to));
+            }
+        }
+        let content = if req.content.is_empty() {
+            plain_text(&req.parts)
+        } else {
+            req.content.to_string()
+        };
+        let expires_at = req
+            .ephemeral_ttl_secs
+            .map(|secs| to_timestamp(self.t_space.now() + Duration::from_secs(secs.into())));
+        let mut metadata = req.metadata.clone();
+        match self.check_content(&content, session).await {
+            FilterVerdict::Allow => {}
+            FilterVerdict::Reject(reason) => return Err(TimMessageError::Rejected(reason)),
+            FilterVerdict::Flag => {
+                metadata.insert(FLAGGED_METADATA_KEY.to_string(), "true".to_string());
+            }
+        }
+        // ids are taken only for accepted messages
         let msg_id = self.msg_counter.fetch_add(1, Ordering::Relaxed) + 1;
         let message = Message {
             id: msg_id,
             sender_id: session.timite_id,
-            content: req.content.to_string(),
+            content,
+            reply_to_message_id: req.reply_to_message_id,
+            metadata,
+            parts: req.parts.clone(),
+            expires_at,
+            deleted: false,
+            priority: Default::default(),
+        };
+        self.t_store.store_message(msg_id, &message)?;
+        let event_id = self.t_space.publish_message(&req.room_id, &message).await?;
+        if let Some(expires_at) = message.expires_at.as_ref() {
+            self.t_store
+                .store_message_expiry(msg_id, &req.room_id, event_id, expires_at)?;
+        }
+        Ok(msg_id)
+    }
+
+    /// Posts an operator announcement as the system sender and returns its id. The
+    /// content filter is skipped, the text comes from an admin.
+    pub async fn announce(&self, req: &AnnounceReq) -> Result<u64, TimMessageError> {
+        let priority = match req.priority() {
+            MessagePriority::Normal => MessagePriority::Announcement,
+            priority => priority,
+        };
+        let mut metadata = HashMap::new();
+        if req.pin {
+            metadata.insert(PINNED_METADATA_KEY.to_string(), "true".to_string());
+        }
+        let msg_id = self.msg_counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let message = Message {
+            id: msg_id,
+            sender_id: SYSTEM_SENDER_ID,
+            content: req.content.clone(),
+            reply_to_message_id: None,
+            metadata,
+            parts: Vec::new(),
+            expires_at: None,
+            deleted: false,
+            priority: priority.into(),
         };
         self.t_store.store_message(msg_id, &message)?;
-        self.t_space.publish_message(&message).await?;
+        self.t_space
+            .publish_announcement(&req.room_id, &message, req.pin)
+            .await?;
         Ok(msg_id)
     }
 
+    /// Deletes ephemeral messages whose time is up and tells subscribers to blank
+    /// them. Copies clients already received or paged stay with them, so expiry is
+    /// best-effort. Returns the number of messages deleted.
+    pub async fn expire_messages(&self) -> Result<usize, TimMessageError> {
+        let now = to_timestamp(self.t_space.now());
+        let mut deleted = 0;
+        for expiry in self.t_store.expired_messages(&now, EXPIRY_BATCH)? {
+            if self.t_store.tombstone_message(&expiry)? {
+                self.t_space
+                    .publish_message_deleted(&expiry.room_id, expiry.message_id)
+                    .await?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Tombstones everything `timite_id` said, see `TimStorage::erase_timite`, and
+    /// tells subscribers to blank each message. Returns the number of messages erased.
+    pub async fn erase_timite(
+        &self,
+        timite_id: u64,
+        alias: Option<&Timite>,
+    ) -> Result<usize, TimMessageError> {
+        let erased = self.t_store.erase_timite(timite_id, alias)?;
+        for message in &erased {
+            self.t_space
+                .publish_message_deleted(&message.room_id, message.message_id)
+                .await?;
+        }
+        Ok(erased.len())
+    }
+
+    /// Deletes expired messages every `interval` until `shutdown` fires.
+    pub async fn run_expiry(&self, interval: Duration, shutdown: CancellationToken) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.cancelled() => break,
+            }
+            match self.expire_messages().await {
+                Ok(deleted) if deleted > 0 => info!("Deleted {deleted} expired message(s)"),
+                Ok(_) => {}
+                Err(error) => warn!("Failed to delete expired messages: {error}"),
+            }
+        }
+    }
+
+    async fn check_content(&self, content: &str, session: &Session) -> FilterVerdict {
+        match tokio::time::timeout(self.filter_timeout, self.filter.check(content)).await {
+            Ok(verdict) => verdict,
+            Err(_) => {
+                warn!(
+                    "Content filter timed out on a message from timite {}, letting it through",
+                    session.timite_id
+                );
+                FilterVerdict::Allow
+            }
+        }
+    }
+
     pub fn find_message(&self, msg_id: u64) -> Result<Message, TimMessageError> {
         self.t_store
             .fetch_message(msg_id)?
             .ok_or(TimMessageError::MessageMissing(msg_id))
     }
 }
+
+/// Plain text rendering of message parts, for clients that only show `content`.
+pub fn plain_text(parts: &[MessageContent]) -> String {
+    parts
+        .iter()
+        .filter_map(|content| content.part.as_ref())
+        .map(|part| match part {
+            Part::Text(text) => text.clone(),
+            Part::Code(code) => format!("```{}\n{}\n```", code.lang, code.body.trim_end()),
+            Part::Link(link) if link.title.is_empty() => link.url.clone(),
+            Part::Link(link) => format!("{} <{}>", link.title, link.url),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
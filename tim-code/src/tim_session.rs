@@ -2,13 +2,11 @@ use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
 
-use chrono::DateTime;
-use chrono::Utc;
+use futures::future::ready;
 use futures::future::Either;
 use futures::future::Ready;
 use http::Request;
 use http::Response;
-use prost_types::Timestamp;
 use rand::Rng;
 use tonic::body::Body as GrpcBody;
 use tower::Layer;
@@ -20,10 +18,22 @@ use tracing::trace;
 use crate::api::ClientInfo;
 use crate::api::Session;
 use crate::api::Timite;
+use crate::tim_clock::now_timestamp;
+use crate::tim_clock::system_clock;
+use crate::tim_clock::Clock;
 use crate::tim_storage::TimStorage;
 use crate::tim_storage::TimStorageError;
 
-const SESSION_METADATA_KEY: &str = "tim-session-key";
+/// Request header carrying the session key.
+pub const SESSION_METADATA_KEY: &str = "tim-session-key";
+/// Request header carrying the admin token for admin RPCs.
+pub const ADMIN_METADATA_KEY: &str = "tim-admin-token";
+const ADMIN_PATHS: &[&str] = &[
+    "/tim.api.g1.TimGrpcApi/ListSubscribers",
+    "/tim.api.g1.TimGrpcApi/Kick",
+    "/tim.api.g1.TimGrpcApi/EraseTimite",
+    "/tim.api.g1.TimGrpcApi/Announce",
+];
 
 #[derive(Debug, thiserror::Error)]
 pub enum TimSessionError {
@@ -34,11 +44,17 @@ pub enum TimSessionError {
 #[derive(Clone)]
 pub struct TimSession {
     storage: Arc<TimStorage>,
+    clock: Arc<dyn Clock>,
 }
 
 impl TimSession {
     pub fn new(storage: Arc<TimStorage>) -> Self {
-        Self { storage }
+        Self::with_clock(storage, system_clock())
+    }
+
+    /// Sessions stamped by `clock`, for deterministic tests.
+    pub fn with_clock(storage: Arc<TimStorage>, clock: Arc<dyn Clock>) -> Self {
+        Self { storage, clock }
     }
 
     pub fn create(
@@ -47,12 +63,15 @@ impl TimSession {
         client_info: &ClientInfo,
     ) -> Result<Session, TimSessionError> {
         let key = generate_session_key();
-        let created_at = Utc::now();
         let session = Session {
             key,
             timite_id: timite.id,
-            created_at: Some(to_proto_timestamp(&created_at)),
-            client_info: Some(client_info.clone()),
+            created_at: Some(now_timestamp(self.clock.as_ref())),
+            // the token is a credential, it isn't kept with the session
+            client_info: Some(ClientInfo {
+                auth_token: String::new(),
+                ..client_info.clone()
+            }),
         };
         self.storage.store_session(&session)?;
         Ok(session)
@@ -61,16 +80,25 @@ impl TimSession {
     pub fn get(&self, session_key: &str) -> Result<Option<Session>, TimSessionError> {
         Ok(self.storage.find_session(session_key)?)
     }
+
+    pub fn revoke(&self, session_key: &str) -> Result<(), TimSessionError> {
+        Ok(self.storage.delete_session(session_key)?)
+    }
 }
 
 #[derive(Clone)]
 pub struct SessionLayer {
     sessions: Arc<TimSession>,
+    admin_token: Option<Arc<str>>,
 }
 
 impl SessionLayer {
-    pub fn new(sessions: Arc<TimSession>) -> Self {
-        Self { sessions }
+    /// Admin RPCs are rejected for everyone when `admin_token` is not configured.
+    pub fn new(sessions: Arc<TimSession>, admin_token: Option<String>) -> Self {
+        Self {
+            sessions,
+            admin_token: admin_token.map(Arc::from),
+        }
     }
 }
 
@@ -80,6 +108,7 @@ impl<S> Layer<S> for SessionLayer {
         SessionMiddleware {
             inner,
             sessions: self.sessions.clone(),
+            admin_token: self.admin_token.clone(),
         }
     }
 }
@@ -88,9 +117,10 @@ impl<S> Layer<S> for SessionLayer {
 pub struct SessionMiddleware<S> {
     inner: S,
     sessions: Arc<TimSession>,
+    admin_token: Option<Arc<str>>,
 }
 
-impl<'a, S, Body> Service<http::Request<Body>> for SessionMiddleware<S>
+impl<S, Body> Service<http::Request<Body>> for SessionMiddleware<S>
 where
     S: Service<Request<Body>, Response = Response<GrpcBody>>,
 {
@@ -105,10 +135,19 @@ where
     fn call(&mut self, mut req: http::Request<Body>) -> Self::Future {
         if req.uri().path() == "/tim.api.g1.TimGrpcApi/TrustedConnect"
             || req.uri().path() == "/tim.api.g1.TimGrpcApi/TrustedRegister"
+            || req.uri().path() == "/tim.api.g1.TimGrpcApi/Health"
         {
             return Either::Left(self.inner.call(req));
         }
 
+        if ADMIN_PATHS.contains(&req.uri().path()) {
+            if !is_admin(self.admin_token.as_deref(), &req) {
+                let status = tonic::Status::permission_denied("admin token required");
+                return Either::Right(ready(Ok(status.into_http())));
+            }
+            return Either::Left(self.inner.call(req));
+        }
+
         if let Some(session) = extract_session(&self.sessions, &req) {
             req.extensions_mut().insert(session);
         }
@@ -138,15 +177,23 @@ fn extract_session<B>(sessions: &Arc<TimSession>, req: &http::Request<B>) -> Opt
     }
 }
 
+fn is_admin<B>(admin_token: Option<&str>, req: &http::Request<B>) -> bool {
+    let Some(expected) = admin_token else {
+        return false;
+    };
+    req.headers()
+        .get(ADMIN_METADATA_KEY)
+        .is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+}
+
+/// Compares without an early exit, so the time taken doesn't tell how much of
+/// a guessed token was right. Only the length is leaked.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 fn generate_session_key() -> String {
     let mut rng = rand::thread_rng();
     let random_bytes: [u8; 32] = rng.gen();
     hex::encode(random_bytes)
 }
-
-fn to_proto_timestamp(dt: &DateTime<Utc>) -> Timestamp {
-    Timestamp {
-        seconds: dt.timestamp(),
-        nanos: dt.timestamp_subsec_nanos() as i32,
-    }
-}
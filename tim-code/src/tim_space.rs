@@ -1,72 +1,363 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
 use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::sync::RwLockWriteGuard;
+use std::time::Duration;
+use std::time::Instant;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
+use futures::stream;
+use futures::StreamExt;
 use prost_types::Timestamp;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use tracing::warn;
 
 use crate::api::space_event::Data as EventData;
 use crate::api::space_event::Metadata as EventMetadata;
+use crate::api::Activity;
 use crate::api::CallAbility;
 use crate::api::CallAbilityOutcome;
+use crate::api::DisconnectReason;
+use crate::api::EventAbilitiesChanged;
 use crate::api::EventCallAbility;
 use crate::api::EventCallAbilityOutcome;
+use crate::api::EventMessageDeleted;
 use crate::api::EventNewMessage;
+use crate::api::EventTimiteActivity;
 use crate::api::EventTimiteConnected;
 use crate::api::EventTimiteDisconnected;
 use crate::api::Message;
 use crate::api::Session;
 use crate::api::SpaceEvent;
 use crate::api::SubscribeToSpaceReq;
+use crate::api::SubscriberInfo;
 use crate::api::Timite;
+use crate::tim_clock::now_timestamp;
+use crate::tim_clock::system_clock;
+use crate::tim_clock::to_timestamp;
+use crate::tim_clock::Clock;
 use crate::tim_storage::TimStorage;
 use crate::tim_storage::TimStorageError;
 
 const BUFFER_SIZE: usize = 10;
+/// Upper bound for `replay_on_subscribe`, full history is for `get_timeline`.
+pub const MAX_REPLAY_ON_SUBSCRIBE: u32 = 1000;
+/// Sender of messages produced by the server itself.
+pub const SYSTEM_SENDER_ID: u64 = 0;
+const SESSION_KEY_PREFIX_CHARS: usize = 6;
 
 #[derive(Debug, thiserror::Error)]
 pub enum TimSpaceError {
     #[error("Lock poisoned: {0}")]
     LockPoisoned(String),
 
+    #[error("Unknown space event kind: {0}")]
+    UnknownEventKind(String),
+
     #[error("Send failed: {0}")]
-    ChannelError(#[from] SendError<SpaceEvent>),
+    ChannelError(#[from] Box<SendError<SpaceEvent>>),
 
     #[error("Timeline error: {0}")]
     Timeline(#[from] TimStorageError),
+
+    #[error("Timite {timite_id} already has {limit} open subscriptions")]
+    TooManySubscriptions { timite_id: u64, limit: usize },
+
+    #[error("Space is at its limit of {limit} connections, try again later")]
+    SpaceFull { limit: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpaceEventKind {
+    NewMessage,
+    CallAbility,
+    CallAbilityOutcome,
+    TimiteConnected,
+    TimiteDisconnected,
+    AbilitiesChanged,
+    TimiteActivity,
+    MessageDeleted,
+}
+
+impl SpaceEventKind {
+    pub const ALL: [SpaceEventKind; 8] = [
+        Self::NewMessage,
+        Self::CallAbility,
+        Self::CallAbilityOutcome,
+        Self::TimiteConnected,
+        Self::TimiteDisconnected,
+        Self::AbilitiesChanged,
+        Self::TimiteActivity,
+        Self::MessageDeleted,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NewMessage => "new_message",
+            Self::CallAbility => "call_ability",
+            Self::CallAbilityOutcome => "call_ability_outcome",
+            Self::TimiteConnected => "timite_connected",
+            Self::TimiteDisconnected => "timite_disconnected",
+            Self::AbilitiesChanged => "abilities_changed",
+            Self::TimiteActivity => "timite_activity",
+            Self::MessageDeleted => "message_deleted",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    fn of(data: &EventData) -> Self {
+        match data {
+            EventData::EventNewMessage(_) => Self::NewMessage,
+            EventData::EventCallAbility(_) => Self::CallAbility,
+            EventData::EventCallAbilityOutcome(_) => Self::CallAbilityOutcome,
+            EventData::EventTimiteConnected(_) => Self::TimiteConnected,
+            EventData::EventTimiteDisconnected(_) => Self::TimiteDisconnected,
+            EventData::EventAbilitiesChanged(_) => Self::AbilitiesChanged,
+            EventData::EventTimiteActivity(_) => Self::TimiteActivity,
+            EventData::EventMessageDeleted(_) => Self::MessageDeleted,
+        }
+    }
+}
+
+/// Which event kinds are written to the timeline; transient kinds are only broadcast.
+#[derive(Debug, Clone, Default)]
+pub struct PersistPolicy {
+    transient: HashSet<SpaceEventKind>,
+}
+
+impl PersistPolicy {
+    pub fn transient(kinds: impl IntoIterator<Item = SpaceEventKind>) -> Self {
+        Self {
+            transient: kinds.into_iter().collect(),
+        }
+    }
+
+    /// Parses a comma separated list of kinds, e.g. `timite_connected,timite_disconnected`.
+    pub fn from_transient_list(list: &str) -> Result<Self, TimSpaceError> {
+        let kinds = list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                SpaceEventKind::from_name(name)
+                    .ok_or_else(|| TimSpaceError::UnknownEventKind(name.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::transient(kinds))
+    }
+
+    /// Kinds configured as transient, in `SpaceEventKind::ALL` order.
+    pub fn transient_kinds(&self) -> Vec<SpaceEventKind> {
+        SpaceEventKind::ALL
+            .into_iter()
+            .filter(|kind| self.transient.contains(kind))
+            .collect()
+    }
+
+    pub fn persists(&self, kind: SpaceEventKind) -> bool {
+        // activity is stale by the time anyone reads the timeline
+        kind != SpaceEventKind::TimiteActivity && !self.transient.contains(&kind)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimSpaceConf {
+    pub persist: PersistPolicy,
+    /// How many subscribers an event is delivered to at once, so one slow subscriber
+    /// doesn't hold up the rest.
+    pub fanout_concurrency: usize,
+    /// Sent only to a timite's first session when it comes online.
+    pub motd: Option<String>,
+    /// A subscriber whose buffer stays full this long without taking any event is dropped.
+    pub idle_timeout: Duration,
+    /// Open subscriptions allowed per timite, 0 means unlimited.
+    pub max_subscriptions_per_timite: usize,
+    /// Open subscriptions allowed across the space, 0 means unlimited. New
+    /// registrations are refused as well while the space is full.
+    pub max_subscribers: usize,
+    /// Reaching this many open subscriptions logs a warning, 0 disables it.
+    pub subscribers_high_water: usize,
+    /// Period of the sweep for closed and backpressured subscribers.
+    pub cleanup_interval: Duration,
+    /// Stamps event emit and connect times, replaceable for deterministic tests.
+    pub clock: Arc<dyn Clock>,
+    /// Events a subscriber with a full channel may fall behind by, kept in order and
+    /// handed over as it catches up. Going past it drops the subscriber. 0 disables
+    /// the backlog: events then skip a subscriber while its channel is full.
+    pub subscriber_backlog: usize,
+    /// Declarations made within this long of each other are announced with a single
+    /// abilities changed event, sent once it has passed.
+    pub abilities_changed_delay: Duration,
+    /// Recent timeline events a new subscription starts with, ahead of live events.
+    /// 0 disables the replay, values above `MAX_REPLAY_ON_SUBSCRIBE` are clamped.
+    pub replay_on_subscribe: u32,
+    /// Leaves events older than this out of the subscribe replay.
+    pub replay_max_age: Option<Duration>,
+    /// Activity other than idle lapses back to idle when not renewed within this long,
+    /// so a client gone mid-reply doesn't leave it showing.
+    pub activity_ttl: Duration,
+}
+
+impl Default for TimSpaceConf {
+    fn default() -> Self {
+        Self {
+            persist: PersistPolicy::default(),
+            fanout_concurrency: 32,
+            motd: None,
+            idle_timeout: Duration::from_secs(60),
+            max_subscriptions_per_timite: 0,
+            max_subscribers: 0,
+            subscribers_high_water: 0,
+            cleanup_interval: Duration::from_secs(60),
+            clock: system_clock(),
+            subscriber_backlog: 0,
+            abilities_changed_delay: Duration::from_millis(200),
+            replay_on_subscribe: 0,
+            replay_max_age: None,
+            activity_ttl: Duration::from_secs(30),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct Subscriber {
     receive_own_messages: bool,
+    room: String,
     chan: mpsc::Sender<SpaceEvent>,
     session: Session,
     timite: Timite,
+    connected_at: Timestamp,
+    /// Set when a send finds the buffer full, cleared by the next delivered event.
+    full_since: Arc<Mutex<Option<Instant>>>,
+    backlog: Arc<Mutex<Backlog>>,
+    /// Id of the last event replayed on subscribe; broadcasts up to it were already sent.
+    replayed_up_to: u64,
+}
+
+/// Subscribers are kept per session and room, a session may be in several rooms.
+fn subscription_key(session_key: &str, room: &str) -> String {
+    format!("{room}/{session_key}")
+}
+
+/// Overflow of a subscriber's channel, only used with `subscriber_backlog` set.
+#[derive(Debug, Default)]
+struct Backlog {
+    events: VecDeque<SpaceEvent>,
+    /// A drain task owns the backlog; new events queue behind it to keep order.
+    draining: bool,
+}
+
+impl Subscriber {
+    fn key(&self) -> String {
+        subscription_key(&self.session.key, &self.room)
+    }
+
+    fn replayed(&self, event: &SpaceEvent) -> bool {
+        event
+            .metadata
+            .as_ref()
+            .is_some_and(|meta| meta.id <= self.replayed_up_to)
+    }
+
+    fn mark_full(&self) {
+        self.full_since().get_or_insert_with(Instant::now);
+    }
+
+    fn clear_full(&self) {
+        *self.full_since() = None;
+    }
+
+    fn full_for(&self) -> Option<Duration> {
+        self.full_since().map(|since| since.elapsed())
+    }
+
+    fn full_since(&self) -> MutexGuard<'_, Option<Instant>> {
+        // a plain timestamp can't be left half written, so a poisoned value is still good
+        self.full_since
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn backlog(&self) -> MutexGuard<'_, Backlog> {
+        // pushes and pops can't leave the queue half updated either
+        self.backlog.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Moves a subscriber's backlog into its channel as room frees up. Gives up when
+/// the channel stays full for `idle`, leaving the subscriber to the eviction sweep.
+async fn drain_backlog(sub: Subscriber, idle: Duration) {
+    loop {
+        let Ok(Ok(permit)) = timeout(idle, sub.chan.reserve()).await else {
+            return;
+        };
+        let mut backlog = sub.backlog();
+        match backlog.events.pop_front() {
+            Some(event) => permit.send(event),
+            None => {
+                backlog.draining = false;
+                drop(backlog);
+                sub.clear_full();
+                return;
+            }
+        }
+    }
 }
 
 pub struct TimSpace {
     upd_counter: AtomicU64,
     subscribers: RwLock<HashMap<String, Subscriber>>,
+    /// Timites with an abilities changed event waiting out the coalescing delay.
+    pending_ability_changes: Mutex<HashSet<u64>>,
+    /// Timites with an activity other than idle, by the id of the event that set it.
+    activities: Mutex<HashMap<u64, u64>>,
+    /// Id of the last event stored in each room, read from storage on first use.
+    room_heads: Mutex<HashMap<String, u64>>,
     storage: Arc<TimStorage>,
+    conf: TimSpaceConf,
 }
 
-fn event_new_message(upd_id: u64, message: &Message) -> SpaceEvent {
+fn event_new_message(metadata: Option<EventMetadata>, message: &Message) -> SpaceEvent {
     SpaceEvent {
-        metadata: event_metadata(upd_id),
+        metadata,
         data: Some(EventData::EventNewMessage(EventNewMessage {
             message: Some(message.clone()),
         })),
     }
 }
 
-fn event_call_ability_outcome(upd_id: u64, outcome: &CallAbilityOutcome) -> SpaceEvent {
+fn event_message_deleted(metadata: Option<EventMetadata>, message_id: u64) -> SpaceEvent {
+    SpaceEvent {
+        metadata,
+        data: Some(EventData::EventMessageDeleted(EventMessageDeleted {
+            message_id,
+        })),
+    }
+}
+
+fn event_call_ability_outcome(
+    metadata: Option<EventMetadata>,
+    outcome: &CallAbilityOutcome,
+) -> SpaceEvent {
     SpaceEvent {
-        metadata: event_metadata(upd_id),
+        metadata,
         data: Some(EventData::EventCallAbilityOutcome(
             EventCallAbilityOutcome {
                 call_ability_outcome: Some(outcome.clone()),
@@ -75,104 +366,238 @@ fn event_call_ability_outcome(upd_id: u64, outcome: &CallAbilityOutcome) -> Spac
     }
 }
 
-fn event_call_ability(upd_id: u64, call_ability: &CallAbility) -> SpaceEvent {
+fn event_call_ability(metadata: Option<EventMetadata>, call_ability: &CallAbility) -> SpaceEvent {
     SpaceEvent {
-        metadata: event_metadata(upd_id),
+        metadata,
         data: Some(EventData::EventCallAbility(EventCallAbility {
             call_ability: Some(call_ability.clone()),
         })),
     }
 }
 
-fn event_timite_connected(upd_id: u64, timite: &Timite) -> SpaceEvent {
+fn event_timite_connected(metadata: Option<EventMetadata>, timite: &Timite) -> SpaceEvent {
     SpaceEvent {
-        metadata: event_metadata(upd_id),
+        metadata,
         data: Some(EventData::EventTimiteConnected(EventTimiteConnected {
             timite: Some(timite.clone()),
         })),
     }
 }
 
-fn event_timite_disconnected(upd_id: u64, timite: &Timite) -> SpaceEvent {
+fn event_timite_disconnected(
+    metadata: Option<EventMetadata>,
+    timite: &Timite,
+    reason: DisconnectReason,
+) -> SpaceEvent {
     SpaceEvent {
-        metadata: event_metadata(upd_id),
+        metadata,
         data: Some(EventData::EventTimiteDisconnected(
             EventTimiteDisconnected {
                 timite: Some(timite.clone()),
+                reason: reason.into(),
             },
         )),
     }
 }
 
-fn now_timestamp_ms() -> Timestamp {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
-    Timestamp {
-        seconds: now.as_secs() as i64,
-        nanos: (now.subsec_millis() * 1_000_000) as i32,
+fn event_abilities_changed(metadata: Option<EventMetadata>, timite_id: u64) -> SpaceEvent {
+    SpaceEvent {
+        metadata,
+        data: Some(EventData::EventAbilitiesChanged(EventAbilitiesChanged {
+            timite_id,
+        })),
+    }
+}
+
+fn event_timite_activity(
+    metadata: Option<EventMetadata>,
+    timite_id: u64,
+    activity: Activity,
+) -> SpaceEvent {
+    SpaceEvent {
+        metadata,
+        data: Some(EventData::EventTimiteActivity(EventTimiteActivity {
+            timite_id,
+            activity: activity.into(),
+        })),
+    }
+}
+
+/// A subscriber that failed a delivery either went away or stopped reading.
+fn delivery_failure(sub: &Subscriber) -> DisconnectReason {
+    if sub.chan.is_closed() {
+        DisconnectReason::Left
+    } else {
+        DisconnectReason::TimedOut
     }
 }
 
-fn event_metadata(upd_id: u64) -> Option<EventMetadata> {
-    Some(EventMetadata {
-        id: upd_id,
-        emitted_at: Some(now_timestamp_ms()),
-    })
+fn key_prefix(key: &str) -> String {
+    key.chars().take(SESSION_KEY_PREFIX_CHARS).collect()
 }
 
 impl TimSpace {
-    pub fn new(storage: Arc<TimStorage>) -> Result<TimSpace, TimSpaceError> {
+    pub fn new(storage: Arc<TimStorage>, conf: TimSpaceConf) -> Result<TimSpace, TimSpaceError> {
         let max_event_id = storage.fetch_max_event_id()?;
         Ok(TimSpace {
             upd_counter: AtomicU64::new(max_event_id),
             subscribers: RwLock::new(HashMap::new()),
+            pending_ability_changes: Mutex::new(HashSet::new()),
+            activities: Mutex::new(HashMap::new()),
+            room_heads: Mutex::new(HashMap::new()),
             storage,
+            conf,
         })
     }
 
-    pub async fn publish_message(&self, message: &Message) -> Result<(), TimSpaceError> {
-        let upd_id = self.upd_counter.fetch_add(1, Ordering::Relaxed);
-        let event = event_new_message(upd_id, message);
-        self.storage.store_space_event(&event)?;
+    /// Publishes into `room`. Returns the id of the event, `None` when new messages
+    /// aren't persisted.
+    pub async fn publish_message(
+        &self,
+        room: &str,
+        message: &Message,
+    ) -> Result<Option<u64>, TimSpaceError> {
+        let (upd_id, event, stored) = self.emit(room, event_new_message(None, message))?;
 
         let disconnected = self
-            .broadcast_event(&event, Some(message.sender_id))
+            .broadcast_event(&event, Some(room), Some(message.sender_id))
             .await?;
-        let removed = self.prune_disconnected(disconnected);
+        let removed = self.prune_disconnected(disconnected, delivery_failure);
+        self.publish_disconnected_batch(removed).await?;
+        Ok(stored.then_some(upd_id))
+    }
+
+    /// Publishes an operator announcement. It is stored even when new messages are
+    /// transient, and with `pin` every later subscriber of the room gets it first.
+    pub async fn publish_announcement(
+        &self,
+        room: &str,
+        message: &Message,
+        pin: bool,
+    ) -> Result<u64, TimSpaceError> {
+        let (upd_id, event) = self.emit_stored(room, event_new_message(None, message))?;
+        if pin {
+            self.storage.store_pinned_event(room, &event)?;
+        }
+
+        let disconnected = self.broadcast_event(&event, Some(room), None).await?;
+        let removed = self.prune_disconnected(disconnected, delivery_failure);
+        self.publish_disconnected_batch(removed).await?;
+        Ok(upd_id)
+    }
+
+    /// Tells clients in `room` to blank message `message_id`, it was deleted.
+    pub async fn publish_message_deleted(
+        &self,
+        room: &str,
+        message_id: u64,
+    ) -> Result<(), TimSpaceError> {
+        let (_, event, _) = self.emit(room, event_message_deleted(None, message_id))?;
+
+        let disconnected = self.broadcast_event(&event, Some(room), None).await?;
+        let removed = self.prune_disconnected(disconnected, delivery_failure);
         self.publish_disconnected_batch(removed).await
     }
 
+    /// Current time of the space clock.
+    pub fn now(&self) -> SystemTime {
+        self.conf.clock.now()
+    }
+
     pub async fn subscribe(
         &self,
         req: &SubscribeToSpaceReq,
         session: &Session,
         timite: Timite,
     ) -> Result<mpsc::Receiver<SpaceEvent>, TimSpaceError> {
-        let (sender, receiver) = mpsc::channel(BUFFER_SIZE);
-        let was_present = {
-            let mut guard = self
-                .subscribers
-                .write()
-                .expect("space events subscribers lock poisoned");
+        let room = req.room_id.as_str();
+        let sub_key = subscription_key(&session.key, room);
+        let (sender, receiver, was_online, was_present) = {
+            let mut guard = self.write_subscribers();
+            // closed channels of earlier connections must not count against the limit
             guard.retain(|_, sub| !sub.chan.is_closed());
-            let present = guard
-                .values()
-                .any(|subscriber| subscriber.timite.id == timite.id);
-            guard.insert(
-                session.key.clone(),
+            // resubscribing with the same session to the same room replaces its entry;
+            // the timite never left, so neither a leave nor a join is announced
+            let replacing = guard.contains_key(&sub_key);
+            let others: Vec<&Subscriber> = guard
+                .iter()
+                .filter(|(key, sub)| sub.timite.id == timite.id && **key != sub_key)
+                .map(|(_, sub)| sub)
+                .collect();
+            let open = others.len();
+            let limit = self.conf.max_subscriptions_per_timite;
+            if limit > 0 && open >= limit {
+                return Err(TimSpaceError::TooManySubscriptions {
+                    timite_id: timite.id,
+                    limit,
+                });
+            }
+            let total = guard.len() - usize::from(guard.contains_key(&sub_key));
+            let limit = self.conf.max_subscribers;
+            if limit > 0 && total >= limit {
+                warn!(
+                    "Refused subscription of timite {}, space is full",
+                    timite.id
+                );
+                return Err(TimSpaceError::SpaceFull { limit });
+            }
+            // warned when crossing the mark only, not for every subscription above it
+            if self.conf.subscribers_high_water > 0 && total + 1 == self.conf.subscribers_high_water
+            {
+                warn!("Space reached {} open subscriptions", total + 1);
+            }
+            let online = open > 0;
+            let present = replacing || others.iter().any(|sub| sub.room == room);
+            // read under the lock so no broadcast slips in between the replay and
+            // the subscriber being registered
+            let mut replay = self.replay_window(room)?;
+            // the pinned announcement reaches late joiners whether or not the replay covers it
+            if let Some(pinned) = self.storage.fetch_pinned_event(room)? {
+                if !replay.iter().any(|event| event.metadata == pinned.metadata) {
+                    replay.insert(0, pinned);
+                }
+            }
+            // room for the whole replay, it is queued before any live event
+            let (sender, receiver) = mpsc::channel(BUFFER_SIZE + replay.len());
+            let replayed_up_to = replay
+                .iter()
+                .filter_map(|event| event.metadata.as_ref())
+                .map(|meta| meta.id)
+                .max()
+                .unwrap_or(0);
+            for event in replay {
+                let _ = sender.try_send(event);
+            }
+            let previous = guard.insert(
+                sub_key,
                 Subscriber {
                     receive_own_messages: req.receive_own_messages,
-                    chan: sender,
+                    room: room.to_string(),
+                    chan: sender.clone(),
                     session: session.clone(),
                     timite: timite.clone(),
+                    connected_at: now_timestamp(self.conf.clock.as_ref()),
+                    full_since: Arc::new(Mutex::new(None)),
+                    backlog: Arc::new(Mutex::new(Backlog::default())),
+                    replayed_up_to,
                 },
             );
-            present
+            if previous.is_some() {
+                // dropping the entry drops its sender, which ends the earlier stream
+                info!(
+                    "Session {} resubscribed to room {room:?}, closing its earlier stream",
+                    key_prefix(&session.key)
+                );
+            }
+            (sender, receiver, online, present)
         };
 
         if !was_present {
-            self.publish_timite_connected(&timite).await?;
+            self.publish_timite_connected(&timite, room).await?;
+        }
+        // once per visit, not for every room joined
+        if !was_online {
+            self.send_motd(&sender, room).await;
         }
 
         Ok(receiver)
@@ -183,12 +608,12 @@ impl TimSpace {
         outcome: &CallAbilityOutcome,
         sender_timite_id: u64,
     ) -> Result<(), TimSpaceError> {
-        let upd_id = self.upd_counter.fetch_add(1, Ordering::Relaxed);
-        let event = event_call_ability_outcome(upd_id, outcome);
-        self.storage.store_space_event(&event)?;
+        let (_, event, _) = self.emit("", event_call_ability_outcome(None, outcome))?;
 
-        let disconnected = self.broadcast_event(&event, Some(sender_timite_id)).await?;
-        let removed = self.prune_disconnected(disconnected);
+        let disconnected = self
+            .broadcast_event(&event, None, Some(sender_timite_id))
+            .await?;
+        let removed = self.prune_disconnected(disconnected, delivery_failure);
         self.publish_disconnected_batch(removed).await
     }
 
@@ -196,17 +621,107 @@ impl TimSpace {
         &self,
         call_ability: &CallAbility,
     ) -> Result<(), TimSpaceError> {
-        let upd_id = self.upd_counter.fetch_add(1, Ordering::Relaxed);
-        let event = event_call_ability(upd_id, call_ability);
-        self.storage.store_space_event(&event)?;
+        let (_, event, _) = self.emit("", event_call_ability(None, call_ability))?;
+
+        let disconnected = self.broadcast_event(&event, None, None).await?;
+        let removed = self.prune_disconnected(disconnected, delivery_failure);
+        self.publish_disconnected_batch(removed).await
+    }
+
+    /// Announces that the abilities of `timite_id` changed. Resolves after the
+    /// coalescing delay; calls for the same timite made meanwhile return right away,
+    /// as the pending event covers them.
+    pub async fn publish_abilities_changed(&self, timite_id: u64) -> Result<(), TimSpaceError> {
+        if !self.pending_ability_changes().insert(timite_id) {
+            return Ok(());
+        }
+        tokio::time::sleep(self.conf.abilities_changed_delay).await;
+        // cleared before sending, a declaration after this point gets an event of its own
+        self.pending_ability_changes().remove(&timite_id);
+
+        let (_, event, _) = self.emit("", event_abilities_changed(None, timite_id))?;
 
-        let disconnected = self.broadcast_event(&event, None).await?;
-        let removed = self.prune_disconnected(disconnected);
+        let disconnected = self.broadcast_event(&event, None, None).await?;
+        let removed = self.prune_disconnected(disconnected, delivery_failure);
         self.publish_disconnected_batch(removed).await
     }
 
-    pub fn timeline(&self, offset: u64, size: u32) -> Result<Vec<SpaceEvent>, TimSpaceError> {
-        self.storage.timeline(offset, size).map_err(Into::into)
+    /// Broadcasts the activity of `timite_id`. Returns the id of the event, which
+    /// `expire_activity` takes to tell whether the activity was renewed since.
+    pub async fn publish_activity(
+        &self,
+        timite_id: u64,
+        activity: Activity,
+    ) -> Result<u64, TimSpaceError> {
+        let (upd_id, event, _) = self.emit("", event_timite_activity(None, timite_id, activity))?;
+        if activity == Activity::Idle {
+            self.activities().remove(&timite_id);
+        } else {
+            self.activities().insert(timite_id, upd_id);
+        }
+
+        let disconnected = self.broadcast_event(&event, None, Some(timite_id)).await?;
+        let removed = self.prune_disconnected(disconnected, delivery_failure);
+        self.publish_disconnected_batch(removed).await?;
+        Ok(upd_id)
+    }
+
+    /// Waits out `activity_ttl`, then sets the activity of event `upd_id` back to
+    /// idle unless it was renewed or cleared meanwhile.
+    pub async fn expire_activity(&self, timite_id: u64, upd_id: u64) -> Result<(), TimSpaceError> {
+        tokio::time::sleep(self.conf.activity_ttl).await;
+        {
+            let mut activities = self.activities();
+            if activities.get(&timite_id) != Some(&upd_id) {
+                return Ok(());
+            }
+            activities.remove(&timite_id);
+        }
+        self.publish_activity(timite_id, Activity::Idle).await?;
+        Ok(())
+    }
+
+    pub fn timeline(
+        &self,
+        room: &str,
+        offset: u64,
+        size: u32,
+    ) -> Result<Vec<SpaceEvent>, TimSpaceError> {
+        self.storage
+            .timeline(room, offset, size)
+            .map_err(Into::into)
+    }
+
+    pub fn timeline_from(
+        &self,
+        room: &str,
+        start_id: u64,
+        size: u32,
+    ) -> Result<Vec<SpaceEvent>, TimSpaceError> {
+        self.storage
+            .timeline_from(room, start_id, size)
+            .map_err(Into::into)
+    }
+
+    pub fn timeline_since(
+        &self,
+        room: &str,
+        since: &Timestamp,
+        size: u32,
+    ) -> Result<Vec<SpaceEvent>, TimSpaceError> {
+        self.storage
+            .timeline_since(room, since, size)
+            .map_err(Into::into)
+    }
+
+    /// Id of the newest stored event of `room`.
+    pub fn last_event_id(&self, room: &str) -> Result<Option<u64>, TimSpaceError> {
+        self.storage.last_event_id(room).map_err(Into::into)
+    }
+
+    /// Highest event id stored in any room.
+    pub fn max_event_id(&self) -> Result<u64, TimSpaceError> {
+        self.storage.fetch_max_event_id().map_err(Into::into)
     }
 
     /// Periodic cleanup task that removes all disconnected subscribers
@@ -217,89 +732,430 @@ impl TimSpace {
             .filter(|sub| sub.chan.is_closed())
             .collect();
         let removed = closed.len();
-        let removed_timites = self.prune_disconnected(closed);
+        let removed_timites = self.prune_disconnected(closed, |_| DisconnectReason::Left);
         self.publish_disconnected_batch(removed_timites).await?;
         Ok(removed)
     }
 
+    /// Sweeps subscribers every `cleanup_interval` until `shutdown` fires, then
+    /// once more so presence is settled before the server exits.
+    pub async fn run_cleanup(&self, shutdown: CancellationToken) {
+        let mut interval = tokio::time::interval(self.conf.cleanup_interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => self.sweep().await,
+                _ = shutdown.cancelled() => break,
+            }
+        }
+        self.sweep().await;
+    }
+
+    async fn sweep(&self) {
+        match self.cleanup_disconnected().await {
+            Ok(removed) if removed > 0 => {
+                info!("Cleaned up {removed} disconnected subscriber(s)");
+            }
+            Ok(_) => {}
+            Err(error) => {
+                warn!("Failed to cleanup disconnected subscribers: {error}");
+            }
+        }
+        match self.evict_backpressured().await {
+            Ok(evicted) if evicted > 0 => {
+                info!("Evicted {evicted} backpressured subscriber(s)");
+            }
+            Ok(_) => {}
+            Err(error) => {
+                warn!("Failed to evict backpressured subscribers: {error}");
+            }
+        }
+    }
+
+    /// Open subscriptions; pruned and closed subscribers are not counted.
+    pub fn subscriber_count(&self) -> usize {
+        self.read_subscribers()
+            .values()
+            .filter(|sub| !sub.chan.is_closed())
+            .count()
+    }
+
+    /// Fails with `SpaceFull` when no further subscription would be accepted.
+    pub fn check_capacity(&self) -> Result<(), TimSpaceError> {
+        let limit = self.conf.max_subscribers;
+        if limit > 0 && self.subscriber_count() >= limit {
+            return Err(TimSpaceError::SpaceFull { limit });
+        }
+        Ok(())
+    }
+
+    /// Lists live subscribers; session keys are reduced to a short prefix.
+    pub fn list_subscribers(&self) -> Vec<SubscriberInfo> {
+        self.subscriber_snapshot()
+            .into_iter()
+            .filter(|sub| !sub.chan.is_closed())
+            .map(|sub| SubscriberInfo {
+                session_key_prefix: key_prefix(&sub.session.key),
+                timite: Some(sub.timite),
+                connected_at: Some(sub.connected_at),
+            })
+            .collect()
+    }
+
+    /// Drops the subscribers of the session in every room, closing their streams.
+    /// Unknown sessions are ignored.
+    pub async fn kick(&self, session_key: &str) -> Result<(), TimSpaceError> {
+        self.remove_session(session_key, DisconnectReason::Kicked)
+            .await
+    }
+
+    /// Drops the subscribers of every session of `timite_id`, announcing it as kicked
+    /// from its rooms. Returns the keys of the sessions that were subscribed.
+    pub async fn kick_timite(&self, timite_id: u64) -> Result<Vec<String>, TimSpaceError> {
+        let target: Vec<Subscriber> = self
+            .subscriber_snapshot()
+            .into_iter()
+            .filter(|sub| sub.timite.id == timite_id)
+            .collect();
+        let mut session_keys: Vec<String> =
+            target.iter().map(|sub| sub.session.key.clone()).collect();
+        session_keys.sort();
+        session_keys.dedup();
+        let removed = self.prune_disconnected(target, |_| DisconnectReason::Kicked);
+        self.publish_disconnected_batch(removed).await?;
+        Ok(session_keys)
+    }
+
+    /// Removes the subscribers of a client that is leaving on purpose. The timite is
+    /// announced as gone from a room only when none of its other sessions are in it.
+    pub async fn disconnect(&self, session: &Session) -> Result<(), TimSpaceError> {
+        self.remove_session(&session.key, DisconnectReason::Left)
+            .await
+    }
+
+    async fn remove_session(
+        &self,
+        session_key: &str,
+        reason: DisconnectReason,
+    ) -> Result<(), TimSpaceError> {
+        let target: Vec<Subscriber> = self
+            .subscriber_snapshot()
+            .into_iter()
+            .filter(|sub| sub.session.key == session_key)
+            .collect();
+        let removed = self.prune_disconnected(target, |_| reason);
+        self.publish_disconnected_batch(removed).await
+    }
+
+    /// Drops subscribers that have not taken an event for `idle_timeout` while their
+    /// buffer was full. Subscribers that read again, however slowly, are kept.
+    pub async fn evict_backpressured(&self) -> Result<usize, TimSpaceError> {
+        let stalled: Vec<Subscriber> = self
+            .subscriber_snapshot()
+            .into_iter()
+            .filter(|sub| {
+                if sub.chan.capacity() > 0 {
+                    sub.clear_full();
+                    return false;
+                }
+                sub.full_for()
+                    .is_some_and(|full_for| full_for >= self.conf.idle_timeout)
+            })
+            .collect();
+        let evicted = stalled.len();
+        let removed = self.prune_disconnected(stalled, |_| DisconnectReason::TimedOut);
+        self.publish_disconnected_batch(removed).await?;
+        Ok(evicted)
+    }
+
+    fn event_metadata(&self, upd_id: u64, room: &str) -> Option<EventMetadata> {
+        Some(EventMetadata {
+            id: upd_id,
+            emitted_at: Some(now_timestamp(self.conf.clock.as_ref())),
+            room_id: room.to_string(),
+            prev_id: None,
+        })
+    }
+
+    /// A panic while the lock was held leaves the map structurally intact, so readers
+    /// carry on with it and leave clearing the poison to the next writer.
+    fn read_subscribers(&self) -> RwLockReadGuard<'_, HashMap<String, Subscriber>> {
+        self.subscribers.read().unwrap_or_else(|poisoned| {
+            warn!("space subscribers lock poisoned, reading through it");
+            poisoned.into_inner()
+        })
+    }
+
+    fn write_subscribers(&self) -> RwLockWriteGuard<'_, HashMap<String, Subscriber>> {
+        self.subscribers.write().unwrap_or_else(|poisoned| {
+            warn!("space subscribers lock poisoned, recovering");
+            let mut guard = poisoned.into_inner();
+            // the panicking writer may have stopped before pruning what it meant to
+            guard.retain(|_, sub| !sub.chan.is_closed());
+            self.subscribers.clear_poison();
+            guard
+        })
+    }
+
+    fn pending_ability_changes(&self) -> MutexGuard<'_, HashSet<u64>> {
+        // inserts and removes of plain ids can't leave the set half updated
+        self.pending_ability_changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn activities(&self) -> MutexGuard<'_, HashMap<u64, u64>> {
+        // single inserts and removes, a poisoned map is still consistent
+        self.activities
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
     fn subscriber_snapshot(&self) -> Vec<Subscriber> {
-        let guard = self
-            .subscribers
-            .read()
-            .expect("space events subscribers lock poisoned");
-        guard.iter().map(|(_, entry)| entry.clone()).collect()
+        let guard = self.read_subscribers();
+        guard.values().cloned().collect()
     }
 
-    async fn publish_timite_connected(&self, timite: &Timite) -> Result<(), TimSpaceError> {
-        let upd_id = self.upd_counter.fetch_add(1, Ordering::Relaxed);
-        let event = event_timite_connected(upd_id, timite);
-        self.storage.store_space_event(&event)?;
-        let disconnected = self.broadcast_event(&event, None).await?;
-        let removed = self.prune_disconnected(disconnected);
+    async fn publish_timite_connected(
+        &self,
+        timite: &Timite,
+        room: &str,
+    ) -> Result<(), TimSpaceError> {
+        let (_, event, _) = self.emit(room, event_timite_connected(None, timite))?;
+        let disconnected = self.broadcast_event(&event, Some(room), None).await?;
+        let removed = self.prune_disconnected(disconnected, delivery_failure);
         self.publish_disconnected_batch(removed).await
     }
 
-    async fn publish_timite_disconnected(&self, timite: &Timite) -> Result<(), TimSpaceError> {
-        let upd_id = self.upd_counter.fetch_add(1, Ordering::Relaxed);
-        let event = event_timite_disconnected(upd_id, timite);
-        self.storage.store_space_event(&event)?;
-        let disconnected = self.broadcast_event(&event, None).await?;
-        let _ = self.prune_disconnected(disconnected);
+    async fn publish_timite_disconnected(
+        &self,
+        timite: &Timite,
+        room: &str,
+        reason: DisconnectReason,
+    ) -> Result<(), TimSpaceError> {
+        let (_, event, _) = self.emit(room, event_timite_disconnected(None, timite, reason))?;
+        let disconnected = self.broadcast_event(&event, Some(room), None).await?;
+        let _ = self.prune_disconnected(disconnected, delivery_failure);
         Ok(())
     }
 
-    fn prune_disconnected(&self, disconnected: Vec<Subscriber>) -> Vec<Timite> {
+    async fn send_motd(&self, chan: &mpsc::Sender<SpaceEvent>, room: &str) {
+        let Some(motd) = self.conf.motd.as_ref() else {
+            return;
+        };
+        let upd_id = self.upd_counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let message = Message {
+            id: 0,
+            sender_id: SYSTEM_SENDER_ID,
+            content: motd.clone(),
+            reply_to_message_id: None,
+            metadata: Default::default(),
+            parts: Vec::new(),
+            expires_at: None,
+            deleted: false,
+            priority: Default::default(),
+        };
+        // a subscriber gone already is pruned by the next broadcast
+        let _ = chan
+            .send(event_new_message(
+                self.event_metadata(upd_id, room),
+                &message,
+            ))
+            .await;
+    }
+
+    /// The last `replay_on_subscribe` events of the room's timeline within
+    /// `replay_max_age`, oldest first.
+    fn replay_window(&self, room: &str) -> Result<Vec<SpaceEvent>, TimSpaceError> {
+        let size = self.conf.replay_on_subscribe.min(MAX_REPLAY_ON_SUBSCRIBE);
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let mut events = self.storage.timeline(room, 0, size)?;
+        if let Some(max_age) = self.conf.replay_max_age {
+            let now = self.conf.clock.now();
+            let cutoff = to_timestamp(now.checked_sub(max_age).unwrap_or(UNIX_EPOCH));
+            events.retain(|event| {
+                event
+                    .metadata
+                    .as_ref()
+                    .and_then(|meta| meta.emitted_at.as_ref())
+                    .is_some_and(|at| (at.seconds, at.nanos) >= (cutoff.seconds, cutoff.nanos))
+            });
+        }
+        Ok(events)
+    }
+
+    /// Numbers `event` and stores it when its kind persists. Returns the id and
+    /// whether the event was written to the timeline.
+    fn emit(
+        &self,
+        room: &str,
+        event: SpaceEvent,
+    ) -> Result<(u64, SpaceEvent, bool), TimSpaceError> {
+        let persist = event
+            .data
+            .as_ref()
+            .is_none_or(|data| self.conf.persist.persists(SpaceEventKind::of(data)));
+        if persist {
+            let (upd_id, event) = self.emit_stored(room, event)?;
+            return Ok((upd_id, event, true));
+        }
+        let mut event = event;
+        let upd_id = self.upd_counter.fetch_add(1, Ordering::Relaxed) + 1;
+        event.metadata = self.event_metadata(upd_id, room);
+        Ok((upd_id, event, false))
+    }
+
+    /// Numbers and stores `event` whatever its kind. Both happen under the room
+    /// heads lock, so each room's `prev_id` chain follows the id order.
+    fn emit_stored(
+        &self,
+        room: &str,
+        mut event: SpaceEvent,
+    ) -> Result<(u64, SpaceEvent), TimSpaceError> {
+        let mut heads = self
+            .room_heads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let prev_id = match heads.get(room) {
+            Some(prev_id) => *prev_id,
+            None => self.storage.last_event_id(room)?.unwrap_or(0),
+        };
+        let upd_id = self.upd_counter.fetch_add(1, Ordering::Relaxed) + 1;
+        event.metadata = self
+            .event_metadata(upd_id, room)
+            .map(|metadata| EventMetadata {
+                prev_id: Some(prev_id),
+                ..metadata
+            });
+        self.storage.store_space_event(&event)?;
+        heads.insert(room.to_string(), upd_id);
+        Ok((upd_id, event))
+    }
+
+    /// Removes the subscribers and returns the timites left without any in a room,
+    /// each with the room and the reason its last session there went away.
+    fn prune_disconnected(
+        &self,
+        disconnected: Vec<Subscriber>,
+        reason: impl Fn(&Subscriber) -> DisconnectReason,
+    ) -> Vec<(Timite, String, DisconnectReason)> {
         if disconnected.is_empty() {
             return Vec::new();
         }
 
-        let mut guard = self
-            .subscribers
-            .write()
-            .expect("space events subscribers lock poisoned");
+        let mut guard = self.write_subscribers();
 
         let mut removed_timites = Vec::new();
         let mut seen = HashSet::new();
         for sub in disconnected {
-            let removed = guard.remove(&sub.session.key);
-            if removed.is_none() {
+            // the key may have been taken over by a resubscribe of the same session
+            let current = guard
+                .get(&sub.key())
+                .is_some_and(|entry| entry.chan.same_channel(&sub.chan));
+            if !current {
                 continue;
             }
-            if seen.insert(sub.timite.id)
-                && !guard
-                    .values()
-                    .any(|candidate| candidate.timite.id == sub.timite.id)
+            guard.remove(&sub.key());
+            if seen.insert((sub.timite.id, sub.room.clone()))
+                && !guard.values().any(|candidate| {
+                    candidate.timite.id == sub.timite.id && candidate.room == sub.room
+                })
             {
-                removed_timites.push(sub.timite.clone());
+                removed_timites.push((sub.timite.clone(), sub.room.clone(), reason(&sub)));
             }
         }
 
         removed_timites
     }
 
+    /// Delivers to the subscribers of `room`, or to all of them when `room` is `None`.
     async fn broadcast_event(
         &self,
         event: &SpaceEvent,
+        room: Option<&str>,
         skip_sender: Option<u64>,
     ) -> Result<Vec<Subscriber>, TimSpaceError> {
-        let snapshot = self.subscriber_snapshot();
-        let mut disconnected = Vec::new();
-        for sub in snapshot {
-            if let Some(sender_id) = skip_sender {
-                if !sub.receive_own_messages && sub.session.timite_id == sender_id {
-                    continue;
+        let recipients = self.subscriber_snapshot().into_iter().filter(|sub| {
+            room.is_none_or(|room| sub.room == room)
+                && skip_sender.is_none_or(|sender_id| {
+                    sub.receive_own_messages || sub.session.timite_id != sender_id
+                })
+        });
+        // per-subscriber order is kept by each channel, across subscribers it is not needed
+        let disconnected = stream::iter(recipients)
+            .map(|sub| async move {
+                if self.deliver(&sub, event) {
+                    None
+                } else {
+                    Some(sub)
                 }
+            })
+            .buffer_unordered(self.conf.fanout_concurrency.max(1))
+            .filter_map(|sub| async move { sub })
+            .collect()
+            .await;
+        Ok(disconnected)
+    }
+
+    /// Returns false when the subscriber is gone. A full channel skips the event and
+    /// only marks the subscriber, the cleanup sweep drops it once it stays full for
+    /// `idle_timeout`.
+    fn deliver(&self, sub: &Subscriber, event: &SpaceEvent) -> bool {
+        if sub.replayed(event) {
+            return true;
+        }
+        if self.conf.subscriber_backlog > 0 {
+            return self.deliver_backlogged(sub, event);
+        }
+        match sub.chan.try_send(event.clone()) {
+            Ok(()) => {
+                sub.clear_full();
+                true
             }
-            if sub.chan.is_closed() || sub.chan.send(event.clone()).await.is_err() {
-                disconnected.push(sub);
+            Err(TrySendError::Closed(_)) => false,
+            Err(TrySendError::Full(_)) => {
+                sub.mark_full();
+                true
+            }
+        }
+    }
+
+    /// Delivers without waiting: a full channel spills into the subscriber's backlog,
+    /// which a drain task empties in order before newer events go straight through.
+    fn deliver_backlogged(&self, sub: &Subscriber, event: &SpaceEvent) -> bool {
+        if sub.chan.is_closed() {
+            return false;
+        }
+        let mut backlog = sub.backlog();
+        if backlog.draining {
+            if backlog.events.len() >= self.conf.subscriber_backlog {
+                return false;
+            }
+            backlog.events.push_back(event.clone());
+            return true;
+        }
+        match sub.chan.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Closed(_)) => false,
+            Err(TrySendError::Full(event)) => {
+                backlog.events.push_back(event);
+                backlog.draining = true;
+                drop(backlog);
+                sub.mark_full();
+                tokio::spawn(drain_backlog(sub.clone(), self.conf.idle_timeout));
+                true
             }
         }
-        Ok(disconnected)
     }
 
-    async fn publish_disconnected_batch(&self, removed: Vec<Timite>) -> Result<(), TimSpaceError> {
-        for timite in removed {
-            self.publish_timite_disconnected(&timite).await?;
+    async fn publish_disconnected_batch(
+        &self,
+        removed: Vec<(Timite, String, DisconnectReason)>,
+    ) -> Result<(), TimSpaceError> {
+        for (timite, room, reason) in removed {
+            self.publish_timite_disconnected(&timite, &room, reason)
+                .await?;
         }
         Ok(())
     }
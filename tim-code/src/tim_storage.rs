@@ -1,7 +1,19 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use prost_types::Timestamp;
+use tim_lib::kvstore::FamilyPaths;
 use tim_lib::kvstore::KvStore;
+use tim_lib::kvstore::KvStoreConf;
 use tim_lib::kvstore::KvStoreError;
+use tim_lib::kvstore::LogBatch;
+use tim_lib::kvstore::Migration;
 use tracing::instrument;
 
+use crate::api::space_event::Data as EventData;
+use crate::api::space_event::Metadata as EventMetadata;
 use crate::api::Ability;
 use crate::api::CallAbility;
 use crate::api::Message;
@@ -9,6 +21,7 @@ use crate::api::Session;
 use crate::api::SpaceEvent;
 use crate::api::Timite;
 use crate::api::TimiteAbilities;
+use crate::storage::StoredMessageExpiry;
 use crate::storage::StoredTimiteAbilities;
 
 mod key {
@@ -46,16 +59,66 @@ mod key {
         k
     }
 
-    pub fn timeline_prefix() -> Vec<u8> {
-        b"ev:".to_vec()
+    // the default room keeps the keys from before rooms existed; room ids can't
+    // contain ':', so one room's prefix never covers another's
+    pub fn timeline_prefix(room: &str) -> Vec<u8> {
+        if room.is_empty() {
+            b"ev:".to_vec()
+        } else {
+            format!("rev:{room}:").into_bytes()
+        }
+    }
+
+    pub fn timeline_event(room: &str, id: u64) -> Vec<u8> {
+        let mut k = timeline_prefix(room);
+        k.extend(id.to_be_bytes());
+        k
+    }
+
+    // kept outside "ev:" so scans of the timeline never see index entries
+    pub fn timeline_ts_prefix(room: &str) -> Vec<u8> {
+        if room.is_empty() {
+            b"evts:".to_vec()
+        } else {
+            format!("revts:{room}:").into_bytes()
+        }
+    }
+
+    pub fn timeline_ts(room: &str, emitted_ms: u64) -> Vec<u8> {
+        let mut k = timeline_ts_prefix(room);
+        k.extend(emitted_ms.to_be_bytes());
+        k
+    }
+
+    /// Ordered by emit time first, the event id breaks ties.
+    pub fn timeline_ts_event(room: &str, emitted_ms: u64, id: u64) -> Vec<u8> {
+        let mut k = timeline_ts(room, emitted_ms);
+        k.extend(id.to_be_bytes());
+        k
+    }
+
+    /// Covers the timelines of all rooms but the default one.
+    pub fn room_timelines_prefix() -> Vec<u8> {
+        b"rev:".to_vec()
+    }
+
+    /// Ids of events outside the default room, so the greatest id is found without
+    /// visiting every room.
+    pub fn room_event_id_prefix() -> Vec<u8> {
+        b"revid:".to_vec()
     }
 
-    pub fn timeline_event(id: u64) -> Vec<u8> {
-        let mut k = timeline_prefix();
+    pub fn room_event_id(id: u64) -> Vec<u8> {
+        let mut k = room_event_id_prefix();
         k.extend(id.to_be_bytes());
         k
     }
 
+    /// The announcement pinned to a room, at most one per room.
+    pub fn pinned_event(room: &str) -> Vec<u8> {
+        format!("pin:{room}").into_bytes()
+    }
+
     pub fn message_prefix() -> Vec<u8> {
         b"msg:".to_vec()
     }
@@ -65,8 +128,31 @@ mod key {
         k.extend(id.to_be_bytes());
         k
     }
+
+    // outside "msg:" for the same reason as "evts:"
+    pub fn message_expiry_prefix() -> Vec<u8> {
+        b"msgexp:".to_vec()
+    }
+
+    /// Ordered by expiry time, so a sweep only reads entries that are due.
+    pub fn message_expiry(expires_ms: u64, id: u64) -> Vec<u8> {
+        let mut k = message_expiry_prefix();
+        k.extend(expires_ms.to_be_bytes());
+        k.extend(id.to_be_bytes());
+        k
+    }
 }
 
+/// Layout of the keys and records this build reads and writes. Bump it together with
+/// a migration in `MIGRATIONS` whenever stored data changes shape.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Stores from before the version marker already have the schema 1 layout.
+const MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    run: |_| Ok(()),
+}];
+
 #[derive(Debug, thiserror::Error)]
 pub enum TimStorageError {
     #[error("Store error: {0}")]
@@ -76,14 +162,79 @@ pub enum TimStorageError {
     Timeline(String),
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct TimStorageConf {
+    pub kv: KvStoreConf,
+    /// Space events buffered before they are written as one batch; 0 or 1 writes each event
+    /// immediately. Buffered events are lost on a crash until flushed.
+    pub event_batch_size: usize,
+    /// How often the owner should call `flush_space_events` while batching.
+    pub event_flush_interval: Duration,
+    /// Upgrades stores written by older versions on open; when off they are refused instead.
+    pub auto_migrate: bool,
+}
+
+impl Default for TimStorageConf {
+    fn default() -> Self {
+        Self {
+            kv: KvStoreConf::default(),
+            event_batch_size: 0,
+            event_flush_interval: Duration::from_millis(50),
+            auto_migrate: true,
+        }
+    }
+}
+
+impl TimStorageConf {
+    pub fn batches_events(&self) -> bool {
+        self.event_batch_size > 1
+    }
+}
+
+/// A message `TimStorage::erase_timite` tombstoned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErasedMessage {
+    pub message_id: u64,
+    /// Room the message was sent to; empty for the default room and for messages
+    /// whose event was not persisted.
+    pub room_id: String,
+}
+
 pub struct TimStorage {
     store: KvStore,
+    conf: TimStorageConf,
+    /// Whole entries are pushed and the batch is cleared only after it was written, so
+    /// a poisoned lock still guards a usable buffer.
+    pending_events: Mutex<Vec<(Vec<u8>, SpaceEvent)>>,
 }
 
 impl TimStorage {
-    pub fn new(path: &str) -> Result<TimStorage, TimStorageError> {
-        let store = KvStore::new(path)?;
-        Ok(Self { store: store })
+    pub fn new(path: &str, conf: TimStorageConf) -> Result<TimStorage, TimStorageError> {
+        Self::with_family_paths(path, &FamilyPaths::default(), conf)
+    }
+
+    /// Storage with some families outside of `path`, see `FamilyPaths`.
+    pub fn with_family_paths(
+        path: &str,
+        paths: &FamilyPaths,
+        conf: TimStorageConf,
+    ) -> Result<TimStorage, TimStorageError> {
+        let store = KvStore::with_family_paths(path, paths, conf.kv)?;
+        store.migrate(SCHEMA_VERSION, MIGRATIONS, conf.auto_migrate)?;
+        Ok(Self::with_store(store, conf))
+    }
+
+    /// Storage that lives only as long as the value, for tests.
+    pub fn in_memory(conf: TimStorageConf) -> TimStorage {
+        Self::with_store(KvStore::in_memory(), conf)
+    }
+
+    fn with_store(store: KvStore, conf: TimStorageConf) -> TimStorage {
+        Self {
+            store,
+            conf,
+            pending_events: Mutex::new(Vec::new()),
+        }
     }
 
     #[instrument(skip(self), level = "trace", fields(service = "storage"))]
@@ -96,7 +247,7 @@ impl TimStorage {
     pub fn store_timite_abilities(
         &self,
         timite_id: u64,
-        abilities: &Vec<Ability>,
+        abilities: &[Ability],
     ) -> Result<(), TimStorageError> {
         let record = StoredTimiteAbilities {
             timite_id,
@@ -139,6 +290,12 @@ impl TimStorage {
         Ok(self.store.fetch_secret::<Session>(&key::session(key))?)
     }
 
+    #[instrument(skip(self, key), level = "trace", fields(service = "storage"))]
+    pub fn delete_session(&self, key: &str) -> Result<(), TimStorageError> {
+        self.store.delete_secret(&key::session(key))?;
+        Ok(())
+    }
+
     #[instrument(skip(self), level = "trace", fields(service = "storage"))]
     pub fn fetch_max_timite_id(&self) -> Result<u64, TimStorageError> {
         let timite_opt = self.store.fetch_max_data::<Timite>(&key::timite_prefix())?;
@@ -154,6 +311,29 @@ impl TimStorage {
         Ok(self.store.fetch_data::<Timite>(&key::timite(timite_id))?)
     }
 
+    /// Replaces the room's pinned announcement with `event`.
+    #[instrument(skip(self, event), level = "trace", fields(service = "storage"))]
+    pub fn store_pinned_event(
+        &self,
+        room: &str,
+        event: &SpaceEvent,
+    ) -> Result<(), TimStorageError> {
+        self.store.store_data(&key::pinned_event(room), event)?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "trace", fields(service = "storage"))]
+    pub fn fetch_pinned_event(&self, room: &str) -> Result<Option<SpaceEvent>, TimStorageError> {
+        Ok(self
+            .store
+            .fetch_data::<SpaceEvent>(&key::pinned_event(room))?)
+    }
+
+    #[instrument(skip(self), level = "trace", fields(service = "storage"))]
+    pub fn fetch_timites(&self) -> Result<Vec<Timite>, TimStorageError> {
+        Ok(self.store.fetch_all_data::<Timite>(&key::timite_prefix())?)
+    }
+
     #[instrument(skip(self), level = "trace", fields(service = "storage"))]
     pub fn fetch_max_call_ability_id(&self) -> Result<u64, TimStorageError> {
         let record = self
@@ -202,39 +382,176 @@ impl TimStorage {
             .metadata
             .as_ref()
             .ok_or_else(|| TimStorageError::Timeline("space event missing metadata".into()))?;
-        let key = key::timeline_event(metadata.id);
-        self.store.store_log(&key, event)?;
+        let room = metadata.room_id.as_str();
+        let mut entries = vec![
+            (key::timeline_event(room, metadata.id), event.clone()),
+            (
+                key::timeline_ts_event(room, timestamp_ms(metadata), metadata.id),
+                index_entry(metadata),
+            ),
+        ];
+        if !room.is_empty() {
+            entries.push((key::room_event_id(metadata.id), index_entry(metadata)));
+        }
+        if !self.conf.batches_events() {
+            self.store.store_log_batch(&entries)?;
+            return Ok(());
+        }
+
+        // index entries don't count against the batch size
+        let full = {
+            let mut pending = self
+                .pending_events
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            pending.extend(entries);
+            let indexed = pending
+                .iter()
+                .filter(|(_, entry)| entry.data.is_some())
+                .count();
+            indexed >= self.conf.event_batch_size
+        };
+        if full {
+            self.flush_space_events()?;
+        }
+        Ok(())
+    }
+
+    /// Writes buffered space events. Reads of the timeline flush first, so an event a
+    /// subscriber was already sent is always visible to its following `timeline` call.
+    #[instrument(skip(self), level = "trace", fields(service = "storage"))]
+    pub fn flush_space_events(&self) -> Result<(), TimStorageError> {
+        // the lock is held while writing so readers can't pass an in-flight batch
+        let mut pending = self
+            .pending_events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.store.store_log_batch(&pending)?;
+        pending.clear();
         Ok(())
     }
 
+    /// Events of `room`: the latest `size` with offset 0, otherwise up to `size`
+    /// from event id `offset` on.
     #[instrument(skip(self), level = "trace", fields(service = "storage"))]
-    pub fn timeline(&self, offset: u64, size: u32) -> Result<Vec<SpaceEvent>, TimStorageError> {
+    pub fn timeline(
+        &self,
+        room: &str,
+        offset: u64,
+        size: u32,
+    ) -> Result<Vec<SpaceEvent>, TimStorageError> {
         if size == 0 {
             return Ok(Vec::new());
         }
-        let prefix = key::timeline_prefix();
+        self.flush_space_events()?;
+        let prefix = key::timeline_prefix(room);
         if offset == 0 {
-            let Some(last_event) = self.store.fetch_max_log::<SpaceEvent>(&prefix)? else {
-                return Ok(Vec::new());
-            };
-            let last_id = last_event
-                .metadata
-                .as_ref()
-                .map(|meta| meta.id)
-                .ok_or_else(|| TimStorageError::Timeline("space event missing metadata".into()))?;
-            let span = size.saturating_sub(1) as u64;
-            let start_id = last_id.saturating_sub(span);
-            let start = key::timeline_event(start_id);
-            return Ok(self
-                .store
-                .fetch_log_range::<SpaceEvent>(&prefix, &start, size as usize)?);
+            return self.latest_events(room, size as usize);
         }
-        let start = key::timeline_event(offset);
+        let start = key::timeline_event(room, offset);
         Ok(self
             .store
             .fetch_log_range::<SpaceEvent>(&prefix, &start, size as usize)?)
     }
 
+    /// The last `size` events of `room`. Ids are shared by all rooms, so a room's
+    /// ids have gaps; the window is widened until it holds enough events.
+    fn latest_events(&self, room: &str, size: usize) -> Result<Vec<SpaceEvent>, TimStorageError> {
+        let prefix = key::timeline_prefix(room);
+        let Some(last_id) = self.stored_last_event_id(room)? else {
+            return Ok(Vec::new());
+        };
+        let mut span = size as u64;
+        loop {
+            let start_id = last_id.saturating_sub(span - 1);
+            let mut events = self.store.fetch_log_range::<SpaceEvent>(
+                &prefix,
+                &key::timeline_event(room, start_id),
+                span as usize,
+            )?;
+            if events.len() >= size || start_id == 0 {
+                let excess = events.len().saturating_sub(size);
+                events.drain(..excess);
+                return Ok(events);
+            }
+            span = span.saturating_mul(2);
+        }
+    }
+
+    /// Id of the newest event of `room` still stored, `None` for an empty room.
+    #[instrument(skip(self), level = "trace", fields(service = "storage"))]
+    pub fn last_event_id(&self, room: &str) -> Result<Option<u64>, TimStorageError> {
+        self.flush_space_events()?;
+        self.stored_last_event_id(room)
+    }
+
+    fn stored_last_event_id(&self, room: &str) -> Result<Option<u64>, TimStorageError> {
+        let Some(last_event) = self
+            .store
+            .fetch_max_log::<SpaceEvent>(&key::timeline_prefix(room))?
+        else {
+            return Ok(None);
+        };
+        last_event
+            .metadata
+            .as_ref()
+            .map(|meta| Some(meta.id))
+            .ok_or_else(|| TimStorageError::Timeline("space event missing metadata".into()))
+    }
+
+    /// Up to `size` events of `room` with ids from `start_id` on, in id order.
+    #[instrument(skip(self), level = "trace", fields(service = "storage"))]
+    pub fn timeline_from(
+        &self,
+        room: &str,
+        start_id: u64,
+        size: u32,
+    ) -> Result<Vec<SpaceEvent>, TimStorageError> {
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        self.flush_space_events()?;
+        let prefix = key::timeline_prefix(room);
+        let start = key::timeline_event(room, start_id);
+        Ok(self
+            .store
+            .fetch_log_range::<SpaceEvent>(&prefix, &start, size as usize)?)
+    }
+
+    /// Up to `size` events emitted at or after `since`, ordered by emit time and then id.
+    /// Events stored before the emit time index existed are not found.
+    #[instrument(skip(self), level = "trace", fields(service = "storage"))]
+    pub fn timeline_since(
+        &self,
+        room: &str,
+        since: &Timestamp,
+        size: u32,
+    ) -> Result<Vec<SpaceEvent>, TimStorageError> {
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        self.flush_space_events()?;
+        let start = key::timeline_ts(room, to_ms(since));
+        let index = self.store.fetch_log_range::<SpaceEvent>(
+            &key::timeline_ts_prefix(room),
+            &start,
+            size as usize,
+        )?;
+        let mut events = Vec::with_capacity(index.len());
+        for entry in index {
+            let Some(metadata) = entry.metadata else {
+                continue;
+            };
+            if let Some(event) = self
+                .store
+                .fetch_log::<SpaceEvent>(&key::timeline_event(room, metadata.id))?
+            {
+                events.push(event);
+            }
+        }
+        Ok(events)
+    }
+
     #[instrument(skip(self), level = "trace", fields(service = "storage"))]
     pub fn fetch_max_message_id(&self) -> Result<u64, TimStorageError> {
         let record = self
@@ -257,14 +574,208 @@ impl TimStorage {
         Ok(record)
     }
 
+    /// Indexes `msg_id` for deletion at `expires_at`. `event_id` is the timeline
+    /// event carrying the message, when it was persisted.
     #[instrument(skip(self), level = "trace", fields(service = "storage"))]
-    pub fn fetch_max_event_id(&self) -> Result<u64, TimStorageError> {
-        let record = self
+    pub fn store_message_expiry(
+        &self,
+        msg_id: u64,
+        room: &str,
+        event_id: Option<u64>,
+        expires_at: &Timestamp,
+    ) -> Result<(), TimStorageError> {
+        let expires_ms = to_ms(expires_at);
+        let entry = StoredMessageExpiry {
+            message_id: msg_id,
+            event_id,
+            expires_ms,
+            room_id: room.to_string(),
+        };
+        self.store
+            .store_log(&key::message_expiry(expires_ms, msg_id), &entry)?;
+        Ok(())
+    }
+
+    /// Up to `size` index entries expiring at or before `now`, earliest first.
+    #[instrument(skip(self), level = "trace", fields(service = "storage"))]
+    pub fn expired_messages(
+        &self,
+        now: &Timestamp,
+        size: usize,
+    ) -> Result<Vec<StoredMessageExpiry>, TimStorageError> {
+        let now_ms = to_ms(now);
+        let prefix = key::message_expiry_prefix();
+        let mut due = self
             .store
-            .fetch_max_log::<SpaceEvent>(&key::timeline_prefix())?;
-        Ok(record
-            .and_then(|event| event.metadata)
-            .map(|meta| meta.id)
-            .unwrap_or(0))
+            .fetch_log_range::<StoredMessageExpiry>(&prefix, &prefix, size)?;
+        due.retain(|entry| entry.expires_ms <= now_ms);
+        Ok(due)
+    }
+
+    /// Tombstones every message `timite_id` sent, in the message log and in the
+    /// timeline events carrying them, and drops its abilities. With `alias` set the
+    /// timite record and its stored presence events show the alias instead. The log
+    /// writes go out in one batch. Reads the whole log, it is meant for rare admin use.
+    #[instrument(skip(self, alias), level = "trace", fields(service = "storage"))]
+    pub fn erase_timite(
+        &self,
+        timite_id: u64,
+        alias: Option<&Timite>,
+    ) -> Result<Vec<ErasedMessage>, TimStorageError> {
+        self.flush_space_events()?;
+        let mut batch = LogBatch::default();
+        let mut rooms = HashMap::new();
+        for prefix in [key::timeline_prefix(""), key::room_timelines_prefix()] {
+            for mut event in self.store.fetch_all_log::<SpaceEvent>(&prefix)? {
+                let Some(metadata) = event.metadata.clone() else {
+                    continue;
+                };
+                let changed = match event.data.as_mut() {
+                    Some(EventData::EventNewMessage(payload)) => {
+                        let erase = payload.message.as_ref().is_some_and(|message| {
+                            message.sender_id == timite_id && !message.deleted
+                        });
+                        if let Some(message) = payload.message.take() {
+                            if erase {
+                                rooms.insert(message.id, metadata.room_id.clone());
+                                payload.message = Some(tombstone(message));
+                            } else {
+                                payload.message = Some(message);
+                            }
+                        }
+                        erase
+                    }
+                    Some(EventData::EventTimiteConnected(payload)) => {
+                        rename(&mut payload.timite, alias)
+                    }
+                    Some(EventData::EventTimiteDisconnected(payload)) => {
+                        rename(&mut payload.timite, alias)
+                    }
+                    _ => false,
+                };
+                if changed {
+                    batch.put(key::timeline_event(&metadata.room_id, metadata.id), &event);
+                }
+            }
+        }
+        let mut erased = Vec::new();
+        for message in self
+            .store
+            .fetch_all_log::<Message>(&key::message_prefix())?
+        {
+            if message.sender_id != timite_id || message.deleted {
+                continue;
+            }
+            erased.push(ErasedMessage {
+                message_id: message.id,
+                room_id: rooms.remove(&message.id).unwrap_or_default(),
+            });
+            batch.put(key::message(message.id), &tombstone(message));
+        }
+        self.store.write_log_batch(batch)?;
+        self.store.delete_data(&key::timite_abilities(timite_id))?;
+        if let Some(alias) = alias {
+            self.store_timite(alias)?;
+        }
+        Ok(erased)
+    }
+
+    /// Replaces the message, and the timeline event carrying it, with a tombstone
+    /// and drops its index entry. Returns false when the message is gone already.
+    #[instrument(skip(self, expiry), level = "trace", fields(service = "storage"))]
+    pub fn tombstone_message(&self, expiry: &StoredMessageExpiry) -> Result<bool, TimStorageError> {
+        let found = match self.fetch_message(expiry.message_id)? {
+            // erased before it was due
+            Some(message) if !message.deleted => {
+                let tombstone = tombstone(message);
+                self.store
+                    .store_log(&key::message(expiry.message_id), &tombstone)?;
+                if let Some(event_id) = expiry.event_id {
+                    self.tombstone_event(&expiry.room_id, event_id, &tombstone)?;
+                }
+                true
+            }
+            _ => false,
+        };
+        self.store
+            .delete_log(&key::message_expiry(expiry.expires_ms, expiry.message_id))?;
+        Ok(found)
+    }
+
+    fn tombstone_event(
+        &self,
+        room: &str,
+        event_id: u64,
+        tombstone: &Message,
+    ) -> Result<(), TimStorageError> {
+        // the event may still sit in the write batch
+        self.flush_space_events()?;
+        let key = key::timeline_event(room, event_id);
+        let Some(mut event) = self.store.fetch_log::<SpaceEvent>(&key)? else {
+            return Ok(());
+        };
+        if let Some(EventData::EventNewMessage(payload)) = event.data.as_mut() {
+            payload.message = Some(tombstone.clone());
+            self.store.store_log(&key, &event)?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "trace", fields(service = "storage"))]
+    pub fn fetch_max_event_id(&self) -> Result<u64, TimStorageError> {
+        self.flush_space_events()?;
+        let last_id = |prefix: Vec<u8>| -> Result<u64, TimStorageError> {
+            let record = self.store.fetch_max_log::<SpaceEvent>(&prefix)?;
+            Ok(record
+                .and_then(|event| event.metadata)
+                .map(|meta| meta.id)
+                .unwrap_or(0))
+        };
+        Ok(last_id(key::timeline_prefix(""))?.max(last_id(key::room_event_id_prefix())?))
+    }
+}
+
+/// Swaps the timite of a presence event for `alias` when it is the one renamed.
+fn rename(timite: &mut Option<Timite>, alias: Option<&Timite>) -> bool {
+    match (timite.as_mut(), alias) {
+        (Some(timite), Some(alias)) if timite.id == alias.id => {
+            *timite = alias.clone();
+            true
+        }
+        _ => false,
+    }
+}
+
+fn to_ms(ts: &Timestamp) -> u64 {
+    // emit times before the epoch only come from a badly skewed clock, they sort first
+    let ms = ts.seconds.saturating_mul(1000) + i64::from(ts.nanos / 1_000_000);
+    u64::try_from(ms).unwrap_or(0)
+}
+
+/// Keeps what threads and ordering rely on, drops everything the sender wrote.
+fn tombstone(message: Message) -> Message {
+    Message {
+        content: String::new(),
+        parts: Vec::new(),
+        metadata: Default::default(),
+        deleted: true,
+        ..message
+    }
+}
+
+fn timestamp_ms(metadata: &EventMetadata) -> u64 {
+    metadata.emitted_at.as_ref().map(to_ms).unwrap_or(0)
+}
+
+/// Index entries carry only the metadata, so they batch with the events they point to.
+fn index_entry(metadata: &EventMetadata) -> SpaceEvent {
+    SpaceEvent {
+        metadata: Some(EventMetadata {
+            id: metadata.id,
+            emitted_at: metadata.emitted_at,
+            room_id: metadata.room_id.clone(),
+            prev_id: None,
+        }),
+        data: None,
     }
 }
@@ -1,9 +1,15 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
 
 use crate::api::Ability;
 use crate::api::Timite;
+use crate::api::TimiteRole;
 use crate::tim_storage::TimStorage;
 use crate::tim_storage::TimStorageError;
 
@@ -11,11 +17,77 @@ use crate::tim_storage::TimStorageError;
 pub enum TimTimiteError {
     #[error("Storage error")]
     StorageError(#[from] TimStorageError),
+
+    #[error("Invalid nick fallback: {0}")]
+    InvalidNickFallback(String),
+}
+
+/// Nick an empty registration starts from when no base is configured.
+pub const DEFAULT_FALLBACK_NICK: &str = "timite";
+
+/// What a registration gets when the nick it asks for is empty or already taken.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum NickFallback {
+    /// The nick is registered as given, duplicates and empty nicks included.
+    #[default]
+    Off,
+    /// Appends `-2`, `-3`, ... until the nick is free; empty nicks start from `base`.
+    Suffix { base: String },
+}
+
+impl NickFallback {
+    /// The first of `nick`, `nick-2`, `nick-3`, ... not in `taken`.
+    fn pick(&self, nick: &str, taken: &HashSet<String>) -> String {
+        let stem = match self {
+            NickFallback::Off => return nick.to_string(),
+            NickFallback::Suffix { base } => match nick.trim() {
+                "" => base.as_str(),
+                trimmed => trimmed,
+            },
+        };
+        std::iter::once(stem.to_string())
+            .chain((2u64..).map(|n| format!("{stem}-{n}")))
+            .find(|candidate| !taken.contains(candidate))
+            .expect("suffixes are unbounded")
+    }
+}
+
+/// Parses `off`, `suffix` or `suffix:<base>`.
+impl FromStr for NickFallback {
+    type Err = TimTimiteError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (kind, base) = match value.split_once(':') {
+            Some((kind, base)) => (kind, Some(base.trim())),
+            None => (value, None),
+        };
+        match (kind.trim(), base) {
+            ("off", None) => Ok(NickFallback::Off),
+            ("suffix", None) => Ok(NickFallback::Suffix {
+                base: DEFAULT_FALLBACK_NICK.to_string(),
+            }),
+            ("suffix", Some(base)) if !base.is_empty() => Ok(NickFallback::Suffix {
+                base: base.to_string(),
+            }),
+            _ => Err(TimTimiteError::InvalidNickFallback(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for NickFallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NickFallback::Off => write!(f, "off"),
+            NickFallback::Suffix { base } => write!(f, "suffix:{base}"),
+        }
+    }
 }
 
 pub struct TimTimite {
     t_store: Arc<TimStorage>,
     id_cnt: Arc<AtomicU64>,
+    // held from looking up the taken nicks until the new timite is stored
+    nick_lock: Mutex<()>,
 }
 
 impl TimTimite {
@@ -24,27 +96,84 @@ impl TimTimite {
         Ok(Self {
             t_store,
             id_cnt: Arc::new(AtomicU64::new(max_id)),
+            nick_lock: Mutex::new(()),
         })
     }
 
     pub fn create(&self, nick: &str) -> Result<Timite, TimTimiteError> {
+        self.create_with_role(nick, TimiteRole::Human)
+    }
+
+    /// The role is stored with the timite and not changed by later connects.
+    pub fn create_with_role(&self, nick: &str, role: TimiteRole) -> Result<Timite, TimTimiteError> {
         let id = self.id_cnt.fetch_add(1, Ordering::Relaxed) + 1;
+        let role = match role {
+            TimiteRole::Unspecified => TimiteRole::Human,
+            role => role,
+        };
         let timite = Timite {
             id,
             nick: nick.to_string(),
+            avatar_seed: avatar_seed(nick),
+            role: role.into(),
         };
         Ok(self.t_store.store_timite(&timite).map(|_| timite)?)
     }
 
+    /// Creates the timite under `nick`, or under the nick `fallback` picks when it is
+    /// empty or another timite already has it. `force` keeps the nick as given.
+    pub fn register(
+        &self,
+        nick: &str,
+        role: TimiteRole,
+        fallback: &NickFallback,
+        force: bool,
+    ) -> Result<Timite, TimTimiteError> {
+        if force || *fallback == NickFallback::Off {
+            return self.create_with_role(nick, role);
+        }
+        let _guard = self
+            .nick_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let taken: HashSet<String> = self
+            .t_store
+            .fetch_timites()?
+            .into_iter()
+            .map(|timite| timite.nick)
+            .collect();
+        self.create_with_role(&fallback.pick(nick, &taken), role)
+    }
+
     pub fn declare_abilities(
         &self,
         timite_id: u64,
-        abilities: &Vec<Ability>,
+        abilities: &[Ability],
     ) -> Result<(), TimTimiteError> {
         Ok(self.t_store.store_timite_abilities(timite_id, abilities)?)
     }
 
     pub fn get(&self, timite_id: u64) -> Result<Option<Timite>, TimTimiteError> {
-        Ok(self.t_store.fetch_timite(timite_id)?)
+        // timites stored before avatar seeds or roles existed get theirs on read
+        Ok(self.t_store.fetch_timite(timite_id)?.map(|mut timite| {
+            if timite.avatar_seed == 0 {
+                timite.avatar_seed = avatar_seed(&timite.nick);
+            }
+            if timite.role() == TimiteRole::Unspecified {
+                timite.set_role(TimiteRole::Human);
+            }
+            timite
+        }))
     }
 }
+
+/// FNV-1a of the nick, so the identicon stays the same across sessions and
+/// restarts. Never zero, zero marks a timite without a seed.
+pub fn avatar_seed(nick: &str) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    let hash = nick.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+    hash.max(1)
+}
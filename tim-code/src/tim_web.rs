@@ -0,0 +1,22 @@
+use std::path::Path;
+
+use tonic::service::Routes;
+use tower_http::services::ServeDir;
+use tower_http::services::ServeFile;
+
+/// Serves the built front-end from `root` on every path the gRPC routes don't claim,
+/// so the browser client can be loaded from the same origin as the API.
+///
+/// Missing files answer 404. With `spa` set they get `index.html` instead, leaving
+/// unknown paths to the client-side router.
+pub fn with_web_root(routes: Routes, root: impl AsRef<Path>, spa: bool) -> Routes {
+    let root = root.as_ref();
+    let files = ServeDir::new(root);
+    let router = routes.into_axum_router();
+    let router = if spa {
+        router.fallback_service(files.fallback(ServeFile::new(root.join("index.html"))))
+    } else {
+        router.fallback_service(files)
+    };
+    Routes::from(router)
+}
@@ -1,41 +1,151 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use tempfile::tempdir;
-use tempfile::TempDir;
+use hyper_util::rt::TokioIo;
+use tim_code::api::tim_grpc_api_client::TimGrpcApiClient;
+use tim_code::api::tim_grpc_api_server::TimGrpcApiServer;
+use tim_code::api::ClientInfo;
+use tim_code::api::Session;
+use tim_code::api::TrustedRegisterReq;
 use tim_code::tim_ability::TimAbility;
 use tim_code::tim_api::TimApi;
+use tim_code::tim_api::TimApiConf;
+use tim_code::tim_auth::RegistrationAuthorizer;
+use tim_code::tim_filter::ContentFilter;
+use tim_code::tim_filter::DEFAULT_FILTER_TIMEOUT;
+use tim_code::tim_grpc_api::TimGrpcApiService;
 use tim_code::tim_message::TimMessage;
+use tim_code::tim_session::SessionLayer;
 use tim_code::tim_session::TimSession;
 use tim_code::tim_space::TimSpace;
+use tim_code::tim_space::TimSpaceConf;
 use tim_code::tim_storage::TimStorage;
+use tim_code::tim_storage::TimStorageConf;
 use tim_code::tim_timite::TimTimite;
+use tonic::transport::Channel;
+use tonic::transport::Endpoint;
+use tonic::transport::Server;
+use tonic::transport::Uri;
+use tower::service_fn;
+
+#[derive(Default)]
+pub struct TimApiTestConf {
+    pub storage: TimStorageConf,
+    pub space: TimSpaceConf,
+    pub api: TimApiConf,
+    pub authorizer: Option<Arc<dyn RegistrationAuthorizer>>,
+    pub content_filter: Option<Arc<dyn ContentFilter>>,
+    /// Defaults to `DEFAULT_FILTER_TIMEOUT`.
+    pub filter_timeout: Option<Duration>,
+}
 
 pub struct TimApiTestCtx {
-    _temp_dir: TempDir,
     api: Arc<TimApi>,
+    message: Arc<TimMessage>,
+    session: Arc<TimSession>,
 }
 
 impl TimApiTestCtx {
+    #[allow(dead_code)]
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let temp_dir = tempdir()?;
-        let db_path = temp_dir.path().join("kv");
-        let db_path = db_path.to_string_lossy().to_string();
+        Self::with_conf(TimApiTestConf::default())
+    }
 
-        let storage = Arc::new(TimStorage::new(&db_path)?);
+    pub fn with_conf(conf: TimApiTestConf) -> Result<Self, Box<dyn std::error::Error>> {
+        let storage = Arc::new(TimStorage::in_memory(conf.storage));
         let session = Arc::new(TimSession::new(storage.clone()));
-        let space = Arc::new(TimSpace::new(storage.clone())?);
+        let space = Arc::new(TimSpace::new(storage.clone(), conf.space)?);
         let timite = Arc::new(TimTimite::new(storage.clone())?);
         let ability = Arc::new(TimAbility::new(storage.clone(), space.clone())?);
-        let message = Arc::new(TimMessage::new(storage.clone(), space.clone())?);
-        let api = Arc::new(TimApi::new(session, space, timite, ability, message));
+        let mut message = TimMessage::new(storage.clone(), space.clone())?;
+        if let Some(filter) = conf.content_filter {
+            let timeout = conf.filter_timeout.unwrap_or(DEFAULT_FILTER_TIMEOUT);
+            message = message.with_filter(filter, timeout);
+        }
+        let message = Arc::new(message);
+        let mut api = TimApi::new(
+            session.clone(),
+            space,
+            timite,
+            ability,
+            message.clone(),
+            conf.api,
+        );
+        if let Some(authorizer) = conf.authorizer {
+            api = api.with_authorizer(authorizer);
+        }
+        let api = Arc::new(api);
 
         Ok(Self {
-            _temp_dir: temp_dir,
             api,
+            message,
+            session,
         })
     }
 
+    #[allow(dead_code)]
     pub fn api(&self) -> Arc<TimApi> {
         self.api.clone()
     }
+
+    #[allow(dead_code)]
+    pub fn message(&self) -> Arc<TimMessage> {
+        self.message.clone()
+    }
+
+    /// Serves the API over an in-memory connection, behind the same session
+    /// middleware as the server binary, and returns a client for it. No port is
+    /// bound; each call gets a server of its own over the shared state.
+    #[allow(dead_code)]
+    pub async fn grpc_client(
+        &self,
+        admin_token: Option<&str>,
+    ) -> Result<TimGrpcApiClient<Channel>, Box<dyn std::error::Error>> {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let service = TimGrpcApiServer::new(TimGrpcApiService::new(self.api.clone()));
+        let layer = SessionLayer::new(self.session.clone(), admin_token.map(str::to_string));
+        tokio::spawn(async move {
+            let _ = Server::builder()
+                .layer(layer)
+                .add_service(service)
+                .serve_with_incoming(tokio_stream::once(Ok::<_, std::io::Error>(server_io)))
+                .await;
+        });
+
+        // the connector hands out the one in-memory stream, the URI is never dialed
+        let mut client_io = Some(client_io);
+        let channel = Endpoint::try_from("http://in-memory")?
+            .connect_with_connector(service_fn(move |_: Uri| {
+                let io = client_io.take();
+                async move {
+                    io.map(TokioIo::new)
+                        .ok_or_else(|| std::io::Error::other("in-memory connection already used"))
+                }
+            }))
+            .await?;
+        Ok(TimGrpcApiClient::new(channel))
+    }
+}
+
+#[allow(dead_code)]
+pub fn client_info() -> ClientInfo {
+    ClientInfo {
+        platform: "test".into(),
+        auth_token: String::new(),
+    }
+}
+
+/// Registers `nick` as a human timite and returns its session.
+#[allow(dead_code)]
+pub async fn register(api: &TimApi, nick: &str) -> Result<Session, Box<dyn std::error::Error>> {
+    Ok(api
+        .trusted_register(&TrustedRegisterReq {
+            nick: nick.into(),
+            client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
+        })
+        .await?
+        .session
+        .expect("missing session"))
 }
@@ -18,6 +18,7 @@ use tokio::time::timeout;
 fn client_info() -> ClientInfo {
     ClientInfo {
         platform: "cli-test".into(),
+        auth_token: String::new(),
     }
 }
 
@@ -27,11 +28,13 @@ fn sample_abilities() -> Vec<Ability> {
             name: "echo".into(),
             description: "Echo input back to the caller".into(),
             params: Vec::new(),
+            allowed_caller_ids: Vec::new(),
         },
         Ability {
             name: "ping".into(),
             description: "Health check signal".into(),
             params: Vec::new(),
+            allowed_caller_ids: Vec::new(),
         },
     ]
 }
@@ -45,6 +48,8 @@ async fn tim_api_flow_abilities_list_declared_skills() -> Result<(), Box<dyn std
         .trusted_register(&TrustedRegisterReq {
             nick: "alpha".into(),
             client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
         })
         .await?
         .session
@@ -97,6 +102,8 @@ async fn tim_api_flow_abilities_call_cycle() -> Result<(), Box<dyn std::error::E
         .trusted_register(&TrustedRegisterReq {
             nick: "alpha".into(),
             client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
         })
         .await?
         .session
@@ -114,6 +121,7 @@ async fn tim_api_flow_abilities_call_cycle() -> Result<(), Box<dyn std::error::E
         .subscribe(
             &SubscribeToSpaceReq {
                 receive_own_messages: false,
+                room_id: String::new(),
             },
             &alpha_session,
         )
@@ -123,6 +131,8 @@ async fn tim_api_flow_abilities_call_cycle() -> Result<(), Box<dyn std::error::E
         .trusted_register(&TrustedRegisterReq {
             nick: "beta".into(),
             client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
         })
         .await?
         .session
@@ -161,7 +171,8 @@ async fn tim_api_flow_abilities_call_cycle() -> Result<(), Box<dyn std::error::E
                 break event.call_ability.expect("missing call ability payload");
             }
             Some(space_event::Data::EventTimiteConnected(_))
-            | Some(space_event::Data::EventTimiteDisconnected(_)) => continue,
+            | Some(space_event::Data::EventTimiteDisconnected(_))
+            | Some(space_event::Data::EventAbilitiesChanged(_)) => continue,
             other => panic!("unexpected alpha event event: {:?}", other),
         };
     };
@@ -186,6 +197,7 @@ async fn tim_api_flow_abilities_call_cycle() -> Result<(), Box<dyn std::error::E
         .subscribe(
             &SubscribeToSpaceReq {
                 receive_own_messages: false,
+                room_id: String::new(),
             },
             &beta_session,
         )
@@ -198,6 +210,8 @@ async fn tim_api_flow_abilities_call_cycle() -> Result<(), Box<dyn std::error::E
                 call_ability_id,
                 payload: Some(outcome_payload.into()),
                 error: None,
+                structured_payload: None,
+                status: Default::default(),
             }),
         },
         &alpha_session,
@@ -216,7 +230,8 @@ async fn tim_api_flow_abilities_call_cycle() -> Result<(), Box<dyn std::error::E
                     .expect("missing call ability outcome payload");
             }
             Some(space_event::Data::EventTimiteConnected(_))
-            | Some(space_event::Data::EventTimiteDisconnected(_)) => continue,
+            | Some(space_event::Data::EventTimiteDisconnected(_))
+            | Some(space_event::Data::EventAbilitiesChanged(_)) => continue,
             other => panic!("unexpected beta event event: {:?}", other),
         };
     };
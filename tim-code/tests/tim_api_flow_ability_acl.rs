@@ -0,0 +1,72 @@
+mod common;
+
+use common::register;
+use common::TimApiTestCtx;
+use tim_code::api::Ability;
+use tim_code::api::CallAbility;
+use tim_code::api::DeclareAbilitiesReq;
+use tim_code::api::SendCallAbilityReq;
+use tim_code::api::Session;
+use tim_code::tim_ability::TimAbilityError;
+use tim_code::tim_api::TimApi;
+use tim_code::tim_api::TimApiError;
+
+const ABILITY: &str = "admin.reset";
+
+async fn call(api: &TimApi, owner: &Session, caller: &Session) -> Result<u64, TimApiError> {
+    api.send_call_ability(
+        &SendCallAbilityReq {
+            call_ability: Some(CallAbility {
+                timite_id: owner.timite_id,
+                sender_id: caller.timite_id,
+                name: ABILITY.into(),
+                payload: String::new(),
+                call_ability_id: None,
+            }),
+        },
+        caller,
+    )
+    .await
+    .map(|res| res.call_ability_id)
+}
+
+#[tokio::test]
+async fn ability_acl_allows_listed_callers_only() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let alpha = register(&api, "alpha").await?;
+    let beta = register(&api, "beta").await?;
+    let gamma = register(&api, "gamma").await?;
+
+    api.declare_abilities(
+        &DeclareAbilitiesReq {
+            abilities: vec![Ability {
+                name: ABILITY.into(),
+                description: "Admin only".into(),
+                params: Vec::new(),
+                allowed_caller_ids: vec![beta.timite_id],
+            }],
+        },
+        &alpha,
+    )
+    .await?;
+
+    let allowed_id = call(&api, &alpha, &beta).await?;
+
+    let denied = call(&api, &alpha, &gamma).await;
+    assert!(matches!(
+        denied,
+        Err(TimApiError::AbilityError(TimAbilityError::PermissionDenied { caller_id, .. }))
+            if caller_id == gamma.timite_id
+    ));
+
+    let owner_id = call(&api, &alpha, &alpha).await?;
+    assert_eq!(
+        owner_id,
+        allowed_id + 1,
+        "rejected calls must not consume call ability ids"
+    );
+
+    Ok(())
+}
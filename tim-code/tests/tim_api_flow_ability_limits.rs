@@ -0,0 +1,97 @@
+mod common;
+
+use common::register;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::Ability;
+use tim_code::api::AbilityParameter;
+use tim_code::api::DeclareAbilitiesReq;
+use tim_code::tim_api::TimApiConf;
+use tim_code::tim_api::TimApiError;
+
+const MAX_ABILITIES: usize = 3;
+const MAX_BYTES: usize = 64;
+
+fn ability(name: &str, description: &str) -> Ability {
+    Ability {
+        name: name.into(),
+        description: description.into(),
+        params: Vec::new(),
+        allowed_caller_ids: Vec::new(),
+    }
+}
+
+fn declare(abilities: Vec<Ability>) -> DeclareAbilitiesReq {
+    DeclareAbilitiesReq { abilities }
+}
+
+#[tokio::test]
+async fn ability_count_and_size_are_capped() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::with_conf(TimApiTestConf {
+        api: TimApiConf {
+            max_abilities_per_timite: MAX_ABILITIES,
+            max_abilities_bytes: MAX_BYTES,
+            ..Default::default()
+        },
+        ..Default::default()
+    })?;
+    let api = ctx.api();
+
+    let session = register(&api, "crawler").await?;
+
+    let at_limit = (0..MAX_ABILITIES)
+        .map(|i| ability(&format!("a.{i}"), "x"))
+        .collect::<Vec<_>>();
+    api.declare_abilities(&declare(at_limit.clone()), &session)
+        .await?;
+
+    let mut over_limit = at_limit;
+    over_limit.push(ability("a.extra", "x"));
+    let err = api
+        .declare_abilities(&declare(over_limit), &session)
+        .await
+        .expect_err("one ability over the limit must be rejected");
+    assert!(matches!(err, TimApiError::InvalidArgError(_)));
+    assert!(err.to_string().contains(&MAX_ABILITIES.to_string()));
+
+    // the rejected declaration leaves the previous set in place
+    let listed = api.list_abilities().await?.abilities;
+    let declared = listed
+        .iter()
+        .find(|entry| entry.timite.as_ref().map(|t| t.id) == Some(session.timite_id))
+        .expect("abilities of the timite");
+    assert_eq!(declared.abilities.len(), MAX_ABILITIES);
+
+    // name, description and params all count, 64 bytes exactly is allowed
+    let mut exact = ability("web.crawl", &"d".repeat(MAX_BYTES - 9 - 8));
+    exact.params.push(AbilityParameter {
+        name: "url".into(),
+        description: "link".into(),
+    });
+    assert_eq!(
+        exact.name.len()
+            + exact.description.len()
+            + exact.params[0].name.len()
+            + exact.params[0].description.len(),
+        MAX_BYTES - 1
+    );
+    exact.description.push('d');
+    api.declare_abilities(&declare(vec![exact.clone()]), &session)
+        .await?;
+
+    exact.params[0].description.push('!');
+    let err = api
+        .declare_abilities(&declare(vec![exact]), &session)
+        .await
+        .expect_err("one byte over the limit must be rejected");
+    assert!(matches!(
+        err,
+        TimApiError::PayloadTooLarge {
+            limit: MAX_BYTES,
+            actual,
+            ..
+        } if actual == MAX_BYTES + 1
+    ));
+
+    Ok(())
+}
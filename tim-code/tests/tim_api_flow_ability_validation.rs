@@ -0,0 +1,76 @@
+mod common;
+
+use common::register;
+use common::TimApiTestCtx;
+use tim_code::api::Ability;
+use tim_code::api::AbilityParameter;
+use tim_code::api::DeclareAbilitiesReq;
+use tim_code::tim_api::TimApiError;
+
+fn ability(name: &str, params: &[&str]) -> Ability {
+    Ability {
+        name: name.into(),
+        description: "does things".into(),
+        params: params
+            .iter()
+            .map(|name| AbilityParameter {
+                name: name.to_string(),
+                description: String::new(),
+            })
+            .collect(),
+        allowed_caller_ids: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn malformed_ability_names_are_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let session = register(&api, "crawler").await?;
+
+    let cases = [
+        (vec![ability("  ", &[])], "empty name"),
+        (
+            vec![ability("search", &[]), ability(" search ", &[])],
+            "search is declared more than once",
+        ),
+        (vec![ability("search", &["query", ""])], "empty name"),
+        (
+            vec![ability("search", &["query", "query "])],
+            "parameter query",
+        ),
+    ];
+    for (abilities, expected) in cases {
+        let err = api
+            .declare_abilities(&DeclareAbilitiesReq { abilities }, &session)
+            .await
+            .expect_err("declaration must be rejected");
+        assert!(matches!(err, TimApiError::InvalidArgError(_)));
+        assert!(
+            err.to_string().contains(expected),
+            "unexpected error: {err}"
+        );
+    }
+    let listed = api.list_abilities().await?.abilities;
+    assert!(listed.iter().all(|entry| entry.abilities.is_empty()));
+
+    // accepted names are stored trimmed
+    api.declare_abilities(
+        &DeclareAbilitiesReq {
+            abilities: vec![ability(" search ", &[" query"])],
+        },
+        &session,
+    )
+    .await?;
+    let listed = api.list_abilities().await?.abilities;
+    let declared = listed
+        .iter()
+        .find(|entry| entry.timite.as_ref().map(|t| t.id) == Some(session.timite_id))
+        .and_then(|entry| entry.abilities.first())
+        .expect("declared ability");
+    assert_eq!(declared.name, "search");
+    assert_eq!(declared.params[0].name, "query");
+
+    Ok(())
+}
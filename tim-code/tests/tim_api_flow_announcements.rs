@@ -0,0 +1,165 @@
+mod common;
+
+use common::register;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::space_event;
+use tim_code::api::AnnounceReq;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::Message;
+use tim_code::api::MessagePriority;
+use tim_code::api::Session;
+use tim_code::api::SpaceEvent;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::tim_api::TimApi;
+use tim_code::tim_api::TimApiError;
+use tim_code::tim_message::PINNED_METADATA_KEY;
+use tim_code::tim_space::PersistPolicy;
+use tim_code::tim_space::SpaceEventKind;
+use tim_code::tim_space::TimSpaceConf;
+use tim_code::tim_space::SYSTEM_SENDER_ID;
+
+fn announcement(content: &str, priority: MessagePriority, pin: bool) -> AnnounceReq {
+    AnnounceReq {
+        content: content.into(),
+        room_id: String::new(),
+        priority: priority.into(),
+        pin,
+    }
+}
+
+fn message(event: &SpaceEvent) -> Option<&Message> {
+    match &event.data {
+        Some(space_event::Data::EventNewMessage(payload)) => payload.message.as_ref(),
+        _ => None,
+    }
+}
+
+fn timeline_messages(
+    api: &TimApi,
+    session: &Session,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let timeline = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 100,
+            room_id: String::new(),
+        },
+        session,
+    )?;
+    Ok(timeline
+        .events
+        .iter()
+        .filter_map(message)
+        .cloned()
+        .collect())
+}
+
+#[tokio::test]
+async fn announcements_are_stored_as_system_messages() -> Result<(), Box<dyn std::error::Error>> {
+    // announcements are kept even where plain messages are not
+    let ctx = TimApiTestCtx::with_conf(TimApiTestConf {
+        space: TimSpaceConf {
+            persist: PersistPolicy::transient([SpaceEventKind::NewMessage]),
+            ..Default::default()
+        },
+        ..Default::default()
+    })?;
+    let api = ctx.api();
+
+    let maintenance = api
+        .announce(&announcement(
+            "maintenance at noon",
+            MessagePriority::Normal,
+            false,
+        ))
+        .await?;
+    let outage = api
+        .announce(&announcement(
+            "db is degraded",
+            MessagePriority::High,
+            false,
+        ))
+        .await?;
+
+    let session = register(&api, "alpha").await?;
+    let stored = timeline_messages(&api, &session)?;
+    let priorities: Vec<_> = stored
+        .iter()
+        .map(|message| (message.id, message.sender_id, message.priority()))
+        .collect();
+    assert_eq!(
+        priorities,
+        [
+            (
+                maintenance.message_id,
+                SYSTEM_SENDER_ID,
+                MessagePriority::Announcement
+            ),
+            (outage.message_id, SYSTEM_SENDER_ID, MessagePriority::High),
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pinned_announcement_reaches_late_joiners() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    api.announce(&announcement(
+        "old news",
+        MessagePriority::Announcement,
+        true,
+    ))
+    .await?;
+    let pinned = api
+        .announce(&announcement(
+            "read the rules",
+            MessagePriority::Announcement,
+            true,
+        ))
+        .await?;
+    api.announce(&announcement(
+        "not pinned",
+        MessagePriority::Announcement,
+        false,
+    ))
+    .await?;
+
+    // nothing is replayed on subscribe by default, the pin comes anyway
+    let session = register(&api, "alpha").await?;
+    let mut events = api
+        .subscribe(
+            &SubscribeToSpaceReq {
+                receive_own_messages: false,
+                room_id: String::new(),
+            },
+            &session,
+        )
+        .await?;
+    let mut received = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        received.extend(message(&event).cloned());
+    }
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].id, pinned.message_id);
+    assert_eq!(received[0].content, "read the rules");
+    assert!(received[0].metadata.contains_key(PINNED_METADATA_KEY));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn empty_announcement_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let res = api
+        .announce(&announcement("  ", MessagePriority::Announcement, false))
+        .await;
+    assert!(matches!(res, Err(TimApiError::InvalidArgError(_))));
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::client_info;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::Session;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::api::TrustedRegisterReq;
+use tim_code::tim_api::TimApi;
+use tim_code::tim_api::TimApiError;
+use tim_code::tim_space::TimSpaceConf;
+use tim_code::tim_space::TimSpaceError;
+
+async fn register(api: &TimApi, nick: &str) -> Result<Session, TimApiError> {
+    Ok(api
+        .trusted_register(&TrustedRegisterReq {
+            nick: nick.into(),
+            client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
+        })
+        .await?
+        .session
+        .expect("missing session"))
+}
+
+fn req() -> SubscribeToSpaceReq {
+    SubscribeToSpaceReq {
+        receive_own_messages: false,
+        room_id: String::new(),
+    }
+}
+
+#[tokio::test]
+async fn full_space_refuses_and_recovers() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::with_conf(TimApiTestConf {
+        space: TimSpaceConf {
+            max_subscribers: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    })?;
+    let api = ctx.api();
+
+    let alpha = register(&api, "alpha").await?;
+    let beta = register(&api, "beta").await?;
+    let gamma = register(&api, "gamma").await?;
+    let alpha_events = api.subscribe(&req(), &alpha).await?;
+    let _beta_events = api.subscribe(&req(), &beta).await?;
+    assert_eq!(api.health().subscribers, 2);
+
+    let res = api.subscribe(&req(), &gamma).await;
+    assert!(matches!(
+        res,
+        Err(TimApiError::SpaceError(TimSpaceError::SpaceFull {
+            limit: 2
+        }))
+    ));
+    let res = register(&api, "delta").await;
+    assert!(matches!(
+        res,
+        Err(TimApiError::SpaceError(TimSpaceError::SpaceFull { .. }))
+    ));
+    // resubscribing an open session replaces it and doesn't need a free slot
+    let _beta_events = api.subscribe(&req(), &beta).await?;
+
+    drop(alpha_events);
+    assert_eq!(api.health().subscribers, 1);
+    let _gamma_events = api.subscribe(&req(), &gamma).await?;
+    assert_eq!(api.health().subscribers, 2);
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+mod common;
+
+use async_trait::async_trait;
+use common::register;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::space_event;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::Message;
+use tim_code::api::SendMessageReq;
+use tim_code::api::Session;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::tim_api::TimApi;
+use tim_code::tim_api::TimApiError;
+use tim_code::tim_filter::ContentFilter;
+use tim_code::tim_filter::FilterVerdict;
+use tim_code::tim_filter::FLAGGED_METADATA_KEY;
+use tim_code::tim_message::TimMessageError;
+use tokio::time::timeout;
+
+async fn send(api: &TimApi, session: &Session, content: &str) -> Result<(), TimApiError> {
+    api.send_message(
+        &SendMessageReq {
+            content: content.into(),
+            reply_to_message_id: None,
+            metadata: Default::default(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: String::new(),
+        },
+        session,
+    )
+    .await
+    .map(|_| ())
+}
+
+fn timeline_messages(
+    api: &TimApi,
+    session: &Session,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let timeline = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 10,
+            room_id: String::new(),
+        },
+        session,
+    )?;
+    Ok(timeline
+        .events
+        .into_iter()
+        .filter_map(|event| match event.data {
+            Some(space_event::Data::EventNewMessage(payload)) => payload.message,
+            _ => None,
+        })
+        .collect())
+}
+
+// Rejects "spam", flags "maybe" and takes its time on "slow".
+struct TestFilter;
+
+#[async_trait]
+impl ContentFilter for TestFilter {
+    async fn check(&self, content: &str) -> FilterVerdict {
+        if content.contains("spam") {
+            FilterVerdict::Reject("no spam".into())
+        } else if content.contains("maybe") {
+            FilterVerdict::Flag
+        } else if content.contains("slow") {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            FilterVerdict::Reject("too late".into())
+        } else {
+            FilterVerdict::Allow
+        }
+    }
+}
+
+fn filtered_ctx(
+    filter_timeout: Option<Duration>,
+) -> Result<TimApiTestCtx, Box<dyn std::error::Error>> {
+    TimApiTestCtx::with_conf(TimApiTestConf {
+        content_filter: Some(Arc::new(TestFilter)),
+        filter_timeout,
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn filter_allows_rejects_and_flags() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = filtered_ctx(None)?;
+    let api = ctx.api();
+
+    let sender = register(&api, "alpha").await?;
+    let watcher = register(&api, "beta").await?;
+    let mut events = api
+        .subscribe(
+            &SubscribeToSpaceReq {
+                receive_own_messages: false,
+                room_id: String::new(),
+            },
+            &watcher,
+        )
+        .await?;
+
+    let res = send(&api, &sender, "buy spam now").await;
+    assert!(matches!(
+        res,
+        Err(TimApiError::MessageError(TimMessageError::Rejected(reason))) if reason == "no spam"
+    ));
+    send(&api, &sender, "hello").await?;
+    send(&api, &sender, "maybe later").await?;
+
+    let mut broadcast = Vec::new();
+    while broadcast.len() < 2 {
+        let event = timeout(Duration::from_secs(1), events.recv())
+            .await?
+            .expect("subscriber should receive an event");
+        if let Some(space_event::Data::EventNewMessage(payload)) = event.data {
+            broadcast.push(payload.message.expect("message missing"));
+        }
+    }
+    // the rejected message never reached the subscriber
+    assert_eq!(broadcast[0].content, "hello");
+    assert!(!broadcast[0].metadata.contains_key(FLAGGED_METADATA_KEY));
+    assert_eq!(broadcast[1].content, "maybe later");
+    assert_eq!(
+        broadcast[1]
+            .metadata
+            .get(FLAGGED_METADATA_KEY)
+            .map(String::as_str),
+        Some("true")
+    );
+
+    let stored = timeline_messages(&api, &sender)?;
+    let contents: Vec<_> = stored.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["hello", "maybe later"]);
+    assert!(stored[1].metadata.contains_key(FLAGGED_METADATA_KEY));
+    // rejected messages don't take an id
+    assert_eq!(stored[1].id, stored[0].id + 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn slow_filter_does_not_stall_sending() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = filtered_ctx(Some(Duration::from_millis(50)))?;
+    let api = ctx.api();
+    let sender = register(&api, "alpha").await?;
+
+    let started = Instant::now();
+    send(&api, &sender, "slow down").await?;
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    let stored = timeline_messages(&api, &sender)?;
+    assert_eq!(stored.len(), 1);
+    assert!(stored[0].metadata.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use common::register;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::space_event;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::Message;
+use tim_code::api::SendMessageReq;
+use tim_code::api::Session;
+use tim_code::api::SpaceEvent;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::tim_api::TimApi;
+use tim_code::tim_api::TimApiError;
+use tim_code::tim_clock::Clock;
+use tim_code::tim_space::TimSpaceConf;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+#[derive(Debug)]
+struct ManualClock(Mutex<SystemTime>);
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+impl ManualClock {
+    fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+async fn send(
+    api: &TimApi,
+    session: &Session,
+    content: &str,
+    ephemeral_ttl_secs: Option<u32>,
+) -> Result<(), TimApiError> {
+    api.send_message(
+        &SendMessageReq {
+            content: content.into(),
+            reply_to_message_id: None,
+            metadata: Default::default(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs,
+            room_id: String::new(),
+        },
+        session,
+    )
+    .await
+    .map(|_| ())
+}
+
+fn timeline_messages(
+    api: &TimApi,
+    session: &Session,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let timeline = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 20,
+            room_id: String::new(),
+        },
+        session,
+    )?;
+    Ok(timeline
+        .events
+        .into_iter()
+        .filter_map(|event| match event.data {
+            Some(space_event::Data::EventNewMessage(payload)) => payload.message,
+            _ => None,
+        })
+        .collect())
+}
+
+async fn next_matching<T>(
+    events: &mut mpsc::Receiver<SpaceEvent>,
+    pick: impl Fn(space_event::Data) -> Option<T>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    loop {
+        let event = timeout(Duration::from_secs(1), events.recv())
+            .await?
+            .expect("subscriber should receive an event");
+        if let Some(found) = event.data.and_then(&pick) {
+            return Ok(found);
+        }
+    }
+}
+
+#[tokio::test]
+async fn ephemeral_message_is_deleted_after_its_ttl() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Arc::new(ManualClock(Mutex::new(SystemTime::now())));
+    let ctx = TimApiTestCtx::with_conf(TimApiTestConf {
+        space: TimSpaceConf {
+            clock: clock.clone(),
+            ..TimSpaceConf::default()
+        },
+        ..Default::default()
+    })?;
+    let api = ctx.api();
+    let messages = ctx.message();
+
+    let sender = register(&api, "alpha").await?;
+    let watcher = register(&api, "beta").await?;
+    let mut events = api
+        .subscribe(
+            &SubscribeToSpaceReq {
+                receive_own_messages: false,
+                room_id: String::new(),
+            },
+            &watcher,
+        )
+        .await?;
+
+    send(&api, &sender, "short lived", Some(60)).await?;
+    send(&api, &sender, "longer lived", Some(600)).await?;
+    send(&api, &sender, "kept", None).await?;
+
+    let ephemeral = next_matching(&mut events, |data| match data {
+        space_event::Data::EventNewMessage(payload) => payload.message,
+        _ => None,
+    })
+    .await?;
+    assert_eq!(ephemeral.content, "short lived");
+    assert!(ephemeral.expires_at.is_some());
+
+    // nothing is due yet
+    assert_eq!(messages.expire_messages().await?, 0);
+
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(messages.expire_messages().await?, 1);
+    let deleted = next_matching(&mut events, |data| match data {
+        space_event::Data::EventMessageDeleted(payload) => Some(payload.message_id),
+        _ => None,
+    })
+    .await?;
+    assert_eq!(deleted, ephemeral.id);
+
+    let stored = timeline_messages(&api, &sender)?;
+    assert_eq!(stored.len(), 3);
+    assert!(stored[0].deleted);
+    assert!(stored[0].content.is_empty());
+    assert_eq!(stored[1].content, "longer lived");
+    assert!(!stored[1].deleted);
+    assert_eq!(stored[2].content, "kept");
+    assert!(stored[2].expires_at.is_none());
+
+    // a deleted message is not deleted again
+    assert_eq!(messages.expire_messages().await?, 0);
+
+    clock.advance(Duration::from_secs(600));
+    assert_eq!(messages.expire_messages().await?, 1);
+    let stored = timeline_messages(&api, &sender)?;
+    let deleted: Vec<_> = stored.iter().map(|message| message.deleted).collect();
+    assert_eq!(deleted, [true, true, false]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn zero_ttl_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+    let sender = register(&api, "alpha").await?;
+
+    let res = send(&api, &sender, "gone at once", Some(0)).await;
+    assert!(matches!(res, Err(TimApiError::InvalidArgError(_))));
+    assert!(timeline_messages(&api, &sender)?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+mod common;
+
+use common::register;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::space_event;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::SendMessageReq;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::tim_storage::TimStorageConf;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn buffered_events_are_visible_to_timeline() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::with_conf(TimApiTestConf {
+        storage: TimStorageConf {
+            event_batch_size: 64,
+            ..Default::default()
+        },
+        ..Default::default()
+    })?;
+    let api = ctx.api();
+
+    let session = register(&api, "alpha").await?;
+    let mut events = api
+        .subscribe(
+            &SubscribeToSpaceReq {
+                receive_own_messages: true,
+                room_id: String::new(),
+            },
+            &session,
+        )
+        .await?;
+
+    api.send_message(
+        &SendMessageReq {
+            content: "batched".into(),
+            reply_to_message_id: None,
+            metadata: Default::default(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: String::new(),
+        },
+        &session,
+    )
+    .await?;
+
+    // the subscriber has the event before the batch is full or timed out
+    let broadcast_id = loop {
+        let event = timeout(Duration::from_secs(1), events.recv())
+            .await?
+            .expect("subscriber should receive an event");
+        if let Some(space_event::Data::EventNewMessage(_)) = event.data {
+            break event.metadata.expect("event missing metadata").id;
+        }
+    };
+
+    let timeline = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 10,
+            room_id: String::new(),
+        },
+        &session,
+    )?;
+    assert!(timeline
+        .events
+        .iter()
+        .any(|event| event.metadata.as_ref().map(|meta| meta.id) == Some(broadcast_id)));
+
+    Ok(())
+}
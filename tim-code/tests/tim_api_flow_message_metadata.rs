@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+mod common;
+
+use common::register;
+use common::TimApiTestCtx;
+use tim_code::api::space_event;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::SendMessageReq;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::tim_api::TimApiError;
+use tokio::time::timeout;
+
+fn metadata(entries: &[(&str, &str)]) -> HashMap<String, String> {
+    entries
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn metadata_round_trips_through_space() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let session = register(&api, "alpha").await?;
+    let mut events = api
+        .subscribe(
+            &SubscribeToSpaceReq {
+                receive_own_messages: true,
+                room_id: String::new(),
+            },
+            &session,
+        )
+        .await?;
+
+    let sent = metadata(&[("source", "ci"), ("correlation_id", "42")]);
+    api.send_message(
+        &SendMessageReq {
+            content: "build finished".into(),
+            reply_to_message_id: None,
+            metadata: sent.clone(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: String::new(),
+        },
+        &session,
+    )
+    .await?;
+
+    let broadcast = loop {
+        let event = timeout(Duration::from_secs(1), events.recv())
+            .await?
+            .expect("subscriber should receive an event");
+        if let Some(space_event::Data::EventNewMessage(payload)) = event.data {
+            break payload.message.expect("message missing");
+        }
+    };
+    assert_eq!(broadcast.metadata, sent);
+
+    let timeline = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 10,
+            room_id: String::new(),
+        },
+        &session,
+    )?;
+    let stored = timeline
+        .events
+        .into_iter()
+        .find_map(|event| match event.data {
+            Some(space_event::Data::EventNewMessage(payload)) => payload.message,
+            _ => None,
+        })
+        .expect("timeline should contain the message");
+    assert_eq!(stored.metadata, sent);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn metadata_limits_are_enforced() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let session = register(&api, "alpha").await?;
+
+    let too_many: HashMap<String, String> = (0..17)
+        .map(|index| (format!("k{index}"), String::new()))
+        .collect();
+    let too_large = metadata(&[("blob", &"x".repeat(8 * 1024))]);
+    let reserved = metadata(&[("tim.internal", "1")]);
+
+    for (entries, large) in [(too_many, false), (too_large, true), (reserved, false)] {
+        let res = api
+            .send_message(
+                &SendMessageReq {
+                    content: "tagged".into(),
+                    reply_to_message_id: None,
+                    metadata: entries,
+                    parts: Vec::new(),
+                    ephemeral_ttl_secs: None,
+                    room_id: String::new(),
+                },
+                &session,
+            )
+            .await;
+        match res {
+            Err(TimApiError::PayloadTooLarge { .. }) => assert!(large),
+            Err(TimApiError::InvalidArgError(_)) => assert!(!large),
+            _ => panic!("metadata should be rejected"),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use common::client_info;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::SendMessageReq;
+use tim_code::api::TrustedRegisterReq;
+use tim_code::api::TrustedRegisterRes;
+use tim_code::tim_api::TimApi;
+use tim_code::tim_api::TimApiConf;
+use tim_code::tim_timite::NickFallback;
+
+fn ctx_with_fallback() -> Result<TimApiTestCtx, Box<dyn std::error::Error>> {
+    TimApiTestCtx::with_conf(TimApiTestConf {
+        api: TimApiConf {
+            nick_fallback: NickFallback::Suffix {
+                base: "guest".into(),
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+async fn register(
+    api: &TimApi,
+    nick: &str,
+    force: bool,
+) -> Result<TrustedRegisterRes, Box<dyn std::error::Error>> {
+    Ok(api
+        .trusted_register(&TrustedRegisterReq {
+            nick: nick.into(),
+            client_info: Some(client_info()),
+            role: Default::default(),
+            force,
+        })
+        .await?)
+}
+
+#[tokio::test]
+async fn taken_nick_gets_the_next_free_suffix() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = ctx_with_fallback()?;
+    let api = ctx.api();
+
+    assert_eq!(register(&api, "alpha", false).await?.nick, "alpha");
+    assert_eq!(register(&api, "alpha", false).await?.nick, "alpha-2");
+    // a suggestion that is itself taken is skipped, not handed out twice
+    assert_eq!(register(&api, "alpha-3", false).await?.nick, "alpha-3");
+    let fourth = register(&api, "alpha", false).await?;
+    assert_eq!(fourth.nick, "alpha-4");
+
+    // the session belongs to a timite stored under the assigned nick
+    let session = fourth.session.expect("missing session");
+    api.send_message(
+        &SendMessageReq {
+            content: "hi".into(),
+            reply_to_message_id: None,
+            metadata: Default::default(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: String::new(),
+        },
+        &session,
+    )
+    .await?;
+    let timeline = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 100,
+            room_id: String::new(),
+        },
+        &session,
+    )?;
+    let sender = timeline
+        .timites
+        .iter()
+        .find(|timite| timite.id == session.timite_id)
+        .expect("timeline should resolve the sender");
+    assert_eq!(sender.nick, "alpha-4");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn empty_nick_starts_from_the_base() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = ctx_with_fallback()?;
+    let api = ctx.api();
+
+    assert_eq!(register(&api, "", false).await?.nick, "guest");
+    assert_eq!(register(&api, "  ", false).await?.nick, "guest-2");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn forced_nick_is_kept_as_given() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = ctx_with_fallback()?;
+    let api = ctx.api();
+
+    register(&api, "alpha", false).await?;
+    assert_eq!(register(&api, "alpha", true).await?.nick, "alpha");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn without_a_fallback_duplicates_register_as_asked() -> Result<(), Box<dyn std::error::Error>>
+{
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    register(&api, "alpha", false).await?;
+    assert_eq!(register(&api, "alpha", false).await?.nick, "alpha");
+
+    Ok(())
+}
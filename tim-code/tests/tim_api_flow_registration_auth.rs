@@ -0,0 +1,128 @@
+mod common;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::tim_grpc_api_server::TimGrpcApi;
+use tim_code::api::ClientInfo;
+use tim_code::api::Timite;
+use tim_code::api::TrustedConnectReq;
+use tim_code::api::TrustedRegisterReq;
+use tim_code::tim_api::TimApiError;
+use tim_code::tim_auth::RegistrationAuthorizer;
+use tim_code::tim_auth::RegistrationDenied;
+use tim_code::tim_auth::TokenAuthorizer;
+use tim_code::tim_grpc_api::TimGrpcApiService;
+use tonic::Code;
+use tonic::Request;
+
+fn client_info(auth_token: &str) -> ClientInfo {
+    ClientInfo {
+        platform: "auth-test".into(),
+        auth_token: auth_token.into(),
+    }
+}
+
+fn register_req(nick: &str, auth_token: &str) -> TrustedRegisterReq {
+    TrustedRegisterReq {
+        nick: nick.into(),
+        client_info: Some(client_info(auth_token)),
+        role: Default::default(),
+        force: false,
+    }
+}
+
+struct NickAllowList(Vec<&'static str>);
+
+#[async_trait]
+impl RegistrationAuthorizer for NickAllowList {
+    async fn authorize(&self, _info: &ClientInfo, nick: &str) -> Result<(), RegistrationDenied> {
+        if self.0.contains(&nick) {
+            Ok(())
+        } else {
+            Err(RegistrationDenied::PermissionDenied(format!(
+                "{nick} is not on the list"
+            )))
+        }
+    }
+}
+
+#[tokio::test]
+async fn authorizer_decides_before_anything_is_stored() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::with_conf(TimApiTestConf {
+        authorizer: Some(Arc::new(NickAllowList(vec!["alpha", "beta"]))),
+        ..Default::default()
+    })?;
+    let api = ctx.api();
+
+    let alpha = api
+        .trusted_register(&register_req("alpha", ""))
+        .await?
+        .session
+        .expect("missing alpha session");
+
+    let res = api.trusted_register(&register_req("mallory", "")).await;
+    assert!(matches!(
+        res,
+        Err(TimApiError::RegistrationDenied(
+            RegistrationDenied::PermissionDenied(_)
+        ))
+    ));
+
+    // the denied attempt minted no timite, beta gets the next id
+    let beta = api
+        .trusted_register(&register_req("beta", ""))
+        .await?
+        .session
+        .expect("missing beta session");
+    assert_eq!(beta.timite_id, alpha.timite_id + 1);
+
+    let mallory_connect = TrustedConnectReq {
+        timite: Some(Timite {
+            id: alpha.timite_id,
+            nick: "mallory".into(),
+            avatar_seed: 0,
+            role: Default::default(),
+        }),
+        client_info: Some(client_info("")),
+    };
+    let res = api.trusted_connect(&mallory_connect).await;
+    assert!(matches!(res, Err(TimApiError::RegistrationDenied(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn token_authorizer_maps_to_grpc_codes() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::with_conf(TimApiTestConf {
+        authorizer: Some(Arc::new(TokenAuthorizer::from_list("s3cret, other"))),
+        ..Default::default()
+    })?;
+    let service = TimGrpcApiService::new(ctx.api());
+
+    let status = service
+        .trusted_register(Request::new(register_req("alpha", "")))
+        .await
+        .expect_err("missing token must be refused");
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    let status = service
+        .trusted_register(Request::new(register_req("alpha", "guess")))
+        .await
+        .expect_err("unknown token must be refused");
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let session = service
+        .trusted_register(Request::new(register_req("alpha", "s3cret")))
+        .await?
+        .into_inner()
+        .session
+        .expect("missing session");
+    let info = session.client_info.expect("session client info");
+    assert_eq!(info.platform, "auth-test");
+    assert!(info.auth_token.is_empty(), "token must not be kept");
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use common::register;
+use common::TimApiTestCtx;
+use tim_code::api::space_event;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::Message;
+use tim_code::api::SendMessageReq;
+use tim_code::api::Session;
+use tim_code::tim_api::TimApi;
+use tim_code::tim_api::TimApiError;
+use tim_code::tim_message::TimMessageError;
+
+fn timeline_messages(
+    api: &TimApi,
+    session: &Session,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let timeline = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 100,
+            room_id: String::new(),
+        },
+        session,
+    )?;
+    Ok(timeline
+        .events
+        .into_iter()
+        .filter_map(|event| match event.data {
+            Some(space_event::Data::EventNewMessage(payload)) => payload.message,
+            _ => None,
+        })
+        .collect())
+}
+
+#[tokio::test]
+async fn reply_references_existing_message() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let session = register(&api, "alpha").await?;
+
+    api.send_message(
+        &SendMessageReq {
+            content: "question".into(),
+            reply_to_message_id: None,
+            metadata: Default::default(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: String::new(),
+        },
+        &session,
+    )
+    .await?;
+    let question = timeline_messages(&api, &session)?
+        .pop()
+        .expect("question should be in the timeline");
+
+    api.send_message(
+        &SendMessageReq {
+            content: "answer".into(),
+            reply_to_message_id: Some(question.id),
+            metadata: Default::default(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: String::new(),
+        },
+        &session,
+    )
+    .await?;
+    let answer = timeline_messages(&api, &session)?
+        .pop()
+        .expect("answer should be in the timeline");
+
+    assert_eq!(answer.content, "answer");
+    assert_eq!(answer.reply_to_message_id, Some(question.id));
+    assert_eq!(question.reply_to_message_id, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reply_to_unknown_message_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let session = register(&api, "alpha").await?;
+
+    // the id the message itself would get, so a self reference is rejected too
+    for reply_to in [1, 42] {
+        let res = api
+            .send_message(
+                &SendMessageReq {
+                    content: "orphan".into(),
+                    reply_to_message_id: Some(reply_to),
+                    metadata: Default::default(),
+                    parts: Vec::new(),
+                    ephemeral_ttl_secs: None,
+                    room_id: String::new(),
+                },
+                &session,
+            )
+            .await;
+        assert!(matches!(
+            res,
+            Err(TimApiError::MessageError(TimMessageError::ReplyTargetMissing(id))) if id == reply_to
+        ));
+    }
+    assert!(timeline_messages(&api, &session)?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use common::register;
+use common::TimApiTestCtx;
+use tim_code::api::space_event;
+use tim_code::api::DisconnectReq;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::Message;
+use tim_code::api::SendMessageReq;
+use tim_code::api::Session;
+use tim_code::api::SpaceEvent;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::tim_api::TimApi;
+use tim_code::tim_api::TimApiError;
+use tokio::sync::mpsc;
+
+async fn subscribe(
+    api: &TimApi,
+    session: &Session,
+    room: &str,
+) -> Result<mpsc::Receiver<SpaceEvent>, TimApiError> {
+    api.subscribe(
+        &SubscribeToSpaceReq {
+            receive_own_messages: false,
+            room_id: room.into(),
+        },
+        session,
+    )
+    .await
+}
+
+async fn send(
+    api: &TimApi,
+    session: &Session,
+    room: &str,
+    content: &str,
+) -> Result<(), TimApiError> {
+    api.send_message(
+        &SendMessageReq {
+            content: content.into(),
+            reply_to_message_id: None,
+            metadata: Default::default(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: room.into(),
+        },
+        session,
+    )
+    .await
+    .map(|_| ())
+}
+
+fn timeline(
+    api: &TimApi,
+    session: &Session,
+    room: &str,
+    size: u32,
+) -> Result<Vec<SpaceEvent>, TimApiError> {
+    Ok(api
+        .get_timeline(
+            &GetTimelineReq {
+                offset: 0,
+                size,
+                room_id: room.into(),
+            },
+            session,
+        )?
+        .events)
+}
+
+fn message_contents(events: &[SpaceEvent]) -> Vec<String> {
+    events
+        .iter()
+        .filter_map(|event| match &event.data {
+            Some(space_event::Data::EventNewMessage(payload)) => payload.message.as_ref(),
+            _ => None,
+        })
+        .map(|message: &Message| message.content.clone())
+        .collect()
+}
+
+/// Events already delivered; broadcasts complete before `send_message` returns.
+fn received(events: &mut mpsc::Receiver<SpaceEvent>) -> Vec<SpaceEvent> {
+    let mut received = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    received
+}
+
+fn room_of(event: &SpaceEvent) -> &str {
+    event
+        .metadata
+        .as_ref()
+        .map_or("", |meta| meta.room_id.as_str())
+}
+
+#[tokio::test]
+async fn rooms_keep_messages_and_history_apart() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let alpha = register(&api, "alpha").await?;
+    let beta = register(&api, "beta").await?;
+    let mut lobby = subscribe(&api, &beta, "").await?;
+    let mut dev = subscribe(&api, &beta, "dev").await?;
+
+    send(&api, &alpha, "dev", "in dev").await?;
+    send(&api, &alpha, "", "in lobby").await?;
+
+    let dev_events = received(&mut dev);
+    assert_eq!(message_contents(&dev_events), ["in dev"]);
+    assert!(dev_events.iter().all(|event| room_of(event) == "dev"));
+    let lobby_events = received(&mut lobby);
+    assert_eq!(message_contents(&lobby_events), ["in lobby"]);
+    assert!(lobby_events.iter().all(|event| room_of(event).is_empty()));
+
+    assert_eq!(
+        message_contents(&timeline(&api, &alpha, "dev", 10)?),
+        ["in dev"]
+    );
+    assert_eq!(
+        message_contents(&timeline(&api, &alpha, "", 10)?),
+        ["in lobby"]
+    );
+    assert!(timeline(&api, &alpha, "ops", 10)?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn presence_is_per_room() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let alpha = register(&api, "alpha").await?;
+    let beta = register(&api, "beta").await?;
+    let mut dev = subscribe(&api, &beta, "dev").await?;
+    let mut ops = subscribe(&api, &beta, "ops").await?;
+    received(&mut dev);
+    received(&mut ops);
+
+    let _alpha_dev = subscribe(&api, &alpha, "dev").await?;
+    let connected = received(&mut dev);
+    assert!(connected.iter().any(|event| matches!(
+        &event.data,
+        Some(space_event::Data::EventTimiteConnected(payload))
+            if payload.timite.as_ref().is_some_and(|timite| timite.id == alpha.timite_id)
+    )));
+    assert!(received(&mut ops).is_empty());
+
+    let dev_timeline = timeline(&api, &beta, "dev", 10)?;
+    assert!(dev_timeline
+        .iter()
+        .any(|event| matches!(event.data, Some(space_event::Data::EventTimiteConnected(_)))));
+    assert!(timeline(&api, &beta, "", 10)?.iter().all(|event| !matches!(
+        &event.data,
+        Some(space_event::Data::EventTimiteConnected(payload))
+            if payload.timite.as_ref().is_some_and(|timite| timite.id == alpha.timite_id)
+    )));
+
+    api.disconnect(&DisconnectReq {}, &alpha).await?;
+    let left = received(&mut dev);
+    assert!(left.iter().any(|event| matches!(
+        event.data,
+        Some(space_event::Data::EventTimiteDisconnected(_))
+    )));
+    assert!(received(&mut ops).is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn latest_page_of_a_room_skips_other_rooms() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+    let alpha = register(&api, "alpha").await?;
+
+    for n in 0..5 {
+        send(&api, &alpha, "dev", &format!("dev {n}")).await?;
+        send(&api, &alpha, "", &format!("lobby {n}")).await?;
+        send(&api, &alpha, "ops", &format!("ops {n}")).await?;
+    }
+
+    // room ids interleave, the newest page still holds as many events as asked for
+    assert_eq!(
+        message_contents(&timeline(&api, &alpha, "dev", 3)?),
+        ["dev 2", "dev 3", "dev 4"]
+    );
+    assert_eq!(
+        message_contents(&timeline(&api, &alpha, "", 2)?),
+        ["lobby 3", "lobby 4"]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_room_ids_are_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+    let alpha = register(&api, "alpha").await?;
+
+    for room in ["Dev", "dev:ops", "a b", &"x".repeat(65)] {
+        assert!(matches!(
+            send(&api, &alpha, room, "hello").await,
+            Err(TimApiError::InvalidArgError(_))
+        ));
+        assert!(matches!(
+            subscribe(&api, &alpha, room).await,
+            Err(TimApiError::InvalidArgError(_))
+        ));
+        assert!(matches!(
+            timeline(&api, &alpha, room, 10),
+            Err(TimApiError::InvalidArgError(_))
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+mod common;
+
+use common::register;
+use common::TimApiTestCtx;
+use tim_code::api::space_event;
+use tim_code::api::SendMessageReq;
+use tim_code::api::Session;
+use tim_code::api::SpaceEvent;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::tim_api::TimApi;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+// matches the subscriber channel capacity in TimSpace
+const CHANNEL_CAPACITY: usize = 10;
+// fits into the fast subscriber's channel, so it can be read after the burst
+const BURST: usize = 5;
+
+async fn send(
+    api: &TimApi,
+    session: &Session,
+    content: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let req = SendMessageReq {
+        content,
+        reply_to_message_id: None,
+        metadata: Default::default(),
+        parts: Vec::new(),
+        ephemeral_ttl_secs: None,
+        room_id: String::new(),
+    };
+    // a send waiting on a full subscriber would run into the timeout
+    timeout(Duration::from_secs(1), api.send_message(&req, session)).await??;
+    Ok(())
+}
+
+async fn next_message(
+    events: &mut mpsc::Receiver<SpaceEvent>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    loop {
+        let event = timeout(Duration::from_secs(1), events.recv())
+            .await?
+            .expect("fast subscriber should receive an event");
+        if let Some(space_event::Data::EventNewMessage(payload)) = event.data {
+            return Ok(payload.message.expect("message missing").content);
+        }
+    }
+}
+
+#[tokio::test]
+async fn full_subscriber_does_not_stall_others() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+
+    let alpha_session = register(&api, "alpha").await?;
+    let slow_session = register(&api, "slow").await?;
+    let fast_session = register(&api, "fast").await?;
+
+    let subscribe_req = SubscribeToSpaceReq {
+        receive_own_messages: false,
+        room_id: String::new(),
+    };
+    let mut slow_events = api.subscribe(&subscribe_req, &slow_session).await?;
+    let mut fast_events = api.subscribe(&subscribe_req, &fast_session).await?;
+
+    // empty the slow subscriber's channel, it is never read again afterwards
+    loop {
+        let event = timeout(Duration::from_secs(1), slow_events.recv())
+            .await?
+            .expect("slow subscriber should receive an event");
+        if let Some(space_event::Data::EventTimiteConnected(payload)) = event.data {
+            if payload.timite.map(|timite| timite.id) == Some(fast_session.timite_id) {
+                break;
+            }
+        }
+    }
+
+    // fills the slow subscriber up, the fast one keeps reading
+    for index in 0..CHANNEL_CAPACITY {
+        let content = format!("fill{index}");
+        send(&api, &alpha_session, content.clone()).await?;
+        assert_eq!(next_message(&mut fast_events).await?, content);
+    }
+
+    for index in 0..BURST {
+        send(&api, &alpha_session, format!("m{index}")).await?;
+    }
+    for index in 0..BURST {
+        assert_eq!(next_message(&mut fast_events).await?, format!("m{index}"));
+    }
+
+    // full but not gone, it is left to the idle timeout
+    let listed = api.list_subscribers()?.subscribers;
+    assert!(listed
+        .iter()
+        .any(|info| info.timite.as_ref().map(|timite| timite.id) == Some(slow_session.timite_id)));
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+mod common;
+
+use common::register;
+use common::TimApiTestCtx;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::SendMessageReq;
+use tim_code::api::Session;
+use tim_code::api::StreamTimelineReq;
+use tim_code::tim_api::TimApi;
+use tokio::time::timeout;
+
+async fn seed(api: &TimApi, count: usize) -> Result<Session, Box<dyn std::error::Error>> {
+    let session = register(api, "alpha").await?;
+    for index in 0..count {
+        api.send_message(
+            &SendMessageReq {
+                content: format!("message {index}"),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                ephemeral_ttl_secs: None,
+                room_id: String::new(),
+            },
+            &session,
+        )
+        .await?;
+    }
+    Ok(session)
+}
+
+#[tokio::test]
+async fn stream_timeline_yields_ordered_chunks() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+    let session = seed(&api, 5).await?;
+
+    let mut chunks = api.stream_timeline(
+        &StreamTimelineReq {
+            offset: 0,
+            page_size: 2,
+            room_id: String::new(),
+        },
+        &session,
+    );
+    let mut sizes = Vec::new();
+    let mut ids = Vec::new();
+    while let Some(chunk) = timeout(Duration::from_secs(1), chunks.recv()).await? {
+        let chunk = chunk?;
+        sizes.push(chunk.events.len());
+        ids.extend(
+            chunk
+                .events
+                .iter()
+                .map(|event| event.metadata.as_ref().expect("event missing metadata").id),
+        );
+        assert!(chunk.timites.iter().any(|t| t.id == session.timite_id));
+    }
+
+    assert_eq!(sizes, vec![2, 2, 1]);
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let full = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 100,
+            room_id: String::new(),
+        },
+        &session,
+    )?;
+    assert_eq!(full.events.len(), ids.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stream_timeline_stops_when_receiver_dropped() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let api = ctx.api();
+    let session = seed(&api, 10).await?;
+
+    let mut chunks = api.stream_timeline(
+        &StreamTimelineReq {
+            offset: 0,
+            page_size: 1,
+            room_id: String::new(),
+        },
+        &session,
+    );
+    let first = timeout(Duration::from_secs(1), chunks.recv())
+        .await?
+        .expect("stream should yield a chunk")?;
+    assert_eq!(first.events.len(), 1);
+    drop(chunks);
+
+    // the api stays usable after a client walks away mid-stream
+    let timeline = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 100,
+            room_id: String::new(),
+        },
+        &session,
+    )?;
+    assert_eq!(timeline.events.len(), 10);
+
+    Ok(())
+}
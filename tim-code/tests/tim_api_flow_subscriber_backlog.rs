@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+mod common;
+
+use common::register;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::space_event;
+use tim_code::api::SendMessageReq;
+use tim_code::api::Session;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::tim_api::TimApi;
+use tim_code::tim_space::TimSpaceConf;
+use tokio::time::timeout;
+
+// matches the subscriber channel capacity in TimSpace
+const CHANNEL_CAPACITY: usize = 10;
+
+fn backlog_ctx(backlog: usize) -> Result<TimApiTestCtx, Box<dyn std::error::Error>> {
+    TimApiTestCtx::with_conf(TimApiTestConf {
+        space: TimSpaceConf {
+            subscriber_backlog: backlog,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+async fn send_all(
+    api: &TimApi,
+    session: &Session,
+    count: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for index in 0..count {
+        api.send_message(
+            &SendMessageReq {
+                content: format!("m{index}"),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                ephemeral_ttl_secs: None,
+                room_id: String::new(),
+            },
+            session,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn slow_subscriber_catches_up_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = backlog_ctx(32)?;
+    let api = ctx.api();
+    let alpha_session = register(&api, "alpha").await?;
+    let slow_session = register(&api, "slow").await?;
+
+    let mut slow_events = api
+        .subscribe(
+            &SubscribeToSpaceReq {
+                receive_own_messages: false,
+                room_id: String::new(),
+            },
+            &slow_session,
+        )
+        .await?;
+
+    // more than the channel holds, none of it waits on the unread subscriber
+    let total = CHANNEL_CAPACITY * 2 + 5;
+    timeout(
+        Duration::from_secs(1),
+        send_all(&api, &alpha_session, total),
+    )
+    .await
+    .expect("sends should not wait on the slow subscriber")?;
+
+    let mut received = Vec::new();
+    while received.len() < total {
+        let event = timeout(Duration::from_secs(1), slow_events.recv())
+            .await?
+            .expect("slow subscriber should stay subscribed");
+        if let Some(space_event::Data::EventNewMessage(payload)) = event.data {
+            received.push(payload.message.expect("message missing").content);
+        }
+    }
+    let expected: Vec<String> = (0..total).map(|index| format!("m{index}")).collect();
+    assert_eq!(received, expected);
+
+    // drained, so later events go straight through again
+    send_all(&api, &alpha_session, 1).await?;
+    let event = timeout(Duration::from_secs(1), slow_events.recv())
+        .await?
+        .expect("slow subscriber should stay subscribed");
+    assert!(matches!(
+        event.data,
+        Some(space_event::Data::EventNewMessage(_))
+    ));
+    assert!(api.list_subscribers()?.subscribers.iter().any(|sub| sub
+        .timite
+        .as_ref()
+        .map(|timite| timite.id)
+        == Some(slow_session.timite_id)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn overflowing_backlog_evicts_subscriber() -> Result<(), Box<dyn std::error::Error>> {
+    let backlog = 5;
+    let ctx = backlog_ctx(backlog)?;
+    let api = ctx.api();
+    let alpha_session = register(&api, "alpha").await?;
+    let stuck_session = register(&api, "stuck").await?;
+
+    // never read, its connect event already takes a slot
+    let _stuck_events = api
+        .subscribe(
+            &SubscribeToSpaceReq {
+                receive_own_messages: false,
+                room_id: String::new(),
+            },
+            &stuck_session,
+        )
+        .await?;
+
+    send_all(&api, &alpha_session, CHANNEL_CAPACITY + backlog - 1).await?;
+    assert_eq!(api.list_subscribers()?.subscribers.len(), 1);
+
+    send_all(&api, &alpha_session, 1).await?;
+    assert!(
+        api.list_subscribers()?.subscribers.is_empty(),
+        "a full backlog should drop the subscriber"
+    );
+
+    Ok(())
+}
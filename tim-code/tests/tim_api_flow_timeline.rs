@@ -10,6 +10,7 @@ use tim_code::api::TrustedRegisterReq;
 fn client_info() -> ClientInfo {
     ClientInfo {
         platform: "timeline-test".into(),
+        auth_token: String::new(),
     }
 }
 
@@ -22,6 +23,8 @@ async fn tim_api_get_timeline_returns_events() -> Result<(), Box<dyn std::error:
         .trusted_register(&TrustedRegisterReq {
             nick: "alpha".into(),
             client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
         })
         .await?
         .session
@@ -34,6 +37,11 @@ async fn tim_api_get_timeline_returns_events() -> Result<(), Box<dyn std::error:
         api.send_message(
             &SendMessageReq {
                 content: content.into(),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                ephemeral_ttl_secs: None,
+                room_id: String::new(),
             },
             &session,
         )
@@ -44,6 +52,7 @@ async fn tim_api_get_timeline_returns_events() -> Result<(), Box<dyn std::error:
         &GetTimelineReq {
             offset: 0,
             size: 10,
+            room_id: String::new(),
         },
         &session,
     )?;
@@ -87,6 +96,7 @@ async fn tim_api_get_timeline_returns_events() -> Result<(), Box<dyn std::error:
         &GetTimelineReq {
             offset: second_id,
             size: 10,
+            room_id: String::new(),
         },
         &session,
     )?;
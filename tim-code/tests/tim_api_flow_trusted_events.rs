@@ -16,6 +16,7 @@ use tokio::time::timeout;
 fn client_info() -> ClientInfo {
     ClientInfo {
         platform: "cli-test".into(),
+        auth_token: String::new(),
     }
 }
 
@@ -28,6 +29,8 @@ async fn trusted_flow_sends_events() -> Result<(), Box<dyn std::error::Error>> {
         .trusted_register(&TrustedRegisterReq {
             nick: "alpha".into(),
             client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
         })
         .await?
         .session
@@ -46,6 +49,8 @@ async fn trusted_flow_sends_events() -> Result<(), Box<dyn std::error::Error>> {
             timite: Some(Timite {
                 id: alpha_session.timite_id,
                 nick: "alpha".into(),
+                avatar_seed: 0,
+                role: Default::default(),
             }),
             client_info: Some(client_info()),
         })
@@ -66,6 +71,8 @@ async fn trusted_flow_sends_events() -> Result<(), Box<dyn std::error::Error>> {
         .trusted_register(&TrustedRegisterReq {
             nick: "beta".into(),
             client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
         })
         .await?
         .session
@@ -75,6 +82,7 @@ async fn trusted_flow_sends_events() -> Result<(), Box<dyn std::error::Error>> {
         .subscribe(
             &SubscribeToSpaceReq {
                 receive_own_messages: false,
+                room_id: String::new(),
             },
             &beta_session,
         )
@@ -85,6 +93,11 @@ async fn trusted_flow_sends_events() -> Result<(), Box<dyn std::error::Error>> {
         .send_message(
             &SendMessageReq {
                 content: content.into(),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                ephemeral_ttl_secs: None,
+                room_id: String::new(),
             },
             &reconnect_session,
         )
@@ -105,6 +118,7 @@ async fn trusted_flow_sends_events() -> Result<(), Box<dyn std::error::Error>> {
             }
             Some(space_event::Data::EventTimiteConnected(_)) => continue,
             Some(space_event::Data::EventTimiteDisconnected(_)) => continue,
+            Some(space_event::Data::EventAbilitiesChanged(_)) => continue,
             other => panic!("unexpected event event {:?}", other),
         }
     };
@@ -0,0 +1,112 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use common::client_info;
+use common::register;
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use prost_types::Timestamp;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::SendMessageReq;
+use tim_code::api::Timite;
+use tim_code::tim_clock::to_timestamp;
+use tim_code::tim_clock::Clock;
+use tim_code::tim_session::TimSession;
+use tim_code::tim_space::TimSpaceConf;
+use tim_code::tim_storage::TimStorage;
+
+const FIXED_SECS: u64 = 1_700_000_000;
+const FIXED_NANOS: u32 = 123_456_789;
+
+#[derive(Debug)]
+struct FixedClock(SystemTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+fn fixed_clock() -> Arc<dyn Clock> {
+    Arc::new(FixedClock(
+        UNIX_EPOCH + Duration::new(FIXED_SECS, FIXED_NANOS),
+    ))
+}
+
+fn fixed_timestamp() -> Timestamp {
+    Timestamp {
+        seconds: FIXED_SECS as i64,
+        nanos: FIXED_NANOS as i32,
+    }
+}
+
+#[tokio::test]
+async fn injected_clock_stamps_events() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::with_conf(TimApiTestConf {
+        space: TimSpaceConf {
+            clock: fixed_clock(),
+            ..TimSpaceConf::default()
+        },
+        ..TimApiTestConf::default()
+    })?;
+    let api = ctx.api();
+
+    let session = register(&api, "alpha").await?;
+    api.send_message(
+        &SendMessageReq {
+            content: "tick".into(),
+            reply_to_message_id: None,
+            metadata: Default::default(),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: String::new(),
+        },
+        &session,
+    )
+    .await?;
+
+    let timeline = api.get_timeline(
+        &GetTimelineReq {
+            offset: 0,
+            size: 10,
+            room_id: String::new(),
+        },
+        &session,
+    )?;
+    assert!(!timeline.events.is_empty());
+    for event in &timeline.events {
+        let metadata = event.metadata.as_ref().expect("missing metadata");
+        assert_eq!(metadata.emitted_at, Some(fixed_timestamp()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn injected_clock_stamps_sessions() -> Result<(), Box<dyn std::error::Error>> {
+    let storage = Arc::new(TimStorage::in_memory(Default::default()));
+    let sessions = TimSession::with_clock(storage, fixed_clock());
+    let timite = Timite {
+        id: 1,
+        nick: "alpha".into(),
+        avatar_seed: 0,
+        role: Default::default(),
+    };
+
+    let session = sessions.create(&timite, &client_info())?;
+
+    assert_eq!(session.created_at, Some(fixed_timestamp()));
+    Ok(())
+}
+
+#[test]
+fn millisecond_timestamps_keep_their_value() {
+    let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+    let stamp = to_timestamp(time);
+    assert_eq!(stamp.seconds, 1_700_000_000);
+    assert_eq!(stamp.nanos, 123_000_000);
+}
@@ -18,6 +18,7 @@ use tonic::Request;
 fn client_info() -> ClientInfo {
     ClientInfo {
         platform: "grpc-test".into(),
+        auth_token: String::new(),
     }
 }
 
@@ -36,6 +37,8 @@ async fn grpc_send_message_notifies_subscribers() -> Result<(), Box<dyn std::err
         .trusted_register(Request::new(TrustedRegisterReq {
             nick: "alpha".into(),
             client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
         }))
         .await?
         .into_inner()
@@ -46,6 +49,8 @@ async fn grpc_send_message_notifies_subscribers() -> Result<(), Box<dyn std::err
         .trusted_register(Request::new(TrustedRegisterReq {
             nick: "beta".into(),
             client_info: Some(client_info()),
+            role: Default::default(),
+            force: false,
         }))
         .await?
         .into_inner()
@@ -56,6 +61,7 @@ async fn grpc_send_message_notifies_subscribers() -> Result<(), Box<dyn std::err
         .subscribe_to_space(request_with_session(
             SubscribeToSpaceReq {
                 receive_own_messages: false,
+                room_id: String::new(),
             },
             &beta_session,
         ))
@@ -66,6 +72,11 @@ async fn grpc_send_message_notifies_subscribers() -> Result<(), Box<dyn std::err
         .send_message(request_with_session(
             SendMessageReq {
                 content: "grpc ping".into(),
+                reply_to_message_id: None,
+                metadata: Default::default(),
+                parts: Vec::new(),
+                ephemeral_ttl_secs: None,
+                room_id: String::new(),
             },
             &alpha_session,
         ))
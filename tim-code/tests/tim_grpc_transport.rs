@@ -0,0 +1,180 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TimApiTestConf;
+use common::TimApiTestCtx;
+use tim_code::api::ClientInfo;
+use tim_code::api::GetTimelineReq;
+use tim_code::api::ListSubscribersReq;
+use tim_code::api::SendMessageReq;
+use tim_code::api::Session;
+use tim_code::api::TrustedRegisterReq;
+use tim_code::tim_auth::TokenAuthorizer;
+use tim_code::tim_session::ADMIN_METADATA_KEY;
+use tim_code::tim_session::SESSION_METADATA_KEY;
+use tonic::Code;
+use tonic::Request;
+
+const ADMIN_TOKEN: &str = "admin-secret";
+
+fn client_info(auth_token: &str) -> ClientInfo {
+    ClientInfo {
+        platform: "transport-test".into(),
+        auth_token: auth_token.into(),
+    }
+}
+
+fn register_req(nick: &str, auth_token: &str) -> TrustedRegisterReq {
+    TrustedRegisterReq {
+        nick: nick.into(),
+        client_info: Some(client_info(auth_token)),
+        role: Default::default(),
+        force: false,
+    }
+}
+
+fn send_req(content: &str, reply_to_message_id: Option<u64>) -> SendMessageReq {
+    SendMessageReq {
+        content: content.into(),
+        reply_to_message_id,
+        metadata: Default::default(),
+        parts: Vec::new(),
+        ephemeral_ttl_secs: None,
+        room_id: String::new(),
+    }
+}
+
+fn with_header<T>(payload: T, key: &'static str, value: &str) -> Request<T> {
+    let mut req = Request::new(payload);
+    req.metadata_mut()
+        .insert(key, value.parse().expect("invalid header value"));
+    req
+}
+
+fn with_session<T>(payload: T, session: &Session) -> Request<T> {
+    with_header(payload, SESSION_METADATA_KEY, &session.key)
+}
+
+#[tokio::test]
+async fn session_header_is_resolved_by_the_middleware() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+    let mut client = ctx.grpc_client(None).await?;
+
+    let session = client
+        .trusted_register(register_req("alpha", ""))
+        .await?
+        .into_inner()
+        .session
+        .expect("missing session");
+
+    client
+        .send_message(with_session(send_req("over the wire", None), &session))
+        .await?;
+    let timeline = client
+        .get_timeline(with_session(
+            GetTimelineReq {
+                offset: 0,
+                size: 10,
+                room_id: String::new(),
+            },
+            &session,
+        ))
+        .await?
+        .into_inner();
+    assert!(!timeline.events.is_empty());
+
+    // no header and an unknown key both leave the request without a session
+    let status = client
+        .send_message(send_req("anonymous", None))
+        .await
+        .expect_err("request without session should fail");
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let status = client
+        .send_message(with_header(
+            send_req("forged", None),
+            SESSION_METADATA_KEY,
+            "not-a-session",
+        ))
+        .await
+        .expect_err("request with unknown session should fail");
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn api_errors_map_to_status_codes() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::with_conf(TimApiTestConf {
+        authorizer: Some(Arc::new(TokenAuthorizer::from_list("letmein"))),
+        ..Default::default()
+    })?;
+    let mut client = ctx.grpc_client(None).await?;
+
+    let status = client
+        .trusted_register(register_req("mallory", ""))
+        .await
+        .expect_err("registration without token should fail");
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let status = client
+        .trusted_register(register_req("mallory", "guess"))
+        .await
+        .expect_err("registration with a wrong token should fail");
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let session = client
+        .trusted_register(register_req("alpha", "letmein"))
+        .await?
+        .into_inner()
+        .session
+        .expect("missing session");
+    let status = client
+        .send_message(with_session(send_req("reply", Some(9999)), &session))
+        .await
+        .expect_err("reply to a missing message should fail");
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn admin_rpcs_require_the_admin_token() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TimApiTestCtx::new()?;
+
+    let mut client = ctx.grpc_client(Some(ADMIN_TOKEN)).await?;
+    let status = client
+        .list_subscribers(ListSubscribersReq {})
+        .await
+        .expect_err("admin RPC without token should fail");
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = client
+        .list_subscribers(with_header(
+            ListSubscribersReq {},
+            ADMIN_METADATA_KEY,
+            "wrong",
+        ))
+        .await
+        .expect_err("admin RPC with a wrong token should fail");
+    assert_eq!(status.code(), Code::PermissionDenied);
+    client
+        .list_subscribers(with_header(
+            ListSubscribersReq {},
+            ADMIN_METADATA_KEY,
+            ADMIN_TOKEN,
+        ))
+        .await?;
+
+    // without a configured token admin RPCs are closed to everyone
+    let mut client = ctx.grpc_client(None).await?;
+    let status = client
+        .list_subscribers(with_header(
+            ListSubscribersReq {},
+            ADMIN_METADATA_KEY,
+            ADMIN_TOKEN,
+        ))
+        .await
+        .expect_err("admin RPC should be closed");
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    Ok(())
+}
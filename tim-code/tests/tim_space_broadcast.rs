@@ -0,0 +1,172 @@
+//! Drives `TimSpace` directly, without the api layer. Deliveries complete
+//! before `publish_*` returns, so each step drains its subscribers with
+//! `try_recv` instead of waiting on timeouts.
+
+use std::sync::Arc;
+
+use tim_code::api::space_event;
+use tim_code::api::Message;
+use tim_code::api::Session;
+use tim_code::api::SpaceEvent;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::api::Timite;
+use tim_code::tim_space::TimSpace;
+use tim_code::tim_space::TimSpaceConf;
+use tim_code::tim_storage::TimStorage;
+use tokio::sync::mpsc;
+
+fn space() -> Result<TimSpace, Box<dyn std::error::Error>> {
+    let storage = Arc::new(TimStorage::in_memory(Default::default()));
+    Ok(TimSpace::new(storage, TimSpaceConf::default())?)
+}
+
+fn timite(id: u64, nick: &str) -> Timite {
+    Timite {
+        id,
+        nick: nick.into(),
+        avatar_seed: 0,
+        role: Default::default(),
+    }
+}
+
+fn session(key: &str, timite_id: u64) -> Session {
+    Session {
+        key: key.into(),
+        timite_id,
+        created_at: None,
+        client_info: None,
+    }
+}
+
+fn message(id: u64, sender_id: u64) -> Message {
+    Message {
+        id,
+        sender_id,
+        content: format!("message {id}"),
+        reply_to_message_id: None,
+        metadata: Default::default(),
+        parts: Vec::new(),
+        expires_at: None,
+        deleted: false,
+        priority: Default::default(),
+    }
+}
+
+async fn subscribe(
+    space: &TimSpace,
+    session: &Session,
+    timite: &Timite,
+    receive_own_messages: bool,
+) -> Result<mpsc::Receiver<SpaceEvent>, Box<dyn std::error::Error>> {
+    Ok(space
+        .subscribe(
+            &SubscribeToSpaceReq {
+                receive_own_messages,
+                room_id: String::new(),
+            },
+            session,
+            timite.clone(),
+        )
+        .await?)
+}
+
+fn drain(events: &mut mpsc::Receiver<SpaceEvent>) -> Vec<SpaceEvent> {
+    let mut drained = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        drained.push(event);
+    }
+    drained
+}
+
+fn message_ids(events: &[SpaceEvent]) -> Vec<u64> {
+    events
+        .iter()
+        .filter_map(|event| match &event.data {
+            Some(space_event::Data::EventNewMessage(payload)) => {
+                payload.message.as_ref().map(|message| message.id)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn disconnected_ids(events: &[SpaceEvent]) -> Vec<u64> {
+    events
+        .iter()
+        .filter_map(|event| match &event.data {
+            Some(space_event::Data::EventTimiteDisconnected(payload)) => {
+                payload.timite.as_ref().map(|timite| timite.id)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn own_messages_follow_receive_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let space = space()?;
+    let (alpha, beta) = (timite(1, "alpha"), timite(2, "beta"));
+    let mut alpha_own = subscribe(&space, &session("a1", 1), &alpha, true).await?;
+    let mut alpha_quiet = subscribe(&space, &session("a2", 1), &alpha, false).await?;
+    let mut beta_events = subscribe(&space, &session("b1", 2), &beta, false).await?;
+    for events in [&mut alpha_own, &mut alpha_quiet, &mut beta_events] {
+        drain(events);
+    }
+
+    space.publish_message("", &message(10, alpha.id)).await?;
+
+    assert_eq!(message_ids(&drain(&mut alpha_own)), vec![10]);
+    assert!(message_ids(&drain(&mut alpha_quiet)).is_empty());
+    assert_eq!(message_ids(&drain(&mut beta_events)), vec![10]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn disconnect_announces_last_session_only() -> Result<(), Box<dyn std::error::Error>> {
+    let space = space()?;
+    let (alpha, beta) = (timite(1, "alpha"), timite(2, "beta"));
+    let (first, second) = (session("a1", 1), session("a2", 1));
+    let _alpha_first = subscribe(&space, &first, &alpha, false).await?;
+    let _alpha_second = subscribe(&space, &second, &alpha, false).await?;
+    let mut observer = subscribe(&space, &session("b1", 2), &beta, false).await?;
+    drain(&mut observer);
+
+    space.disconnect(&first).await?;
+    assert!(disconnected_ids(&drain(&mut observer)).is_empty());
+
+    space.disconnect(&second).await?;
+    assert_eq!(disconnected_ids(&drain(&mut observer)), vec![alpha.id]);
+
+    // a repeated disconnect finds nothing to remove
+    space.disconnect(&second).await?;
+    assert!(drain(&mut observer).is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dropped_streams_are_pruned_on_broadcast() -> Result<(), Box<dyn std::error::Error>> {
+    let space = space()?;
+    let (alpha, beta) = (timite(1, "alpha"), timite(2, "beta"));
+    let alpha_first = subscribe(&space, &session("a1", 1), &alpha, false).await?;
+    let alpha_second = subscribe(&space, &session("a2", 1), &alpha, false).await?;
+    let mut observer = subscribe(&space, &session("b1", 2), &beta, true).await?;
+    drain(&mut observer);
+
+    drop(alpha_first);
+    space.publish_message("", &message(10, beta.id)).await?;
+    let events = drain(&mut observer);
+    assert_eq!(message_ids(&events), vec![10]);
+    assert!(disconnected_ids(&events).is_empty());
+    assert_eq!(space.list_subscribers().len(), 2);
+
+    drop(alpha_second);
+    space.publish_message("", &message(11, beta.id)).await?;
+    let events = drain(&mut observer);
+    assert_eq!(message_ids(&events), vec![11]);
+    assert_eq!(disconnected_ids(&events), vec![alpha.id]);
+    assert_eq!(space.list_subscribers().len(), 1);
+
+    Ok(())
+}
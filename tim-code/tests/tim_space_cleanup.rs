@@ -0,0 +1,74 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tim_code::api::space_event;
+use tim_code::api::Session;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::api::Timite;
+use tim_code::tim_space::TimSpace;
+use tim_code::tim_space::TimSpaceConf;
+use tim_code::tim_storage::TimStorage;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+fn subscriber(id: u64, nick: &str) -> (Session, Timite) {
+    let session = Session {
+        key: format!("{nick}-key"),
+        timite_id: id,
+        created_at: None,
+        client_info: None,
+    };
+    let timite = Timite {
+        id,
+        nick: nick.into(),
+        avatar_seed: 0,
+        role: Default::default(),
+    };
+    (session, timite)
+}
+
+#[tokio::test]
+async fn cleanup_sweeps_once_more_on_shutdown() -> Result<(), Box<dyn std::error::Error>> {
+    let storage = Arc::new(TimStorage::in_memory(Default::default()));
+    let space = Arc::new(TimSpace::new(
+        storage,
+        TimSpaceConf {
+            // only the immediate first tick fires during the test
+            cleanup_interval: Duration::from_secs(3600),
+            ..Default::default()
+        },
+    )?);
+    let req = SubscribeToSpaceReq {
+        receive_own_messages: false,
+        room_id: String::new(),
+    };
+    let (alpha_session, alpha) = subscriber(1, "alpha");
+    let (beta_session, beta) = subscriber(2, "beta");
+    let alpha_events = space.subscribe(&req, &alpha_session, alpha.clone()).await?;
+    let mut beta_events = space.subscribe(&req, &beta_session, beta).await?;
+
+    let shutdown = CancellationToken::new();
+    let cleanup = tokio::spawn({
+        let space = space.clone();
+        let shutdown = shutdown.clone();
+        async move { space.run_cleanup(shutdown).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    drop(alpha_events);
+    shutdown.cancel();
+    timeout(Duration::from_secs(1), cleanup).await??;
+
+    assert_eq!(space.list_subscribers().len(), 1);
+    let left = loop {
+        let event = beta_events
+            .try_recv()
+            .expect("final sweep should announce alpha");
+        if let Some(space_event::Data::EventTimiteDisconnected(payload)) = event.data {
+            break payload.timite.expect("disconnected event missing timite");
+        }
+    };
+    assert_eq!(left.id, alpha.id);
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tim_code::api::space_event;
+use tim_code::api::DisconnectReason;
+use tim_code::api::Message;
+use tim_code::api::Session;
+use tim_code::api::SpaceEvent;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::api::Timite;
+use tim_code::tim_space::TimSpace;
+use tim_code::tim_space::TimSpaceConf;
+use tim_code::tim_storage::TimStorage;
+use tokio::sync::mpsc;
+
+// matches the subscriber channel capacity in TimSpace
+const CHANNEL_CAPACITY: u64 = 10;
+const IDLE_TIMEOUT: Duration = Duration::from_millis(200);
+
+fn subscriber(id: u64, nick: &str) -> (Session, Timite) {
+    let session = Session {
+        key: format!("{nick}-key"),
+        timite_id: id,
+        created_at: None,
+        client_info: None,
+    };
+    let timite = Timite {
+        id,
+        nick: nick.into(),
+        avatar_seed: 0,
+        role: Default::default(),
+    };
+    (session, timite)
+}
+
+fn message(id: u64, sender_id: u64) -> Message {
+    Message {
+        id,
+        sender_id,
+        content: format!("message {id}"),
+        reply_to_message_id: None,
+        metadata: Default::default(),
+        parts: Vec::new(),
+        expires_at: None,
+        deleted: false,
+        priority: Default::default(),
+    }
+}
+
+fn drain(events: &mut mpsc::Receiver<SpaceEvent>) -> Vec<SpaceEvent> {
+    let mut drained = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        drained.push(event);
+    }
+    drained
+}
+
+#[tokio::test]
+async fn only_a_subscriber_full_past_the_idle_timeout_is_evicted(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = Arc::new(TimStorage::in_memory(Default::default()));
+    let space = TimSpace::new(
+        storage,
+        TimSpaceConf {
+            idle_timeout: IDLE_TIMEOUT,
+            ..Default::default()
+        },
+    )?;
+    let req = SubscribeToSpaceReq {
+        receive_own_messages: false,
+        room_id: String::new(),
+    };
+    let (_, alpha) = subscriber(1, "alpha");
+    let (stalled_session, stalled) = subscriber(2, "stalled");
+    let (recovering_session, recovering) = subscriber(3, "recovering");
+    let (watcher_session, watcher) = subscriber(4, "watcher");
+    let _stalled_events = space
+        .subscribe(&req, &stalled_session, stalled.clone())
+        .await?;
+    let mut recovering_events = space
+        .subscribe(&req, &recovering_session, recovering.clone())
+        .await?;
+    let mut watcher_events = space.subscribe(&req, &watcher_session, watcher).await?;
+
+    // fills both channels, the watcher is read as it goes
+    for id in 1..=CHANNEL_CAPACITY {
+        space.publish_message("", &message(id, alpha.id)).await?;
+        drain(&mut watcher_events);
+    }
+
+    // a full buffer alone is not enough
+    assert_eq!(space.evict_backpressured().await?, 0);
+    assert_eq!(space.list_subscribers().len(), 3);
+
+    drain(&mut recovering_events);
+    tokio::time::sleep(IDLE_TIMEOUT * 2).await;
+    assert_eq!(space.evict_backpressured().await?, 1);
+
+    let listed: Vec<u64> = space
+        .list_subscribers()
+        .into_iter()
+        .filter_map(|info| info.timite.map(|timite| timite.id))
+        .collect();
+    assert!(listed.contains(&recovering.id));
+    assert!(!listed.contains(&stalled.id));
+
+    let evicted: Vec<(u64, DisconnectReason)> = drain(&mut watcher_events)
+        .into_iter()
+        .filter_map(|event| match event.data {
+            Some(space_event::Data::EventTimiteDisconnected(payload)) => Some((
+                payload.timite.as_ref().map(|timite| timite.id)?,
+                payload.reason(),
+            )),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(evicted, vec![(stalled.id, DisconnectReason::TimedOut)]);
+
+    Ok(())
+}
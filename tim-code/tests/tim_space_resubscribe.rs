@@ -0,0 +1,117 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tim_code::api::space_event;
+use tim_code::api::Session;
+use tim_code::api::SpaceEvent;
+use tim_code::api::SubscribeToSpaceReq;
+use tim_code::api::Timite;
+use tim_code::tim_space::TimSpace;
+use tim_code::tim_storage::TimStorage;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+fn session(key: &str, timite_id: u64) -> Session {
+    Session {
+        key: key.into(),
+        timite_id,
+        created_at: None,
+        client_info: None,
+    }
+}
+
+fn timite(id: u64, nick: &str) -> Timite {
+    Timite {
+        id,
+        nick: nick.into(),
+        avatar_seed: 0,
+        role: Default::default(),
+    }
+}
+
+fn space() -> Result<TimSpace, Box<dyn std::error::Error>> {
+    let storage = Arc::new(TimStorage::in_memory(Default::default()));
+    Ok(TimSpace::new(storage, Default::default())?)
+}
+
+fn req() -> SubscribeToSpaceReq {
+    SubscribeToSpaceReq {
+        receive_own_messages: false,
+        room_id: String::new(),
+    }
+}
+
+fn presence(events: &mut mpsc::Receiver<SpaceEvent>) -> Vec<SpaceEvent> {
+    let mut presence = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if matches!(
+            event.data,
+            Some(space_event::Data::EventTimiteConnected(_))
+                | Some(space_event::Data::EventTimiteDisconnected(_))
+        ) {
+            presence.push(event);
+        }
+    }
+    presence
+}
+
+#[tokio::test]
+async fn same_session_resubscribing_replaces_its_stream() -> Result<(), Box<dyn std::error::Error>>
+{
+    let space = space()?;
+    let alpha = timite(1, "alpha");
+    let alpha_session = session("alpha-key", alpha.id);
+    let mut beta_events = space
+        .subscribe(&req(), &session("beta-key", 2), timite(2, "beta"))
+        .await?;
+    // beta's own connect
+    assert_eq!(presence(&mut beta_events).len(), 1);
+    let mut first = space
+        .subscribe(&req(), &alpha_session, alpha.clone())
+        .await?;
+    assert_eq!(presence(&mut beta_events).len(), 1);
+
+    let _second = space.subscribe(&req(), &alpha_session, alpha).await?;
+
+    // the earlier stream ends instead of lingering unread
+    timeout(Duration::from_secs(1), async {
+        while first.recv().await.is_some() {}
+    })
+    .await?;
+    assert_eq!(space.subscriber_count(), 2);
+
+    // a sweep after the replace does not take the new stream for the old one
+    drop(first);
+    assert_eq!(space.cleanup_disconnected().await?, 0);
+    assert_eq!(space.subscriber_count(), 2);
+    assert!(presence(&mut beta_events).is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn other_sessions_of_a_timite_coexist() -> Result<(), Box<dyn std::error::Error>> {
+    let space = space()?;
+    let alpha = timite(1, "alpha");
+    let mut beta_events = space
+        .subscribe(&req(), &session("beta-key", 2), timite(2, "beta"))
+        .await?;
+    // beta's own connect
+    assert_eq!(presence(&mut beta_events).len(), 1);
+    let phone = space
+        .subscribe(&req(), &session("alpha-phone", alpha.id), alpha.clone())
+        .await?;
+    let _laptop = space
+        .subscribe(&req(), &session("alpha-laptop", alpha.id), alpha)
+        .await?;
+    assert_eq!(space.subscriber_count(), 3);
+    assert_eq!(presence(&mut beta_events).len(), 1);
+
+    // alpha is still there through the laptop
+    drop(phone);
+    assert_eq!(space.cleanup_disconnected().await?, 1);
+    assert_eq!(space.subscriber_count(), 2);
+    assert!(presence(&mut beta_events).is_empty());
+
+    Ok(())
+}
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use tempfile::tempdir;
 use tim_code::tim_storage::TimStorage;
+use tim_code::tim_storage::TimStorageConf;
 use tim_code::tim_timite::TimTimite;
 
 #[test]
@@ -11,7 +12,7 @@ fn timite_ids_survive_restart() -> Result<(), Box<dyn std::error::Error>> {
     let db_path = db_path.to_string_lossy().to_string();
 
     let last_id = {
-        let storage = Arc::new(TimStorage::new(&db_path)?);
+        let storage = Arc::new(TimStorage::new(&db_path, TimStorageConf::default())?);
         let timite = TimTimite::new(storage)?;
 
         let first = timite.create("alpha")?;
@@ -26,7 +27,7 @@ fn timite_ids_survive_restart() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     {
-        let storage = Arc::new(TimStorage::new(&db_path)?);
+        let storage = Arc::new(TimStorage::new(&db_path, TimStorageConf::default())?);
         let timite = TimTimite::new(storage)?;
 
         let after_restart = timite.create("gamma")?;
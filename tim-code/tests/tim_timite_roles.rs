@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use tempfile::tempdir;
+use tim_code::api::Timite;
+use tim_code::api::TimiteRole;
+use tim_code::tim_storage::TimStorage;
+use tim_code::tim_storage::TimStorageConf;
+use tim_code::tim_timite::TimTimite;
+
+#[test]
+fn timite_role_survives_restart() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let db_path = temp_dir.path().join("kv");
+    let db_path = db_path.to_string_lossy().to_string();
+
+    let (agent_id, human_id) = {
+        let storage = Arc::new(TimStorage::new(&db_path, TimStorageConf::default())?);
+        let timite = TimTimite::new(storage)?;
+
+        let agent = timite.create_with_role("bot", TimiteRole::Agent)?;
+        let human = timite.create("alice")?;
+        assert_eq!(agent.role(), TimiteRole::Agent);
+        assert_eq!(human.role(), TimiteRole::Human);
+
+        (agent.id, human.id)
+    };
+
+    let storage = Arc::new(TimStorage::new(&db_path, TimStorageConf::default())?);
+    let timite = TimTimite::new(storage)?;
+
+    let agent = timite.get(agent_id)?.expect("agent should be stored");
+    let human = timite.get(human_id)?.expect("human should be stored");
+    assert_eq!(agent.role(), TimiteRole::Agent);
+    assert_eq!(human.role(), TimiteRole::Human);
+
+    Ok(())
+}
+
+#[test]
+fn unspecified_role_reads_as_human() -> Result<(), Box<dyn std::error::Error>> {
+    let storage = Arc::new(TimStorage::in_memory(TimStorageConf::default()));
+    let timite = TimTimite::new(storage.clone())?;
+
+    // what timites stored before roles existed look like
+    storage.store_timite(&Timite {
+        id: 42,
+        nick: "legacy".into(),
+        avatar_seed: 0,
+        role: TimiteRole::Unspecified.into(),
+    })?;
+    let legacy = timite.get(42)?.expect("legacy timite should be stored");
+    assert_eq!(legacy.role(), TimiteRole::Human);
+
+    let created = timite.create_with_role("anon", TimiteRole::Unspecified)?;
+    assert_eq!(created.role(), TimiteRole::Human);
+
+    Ok(())
+}
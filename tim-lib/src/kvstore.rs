@@ -1,12 +1,15 @@
+pub mod mem;
+pub mod rocks;
+
 use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use prost::Message;
-use rocksdb::ColumnFamily;
-use rocksdb::DBAccess;
-use rocksdb::DBRawIteratorWithThreadMode;
-use rocksdb::Options;
-use rocksdb::DB;
+
+use crate::kvstore::mem::MemBackend;
+use crate::kvstore::rocks::RocksBackend;
 
 #[derive(Debug, thiserror::Error)]
 pub enum KvStoreError {
@@ -18,57 +21,385 @@ pub enum KvStoreError {
 
     #[error("Protobuf decode error: {0}")]
     DecodeError(#[from] prost::DecodeError),
+
+    #[error("Unknown durability: {0}")]
+    UnknownDurability(String),
+
+    #[error("Unknown family: {0}")]
+    UnknownFamily(String),
+
+    #[error("Layout file error: {0}")]
+    LayoutIo(#[from] std::io::Error),
+
+    #[error("Family {family} is missing from {path}, is the disk mounted?")]
+    FamilyMissing { family: String, path: String },
+
+    #[error(
+        "Store schema {stored} is newer than the {supported} this build knows; upgrade before opening it, or restore a backup"
+    )]
+    SchemaTooNew { stored: u32, supported: u32 },
+
+    #[error(
+        "Store schema {stored} is older than {current} and migrations are disabled; enable them to upgrade the store"
+    )]
+    SchemaOutdated { stored: u32, current: u32 },
+
+    #[error("Schema marker has {0} bytes instead of 4")]
+    CorruptSchemaMarker(usize),
+
+    #[error("Schema migration from {from} failed: {reason}")]
+    MigrationFailed { from: u32, reason: String },
+
+    #[error(
+        "Store at {path} has families [{families}] this build doesn't know, it was written by a newer version"
+    )]
+    UnknownFamilies { path: String, families: String },
+
+    #[error(
+        "Store at {path} was created with family paths [{stored}] but [{configured}] are configured; move the family data before changing paths"
+    )]
+    LayoutMismatch {
+        path: String,
+        stored: String,
+        configured: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Family {
+    Secrets,
+    Data, // metadata, profiles, registry etc.
+    Log,  // chat messages, execution results, events etc.
+}
+
+impl Family {
+    pub const ALL: [Family; 3] = [Family::Secrets, Family::Data, Family::Log];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Family::Secrets => "secrets",
+            Family::Data => "data",
+            Family::Log => "log",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Family> {
+        Family::ALL.into_iter().find(|family| family.name() == name)
+    }
+}
+
+/// Directories for families kept apart from the store, e.g. the log on a larger disk.
+/// Families without one stay in the store directory.
+///
+/// The layout is fixed when the store is created: moving a family of an existing store
+/// means moving its data by hand, and opening with other paths than the store was
+/// created with fails instead of starting an empty family next to the old one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FamilyPaths {
+    pub secrets: Option<PathBuf>,
+    pub data: Option<PathBuf>,
+    pub log: Option<PathBuf>,
+}
+
+impl FamilyPaths {
+    pub fn path(&self, family: Family) -> Option<&Path> {
+        match family {
+            Family::Secrets => self.secrets.as_deref(),
+            Family::Data => self.data.as_deref(),
+            Family::Log => self.log.as_deref(),
+        }
+    }
+
+    fn path_mut(&mut self, family: Family) -> &mut Option<PathBuf> {
+        match family {
+            Family::Secrets => &mut self.secrets,
+            Family::Data => &mut self.data,
+            Family::Log => &mut self.log,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        Family::ALL
+            .iter()
+            .all(|family| self.path(*family).is_none())
+    }
+}
+
+impl FromStr for FamilyPaths {
+    type Err = KvStoreError;
+
+    /// Parses a comma separated list like `log=/mnt/big/tim-log,secrets=/secure/tim`.
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        let mut paths = FamilyPaths::default();
+        for entry in list
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (name, path) = entry.split_once('=').unwrap_or((entry, ""));
+            let family = Family::from_name(name.trim())
+                .ok_or_else(|| KvStoreError::UnknownFamily(name.trim().to_string()))?;
+            let path = path.trim();
+            *paths.path_mut(family) = (!path.is_empty()).then(|| PathBuf::from(path));
+        }
+        Ok(paths)
+    }
+}
+
+/// How hard a write tries to survive a crash. Stronger levels cost write throughput,
+/// since every write waits for the disk instead of the OS page cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Durability {
+    /// Written to the WAL without waiting for the disk; a machine crash may lose recent writes.
+    #[default]
+    Relaxed,
+    /// The WAL is synced (fdatasync) before the write returns.
+    WalSync,
+    /// Like `WalSync` but with a full fsync, which also flushes file metadata.
+    Fsync,
+}
+
+impl FromStr for Durability {
+    type Err = KvStoreError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "relaxed" => Ok(Durability::Relaxed),
+            "wal_sync" => Ok(Durability::WalSync),
+            "fsync" => Ok(Durability::Fsync),
+            _ => Err(KvStoreError::UnknownDurability(name.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KvStoreConf {
+    pub secrets: Durability,
+    pub data: Durability,
+    pub log: Durability,
+}
+
+impl KvStoreConf {
+    /// Same durability for every family.
+    pub fn uniform(durability: Durability) -> Self {
+        Self {
+            secrets: durability,
+            data: durability,
+            log: durability,
+        }
+    }
+
+    pub fn durability(&self, family: Family) -> Durability {
+        match family {
+            Family::Secrets => self.secrets,
+            Family::Data => self.data,
+            Family::Log => self.log,
+        }
+    }
+}
+
+impl Default for KvStoreConf {
+    fn default() -> Self {
+        // losing a session secret logs the client out, so those writes are synced
+        Self {
+            secrets: Durability::WalSync,
+            data: Durability::Relaxed,
+            log: Durability::Relaxed,
+        }
+    }
+}
+
+/// Raw byte storage behind `KvStore`. Keys are ordered lexicographically within a family.
+pub trait KvBackend: Send + Sync {
+    fn get(&self, family: Family, key: &[u8]) -> Result<Option<Vec<u8>>, KvStoreError>;
+
+    fn put(
+        &self,
+        family: Family,
+        key: &[u8],
+        value: Vec<u8>,
+        durability: Durability,
+    ) -> Result<(), KvStoreError>;
+
+    fn delete(
+        &self,
+        family: Family,
+        key: &[u8],
+        durability: Durability,
+    ) -> Result<(), KvStoreError>;
+
+    /// Writes all entries atomically.
+    fn put_batch(
+        &self,
+        family: Family,
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        durability: Durability,
+    ) -> Result<(), KvStoreError>;
+
+    /// Values of keys starting with `prefix`, in key order, beginning at `start`
+    /// (or at `prefix` when `start` is empty) and stopping at the first key outside the prefix.
+    fn scan(
+        &self,
+        family: Family,
+        prefix: &[u8],
+        start: &[u8],
+        limit: Option<usize>,
+    ) -> Result<Vec<Vec<u8>>, KvStoreError>;
+
+    /// Value of the greatest key starting with `prefix`.
+    fn last(&self, family: Family, prefix: &[u8]) -> Result<Option<Vec<u8>>, KvStoreError>;
 }
 
 pub struct KvStore {
-    db: Arc<DB>,
+    backend: Arc<dyn KvBackend>,
+    conf: KvStoreConf,
 }
 
-const F_SECRETS: &str = "secrets";
-const F_DATA: &str = "data"; // metadata, profiles, registry etc.
-const F_LOG: &str = "log"; // chat messages, execution results, events etc.
+/// Reserved data key holding the schema version as a big-endian u32. The leading zero
+/// byte sorts it before any key the store's users write.
+const SCHEMA_VERSION_KEY: &[u8] = b"\0schema_version";
+
+/// Upgrades a store written with schema `from` to `from + 1`.
+pub struct Migration {
+    pub from: u32,
+    pub run: fn(&KvStore) -> Result<(), KvStoreError>,
+}
 
-const FAMILIES: &[&str] = &[F_SECRETS, F_DATA, F_LOG];
+/// Log records of any types, written together by `KvStore::write_log_batch`.
+#[derive(Debug, Default)]
+pub struct LogBatch {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl LogBatch {
+    pub fn put<V: Message>(&mut self, key: Vec<u8>, value: &V) {
+        self.entries.push((key, value.encode_to_vec()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
 
 impl KvStore {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<KvStore, KvStoreError> {
-        let db = start_rocks_db(path)?;
-        Ok(KvStore { db: Arc::new(db) })
+    pub fn new<P: AsRef<Path>>(path: P, conf: KvStoreConf) -> Result<KvStore, KvStoreError> {
+        Self::with_family_paths(path, &FamilyPaths::default(), conf)
+    }
+
+    pub fn with_family_paths<P: AsRef<Path>>(
+        path: P,
+        paths: &FamilyPaths,
+        conf: KvStoreConf,
+    ) -> Result<KvStore, KvStoreError> {
+        let backend = RocksBackend::open_with_paths(path, paths, &conf)?;
+        Ok(Self::with_backend(Arc::new(backend), conf))
+    }
+
+    pub fn in_memory() -> KvStore {
+        Self::with_backend(Arc::new(MemBackend::new()), KvStoreConf::default())
+    }
+
+    pub fn with_backend(backend: Arc<dyn KvBackend>, conf: KvStoreConf) -> KvStore {
+        KvStore { backend, conf }
+    }
+
+    /// Schema version recorded in the store, `None` for stores from before versions
+    /// were recorded and for new ones.
+    pub fn schema_version(&self) -> Result<Option<u32>, KvStoreError> {
+        let Some(bytes) = self.backend.get(Family::Data, SCHEMA_VERSION_KEY)? else {
+            return Ok(None);
+        };
+        let bytes: [u8; 4] = bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| KvStoreError::CorruptSchemaMarker(bytes.len()))?;
+        Ok(Some(u32::from_be_bytes(bytes)))
+    }
+
+    fn set_schema_version(&self, version: u32) -> Result<(), KvStoreError> {
+        let durability = self.conf.data.max(Durability::WalSync);
+        self.backend.put(
+            Family::Data,
+            SCHEMA_VERSION_KEY,
+            version.to_be_bytes().to_vec(),
+            durability,
+        )
+    }
+
+    /// Brings the store to schema `current`. A new store is marked with `current` right
+    /// away; a store without a marker but with data is taken as schema 0. Older stores
+    /// run the `migrations` from their version on, each recorded once it finished, so an
+    /// interrupted upgrade resumes where it stopped. Stores newer than `current` are
+    /// refused, this build would misread them.
+    pub fn migrate(
+        &self,
+        current: u32,
+        migrations: &[Migration],
+        auto_migrate: bool,
+    ) -> Result<(), KvStoreError> {
+        let stored = match self.schema_version()? {
+            Some(stored) => stored,
+            None if self.is_empty()? => return self.set_schema_version(current),
+            None => 0,
+        };
+        if stored > current {
+            return Err(KvStoreError::SchemaTooNew {
+                stored,
+                supported: current,
+            });
+        }
+        if stored < current && !auto_migrate {
+            return Err(KvStoreError::SchemaOutdated { stored, current });
+        }
+        for version in stored..current {
+            let migration = migrations
+                .iter()
+                .find(|migration| migration.from == version)
+                .ok_or_else(|| KvStoreError::MigrationFailed {
+                    from: version,
+                    reason: "no migration for this version".to_string(),
+                })?;
+            (migration.run)(self).map_err(|err| KvStoreError::MigrationFailed {
+                from: version,
+                reason: err.to_string(),
+            })?;
+            self.set_schema_version(version + 1)?;
+        }
+        Ok(())
+    }
+
+    fn is_empty(&self) -> Result<bool, KvStoreError> {
+        for family in Family::ALL {
+            if !self.backend.scan(family, &[], &[], Some(1))?.is_empty() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
     pub fn fetch_max_data<V: Message + Default>(
         &self,
         prefix: &[u8],
     ) -> Result<Option<V>, KvStoreError> {
-        let cf = get_cf(&self.db, F_DATA)?;
-        self.fetch_max_prefixed_value::<V>(cf, prefix)
+        self.fetch_max_prefixed_value(Family::Data, prefix)
     }
 
     pub fn fetch_all_data<V: Message + Default>(
         &self,
         prefix: &[u8],
     ) -> Result<Vec<V>, KvStoreError> {
-        let cf = get_cf(&self.db, F_DATA)?;
-
-        let mut iter = self.db.raw_iterator_cf(&cf);
-        let entries = collect_prefixed_entries(&mut iter, prefix)?;
-
-        let mut result: Vec<V> = Vec::new();
-
-        for bytes in entries {
-            let value = V::decode(bytes.as_slice())?;
-            result.push(value);
-        }
-
-        Ok(result)
+        self.fetch_prefixed_values(Family::Data, prefix, &[], None)
     }
 
     pub fn fetch_secret<V: Message + Default>(
         &self,
         key: &[u8],
     ) -> Result<Option<V>, KvStoreError> {
-        let cf = get_cf(&self.db, F_SECRETS)?;
-        self.get_value(cf, key)
+        self.get_value(Family::Secrets, key)
     }
 
     pub fn store_secret<V: Message + Default>(
@@ -76,13 +407,15 @@ impl KvStore {
         key: &[u8],
         value: &V,
     ) -> Result<(), KvStoreError> {
-        let cf = get_cf(&self.db, F_SECRETS)?;
-        self.put_value(cf, key, value)
+        self.put_value(Family::Secrets, key, value)
+    }
+
+    pub fn delete_secret(&self, key: &[u8]) -> Result<(), KvStoreError> {
+        self.backend.delete(Family::Secrets, key, self.conf.secrets)
     }
 
     pub fn fetch_data<V: Message + Default>(&self, key: &[u8]) -> Result<Option<V>, KvStoreError> {
-        let cf = get_cf(&self.db, F_DATA)?;
-        self.get_value::<V>(cf, key)
+        self.get_value(Family::Data, key)
     }
 
     pub fn store_data<V: Message + Default>(
@@ -90,38 +423,29 @@ impl KvStore {
         key: &[u8],
         value: &V,
     ) -> Result<(), KvStoreError> {
-        let cf = get_cf(&self.db, F_DATA)?;
-        self.put_value(cf, key, value)
+        self.put_value(Family::Data, key, value)
+    }
+
+    pub fn delete_data(&self, key: &[u8]) -> Result<(), KvStoreError> {
+        self.backend.delete(Family::Data, key, self.conf.data)
     }
 
     pub fn fetch_max_log<V: Message + Default>(
         &self,
         prefix: &[u8],
     ) -> Result<Option<V>, KvStoreError> {
-        let cf = get_cf(&self.db, F_LOG)?;
-        self.fetch_max_prefixed_value::<V>(cf, prefix)
+        self.fetch_max_prefixed_value(Family::Log, prefix)
     }
 
     pub fn fetch_log<V: Message + Default>(&self, key: &[u8]) -> Result<Option<V>, KvStoreError> {
-        let cf = get_cf(&self.db, F_LOG)?;
-        self.get_value::<V>(cf, key)
+        self.get_value(Family::Log, key)
     }
 
     pub fn fetch_all_log<V: Message + Default>(
         &self,
         prefix: &[u8],
     ) -> Result<Vec<V>, KvStoreError> {
-        let cf = get_cf(&self.db, F_LOG)?;
-        let mut iter = self.db.raw_iterator_cf(&cf);
-        let entries = collect_prefixed_entries(&mut iter, prefix)?;
-
-        let mut result = Vec::new();
-        for bytes in entries {
-            let value = V::decode(bytes.as_slice())?;
-            result.push(value);
-        }
-
-        Ok(result)
+        self.fetch_prefixed_values(Family::Log, prefix, &[], None)
     }
 
     pub fn fetch_log_range<V: Message + Default>(
@@ -133,30 +457,7 @@ impl KvStore {
         if limit == 0 {
             return Ok(Vec::new());
         }
-        let cf = get_cf(&self.db, F_LOG)?;
-        let mut iter = self.db.raw_iterator_cf(&cf);
-        if start.is_empty() {
-            iter.seek(prefix);
-        } else {
-            iter.seek(start);
-        }
-
-        let mut result = Vec::new();
-        while iter.valid() && result.len() < limit {
-            match iter.key() {
-                Some(key) if key.starts_with(prefix) => {
-                    if let Some(value) = iter.value() {
-                        let decoded = V::decode(value)?;
-                        result.push(decoded);
-                    }
-                }
-                _ => break,
-            }
-            iter.next();
-        }
-
-        iter.status()?;
-        Ok(result)
+        self.fetch_prefixed_values(Family::Log, prefix, start, Some(limit))
     }
 
     pub fn store_log<V: Message + Default>(
@@ -164,16 +465,42 @@ impl KvStore {
         key: &[u8],
         value: &V,
     ) -> Result<(), KvStoreError> {
-        let cf = get_cf(&self.db, F_LOG)?;
-        self.put_value(cf, key, value)
+        self.put_value(Family::Log, key, value)
+    }
+
+    pub fn delete_log(&self, key: &[u8]) -> Result<(), KvStoreError> {
+        self.backend.delete(Family::Log, key, self.conf.log)
+    }
+
+    pub fn store_log_batch<V: Message + Default>(
+        &self,
+        entries: &[(Vec<u8>, V)],
+    ) -> Result<(), KvStoreError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let encoded = entries
+            .iter()
+            .map(|(key, value)| (key.clone(), value.encode_to_vec()))
+            .collect();
+        self.backend.put_batch(Family::Log, encoded, self.conf.log)
+    }
+
+    /// Writes all records of `batch` atomically.
+    pub fn write_log_batch(&self, batch: LogBatch) -> Result<(), KvStoreError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.backend
+            .put_batch(Family::Log, batch.entries, self.conf.log)
     }
 
     fn get_value<V: Message + Default>(
         &self,
-        cf: &ColumnFamily,
+        family: Family,
         key: &[u8],
     ) -> Result<Option<V>, KvStoreError> {
-        match self.db.get_cf(cf, key)? {
+        match self.backend.get(family, key)? {
             Some(bytes) => {
                 let value = V::decode(&bytes[..])?;
                 Ok(Some(value))
@@ -184,24 +511,39 @@ impl KvStore {
 
     fn put_value<V: Message + Default>(
         &self,
-        cf: &ColumnFamily,
+        family: Family,
         key: &[u8],
         value: &V,
     ) -> Result<(), KvStoreError> {
-        let bytes = value.encode_to_vec();
-        self.db.put_cf(cf, key, bytes)?;
-        Ok(())
+        let durability = self.conf.durability(family);
+        self.backend
+            .put(family, key, value.encode_to_vec(), durability)
+    }
+
+    fn fetch_prefixed_values<V: Message + Default>(
+        &self,
+        family: Family,
+        prefix: &[u8],
+        start: &[u8],
+        limit: Option<usize>,
+    ) -> Result<Vec<V>, KvStoreError> {
+        let entries = self.backend.scan(family, prefix, start, limit)?;
+
+        let mut result = Vec::new();
+        for bytes in entries {
+            let value = V::decode(bytes.as_slice())?;
+            result.push(value);
+        }
+
+        Ok(result)
     }
 
     fn fetch_max_prefixed_value<V: Message + Default>(
         &self,
-        cf: &ColumnFamily,
+        family: Family,
         prefix: &[u8],
     ) -> Result<Option<V>, KvStoreError> {
-        let mut iter = self.db.raw_iterator_cf(cf);
-        let bytes = collect_last_prefixed_value(&mut iter, prefix)?;
-
-        match bytes {
+        match self.backend.last(family, prefix)? {
             Some(data) => {
                 let value = V::decode(data.as_slice())?;
                 Ok(Some(value))
@@ -210,73 +552,3 @@ impl KvStore {
         }
     }
 }
-
-fn get_cf<'a>(db: &'a DB, name: &'static str) -> Result<&'a ColumnFamily, KvStoreError> {
-    db.cf_handle(name)
-        .ok_or("failed")
-        .map_err(|e| KvStoreError::KeysetNotFound(e.to_string()))
-}
-
-fn collect_last_prefixed_value<'a, D>(
-    iter: &mut DBRawIteratorWithThreadMode<'a, D>,
-    prefix: &[u8],
-) -> Result<Option<Vec<u8>>, KvStoreError>
-where
-    D: DBAccess,
-{
-    iter.seek(prefix);
-
-    let mut last_value = None;
-
-    while iter.valid() {
-        match iter.key() {
-            Some(key) if key.starts_with(prefix) => {
-                if let Some(value) = iter.value() {
-                    last_value = Some(value.to_vec());
-                }
-            }
-            _ => break,
-        }
-
-        iter.next();
-    }
-
-    iter.status()?;
-    Ok(last_value)
-}
-
-fn collect_prefixed_entries<'a, D>(
-    iter: &mut DBRawIteratorWithThreadMode<'a, D>,
-    prefix: &[u8],
-) -> Result<Vec<Vec<u8>>, KvStoreError>
-where
-    D: DBAccess,
-{
-    iter.seek(prefix);
-
-    let mut result: Vec<Vec<u8>> = Vec::new();
-
-    while iter.valid() {
-        match iter.key() {
-            Some(key) if key.starts_with(prefix) => {
-                if let Some(value) = iter.value() {
-                    result.push(value.to_vec());
-                }
-            }
-            _ => break,
-        }
-
-        iter.next();
-    }
-
-    iter.status()?;
-    Ok(result)
-}
-
-pub fn start_rocks_db<P: AsRef<Path>>(path: P) -> Result<DB, KvStoreError> {
-    let mut opts = Options::default();
-    opts.create_if_missing(true);
-    opts.create_missing_column_families(true);
-    let db = DB::open_cf(&opts, path, FAMILIES)?;
-    Ok(db)
-}
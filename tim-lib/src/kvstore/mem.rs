@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::RwLock;
+
+use crate::kvstore::Durability;
+use crate::kvstore::Family;
+use crate::kvstore::KvBackend;
+use crate::kvstore::KvStoreError;
+
+type Keyspace = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Volatile backend for tests; mirrors the ordering and scan semantics of `RocksBackend`.
+#[derive(Default)]
+pub struct MemBackend {
+    families: RwLock<BTreeMap<Family, Keyspace>>,
+}
+
+impl MemBackend {
+    pub fn new() -> MemBackend {
+        MemBackend::default()
+    }
+}
+
+impl KvBackend for MemBackend {
+    fn get(&self, family: Family, key: &[u8]) -> Result<Option<Vec<u8>>, KvStoreError> {
+        let guard = self.families.read().expect("kv memory lock poisoned");
+        Ok(guard
+            .get(&family)
+            .and_then(|keyspace| keyspace.get(key))
+            .cloned())
+    }
+
+    fn put(
+        &self,
+        family: Family,
+        key: &[u8],
+        value: Vec<u8>,
+        _durability: Durability,
+    ) -> Result<(), KvStoreError> {
+        let mut guard = self.families.write().expect("kv memory lock poisoned");
+        guard.entry(family).or_default().insert(key.to_vec(), value);
+        Ok(())
+    }
+
+    fn delete(
+        &self,
+        family: Family,
+        key: &[u8],
+        _durability: Durability,
+    ) -> Result<(), KvStoreError> {
+        let mut guard = self.families.write().expect("kv memory lock poisoned");
+        if let Some(keyspace) = guard.get_mut(&family) {
+            keyspace.remove(key);
+        }
+        Ok(())
+    }
+
+    fn put_batch(
+        &self,
+        family: Family,
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        _durability: Durability,
+    ) -> Result<(), KvStoreError> {
+        let mut guard = self.families.write().expect("kv memory lock poisoned");
+        guard.entry(family).or_default().extend(entries);
+        Ok(())
+    }
+
+    fn scan(
+        &self,
+        family: Family,
+        prefix: &[u8],
+        start: &[u8],
+        limit: Option<usize>,
+    ) -> Result<Vec<Vec<u8>>, KvStoreError> {
+        let guard = self.families.read().expect("kv memory lock poisoned");
+        let Some(keyspace) = guard.get(&family) else {
+            return Ok(Vec::new());
+        };
+        let seek = if start.is_empty() { prefix } else { start };
+        let values = keyspace
+            .range::<[u8], _>((Bound::Included(seek), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(_, value)| value.clone())
+            .collect();
+        Ok(values)
+    }
+
+    fn last(&self, family: Family, prefix: &[u8]) -> Result<Option<Vec<u8>>, KvStoreError> {
+        let guard = self.families.read().expect("kv memory lock poisoned");
+        let Some(keyspace) = guard.get(&family) else {
+            return Ok(None);
+        };
+        let value = keyspace
+            .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .last()
+            .map(|(_, value)| value.clone());
+        Ok(value)
+    }
+}
@@ -0,0 +1,290 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+
+use rocksdb::ColumnFamily;
+use rocksdb::Options;
+use rocksdb::WriteBatch;
+use rocksdb::WriteOptions;
+use rocksdb::DB;
+
+use crate::kvstore::Durability;
+use crate::kvstore::Family;
+use crate::kvstore::FamilyPaths;
+use crate::kvstore::KvBackend;
+use crate::kvstore::KvStoreConf;
+use crate::kvstore::KvStoreError;
+
+/// Written to the store directory of a store with families kept elsewhere.
+const LAYOUT_FILE: &str = "TIM_FAMILY_PATHS";
+/// Present in every RocksDB directory once the database was created.
+const ROCKS_CURRENT_FILE: &str = "CURRENT";
+
+pub struct RocksBackend {
+    /// One database per directory, the store directory first.
+    dbs: Vec<DB>,
+    /// Index into `dbs` for each family, in `Family::ALL` order.
+    family_db: [usize; Family::ALL.len()],
+}
+
+impl RocksBackend {
+    pub fn open<P: AsRef<Path>>(path: P, conf: &KvStoreConf) -> Result<RocksBackend, KvStoreError> {
+        Self::open_with_paths(path, &FamilyPaths::default(), conf)
+    }
+
+    /// RocksDB here has no per family paths, so each family directory holds a database
+    /// of its own. Fsync is a database wide option, so it is enabled when any family asks
+    /// for it.
+    pub fn open_with_paths<P: AsRef<Path>>(
+        path: P,
+        paths: &FamilyPaths,
+        conf: &KvStoreConf,
+    ) -> Result<RocksBackend, KvStoreError> {
+        let path = path.as_ref();
+        let use_fsync = Family::ALL
+            .iter()
+            .any(|family| conf.durability(*family) == Durability::Fsync);
+
+        let mut dirs = vec![path.to_path_buf()];
+        let mut family_db = [0; Family::ALL.len()];
+        for family in Family::ALL {
+            let Some(dir) = paths.path(family) else {
+                continue;
+            };
+            family_db[family as usize] = match dirs.iter().position(|known| known == dir) {
+                Some(index) => index,
+                None => {
+                    dirs.push(dir.to_path_buf());
+                    dirs.len() - 1
+                }
+            };
+        }
+        check_layout(path, paths, &dirs, &family_db)?;
+
+        let dbs = dirs
+            .iter()
+            .enumerate()
+            .map(|(index, dir)| {
+                let families = Family::ALL
+                    .into_iter()
+                    .filter(|family| family_db[*family as usize] == index)
+                    .map(Family::name);
+                open_rocks_db(dir, use_fsync, families)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(RocksBackend { dbs, family_db })
+    }
+
+    fn cf(&self, family: Family) -> Result<(&DB, &ColumnFamily), KvStoreError> {
+        let db = &self.dbs[self.family_db[family as usize]];
+        let cf = db
+            .cf_handle(family.name())
+            .ok_or_else(|| KvStoreError::KeysetNotFound(family.name().to_string()))?;
+        Ok((db, cf))
+    }
+}
+
+impl KvBackend for RocksBackend {
+    fn get(&self, family: Family, key: &[u8]) -> Result<Option<Vec<u8>>, KvStoreError> {
+        let (db, cf) = self.cf(family)?;
+        Ok(db.get_cf(cf, key)?)
+    }
+
+    fn put(
+        &self,
+        family: Family,
+        key: &[u8],
+        value: Vec<u8>,
+        durability: Durability,
+    ) -> Result<(), KvStoreError> {
+        let (db, cf) = self.cf(family)?;
+        db.put_cf_opt(cf, key, value, &write_options(durability))?;
+        Ok(())
+    }
+
+    fn delete(
+        &self,
+        family: Family,
+        key: &[u8],
+        durability: Durability,
+    ) -> Result<(), KvStoreError> {
+        let (db, cf) = self.cf(family)?;
+        db.delete_cf_opt(cf, key, &write_options(durability))?;
+        Ok(())
+    }
+
+    fn put_batch(
+        &self,
+        family: Family,
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        durability: Durability,
+    ) -> Result<(), KvStoreError> {
+        let (db, cf) = self.cf(family)?;
+        let mut batch = WriteBatch::default();
+        for (key, value) in entries {
+            batch.put_cf(cf, key, value);
+        }
+        db.write_opt(batch, &write_options(durability))?;
+        Ok(())
+    }
+
+    fn scan(
+        &self,
+        family: Family,
+        prefix: &[u8],
+        start: &[u8],
+        limit: Option<usize>,
+    ) -> Result<Vec<Vec<u8>>, KvStoreError> {
+        let (db, cf) = self.cf(family)?;
+        let mut iter = db.raw_iterator_cf(cf);
+        if start.is_empty() {
+            iter.seek(prefix);
+        } else {
+            iter.seek(start);
+        }
+
+        let mut result = Vec::new();
+        while iter.valid() && limit.is_none_or(|limit| result.len() < limit) {
+            match iter.key() {
+                Some(key) if key.starts_with(prefix) => {
+                    if let Some(value) = iter.value() {
+                        result.push(value.to_vec());
+                    }
+                }
+                _ => break,
+            }
+            iter.next();
+        }
+
+        iter.status()?;
+        Ok(result)
+    }
+
+    fn last(&self, family: Family, prefix: &[u8]) -> Result<Option<Vec<u8>>, KvStoreError> {
+        let (db, cf) = self.cf(family)?;
+        let mut iter = db.raw_iterator_cf(cf);
+        iter.seek(prefix);
+
+        let mut last_value = None;
+        while iter.valid() {
+            match iter.key() {
+                Some(key) if key.starts_with(prefix) => {
+                    if let Some(value) = iter.value() {
+                        last_value = Some(value.to_vec());
+                    }
+                }
+                _ => break,
+            }
+            iter.next();
+        }
+
+        iter.status()?;
+        Ok(last_value)
+    }
+}
+
+fn write_options(durability: Durability) -> WriteOptions {
+    let mut opts = WriteOptions::default();
+    opts.set_sync(durability != Durability::Relaxed);
+    opts
+}
+
+pub fn start_rocks_db<P: AsRef<Path>>(path: P, use_fsync: bool) -> Result<DB, KvStoreError> {
+    open_rocks_db(path.as_ref(), use_fsync, Family::ALL.map(Family::name))
+}
+
+fn open_rocks_db<'a>(
+    path: &Path,
+    use_fsync: bool,
+    families: impl IntoIterator<Item = &'a str>,
+) -> Result<DB, KvStoreError> {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    opts.set_use_fsync(use_fsync);
+    if path.join(ROCKS_CURRENT_FILE).exists() {
+        check_families(path, &opts)?;
+    }
+    let db = DB::open_cf(&opts, path, families)?;
+    Ok(db)
+}
+
+/// Opening only the known families would leave the others unread and unwritten, so a
+/// database with families from a newer build is refused.
+fn check_families(path: &Path, opts: &Options) -> Result<(), KvStoreError> {
+    let unknown: Vec<String> = DB::list_cf(opts, path)?
+        .into_iter()
+        .filter(|name| name != rocksdb::DEFAULT_COLUMN_FAMILY_NAME)
+        .filter(|name| Family::from_name(name).is_none())
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(KvStoreError::UnknownFamilies {
+        path: path.display().to_string(),
+        families: unknown.join(","),
+    })
+}
+
+/// Refuses to open with other family paths than the store was created with, and
+/// family directories that lost their database, both of which would otherwise start
+/// an empty family. Stores without a layout file keep every family in one directory.
+fn check_layout(
+    path: &Path,
+    paths: &FamilyPaths,
+    dirs: &[PathBuf],
+    family_db: &[usize],
+) -> Result<(), KvStoreError> {
+    let layout_file = path.join(LAYOUT_FILE);
+    let configured = describe_layout(paths);
+    let stored = match fs::read_to_string(&layout_file) {
+        Ok(stored) => stored.trim().to_string(),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            if !path.join(ROCKS_CURRENT_FILE).exists() {
+                // a new store takes the configured layout
+                if !paths.is_empty() {
+                    fs::create_dir_all(path)?;
+                    fs::write(&layout_file, &configured)?;
+                }
+                return Ok(());
+            }
+            describe_layout(&FamilyPaths::default())
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if stored != configured {
+        return Err(KvStoreError::LayoutMismatch {
+            path: path.display().to_string(),
+            stored,
+            configured,
+        });
+    }
+    // nothing to lose yet if the store itself was never created
+    if !path.join(ROCKS_CURRENT_FILE).exists() {
+        return Ok(());
+    }
+    for family in Family::ALL {
+        let dir = &dirs[family_db[family as usize]];
+        if !dir.join(ROCKS_CURRENT_FILE).exists() {
+            return Err(KvStoreError::FamilyMissing {
+                family: family.name().to_string(),
+                path: dir.display().to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Same format `FamilyPaths` parses, families kept in the store directory left out.
+fn describe_layout(paths: &FamilyPaths) -> String {
+    Family::ALL
+        .into_iter()
+        .filter_map(|family| {
+            paths
+                .path(family)
+                .map(|path| format!("{}={}", family.name(), path.display()))
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -1 +1,4 @@
+#[cfg(feature = "kvstore")]
 pub mod kvstore;
+pub mod sequence;
+pub mod space_stream;
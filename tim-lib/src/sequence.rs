@@ -0,0 +1,57 @@
+use std::ops::Range;
+
+/// Follows the stored events of a room subscription and reports the ids that
+/// never arrived, so a client can fetch them from the timeline.
+///
+/// Event ids are shared by all rooms and by events that are never stored, so a
+/// skipped id alone means nothing. Each stored event names the one stored before
+/// it in its room, and only a previous event that wasn't seen counts as a gap.
+///
+/// Subscriptions that filter stored events skip them on purpose. Those either use
+/// a [`GapDetector::disabled`] detector or drop the filtered events from what the
+/// catch-up returns.
+#[derive(Debug, Clone, Default)]
+pub struct GapDetector {
+    last: Option<u64>,
+    disabled: bool,
+}
+
+impl GapDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A detector that never reports a gap.
+    pub fn disabled() -> Self {
+        Self {
+            last: None,
+            disabled: true,
+        }
+    }
+
+    /// Id of the last stored event seen.
+    pub fn last_seen(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// Records the stored event `id`, which follows `prev_id` in its room, and
+    /// returns the ids between the previous one seen and `prev_id` when that one
+    /// was missed. The first event seen only sets the baseline. Events that aren't
+    /// stored (`prev_id` unset), id 0 (no metadata), repeats and ids older than the
+    /// last one are ignored.
+    pub fn observe(&mut self, id: u64, prev_id: Option<u64>) -> Option<Range<u64>> {
+        let prev_id = prev_id?;
+        if id == 0 {
+            return None;
+        }
+        let last = match self.last {
+            Some(last) if id <= last => return None,
+            last => last,
+        };
+        self.last = Some(id);
+        match last {
+            Some(last) if !self.disabled && prev_id > last => Some(last + 1..prev_id + 1),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::Stream;
+use futures::StreamExt;
+
+/// A live subscription as handed out by [`SpaceSource::subscribe`].
+pub type LiveEvents<E, Err> = BoxStream<'static, Result<E, Err>>;
+
+pub type Subscribing<'a, E, Err> = BoxFuture<'a, Result<LiveEvents<E, Err>, Err>>;
+
+/// What [`ResilientSpaceStream`] needs from a client: a way to subscribe and a way to
+/// read stored events back by id.
+pub trait SpaceSource: Send + 'static {
+    type Event: Send + 'static;
+    type Error: Display + Send + 'static;
+
+    fn subscribe(&mut self) -> Subscribing<'_, Self::Event, Self::Error>;
+
+    /// Up to `limit` stored events with ids from `from_id` on, oldest first.
+    fn replay(
+        &mut self,
+        from_id: u64,
+        limit: u32,
+    ) -> BoxFuture<'_, Result<Vec<Self::Event>, Self::Error>>;
+
+    /// The event's id, 0 when it has none.
+    fn event_id(event: &Self::Event) -> u64;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpaceStreamItem<E> {
+    Event(E),
+    /// The subscription is down; `attempt` counts the tries since it last worked.
+    Reconnecting {
+        attempt: u32,
+        error: String,
+    },
+    /// Subscribed again. Events missed meanwhile follow, then live ones.
+    Reconnected,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Tries in a row before the stream ends, `None` keeps trying forever.
+    pub max_attempts: Option<u32>,
+    /// Most events replayed after a reconnect; older missed ones are skipped.
+    pub replay_limit: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+            max_attempts: None,
+            replay_limit: 1000,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// A space subscription that survives disconnects. Dropped subscriptions are
+/// re-established with backoff and the events missed in between are replayed from
+/// the last seen id, so consumers see each event once and in order. Reconnects are
+/// reported as [`SpaceStreamItem::Reconnecting`] and [`SpaceStreamItem::Reconnected`].
+/// The stream only ends when `max_attempts` runs out.
+pub struct ResilientSpaceStream<S: SpaceSource> {
+    inner: BoxStream<'static, SpaceStreamItem<S::Event>>,
+}
+
+enum Connect<E> {
+    Connected,
+    Retry(SpaceStreamItem<E>),
+    GaveUp,
+}
+
+struct State<S: SpaceSource> {
+    source: S,
+    policy: ReconnectPolicy,
+    live: Option<LiveEvents<S::Event, S::Error>>,
+    last_id: Option<u64>,
+    attempt: u32,
+    ready: VecDeque<SpaceStreamItem<S::Event>>,
+}
+
+impl<S: SpaceSource> ResilientSpaceStream<S> {
+    pub fn new(source: S, policy: ReconnectPolicy) -> Self {
+        let state = State {
+            source,
+            policy,
+            live: None,
+            last_id: None,
+            attempt: 0,
+            ready: VecDeque::new(),
+        };
+        Self {
+            inner: futures::stream::unfold(state, next_item).boxed(),
+        }
+    }
+}
+
+impl<S: SpaceSource> Stream for ResilientSpaceStream<S> {
+    type Item = SpaceStreamItem<S::Event>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+async fn next_item<S: SpaceSource>(
+    mut state: State<S>,
+) -> Option<(SpaceStreamItem<S::Event>, State<S>)> {
+    loop {
+        if let Some(item) = state.ready.pop_front() {
+            return Some((item, state));
+        }
+        let Some(live) = state.live.as_mut() else {
+            match state.connect().await {
+                Connect::Connected => continue,
+                Connect::Retry(reconnecting) => return Some((reconnecting, state)),
+                Connect::GaveUp => return None,
+            }
+        };
+        let error = match live.next().await {
+            Some(Ok(event)) => {
+                if state.is_new(&event) {
+                    return Some((SpaceStreamItem::Event(event), state));
+                }
+                continue;
+            }
+            Some(Err(err)) => err.to_string(),
+            None => "subscription closed".to_string(),
+        };
+        state.live = None;
+        state.attempt = 1;
+        return Some((SpaceStreamItem::Reconnecting { attempt: 1, error }, state));
+    }
+}
+
+impl<S: SpaceSource> State<S> {
+    /// Records the event's id and tells whether it was not seen yet. Events without
+    /// an id can't be deduplicated and always pass.
+    fn is_new(&mut self, event: &S::Event) -> bool {
+        let id = S::event_id(event);
+        if id == 0 {
+            return true;
+        }
+        if self.last_id.is_some_and(|last| id <= last) {
+            return false;
+        }
+        self.last_id = Some(id);
+        true
+    }
+
+    /// Subscribes and queues the events missed since the last seen one.
+    async fn connect(&mut self) -> Connect<S::Event> {
+        if self.attempt > 0 {
+            tokio::time::sleep(self.policy.backoff(self.attempt)).await;
+        }
+        let live = match self.source.subscribe().await {
+            Ok(live) => live,
+            Err(err) => return self.failed(err.to_string()),
+        };
+        let mut replayed = Vec::new();
+        if let Some(last) = self.last_id {
+            match self.source.replay(last + 1, self.policy.replay_limit).await {
+                Ok(events) => replayed = events,
+                Err(err) => return self.failed(err.to_string()),
+            }
+        }
+        if self.attempt > 0 {
+            self.ready.push_back(SpaceStreamItem::Reconnected);
+        }
+        for event in replayed {
+            if self.is_new(&event) {
+                self.ready.push_back(SpaceStreamItem::Event(event));
+            }
+        }
+        self.live = Some(live);
+        self.attempt = 0;
+        Connect::Connected
+    }
+
+    fn failed(&mut self, error: String) -> Connect<S::Event> {
+        if self
+            .policy
+            .max_attempts
+            .is_some_and(|max| self.attempt >= max)
+        {
+            return Connect::GaveUp;
+        }
+        self.attempt += 1;
+        Connect::Retry(SpaceStreamItem::Reconnecting {
+            attempt: self.attempt,
+            error,
+        })
+    }
+}
@@ -0,0 +1,72 @@
+#![cfg(feature = "kvstore")]
+
+use tempfile::tempdir;
+use tim_lib::kvstore::mem::MemBackend;
+use tim_lib::kvstore::rocks::RocksBackend;
+use tim_lib::kvstore::Durability;
+use tim_lib::kvstore::Family;
+use tim_lib::kvstore::KvBackend;
+use tim_lib::kvstore::KvStoreConf;
+
+fn fill(backend: &dyn KvBackend) -> Result<(), Box<dyn std::error::Error>> {
+    let keys: [&[u8]; 7] = [b"a/1", b"a/10", b"a/2", b"a/\xff", b"ab", b"b/1", b"a"];
+    for key in keys {
+        backend.put(Family::Log, key, key.to_vec(), Durability::Relaxed)?;
+    }
+    backend.put_batch(
+        Family::Log,
+        vec![
+            (b"c/1".to_vec(), b"c1".to_vec()),
+            (b"c/0".to_vec(), b"c0".to_vec()),
+        ],
+        Durability::Relaxed,
+    )?;
+    backend.put(Family::Data, b"a/1", b"data".to_vec(), Durability::WalSync)?;
+    backend.put(
+        Family::Secrets,
+        b"gone",
+        b"secret".to_vec(),
+        Durability::Fsync,
+    )?;
+    backend.delete(Family::Secrets, b"gone", Durability::Fsync)?;
+    Ok(())
+}
+
+fn observe(backend: &dyn KvBackend) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut seen = Vec::new();
+    let scans: [(&[u8], &[u8], Option<usize>); 7] = [
+        (b"a/", b"", None),
+        (b"a", b"", None),
+        (b"a/", b"a/10", None),
+        (b"a/", b"a/10", Some(2)),
+        (b"a/", b"0", None),
+        (b"c", b"", None),
+        (b"d", b"", None),
+    ];
+    for (prefix, start, limit) in scans {
+        seen.push(format!(
+            "scan {:?}",
+            backend.scan(Family::Log, prefix, start, limit)?
+        ));
+    }
+    for prefix in [&b"a/"[..], b"a", b"b", b"c", b""] {
+        seen.push(format!("last {:?}", backend.last(Family::Log, prefix)?));
+    }
+    seen.push(format!("get {:?}", backend.get(Family::Data, b"a/1")?));
+    seen.push(format!("get {:?}", backend.get(Family::Data, b"a/2")?));
+    seen.push(format!("get {:?}", backend.get(Family::Secrets, b"gone")?));
+    Ok(seen)
+}
+
+#[test]
+fn mem_backend_matches_rocks_backend() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let rocks = RocksBackend::open(temp_dir.path().join("kv"), &KvStoreConf::default())?;
+    let mem = MemBackend::new();
+
+    fill(&rocks)?;
+    fill(&mem)?;
+
+    assert_eq!(observe(&mem)?, observe(&rocks)?);
+    Ok(())
+}
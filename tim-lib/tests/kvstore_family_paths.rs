@@ -0,0 +1,121 @@
+#![cfg(feature = "kvstore")]
+
+use std::path::PathBuf;
+
+use tempfile::tempdir;
+use tim_lib::kvstore::FamilyPaths;
+use tim_lib::kvstore::KvStore;
+use tim_lib::kvstore::KvStoreConf;
+use tim_lib::kvstore::KvStoreError;
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Entry {
+    #[prost(uint64, tag = "1")]
+    value: u64,
+}
+
+fn entry(value: u64) -> Entry {
+    Entry { value }
+}
+
+#[test]
+fn family_paths_parse() -> Result<(), Box<dyn std::error::Error>> {
+    let paths: FamilyPaths = " log=/mnt/big/tim-log , secrets=/secure/tim ".parse()?;
+    assert_eq!(paths.log, Some(PathBuf::from("/mnt/big/tim-log")));
+    assert_eq!(paths.secrets, Some(PathBuf::from("/secure/tim")));
+    assert_eq!(paths.data, None);
+    assert!("".parse::<FamilyPaths>()?.is_empty());
+    assert!(matches!(
+        "logs=/tmp".parse::<FamilyPaths>(),
+        Err(KvStoreError::UnknownFamily(name)) if name == "logs"
+    ));
+    Ok(())
+}
+
+#[test]
+fn split_family_lives_in_its_own_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().join("kv");
+    let log_dir = temp_dir.path().join("log");
+    let paths = FamilyPaths {
+        log: Some(log_dir.clone()),
+        ..FamilyPaths::default()
+    };
+
+    {
+        let store = KvStore::with_family_paths(&root, &paths, KvStoreConf::default())?;
+        store.store_log(b"event/1", &entry(1))?;
+        store.store_data(b"timite/1", &entry(2))?;
+    }
+    assert!(
+        log_dir.join("CURRENT").exists(),
+        "log should get its own database"
+    );
+
+    let store = KvStore::with_family_paths(&root, &paths, KvStoreConf::default())?;
+    assert_eq!(store.fetch_log::<Entry>(b"event/1")?, Some(entry(1)));
+    assert_eq!(store.fetch_data::<Entry>(b"timite/1")?, Some(entry(2)));
+    Ok(())
+}
+
+#[test]
+fn changed_family_paths_are_refused() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let single = temp_dir.path().join("single");
+    let split = temp_dir.path().join("split");
+    let paths = FamilyPaths {
+        log: Some(temp_dir.path().join("log")),
+        ..FamilyPaths::default()
+    };
+
+    drop(KvStore::new(&single, KvStoreConf::default())?);
+    assert!(matches!(
+        KvStore::with_family_paths(&single, &paths, KvStoreConf::default()),
+        Err(KvStoreError::LayoutMismatch { .. })
+    ));
+
+    drop(KvStore::with_family_paths(
+        &split,
+        &paths,
+        KvStoreConf::default(),
+    )?);
+    assert!(matches!(
+        KvStore::new(&split, KvStoreConf::default()),
+        Err(KvStoreError::LayoutMismatch { .. })
+    ));
+    let moved = FamilyPaths {
+        log: Some(temp_dir.path().join("elsewhere")),
+        ..FamilyPaths::default()
+    };
+    assert!(matches!(
+        KvStore::with_family_paths(&split, &moved, KvStoreConf::default()),
+        Err(KvStoreError::LayoutMismatch { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn missing_family_directory_is_refused() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().join("kv");
+    let log_dir = temp_dir.path().join("log");
+    let paths = FamilyPaths {
+        log: Some(log_dir.clone()),
+        ..FamilyPaths::default()
+    };
+
+    drop(KvStore::with_family_paths(
+        &root,
+        &paths,
+        KvStoreConf::default(),
+    )?);
+    // e.g. the disk holding the log is not mounted
+    std::fs::remove_dir_all(&log_dir)?;
+
+    assert!(matches!(
+        KvStore::with_family_paths(&root, &paths, KvStoreConf::default()),
+        Err(KvStoreError::FamilyMissing { family, .. }) if family == "log"
+    ));
+    assert!(!log_dir.exists(), "no empty log should be started");
+    Ok(())
+}
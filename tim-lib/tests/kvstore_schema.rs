@@ -0,0 +1,131 @@
+#![cfg(feature = "kvstore")]
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use tempfile::tempdir;
+use tim_lib::kvstore::KvStore;
+use tim_lib::kvstore::KvStoreConf;
+use tim_lib::kvstore::KvStoreError;
+use tim_lib::kvstore::Migration;
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Entry {
+    #[prost(uint64, tag = "1")]
+    value: u64,
+}
+
+static RENAMES: AtomicUsize = AtomicUsize::new(0);
+
+// schema 0 kept entries under "old/", schema 1 moved them to "new/"
+fn rename_entries(store: &KvStore) -> Result<(), KvStoreError> {
+    RENAMES.fetch_add(1, Ordering::Relaxed);
+    if let Some(entry) = store.fetch_data::<Entry>(b"old/1")? {
+        store.store_data(b"new/1", &entry)?;
+        store.delete_data(b"old/1")?;
+    }
+    Ok(())
+}
+
+fn add_nothing(_: &KvStore) -> Result<(), KvStoreError> {
+    Ok(())
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        run: rename_entries,
+    },
+    Migration {
+        from: 1,
+        run: add_nothing,
+    },
+];
+
+#[test]
+fn new_store_starts_at_the_current_schema() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let store = KvStore::new(temp_dir.path(), KvStoreConf::default())?;
+    assert_eq!(store.schema_version()?, None);
+
+    store.migrate(2, &[], true)?;
+    assert_eq!(store.schema_version()?, Some(2));
+    // nothing left to run on the next open
+    store.migrate(2, &[], false)?;
+
+    Ok(())
+}
+
+#[test]
+fn unmarked_store_with_data_is_migrated_from_zero() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    {
+        let store = KvStore::new(temp_dir.path(), KvStoreConf::default())?;
+        store.store_data(b"old/1", &Entry { value: 7 })?;
+    }
+
+    let store = KvStore::new(temp_dir.path(), KvStoreConf::default())?;
+    store.migrate(2, MIGRATIONS, true)?;
+    assert_eq!(store.schema_version()?, Some(2));
+    assert_eq!(
+        store.fetch_data::<Entry>(b"new/1")?,
+        Some(Entry { value: 7 })
+    );
+    assert_eq!(store.fetch_data::<Entry>(b"old/1")?, None);
+
+    let runs = RENAMES.load(Ordering::Relaxed);
+    store.migrate(2, MIGRATIONS, true)?;
+    assert_eq!(RENAMES.load(Ordering::Relaxed), runs);
+
+    Ok(())
+}
+
+#[test]
+fn newer_or_unmigrated_schema_is_refused() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    {
+        let store = KvStore::new(temp_dir.path(), KvStoreConf::default())?;
+        store.migrate(3, &[], true)?;
+    }
+
+    let store = KvStore::new(temp_dir.path(), KvStoreConf::default())?;
+    assert!(matches!(
+        store.migrate(2, MIGRATIONS, true),
+        Err(KvStoreError::SchemaTooNew {
+            stored: 3,
+            supported: 2
+        })
+    ));
+
+    let old = KvStore::in_memory();
+    old.store_data(b"old/1", &Entry { value: 1 })?;
+    assert!(matches!(
+        old.migrate(2, MIGRATIONS, false),
+        Err(KvStoreError::SchemaOutdated {
+            stored: 0,
+            current: 2
+        })
+    ));
+    // refusing leaves the data as it was
+    assert_eq!(old.fetch_data::<Entry>(b"old/1")?, Some(Entry { value: 1 }));
+
+    Ok(())
+}
+
+#[test]
+fn families_of_a_newer_build_are_refused() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    {
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        rocksdb::DB::open_cf(&opts, temp_dir.path(), ["secrets", "data", "log", "blobs"])?;
+    }
+
+    assert!(matches!(
+        KvStore::new(temp_dir.path(), KvStoreConf::default()),
+        Err(KvStoreError::UnknownFamilies { families, .. }) if families == "blobs"
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use tim_lib::sequence::GapDetector;
+
+#[test]
+fn chained_events_have_no_gap() {
+    let mut gaps = GapDetector::new();
+
+    assert_eq!(gaps.observe(7, Some(3)), None);
+    assert_eq!(gaps.observe(8, Some(7)), None);
+    // ids in between belong to other rooms or weren't stored
+    assert_eq!(gaps.observe(12, Some(8)), None);
+    assert_eq!(gaps.last_seen(), Some(12));
+}
+
+#[test]
+fn missed_stored_events_are_reported_once() {
+    let mut gaps = GapDetector::new();
+
+    gaps.observe(1, Some(0));
+    assert_eq!(gaps.observe(4, Some(3)), Some(2..4));
+    assert_eq!(gaps.observe(5, Some(4)), None);
+    assert_eq!(gaps.observe(10, Some(8)), Some(6..9));
+}
+
+#[test]
+fn unstored_events_are_ignored() {
+    let mut gaps = GapDetector::new();
+
+    gaps.observe(5, Some(2));
+    assert_eq!(gaps.observe(6, None), None);
+    assert_eq!(gaps.observe(9, None), None);
+    assert_eq!(gaps.last_seen(), Some(5));
+    assert_eq!(gaps.observe(10, Some(5)), None);
+}
+
+#[test]
+fn stale_and_missing_ids_are_ignored() {
+    let mut gaps = GapDetector::new();
+
+    gaps.observe(5, Some(4));
+    assert_eq!(gaps.observe(0, Some(5)), None);
+    assert_eq!(gaps.observe(5, Some(4)), None);
+    assert_eq!(gaps.observe(3, Some(1)), None);
+    assert_eq!(gaps.last_seen(), Some(5));
+    assert_eq!(gaps.observe(6, Some(5)), None);
+}
+
+#[test]
+fn disabled_detector_tracks_without_reporting() {
+    let mut gaps = GapDetector::disabled();
+
+    gaps.observe(1, Some(0));
+    assert_eq!(gaps.observe(9, Some(8)), None);
+    assert_eq!(gaps.last_seen(), Some(9));
+}
@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::FutureExt;
+use futures::StreamExt;
+use tim_lib::space_stream::ReconnectPolicy;
+use tim_lib::space_stream::ResilientSpaceStream;
+use tim_lib::space_stream::SpaceSource;
+use tim_lib::space_stream::SpaceStreamItem;
+
+type Subscription = Result<Vec<Result<u64, String>>, String>;
+
+// Events are bare ids. Each subscribe takes the next scripted outcome, replays
+// read from the full history.
+struct FakeSource {
+    subscriptions: VecDeque<Subscription>,
+    history: Vec<u64>,
+    replays: Arc<Mutex<Vec<u64>>>,
+}
+
+impl SpaceSource for FakeSource {
+    type Event = u64;
+    type Error = String;
+
+    fn subscribe(
+        &mut self,
+    ) -> BoxFuture<'_, Result<BoxStream<'static, Result<u64, String>>, String>> {
+        let next = self
+            .subscriptions
+            .pop_front()
+            .unwrap_or_else(|| Err("no more subscriptions".to_string()));
+        async move { next.map(|events| futures::stream::iter(events).boxed()) }.boxed()
+    }
+
+    fn replay(&mut self, from_id: u64, limit: u32) -> BoxFuture<'_, Result<Vec<u64>, String>> {
+        self.replays.lock().unwrap().push(from_id);
+        let events = self
+            .history
+            .iter()
+            .copied()
+            .filter(|id| *id >= from_id)
+            .take(limit as usize)
+            .collect();
+        async move { Ok(events) }.boxed()
+    }
+
+    fn event_id(event: &u64) -> u64 {
+        *event
+    }
+}
+
+fn policy(max_attempts: Option<u32>) -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+        max_attempts,
+        replay_limit: 100,
+    }
+}
+
+fn reconnecting(attempt: u32, error: &str) -> SpaceStreamItem<u64> {
+    SpaceStreamItem::Reconnecting {
+        attempt,
+        error: error.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn resumes_after_disconnect_without_duplicates() {
+    let replays: Arc<Mutex<Vec<u64>>> = Default::default();
+    let source = FakeSource {
+        subscriptions: VecDeque::from([
+            Ok(vec![Ok(1), Ok(2), Err("connection reset".to_string())]),
+            Err("refused".to_string()),
+            // the new subscription starts before the replay ends, 3 comes twice
+            Ok(vec![Ok(3), Ok(4)]),
+        ]),
+        history: vec![1, 2, 3],
+        replays: replays.clone(),
+    };
+    let stream = ResilientSpaceStream::new(source, policy(Some(3)));
+
+    let items: Vec<_> = stream.collect().await;
+
+    assert_eq!(
+        items,
+        vec![
+            SpaceStreamItem::Event(1),
+            SpaceStreamItem::Event(2),
+            reconnecting(1, "connection reset"),
+            reconnecting(2, "refused"),
+            SpaceStreamItem::Reconnected,
+            SpaceStreamItem::Event(3),
+            SpaceStreamItem::Event(4),
+            reconnecting(1, "subscription closed"),
+            reconnecting(2, "no more subscriptions"),
+            reconnecting(3, "no more subscriptions"),
+        ]
+    );
+    assert_eq!(*replays.lock().unwrap(), vec![3]);
+}
+
+#[tokio::test]
+async fn first_subscription_is_not_a_reconnect() {
+    let source = FakeSource {
+        subscriptions: VecDeque::from([Ok(vec![Ok(5)])]),
+        history: Vec::new(),
+        replays: Default::default(),
+    };
+    let mut stream = ResilientSpaceStream::new(source, policy(Some(0)));
+
+    assert_eq!(stream.next().await, Some(SpaceStreamItem::Event(5)));
+    assert_eq!(
+        stream.next().await,
+        Some(reconnecting(1, "subscription closed"))
+    );
+    assert_eq!(stream.next().await, None);
+}
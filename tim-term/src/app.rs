@@ -1,21 +1,55 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::client::{
-    CallAbility, CallAbilityOutcome, EventData, Message, SpaceEvent, Timite, TimiteAbilities,
+    Activity, CallAbility, CallAbilityOutcome, DisconnectReason, EventData, HealthRes, Message, MessageContent, MessagePart, MessagePriority, OutcomeStatus, SpaceEvent, Timite, TimiteAbilities, TimiteRole, LOCAL_ID_METADATA_KEY, PINNED_METADATA_KEY,
 };
+use crate::identicon::{seed_for, seed_of, IdenticonStyle};
+
+const MAX_TRACKED_CALLS: usize = 512;
+const PAYLOAD_PREVIEW_CHARS: usize = 80;
+/// Sender id the server uses for its own messages, e.g. the MOTD
+const SYSTEM_SENDER_ID: u64 = 0;
+const DEFAULT_EXPORT_PATH: &str = "~/tim-timeline.md";
+/// Shown in place of a message that expired
+const DELETED_PLACEHOLDER: &str = "(message deleted)";
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InputMode {
     Normal,
     Insert,
+    /// Typing the path the timeline is exported to
+    Export,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Shown locally, the server has not echoed it back yet
+    Pending,
+    Confirmed,
+    Failed,
 }
 
 #[derive(Debug, Clone)]
 pub enum TimelineItem {
     Message {
+        /// 0 until the server echo of a local message arrives
+        id: u64,
         sender: String,
+        /// Identicon seed of the sender, 0 for server messages
+        avatar_seed: u64,
+        /// Sender registered as an agent
+        agent: bool,
         content: String,
+        /// Structured parts, shown instead of `content` when present
+        parts: Vec<MessageContent>,
+        /// Short description of the message this one replies to
+        reply_to: Option<String>,
         timestamp: u64,
+        delivery: Delivery,
+        /// Correlates a local echo with its server copy, only set on our own messages
+        local_id: Option<String>,
+        priority: MessagePriority,
     },
     TimiteConnected {
         nick: String,
@@ -23,22 +57,43 @@ pub enum TimelineItem {
     },
     TimiteDisconnected {
         nick: String,
+        reason: DisconnectReason,
         timestamp: u64,
     },
     AbilityCall {
         caller: String,
         ability_name: String,
+        payload: String,
         timestamp: u64,
     },
     AbilityOutcome {
         ability_name: String,
+        caller: Option<String>,
         success: bool,
+        /// A progress report, the final outcome of the call comes later
+        in_progress: bool,
+        detail: Option<String>,
         timestamp: u64,
     },
 }
 
+/// How a line of a message is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Text,
+    /// Opening or closing line of a code block
+    CodeFence,
+    Code,
+    Link,
+}
+
+#[derive(Debug, Clone)]
+struct TrackedCall {
+    ability_name: String,
+    caller: String,
+}
+
 impl TimelineItem {
-    #[allow(dead_code)]
     pub fn timestamp(&self) -> u64 {
         match self {
             TimelineItem::Message { timestamp, .. }
@@ -59,16 +114,38 @@ pub struct App {
     pub timeline_scroll: usize,
     pub online_timites: HashMap<u64, Timite>,
     pub timite_nick_cache: HashMap<u64, String>,
+    pub avatar_seeds: HashMap<u64, u64>,
+    pub timite_roles: HashMap<u64, TimiteRole>,
+    /// What online timites are doing; idle ones are left out
+    pub activities: HashMap<u64, Activity>,
+    pub identicon_style: IdenticonStyle,
     pub abilities: Vec<TimiteAbilities>,
     pub my_timite_id: u64,
     pub my_nick: String,
     pub show_help: bool,
+    pub expand_payloads: bool,
+    pub export_path: String,
+    /// One-line feedback shown in the header until the next key press
+    pub status: Option<String>,
+    /// Reconnect attempt while the space subscription is down
+    pub reconnecting: Option<u32>,
+    /// Last health answer, unset when the server has no health RPC
+    pub server_health: Option<HealthRes>,
+    /// Id and text of the announcement pinned to the room, shown above the timeline
+    pub pinned: Option<(u64, String)>,
+    calls: HashMap<u64, TrackedCall>,
+    call_order: VecDeque<u64>,
+    /// Distinguishes our local ids from those of other clients of the same timite
+    local_id_prefix: String,
+    next_local_id: u64,
 }
 
 impl App {
     pub fn new(my_timite_id: u64, my_nick: String) -> Self {
         let mut timite_nick_cache = HashMap::new();
         timite_nick_cache.insert(my_timite_id, my_nick.clone());
+        let mut avatar_seeds = HashMap::new();
+        avatar_seeds.insert(my_timite_id, seed_for(&my_nick));
         Self {
             running: true,
             input_mode: InputMode::Normal,
@@ -78,10 +155,24 @@ impl App {
             timeline_scroll: 0,
             online_timites: HashMap::new(),
             timite_nick_cache,
+            avatar_seeds,
+            timite_roles: HashMap::new(),
+            activities: HashMap::new(),
+            identicon_style: IdenticonStyle::detect(),
             abilities: Vec::new(),
             my_timite_id,
             my_nick,
             show_help: false,
+            expand_payloads: false,
+            export_path: String::new(),
+            status: None,
+            reconnecting: None,
+            server_health: None,
+            pinned: None,
+            calls: HashMap::new(),
+            call_order: VecDeque::new(),
+            local_id_prefix: format!("{:x}", now_ms()),
+            next_local_id: 0,
         }
     }
 
@@ -93,6 +184,21 @@ impl App {
         self.show_help = !self.show_help;
     }
 
+    /// Drops locally accumulated history; identity, abilities and presence are kept.
+    pub fn clear_history(&mut self) {
+        self.timeline.clear();
+        self.timeline_scroll = 0;
+        self.calls.clear();
+        self.call_order.clear();
+        self.timite_nick_cache.clear();
+        self.timite_nick_cache
+            .insert(self.my_timite_id, self.my_nick.clone());
+    }
+
+    pub fn toggle_payloads(&mut self) {
+        self.expand_payloads = !self.expand_payloads;
+    }
+
     pub fn enter_insert_mode(&mut self) {
         self.input_mode = InputMode::Insert;
     }
@@ -101,6 +207,64 @@ impl App {
         self.input_mode = InputMode::Normal;
     }
 
+    /// Prompts for the export path, keeping the last one typed.
+    pub fn enter_export_mode(&mut self) {
+        if self.export_path.is_empty() {
+            self.export_path = DEFAULT_EXPORT_PATH.to_string();
+        }
+        self.input_mode = InputMode::Export;
+    }
+
+    /// Renders the timeline for saving, as markdown or as plain text.
+    pub fn export_timeline(&self, markdown: bool) -> String {
+        let mut out = String::new();
+        if markdown {
+            out.push_str(&format!("# Tim timeline of @{}\n\n", self.my_nick));
+        }
+        for item in &self.timeline {
+            let time = export_time(item.timestamp());
+            let entry = match item {
+                TimelineItem::Message { sender, content, reply_to, delivery, .. } => {
+                    let unsent = if *delivery == Delivery::Failed { " (not sent)" } else { "" };
+                    let reply = reply_to.as_ref().map(|target| format!(" ↪ {}", target)).unwrap_or_default();
+                    if markdown {
+                        format!("**{}** · {}{}{}\n\n{}\n", sender, time, reply, unsent, content.trim_end())
+                    } else {
+                        format!("[{}] {}{}{}: {}\n", time, sender, reply, unsent, indent_continuation(content.trim_end()))
+                    }
+                }
+                TimelineItem::TimiteConnected { nick, .. } => export_notice(markdown, &time, &format!("{} joined", nick)),
+                TimelineItem::TimiteDisconnected { nick, reason, .. } => {
+                    let label = match reason {
+                        DisconnectReason::Unspecified | DisconnectReason::Left => "left",
+                        DisconnectReason::TimedOut => "timed out",
+                        DisconnectReason::Kicked => "was kicked",
+                    };
+                    export_notice(markdown, &time, &format!("{} {}", nick, label))
+                }
+                TimelineItem::AbilityCall { caller, ability_name, payload, .. } => {
+                    let notice = export_notice(markdown, &time, &format!("{} called {}", caller, ability_name));
+                    notice + &export_payload(markdown, payload)
+                }
+                TimelineItem::AbilityOutcome { ability_name, caller, success, in_progress, detail, .. } => {
+                    let by = caller.as_ref().map(|caller| format!(" (called by {})", caller)).unwrap_or_default();
+                    let result = match (*in_progress, *success) {
+                        (true, _) => "in progress",
+                        (false, true) => "completed",
+                        (false, false) => "failed",
+                    };
+                    let notice = export_notice(markdown, &time, &format!("{}{} {}", ability_name, by, result));
+                    notice + &export_payload(markdown, detail.as_deref().unwrap_or_default())
+                }
+            };
+            out.push_str(&entry);
+            if markdown {
+                out.push('\n');
+            }
+        }
+        out
+    }
+
     pub fn move_cursor_left(&mut self) {
         let cursor_moved_left = self.cursor_position.saturating_sub(1);
         self.cursor_position = self.clamp_cursor(cursor_moved_left);
@@ -230,12 +394,36 @@ impl App {
         self.timeline
             .iter()
             .map(|item| match item {
-                TimelineItem::Message { content, .. } => content.lines().count().max(1),
+                TimelineItem::Message { content, parts, reply_to, .. } => message_lines(content, parts).len().max(1) + usize::from(reply_to.is_some()),
+                TimelineItem::AbilityCall { payload, .. } => 1 + self.payload_view(payload).len(),
+                TimelineItem::AbilityOutcome { detail, .. } => {
+                    1 + detail.as_deref().map_or(0, |d| self.payload_view(d).len())
+                }
                 _ => 1,
             })
             .sum()
     }
 
+    /// Lines to show for an ability payload: a truncated preview unless expanded.
+    pub fn payload_view(&self, payload: &str) -> Vec<String> {
+        if payload.is_empty() {
+            return Vec::new();
+        }
+        // Control characters would garble the terminal, show only the size
+        if payload.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t')) {
+            return vec![format!("<{} bytes of binary data>", payload.len())];
+        }
+        if self.expand_payloads {
+            return payload.lines().map(str::to_string).collect();
+        }
+        let first_line = payload.lines().next().unwrap_or_default();
+        let mut preview: String = first_line.chars().take(PAYLOAD_PREVIEW_CHARS).collect();
+        if preview.len() < payload.trim_end().len() {
+            preview.push_str(" … [e] expand");
+        }
+        vec![preview]
+    }
+
     pub fn scroll_up(&mut self) {
         self.timeline_scroll = self.timeline_scroll.saturating_sub(1);
     }
@@ -270,8 +458,9 @@ impl App {
                     }
                 }
                 EventData::EventTimiteDisconnected(td) => {
+                    let reason = td.reason();
                     if let Some(timite) = td.timite {
-                        self.timite_disconnected(timite, timestamp);
+                        self.timite_disconnected(timite, reason, timestamp);
                     }
                 }
                 EventData::EventCallAbility(ca) => {
@@ -284,35 +473,173 @@ impl App {
                         self.ability_outcome(outcome, timestamp);
                     }
                 }
+                // the abilities themselves are refetched by the event loop
+                EventData::EventAbilitiesChanged(_) => {}
+                EventData::EventTimiteActivity(ta) => {
+                    let activity = ta.activity();
+                    if activity == Activity::Idle {
+                        self.activities.remove(&ta.timite_id);
+                    } else {
+                        self.activities.insert(ta.timite_id, activity);
+                    }
+                }
+                EventData::EventMessageDeleted(md) => self.message_deleted(md.message_id),
             }
         }
     }
 
+    /// Shows our message right away, dimmed until the server echoes it back.
+    /// Returns the local id to send along so the echo can be matched.
+    pub fn push_local_message(&mut self, content: &str) -> String {
+        self.next_local_id += 1;
+        let local_id = format!("{}-{}", self.local_id_prefix, self.next_local_id);
+        self.timeline.push(TimelineItem::Message {
+            id: 0,
+            sender: self.my_nick.clone(),
+            avatar_seed: self.avatar_seeds.get(&self.my_timite_id).copied().unwrap_or_else(|| seed_for(&self.my_nick)),
+            agent: false,
+            content: content.trim().to_string(),
+            parts: Vec::new(),
+            reply_to: None,
+            timestamp: now_ms(),
+            delivery: Delivery::Pending,
+            local_id: Some(local_id.clone()),
+            priority: MessagePriority::Normal,
+        });
+        local_id
+    }
+
+    pub fn mark_local_failed(&mut self, failed_id: &str) {
+        if let Some(TimelineItem::Message { delivery, .. }) = self.local_message_mut(failed_id) {
+            *delivery = Delivery::Failed;
+        }
+    }
+
+    fn local_message_mut(&mut self, wanted: &str) -> Option<&mut TimelineItem> {
+        self.timeline.iter_mut().rev().find(|item| matches!(item, TimelineItem::Message { local_id: Some(local_id), .. } if local_id == wanted))
+    }
+
+    /// Records the id the server gave our message. The echo may have come first
+    /// without the local id to match it by; that copy is then the one kept.
+    pub fn ack_local_message(&mut self, acked_id: &str, message_id: u64) {
+        let echoed = self.timeline.iter().any(|item| matches!(item, TimelineItem::Message { id, local_id, .. } if *id == message_id && local_id.as_deref() != Some(acked_id)));
+        if echoed {
+            self.timeline.retain(|item| !matches!(item, TimelineItem::Message { local_id: Some(local_id), .. } if local_id == acked_id));
+            return;
+        }
+        if let Some(TimelineItem::Message { id, delivery, .. }) = self.local_message_mut(acked_id) {
+            *id = message_id;
+            *delivery = Delivery::Confirmed;
+        }
+    }
+
+    /// Replaces the local echo with the server copy, so the message is shown once.
+    /// The copy is found by its local id, or by the server id once that was acked.
+    fn confirm_local_message(&mut self, message: &Message, timestamp: u64) -> bool {
+        if message.sender_id != self.my_timite_id {
+            return false;
+        }
+        let wanted = message.metadata.get(LOCAL_ID_METADATA_KEY);
+        let found = self.timeline.iter().rposition(|item| matches!(item, TimelineItem::Message { id, local_id: Some(local_id), .. } if (message.id != 0 && *id == message.id) || wanted == Some(local_id)));
+        let Some(TimelineItem::Message { id, content, timestamp: shown_at, delivery, .. }) = found.and_then(|index| self.timeline.get_mut(index)) else {
+            return false;
+        };
+        *id = message.id;
+        *content = message.content.clone();
+        *shown_at = timestamp;
+        *delivery = Delivery::Confirmed;
+        true
+    }
+
     fn add_message(&mut self, message: Message, timestamp: u64) {
-        let sender = self
-            .timite_nick_cache
-            .get(&message.sender_id)
-            .cloned()
-            .unwrap_or_else(|| format!("user-{}", message.sender_id));
+        if self.confirm_local_message(&message, timestamp) {
+            return;
+        }
+        let sender = if message.sender_id == SYSTEM_SENDER_ID {
+            "system".to_string()
+        } else {
+            self.timite_nick_cache
+                .get(&message.sender_id)
+                .cloned()
+                .unwrap_or_else(|| format!("user-{}", message.sender_id))
+        };
+        let avatar_seed = if message.sender_id == SYSTEM_SENDER_ID {
+            0
+        } else {
+            self.avatar_seeds.get(&message.sender_id).copied().unwrap_or_else(|| seed_for(&sender))
+        };
+        let reply_to = message.reply_to_message_id.map(|id| self.reply_context(id));
+        let local_id = message.metadata.get(LOCAL_ID_METADATA_KEY).cloned();
+        // a newer pin replaces the one shown, replays can bring an older one late
+        if message.metadata.contains_key(PINNED_METADATA_KEY) && !message.deleted && self.pinned.as_ref().is_none_or(|(id, _)| *id <= message.id) {
+            self.pinned = Some((message.id, message.content.clone()));
+        }
+        let priority = message.priority();
         self.timeline.push(TimelineItem::Message {
+            id: message.id,
+            agent: self.is_agent(message.sender_id),
             sender,
-            content: message.content,
+            avatar_seed,
+            content: if message.deleted { DELETED_PLACEHOLDER.to_string() } else { message.content },
+            parts: message.parts,
+            reply_to,
             timestamp,
+            delivery: Delivery::Confirmed,
+            local_id,
+            priority,
         });
     }
 
+    /// Blanks an expired message, it stays in place so replies to it still line up.
+    fn message_deleted(&mut self, message_id: u64) {
+        if self.pinned.as_ref().is_some_and(|(id, _)| *id == message_id) {
+            self.pinned = None;
+        }
+        for item in &mut self.timeline {
+            if let TimelineItem::Message { id, content, parts, .. } = item {
+                if *id == message_id {
+                    *content = DELETED_PLACEHOLDER.to_string();
+                    parts.clear();
+                }
+            }
+        }
+    }
+
+    /// Describes a replied-to message, falling back to its id when it is not loaded.
+    fn reply_context(&self, message_id: u64) -> String {
+        let target = self.timeline.iter().rev().find_map(|item| match item {
+            TimelineItem::Message { id, sender, content, .. } if *id == message_id => Some((sender, content)),
+            _ => None,
+        });
+        match target {
+            Some((sender, content)) => {
+                let first_line = content.lines().next().unwrap_or_default();
+                let mut preview: String = first_line.chars().take(PAYLOAD_PREVIEW_CHARS).collect();
+                if preview.len() < content.trim_end().len() {
+                    preview.push('…');
+                }
+                format!("{}: {}", sender, preview)
+            }
+            None => format!("message #{}", message_id),
+        }
+    }
+
     fn timite_connected(&mut self, timite: Timite, timestamp: u64) {
         let nick = timite.nick.clone();
         self.timite_nick_cache.insert(timite.id, nick.clone());
+        self.avatar_seeds.insert(timite.id, seed_of(&timite));
+        self.timite_roles.insert(timite.id, timite.role());
         self.online_timites.insert(timite.id, timite);
         self.timeline
             .push(TimelineItem::TimiteConnected { nick, timestamp });
     }
 
-    fn timite_disconnected(&mut self, timite: Timite, timestamp: u64) {
+    fn timite_disconnected(&mut self, timite: Timite, reason: DisconnectReason, timestamp: u64) {
         self.online_timites.remove(&timite.id);
+        self.activities.remove(&timite.id);
         self.timeline.push(TimelineItem::TimiteDisconnected {
             nick: timite.nick,
+            reason,
             timestamp,
         });
     }
@@ -323,21 +650,56 @@ impl App {
             .get(&call.sender_id)
             .cloned()
             .unwrap_or_else(|| format!("user-{}", call.sender_id));
+        if let Some(call_id) = call.call_ability_id {
+            self.track_call(call_id, &call.name, &caller);
+        }
         self.timeline.push(TimelineItem::AbilityCall {
             caller,
             ability_name: call.name,
+            payload: call.payload,
             timestamp,
         });
     }
 
     fn ability_outcome(&mut self, outcome: CallAbilityOutcome, timestamp: u64) {
+        // Calls made before we joined are unknown, fall back to the bare call id
+        let (ability_name, caller) = match self.calls.get(&outcome.call_ability_id) {
+            Some(call) => (call.ability_name.clone(), Some(call.caller.clone())),
+            None => (format!("call-{}", outcome.call_ability_id), None),
+        };
+        let in_progress = outcome.status() == OutcomeStatus::InProgress;
+        let detail = outcome
+            .error
+            .clone()
+            .or(outcome.payload)
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty());
         self.timeline.push(TimelineItem::AbilityOutcome {
-            ability_name: format!("call-{}", outcome.call_ability_id),
+            ability_name,
+            caller,
             success: outcome.error.is_none(),
+            in_progress,
+            detail,
             timestamp,
         });
     }
 
+    fn track_call(&mut self, call_id: u64, ability_name: &str, caller: &str) {
+        if self.call_order.len() >= MAX_TRACKED_CALLS {
+            if let Some(oldest) = self.call_order.pop_front() {
+                self.calls.remove(&oldest);
+            }
+        }
+        self.call_order.push_back(call_id);
+        self.calls.insert(
+            call_id,
+            TrackedCall {
+                ability_name: ability_name.to_string(),
+                caller: caller.to_string(),
+            },
+        );
+    }
+
     pub fn set_abilities(&mut self, abilities: Vec<TimiteAbilities>) {
         self.abilities = abilities;
     }
@@ -345,5 +707,97 @@ impl App {
     pub fn add_timite_to_cache(&mut self, timite: &Timite) {
         self.timite_nick_cache
             .insert(timite.id, timite.nick.clone());
+        self.avatar_seeds.insert(timite.id, seed_of(timite));
+        self.timite_roles.insert(timite.id, timite.role());
+    }
+
+    /// E.g. "jarvis is thinking…, bob is typing…", None when everyone else is idle.
+    pub fn activity_line(&self) -> Option<String> {
+        let mut active: Vec<String> = self
+            .activities
+            .iter()
+            .filter(|(id, _)| **id != self.my_timite_id)
+            .map(|(id, activity)| {
+                let doing = match activity {
+                    Activity::Thinking => "thinking",
+                    Activity::Typing | Activity::Idle => "typing",
+                };
+                let nick = self.timite_nick_cache.get(id).cloned().unwrap_or_else(|| format!("user-{}", id));
+                format!("{} is {}…", nick, doing)
+            })
+            .collect();
+        if active.is_empty() {
+            return None;
+        }
+        active.sort();
+        Some(active.join(", "))
     }
+
+    pub fn is_agent(&self, timite_id: u64) -> bool {
+        self.timite_roles.get(&timite_id) == Some(&TimiteRole::Agent)
+    }
+}
+
+/// Lines a message is drawn with: its parts when it has any, its content otherwise.
+pub fn message_lines(content: &str, parts: &[MessageContent]) -> Vec<(LineKind, String)> {
+    if parts.is_empty() {
+        return content.lines().map(|line| (LineKind::Text, line.to_string())).collect();
+    }
+    let mut lines = Vec::new();
+    for part in parts {
+        match &part.part {
+            Some(MessagePart::Text(text)) => lines.extend(text.lines().map(|line| (LineKind::Text, line.to_string()))),
+            Some(MessagePart::Code(code)) => {
+                lines.push((LineKind::CodeFence, format!("```{}", code.lang)));
+                lines.extend(code.body.trim_end().lines().map(|line| (LineKind::Code, line.to_string())));
+                lines.push((LineKind::CodeFence, "```".to_string()));
+            }
+            Some(MessagePart::Link(link)) if link.title.is_empty() => lines.push((LineKind::Link, link.url.clone())),
+            Some(MessagePart::Link(link)) => lines.push((LineKind::Link, format!("{} <{}>", link.title, link.url))),
+            // a kind added after this client was built
+            None => lines.push((LineKind::Text, "[unsupported content]".to_string())),
+        }
+    }
+    lines
+}
+
+fn export_time(ts: u64) -> String {
+    use chrono::{TimeZone, Utc};
+    match Utc.timestamp_millis_opt(ts as i64).single() {
+        Some(dt) if ts > 0 => dt.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        _ => "unknown time".to_string(),
+    }
+}
+
+fn export_notice(markdown: bool, time: &str, text: &str) -> String {
+    if markdown {
+        format!("_{} · {}_\n", text, time)
+    } else {
+        format!("[{}] * {}\n", time, text)
+    }
+}
+
+/// Ability payloads go in a code block, or indented under their line in plain text.
+fn export_payload(markdown: bool, payload: &str) -> String {
+    let payload = payload.trim_end();
+    if payload.is_empty() {
+        return String::new();
+    }
+    if markdown {
+        // a longer fence than any backtick run inside keeps the block closed
+        let longest = payload.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest.max(2) + 1);
+        format!("\n{}\n{}\n{}\n", fence, payload, fence)
+    } else {
+        payload.lines().map(|line| format!("    {}\n", line)).collect()
+    }
+}
+
+/// Keeps the lines after the first of a multi-line message under it in plain text.
+fn indent_continuation(content: &str) -> String {
+    content.lines().collect::<Vec<_>>().join("\n    ")
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_millis() as u64).unwrap_or(0)
 }
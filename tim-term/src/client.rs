@@ -1,38 +1,74 @@
+use std::collections::HashMap;
+use std::error::Error as _;
+use std::io;
+use std::ops::Range;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+// generated, the oneof variants are named after their message types
+#[allow(clippy::enum_variant_names)]
 pub mod tim_api {
     tonic::include_proto!("tim.api.g1");
 }
 
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
 pub use tim_api::space_event::Data as EventData;
+pub use tim_api::Activity;
 use tim_api::tim_grpc_api_client::TimGrpcApiClient;
 pub use tim_api::CallAbility;
 pub use tim_api::CallAbilityOutcome;
 use tim_api::ClientInfo;
+pub use tim_api::DisconnectReason;
+use tim_api::DisconnectReq;
+use tim_api::GetMeReq;
 use tim_api::GetTimelineReq;
 pub use tim_api::GetTimelineRes;
+pub use tim_api::HealthRes;
+use tim_api::HealthReq;
 use tim_api::ListAbilitiesReq;
+pub use tim_api::message_content::Part as MessagePart;
 pub use tim_api::Message;
+pub use tim_api::MessageContent;
+pub use tim_api::MessagePriority;
+pub use tim_api::OutcomeStatus;
 use tim_api::SendMessageReq;
 pub use tim_api::SpaceEvent;
 use tim_api::SubscribeToSpaceReq;
 pub use tim_api::Timite;
 pub use tim_api::TimiteAbilities;
+pub use tim_api::TimiteRole;
 use tim_api::TrustedConnectReq;
 use tim_api::TrustedRegisterReq;
+use tim_lib::space_stream::SpaceSource;
+use tonic::codec::CompressionEncoding;
 use tonic::metadata::Ascii;
 use tonic::metadata::MetadataValue;
+use tonic::transport::Channel;
 use tonic::transport::Endpoint;
 
 use crate::error::{Error, Result};
 
 pub const SESSION_METADATA_KEY: &str = "tim-session-key";
+/// Message metadata key carrying the id the sender gave its local echo
+pub const LOCAL_ID_METADATA_KEY: &str = "term.local_id";
+/// Set by the server on the announcement pinned to the room
+pub const PINNED_METADATA_KEY: &str = "tim.pinned";
+const CONNECT_BACKOFF_MIN: Duration = Duration::from_millis(100);
+const CONNECT_BACKOFF_MAX: Duration = Duration::from_secs(2);
+/// Largest gap fetched back when the subscription skipped events
+const MAX_CATCH_UP: u64 = 1000;
 
 #[derive(Clone)]
 pub struct ClientConfig {
     pub endpoint: String,
     pub nick: String,
     pub timite_id: Option<u64>,
+    /// Previously issued session key, reused when it still works for `timite_id`
+    pub session_key: Option<String>,
+    /// Overall deadline for reaching the server; refused connections are retried until then
+    pub connect_timeout: Duration,
+    /// Sent with registration and connect to servers that require one
+    pub auth_token: Option<String>,
 }
 
 impl Default for ClientConfig {
@@ -41,6 +77,9 @@ impl Default for ClientConfig {
             endpoint: "http://127.0.0.1:8787".to_string(),
             nick: "terminal-user".to_string(),
             timite_id: None,
+            session_key: None,
+            connect_timeout: Duration::from_secs(30),
+            auth_token: None,
         }
     }
 }
@@ -50,23 +89,37 @@ pub struct TimClient {
     client: TimGrpcApiClient<tonic::transport::Channel>,
     token: MetadataValue<Ascii>,
     timite_id: u64,
+    nick: String,
 }
 
 impl TimClient {
     pub async fn connect(conf: ClientConfig) -> Result<Self> {
-        let endpoint = Endpoint::from_str(&conf.endpoint)?;
-        let channel = endpoint.connect().await?;
-        let mut client = TimGrpcApiClient::new(channel);
+        let channel = connect_with_retry(&conf).await?;
+        // advertise gzip, the server decides whether to compress
+        let mut client = TimGrpcApiClient::new(channel).accept_compressed(CompressionEncoding::Gzip);
 
+        if let Some((token, timite)) = resume_session(&mut client, &conf).await {
+            return Ok(TimClient {
+                client,
+                token,
+                timite_id: timite.id,
+                nick: timite.nick,
+            });
+        }
+
+        let mut nick = conf.nick.clone();
         let session = match conf.timite_id {
             Some(timite_id) => {
                 let connect_req = TrustedConnectReq {
                     timite: Some(Timite {
                         id: timite_id,
                         nick: conf.nick.clone(),
+                        avatar_seed: 0,
+                        role: Default::default(),
                     }),
                     client_info: Some(ClientInfo {
                         platform: "tim-term".to_string(),
+                        auth_token: conf.auth_token.clone().unwrap_or_default(),
                     }),
                 };
                 let res = client
@@ -81,14 +134,20 @@ impl TimClient {
                         nick: conf.nick.clone(),
                         client_info: Some(ClientInfo {
                             platform: "tim-term".to_string(),
+                            auth_token: conf.auth_token.clone().unwrap_or_default(),
                         }),
+                        role: TimiteRole::Human.into(),
+                        force: false,
                     };
-                    client
+                    let register_res = client
                         .trusted_register(tonic::Request::new(register_req))
                         .await?
-                        .into_inner()
-                        .session
-                        .ok_or(Error::MissingSession)?
+                        .into_inner();
+                    // the server may have picked another nick when ours was empty or taken
+                    if !register_res.nick.is_empty() {
+                        nick = register_res.nick;
+                    }
+                    register_res.session.ok_or(Error::MissingSession)?
                 }
             }
             None => {
@@ -96,14 +155,19 @@ impl TimClient {
                     nick: conf.nick.clone(),
                     client_info: Some(ClientInfo {
                         platform: "tim-term".to_string(),
+                        auth_token: conf.auth_token.clone().unwrap_or_default(),
                     }),
+                    role: TimiteRole::Human.into(),
+                    force: false,
                 };
-                client
+                let register_res = client
                     .trusted_register(tonic::Request::new(register_req))
                     .await?
-                    .into_inner()
-                    .session
-                    .ok_or(Error::MissingSession)?
+                    .into_inner();
+                if !register_res.nick.is_empty() {
+                    nick = register_res.nick;
+                }
+                register_res.session.ok_or(Error::MissingSession)?
             }
         };
 
@@ -113,6 +177,7 @@ impl TimClient {
             client,
             token,
             timite_id: session.timite_id,
+            nick,
         })
     }
 
@@ -120,23 +185,35 @@ impl TimClient {
         self.timite_id
     }
 
-    pub async fn send_message(&mut self, content: &str) -> Result<()> {
+    /// The nick the server registered, which can differ from the configured one.
+    pub fn nick(&self) -> &str {
+        &self.nick
+    }
+
+    /// Returns the id the server stored the message under, `None` when there was nothing to send.
+    pub async fn send_message(&mut self, content: &str, local_id: &str) -> Result<Option<u64>> {
         let trimmed = content.trim();
         if trimmed.is_empty() {
-            return Ok(());
+            return Ok(None);
         }
         let mut req = tonic::Request::new(SendMessageReq {
             content: trimmed.to_string(),
+            reply_to_message_id: None,
+            metadata: HashMap::from([(LOCAL_ID_METADATA_KEY.to_string(), local_id.to_string())]),
+            parts: Vec::new(),
+            ephemeral_ttl_secs: None,
+            room_id: String::new(),
         });
         req.metadata_mut()
             .insert(SESSION_METADATA_KEY, self.token.clone());
-        self.client.send_message(req).await?;
-        Ok(())
+        let res = self.client.send_message(req).await?.into_inner();
+        Ok(Some(res.message_id))
     }
 
     pub async fn subscribe_to_space(&mut self) -> Result<tonic::Streaming<SpaceEvent>> {
         let sub_req = SubscribeToSpaceReq {
             receive_own_messages: true,
+            room_id: String::new(),
         };
         let mut req = tonic::Request::new(sub_req);
         req.metadata_mut()
@@ -145,12 +222,21 @@ impl TimClient {
     }
 
     pub async fn get_timeline(&mut self, offset: u64, size: u32) -> Result<GetTimelineRes> {
-        let mut req = tonic::Request::new(GetTimelineReq { offset, size });
+        let mut req = tonic::Request::new(GetTimelineReq { offset, size, room_id: String::new() });
         req.metadata_mut()
             .insert(SESSION_METADATA_KEY, self.token.clone());
         Ok(self.client.get_timeline(req).await?.into_inner())
     }
 
+    /// Fetches the events of `gap` the subscription never delivered; ids the timeline
+    /// skips (unstored events) may make the page run past the gap, so it is trimmed.
+    pub async fn catch_up(&mut self, gap: Range<u64>) -> Result<GetTimelineRes> {
+        let start = gap.start.max(gap.end.saturating_sub(MAX_CATCH_UP));
+        let mut res = self.get_timeline(start, (gap.end - start) as u32).await?;
+        res.events.retain(|event| event.metadata.as_ref().is_some_and(|meta| gap.contains(&meta.id)));
+        Ok(res)
+    }
+
     pub async fn list_abilities(&mut self) -> Result<Vec<TimiteAbilities>> {
         let mut req = tonic::Request::new(ListAbilitiesReq { timite_id: None });
         req.metadata_mut()
@@ -158,4 +244,106 @@ impl TimClient {
         let res = self.client.list_abilities(req).await?.into_inner();
         Ok(res.abilities)
     }
+
+    /// Server version and uptime, `None` when the server predates the health RPC.
+    pub async fn health(&mut self) -> Result<Option<HealthRes>> {
+        match self.client.health(tonic::Request::new(HealthReq {})).await {
+            Ok(res) => Ok(Some(res.into_inner())),
+            Err(status) if status.code() == tonic::Code::Unimplemented => Ok(None),
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    /// Tells the server we are leaving so others see it right away instead of after the sweep.
+    pub async fn disconnect(&mut self) -> Result<()> {
+        let mut req = tonic::Request::new(DisconnectReq {});
+        req.metadata_mut()
+            .insert(SESSION_METADATA_KEY, self.token.clone());
+        self.client.disconnect(req).await?;
+        Ok(())
+    }
+}
+
+impl SpaceSource for TimClient {
+    type Event = SpaceEvent;
+    type Error = Error;
+
+    fn subscribe(&mut self) -> BoxFuture<'_, Result<BoxStream<'static, Result<SpaceEvent>>>> {
+        async move {
+            let stream = self.subscribe_to_space().await?;
+            Ok(stream.map(|item| item.map_err(Error::from)).boxed())
+        }
+        .boxed()
+    }
+
+    fn replay(&mut self, from_id: u64, limit: u32) -> BoxFuture<'_, Result<Vec<SpaceEvent>>> {
+        async move { Ok(self.get_timeline(from_id, limit).await?.events) }.boxed()
+    }
+
+    fn event_id(event: &SpaceEvent) -> u64 {
+        event.metadata.as_ref().map_or(0, |meta| meta.id)
+    }
+}
+
+async fn resume_session(
+    client: &mut TimGrpcApiClient<Channel>,
+    conf: &ClientConfig,
+) -> Option<(MetadataValue<Ascii>, Timite)> {
+    let (Some(key), Some(timite_id)) = (conf.session_key.as_deref(), conf.timite_id) else {
+        return None;
+    };
+    let token = MetadataValue::try_from(key).ok()?;
+    let mut req = tonic::Request::new(GetMeReq {});
+    req.metadata_mut()
+        .insert(SESSION_METADATA_KEY, token.clone());
+    let timite = match client.get_me(req).await {
+        Ok(res) => res.into_inner().timite?,
+        Err(status) => {
+            tracing::warn!("session key rejected, connecting again: {}", status);
+            return None;
+        }
+    };
+    // a valid key of someone else must not run under TIM_TIMITE_ID
+    if timite.id != timite_id {
+        tracing::warn!(
+            "session key belongs to timite {}, not {}, connecting again",
+            timite.id,
+            timite_id
+        );
+        return None;
+    }
+    Some((token, timite))
+}
+
+async fn connect_with_retry(conf: &ClientConfig) -> Result<Channel> {
+    // an invalid endpoint fails here, without retries
+    let endpoint = Endpoint::from_str(&conf.endpoint)?.connect_timeout(conf.connect_timeout);
+    let started = Instant::now();
+    let mut backoff = CONNECT_BACKOFF_MIN;
+    loop {
+        let err = match endpoint.connect().await {
+            Ok(channel) => return Ok(channel),
+            Err(err) => err,
+        };
+        if !is_retryable(&err) {
+            return Err(err.into());
+        }
+        if started.elapsed() + backoff >= conf.connect_timeout {
+            return Err(Error::ConnectTimeout { endpoint: conf.endpoint.clone(), timeout: conf.connect_timeout, source: err });
+        }
+        tracing::warn!("tim server not ready, retrying in {:?}: {}", backoff, err);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(CONNECT_BACKOFF_MAX);
+    }
+}
+
+fn is_retryable(err: &tonic::transport::Error) -> bool {
+    let mut source = err.source();
+    while let Some(cause) = source {
+        if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+            return matches!(io_err.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::TimedOut);
+        }
+        source = cause.source();
+    }
+    false
 }
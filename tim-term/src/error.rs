@@ -1,4 +1,5 @@
 use std::io;
+use std::time::Duration;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -8,6 +9,9 @@ pub enum Error {
     #[error("tim connect error: {0}")]
     TimConnect(#[from] tonic::transport::Error),
 
+    #[error("tim server at {endpoint} not reachable within {timeout:?}: {source}")]
+    ConnectTimeout { endpoint: String, timeout: Duration, source: tonic::transport::Error },
+
     #[error("tim grpc error: {0}")]
     TimGrpc(#[from] tonic::Status),
 
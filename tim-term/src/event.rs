@@ -11,7 +11,10 @@ pub enum AppEvent {
     Key(KeyEvent),
     Paste(String),
     Tick,
-    Space(SpaceEvent),
+    Space(Box<SpaceEvent>),
+    /// The space subscription dropped, carries the reconnect attempt
+    Reconnecting(u32),
+    Reconnected,
 }
 
 pub struct EventHandler {
@@ -27,18 +30,13 @@ impl EventHandler {
         std::thread::spawn(move || {
             loop {
                 if event::poll(tick_rate).unwrap_or(false) {
-                    match event::read() {
-                        Ok(CrosstermEvent::Key(key)) => {
-                            if key_tx.send(AppEvent::Key(key)).is_err() {
-                                break;
-                            }
-                        }
-                        Ok(CrosstermEvent::Paste(text)) => {
-                            if key_tx.send(AppEvent::Paste(text)).is_err() {
-                                break;
-                            }
-                        }
-                        _ => {}
+                    let app_event = match event::read() {
+                        Ok(CrosstermEvent::Key(key)) => AppEvent::Key(key),
+                        Ok(CrosstermEvent::Paste(text)) => AppEvent::Paste(text),
+                        _ => continue,
+                    };
+                    if key_tx.send(app_event).is_err() {
+                        break;
                     }
                 } else if key_tx.send(AppEvent::Tick).is_err() {
                     break;
@@ -57,6 +55,6 @@ impl EventHandler {
         self.rx
             .recv()
             .await
-            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "event channel closed").into())
+            .ok_or_else(|| std::io::Error::other("event channel closed").into())
     }
 }
@@ -0,0 +1,64 @@
+use ratatui::{style::{Color, Style}, text::Span};
+
+use crate::client::Timite;
+
+/// Quadrant block glyphs indexed by a 4-bit mask: top-left, top-right, bottom-left, bottom-right.
+const QUADRANTS: [char; 16] = [' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█'];
+const BASIC_COLORS: [Color; 6] = [Color::Red, Color::Green, Color::Yellow, Color::Blue, Color::Magenta, Color::Cyan];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdenticonStyle {
+    /// Mirrored 4x2 block pattern in one of the 256 palette colors.
+    Blocks,
+    /// A single dot in one of the basic ANSI colors.
+    Dot,
+}
+
+impl IdenticonStyle {
+    /// Picks blocks only when the terminal advertises 256 colors or more.
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if matches!(colorterm.as_str(), "truecolor" | "24bit") || term.contains("256color") {
+            IdenticonStyle::Blocks
+        } else {
+            IdenticonStyle::Dot
+        }
+    }
+}
+
+/// Same FNV-1a as the server, used when an older server sends no seed.
+pub fn seed_for(nick: &str) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    let hash = nick.bytes().fold(FNV_OFFSET, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME));
+    hash.max(1)
+}
+
+/// The server-assigned seed, or one derived from the nick when there is none.
+pub fn seed_of(timite: &Timite) -> u64 {
+    if timite.avatar_seed != 0 { timite.avatar_seed } else { seed_for(&timite.nick) }
+}
+
+/// Renders the identicon followed by a space, or nothing for a zero seed.
+pub fn identicon(seed: u64, style: IdenticonStyle) -> Option<Span<'static>> {
+    if seed == 0 {
+        return None;
+    }
+    let text = match style {
+        IdenticonStyle::Blocks => {
+            // never blank, an empty pattern would read as missing
+            let left = ((seed & 0xf) as usize).max(1);
+            // mirror the left cell so the pattern is symmetric like classic identicons
+            let right = ((left & 0b0101) << 1) | ((left & 0b1010) >> 1);
+            format!("{}{} ", QUADRANTS[left], QUADRANTS[right])
+        }
+        IdenticonStyle::Dot => "● ".to_string(),
+    };
+    let color = match style {
+        // skip the 16 system colors and the darkest cube shades
+        IdenticonStyle::Blocks => Color::Indexed(52 + ((seed >> 8) % 180) as u8),
+        IdenticonStyle::Dot => BASIC_COLORS[((seed >> 8) % BASIC_COLORS.len() as u64) as usize],
+    };
+    Some(Span::styled(text, Style::default().fg(color)))
+}
@@ -2,10 +2,12 @@ mod app;
 mod client;
 mod error;
 mod event;
+mod export;
+mod identicon;
 mod ui;
 
 use std::io;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossterm::{
     event::{
@@ -17,13 +19,21 @@ use crossterm::{
 };
 use futures::StreamExt;
 use ratatui::{backend::CrosstermBackend, Terminal};
+use tim_lib::{
+    sequence::GapDetector,
+    space_stream::{ReconnectPolicy, ResilientSpaceStream, SpaceStreamItem},
+};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 use crate::app::{App, InputMode};
-use crate::client::{ClientConfig, TimClient};
+use crate::client::{ClientConfig, EventData, GetTimelineRes, SpaceEvent, TimClient};
 use crate::error::Result;
 use crate::event::{AppEvent, EventHandler};
 
+const HISTORY_PAGE_SIZE: u32 = 100;
+/// How often the server version and uptime in the header are refreshed
+const HEALTH_REFRESH: Duration = Duration::from_secs(30);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenvy::dotenv().ok();
@@ -36,15 +46,29 @@ async fn main() -> Result<()> {
     let endpoint = std::env::var("TIM_ENDPOINT").unwrap_or_else(|_| "http://127.0.0.1:8787".into());
     let nick = std::env::var("TIM_NICK").unwrap_or_else(|_| whoami::username());
 
-    let config = ClientConfig {
+    let timite_id = std::env::var("TIM_TIMITE_ID").ok().and_then(|v| v.parse().ok());
+    let session_key = std::env::var("TIM_SESSION_KEY").ok();
+    if session_key.is_some() && timite_id.is_none() {
+        tracing::warn!("TIM_SESSION_KEY is ignored without a valid TIM_TIMITE_ID");
+    }
+    let auth_token = std::env::var("TIM_AUTH_TOKEN").ok();
+
+    let mut config = ClientConfig {
         endpoint,
-        nick: nick.clone(),
-        timite_id: None,
+        nick,
+        timite_id,
+        session_key,
+        auth_token,
+        ..ClientConfig::default()
     };
+    if let Some(secs) = std::env::var("TIM_CONNECT_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()) {
+        config.connect_timeout = Duration::from_secs(secs);
+    }
 
     tracing::info!("Connecting to Tim server...");
     let mut client = TimClient::connect(config).await?;
     let timite_id = client.timite_id();
+    let nick = client.nick().to_string();
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -59,38 +83,41 @@ async fn main() -> Result<()> {
 
     let mut app = App::new(timite_id, nick);
 
+    // Older servers have no health RPC, the header then just goes without it
+    let health_supported = refresh_health(&mut app, &mut client).await;
+
     // Load initial abilities
     if let Ok(abilities) = client.list_abilities().await {
         app.set_abilities(abilities);
     }
 
     // Load timeline history
-    if let Ok(res) = client.get_timeline(0, 100).await {
-        for timite in &res.timites {
-            app.add_timite_to_cache(timite);
-        }
-        for event in res.events {
-            app.handle_space_event(event);
-        }
-        app.scroll_to_bottom();
-    }
+    let _ = load_history(&mut app, &mut client).await;
 
-    // Subscribe to space events
-    let mut space_stream = client.subscribe_to_space().await?;
+    // Subscribe to space events, reconnecting for as long as the app runs
+    let mut space_stream = ResilientSpaceStream::new(client.clone(), ReconnectPolicy::default());
 
     let mut events = EventHandler::new(Duration::from_millis(250));
     let event_tx = events.sender();
 
     // Spawn task to forward space events
     tokio::spawn(async move {
-        while let Some(Ok(event)) = space_stream.next().await {
-            if event_tx.send(AppEvent::Space(event)).is_err() {
+        while let Some(item) = space_stream.next().await {
+            let event = match item {
+                SpaceStreamItem::Event(event) => AppEvent::Space(Box::new(event)),
+                SpaceStreamItem::Reconnecting { attempt, error } => {
+                    tracing::warn!("Space subscription lost ({}), reconnecting", error);
+                    AppEvent::Reconnecting(attempt)
+                }
+                SpaceStreamItem::Reconnected => AppEvent::Reconnected,
+            };
+            if event_tx.send(event).is_err() {
                 break;
             }
         }
     });
 
-    let result = run_app(&mut terminal, &mut app, &mut events, &mut client).await;
+    let result = run_app(&mut terminal, &mut app, &mut events, &mut client, health_supported).await;
 
     disable_raw_mode()?;
     execute!(
@@ -101,6 +128,10 @@ async fn main() -> Result<()> {
     )?;
     terminal.show_cursor()?;
 
+    if let Err(err) = client.disconnect().await {
+        tracing::warn!("Failed to disconnect cleanly: {}", err);
+    }
+
     result
 }
 
@@ -109,7 +140,10 @@ async fn run_app(
     app: &mut App,
     events: &mut EventHandler,
     client: &mut TimClient,
+    mut health_supported: bool,
 ) -> Result<()> {
+    let mut gaps = GapDetector::new();
+    let mut health_checked = Instant::now();
     while app.running {
         terminal.draw(|f| ui::render(f, app))?;
 
@@ -118,21 +152,137 @@ async fn run_app(
                 handle_key(app, client, key.code, key.modifiers).await?;
             }
             AppEvent::Paste(text) => {
-                if app.input_mode == InputMode::Insert {
-                    app.paste(&text);
+                match app.input_mode {
+                    InputMode::Insert => app.paste(&text),
+                    InputMode::Export => app.export_path.push_str(text.trim()),
+                    InputMode::Normal => {}
+                }
+            }
+            AppEvent::Tick => {
+                if health_supported && health_checked.elapsed() >= HEALTH_REFRESH {
+                    health_checked = Instant::now();
+                    health_supported = refresh_health(app, client).await;
                 }
             }
-            AppEvent::Tick => {}
             AppEvent::Space(event) => {
-                app.handle_space_event(event);
+                catch_up(app, client, &mut gaps, &event).await;
+                if changes_abilities(&event) {
+                    refresh_abilities(app, client).await;
+                }
+                app.handle_space_event(*event);
                 app.scroll_to_bottom();
             }
+            AppEvent::Reconnecting(attempt) => app.reconnecting = Some(attempt),
+            AppEvent::Reconnected => app.reconnecting = None,
         }
     }
 
     Ok(())
 }
 
+async fn load_history(app: &mut App, client: &mut TimClient) -> Result<()> {
+    let res = client.get_timeline(0, HISTORY_PAGE_SIZE).await?;
+    show_history(app, res);
+    Ok(())
+}
+
+/// Replaces the history with the latest page. The old one is only dropped once the page
+/// arrived, a failed fetch leaves it up and ends in the status line.
+async fn reload_history(app: &mut App, client: &mut TimClient) {
+    match client.get_timeline(0, HISTORY_PAGE_SIZE).await {
+        Ok(res) => {
+            app.clear_history();
+            show_history(app, res);
+        }
+        Err(err) => {
+            tracing::warn!("Failed to reload history: {}", err);
+            app.status = Some(format!("Reload failed: {}", err));
+        }
+    }
+}
+
+fn show_history(app: &mut App, res: GetTimelineRes) {
+    for timite in &res.timites {
+        app.add_timite_to_cache(timite);
+    }
+    for event in res.events {
+        app.handle_space_event(event);
+    }
+    app.scroll_to_bottom();
+}
+
+fn changes_abilities(event: &SpaceEvent) -> bool {
+    matches!(event.data, Some(EventData::EventAbilitiesChanged(_)))
+}
+
+/// Updates the server line of the header. Returns false when the server has no health
+/// RPC, so it isn't asked again; other failures keep the last answer shown.
+async fn refresh_health(app: &mut App, client: &mut TimClient) -> bool {
+    match client.health().await {
+        Ok(Some(health)) => {
+            app.server_health = Some(health);
+            true
+        }
+        Ok(None) => {
+            tracing::info!("Server has no health RPC, not showing its version");
+            false
+        }
+        Err(err) => {
+            tracing::debug!("Health check failed: {}", err);
+            true
+        }
+    }
+}
+
+/// Reloads the sidebar abilities; on failure the previous list stays up.
+async fn refresh_abilities(app: &mut App, client: &mut TimClient) {
+    match client.list_abilities().await {
+        Ok(abilities) => app.set_abilities(abilities),
+        Err(err) => tracing::warn!("Failed to refresh abilities: {}", err),
+    }
+}
+
+/// Writes the timeline to the prompted path; failures only end up in the status line.
+fn export_timeline(app: &mut App) {
+    let path = export::expand_path(&app.export_path);
+    if path.as_os_str().is_empty() {
+        app.status = Some("Export cancelled: no path given".to_string());
+        return;
+    }
+    let contents = app.export_timeline(export::is_markdown(&path));
+    app.status = Some(match export::write_export(&path, &contents) {
+        Ok(()) => format!("Exported {} items to {}", app.timeline.len(), path.display()),
+        Err(err) => {
+            tracing::warn!("Failed to export timeline to {}: {}", path.display(), err);
+            format!("Export to {} failed: {}", path.display(), err)
+        }
+    });
+}
+
+/// Applies the events the subscription skipped before `event`, if any.
+async fn catch_up(app: &mut App, client: &mut TimClient, gaps: &mut GapDetector, event: &SpaceEvent) {
+    let (id, prev_id) = event.metadata.as_ref().map_or((0, None), |meta| (meta.id, meta.prev_id));
+    let Some(gap) = gaps.observe(id, prev_id) else {
+        return;
+    };
+    tracing::warn!("Missed space events {}..{}, catching up", gap.start, gap.end);
+    match client.catch_up(gap).await {
+        Ok(res) => {
+            for timite in &res.timites {
+                app.add_timite_to_cache(timite);
+            }
+            let abilities_changed = res.events.iter().any(changes_abilities);
+            for event in res.events {
+                app.handle_space_event(event);
+            }
+            if abilities_changed {
+                refresh_abilities(app, client).await;
+            }
+        }
+        Err(err) => tracing::warn!("Failed to catch up on missed events: {}", err),
+    }
+}
+
 async fn handle_key(
     app: &mut App,
     client: &mut TimClient,
@@ -152,6 +302,8 @@ async fn handle_key(
         return Ok(());
     }
 
+    app.status = None;
+
     match app.input_mode {
         InputMode::Normal => match code {
             KeyCode::Char('q') => app.quit(),
@@ -159,6 +311,12 @@ async fn handle_key(
             KeyCode::Char('j') | KeyCode::Down => app.scroll_down(),
             KeyCode::Char('k') | KeyCode::Up => app.scroll_up(),
             KeyCode::Char('G') => app.scroll_to_bottom(),
+            KeyCode::Char('e') => app.toggle_payloads(),
+            KeyCode::Char('s') => app.enter_export_mode(),
+            // Local only: server state is untouched, the latest page is fetched again
+            KeyCode::Char('l') if modifiers.contains(KeyModifiers::CONTROL) => {
+                reload_history(app, client).await;
+            }
             KeyCode::Char('c') | KeyCode::Char('d') if modifiers.contains(KeyModifiers::CONTROL) => app.quit(),
             _ => {}
         },
@@ -169,7 +327,16 @@ async fn handle_key(
             KeyCode::Enter => {
                 let content = app.take_input();
                 if !content.trim().is_empty() {
-                    client.send_message(&content).await?;
+                    let local_id = app.push_local_message(&content);
+                    app.scroll_to_bottom();
+                    match client.send_message(&content, &local_id).await {
+                        Ok(Some(message_id)) => app.ack_local_message(&local_id, message_id),
+                        Ok(None) => {}
+                        Err(err) => {
+                            tracing::warn!("Failed to send message: {}", err);
+                            app.mark_local_failed(&local_id);
+                        }
+                    }
                 }
             }
             // Handle backspace - some terminals send Ctrl+H
@@ -185,6 +352,19 @@ async fn handle_key(
             KeyCode::Char(c) => app.enter_char(c),
             _ => {}
         },
+        InputMode::Export => match code {
+            KeyCode::Esc => app.enter_normal_mode(),
+            KeyCode::Enter => {
+                export_timeline(app);
+                app.enter_normal_mode();
+            }
+            KeyCode::Backspace | KeyCode::Delete => {
+                app.export_path.pop();
+            }
+            KeyCode::Char('c') | KeyCode::Char('d') if modifiers.contains(KeyModifiers::CONTROL) => app.quit(),
+            KeyCode::Char(c) => app.export_path.push(c),
+            _ => {}
+        },
     }
 
     Ok(())
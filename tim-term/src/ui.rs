@@ -6,9 +6,13 @@ use ratatui::{
     Frame,
 };
 
-use crate::app::{App, InputMode, TimelineItem};
+use crate::app::{message_lines, App, Delivery, InputMode, LineKind, TimelineItem};
+use crate::client::{DisconnectReason, MessagePriority, TimiteRole};
+use crate::identicon::{identicon, seed_of};
 
 const MAX_INPUT_HEIGHT: u16 = 10;
+/// Shown before the nick of timites registered as agents
+const AGENT_MARK: &str = "🤖 ";
 
 pub fn render(frame: &mut Frame, app: &App) {
     // Calculate input height based on content (min 3, max MAX_INPUT_HEIGHT)
@@ -37,9 +41,10 @@ fn render_header(frame: &mut Frame, app: &App, area: Rect) {
     let mode_str = match app.input_mode {
         InputMode::Normal => "NORMAL",
         InputMode::Insert => "INSERT",
+        InputMode::Export => "EXPORT",
     };
 
-    let header = Paragraph::new(Line::from(vec![
+    let mut spans = vec![
         Span::styled(" Tim Terminal ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
         Span::raw(" | "),
         Span::styled(format!("@{}", app.my_nick), Style::default().fg(Color::Green)),
@@ -47,21 +52,72 @@ fn render_header(frame: &mut Frame, app: &App, area: Rect) {
         Span::styled(format!("[{}]", mode_str), Style::default().fg(Color::Yellow)),
         Span::raw(" | "),
         Span::styled("[F1] Help  [q] Quit", Style::default().fg(Color::DarkGray)),
-    ]));
+    ];
+    if let Some(health) = &app.server_health {
+        spans.push(Span::raw(" | "));
+        spans.push(Span::styled(format!("server {} · up {}", health.version, format_uptime(health.uptime_secs)), Style::default().fg(Color::DarkGray)));
+    }
+    if let Some(attempt) = app.reconnecting {
+        spans.push(Span::raw(" | "));
+        spans.push(Span::styled(format!("reconnecting (attempt {})…", attempt), Style::default().fg(Color::Red)));
+    }
+    if let Some(status) = &app.status {
+        spans.push(Span::raw(" | "));
+        spans.push(Span::styled(status.clone(), Style::default().fg(Color::Magenta)));
+    }
+    let header = Paragraph::new(Line::from(spans));
 
     frame.render_widget(header, area);
 }
 
+fn format_uptime(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86_400, secs / 3_600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
 fn render_main(frame: &mut Frame, app: &App, area: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Min(30), Constraint::Length(25)])
         .split(area);
 
-    render_timeline(frame, app, chunks[0]);
+    match &app.pinned {
+        Some((_, content)) => {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(4)])
+                .split(chunks[0]);
+            render_pinned(frame, content, rows[0]);
+            render_timeline(frame, app, rows[1]);
+        }
+        None => render_timeline(frame, app, chunks[0]),
+    }
     render_sidebar(frame, app, chunks[1]);
 }
 
+/// One line above the timeline, so the pinned announcement stays in view while scrolling.
+fn render_pinned(frame: &mut Frame, content: &str, area: Rect) {
+    let first_line = content.lines().next().unwrap_or_default();
+    let pinned = Paragraph::new(format!(" 📌 {}", first_line)).style(priority_style(MessagePriority::Announcement));
+    frame.render_widget(pinned, area);
+}
+
+/// Style of the sender of a message, and a base for its text. Normal messages keep the
+/// plain look, unknown priorities read as normal.
+fn priority_style(priority: MessagePriority) -> Style {
+    match priority {
+        MessagePriority::Normal => Style::default().fg(Color::Cyan),
+        MessagePriority::High => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        MessagePriority::Announcement => Style::default().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD),
+    }
+}
+
 fn render_timeline(frame: &mut Frame, app: &App, area: Rect) {
     // Build all lines for the timeline
     let lines: Vec<Line> = app
@@ -69,37 +125,64 @@ fn render_timeline(frame: &mut Frame, app: &App, area: Rect) {
         .iter()
         .flat_map(|item| {
             match item {
-                TimelineItem::Message { sender, content, timestamp } => {
+                TimelineItem::Message { sender, avatar_seed, agent, content, parts, reply_to, timestamp, delivery, priority, .. } => {
                     let time = format_timestamp(*timestamp);
-                    let prefix_len = format!("[{}] {}: ", time, sender).chars().count();
-
-                    let msg_lines: Vec<Line> = content
-                        .lines()
+                    let sender = if *agent { format!("{}{}", AGENT_MARK, sender) } else { sender.clone() };
+                    let sender_style = priority_style(*priority);
+                    let content_style = match delivery {
+                        Delivery::Pending => Style::default().add_modifier(Modifier::DIM),
+                        Delivery::Confirmed if *priority == MessagePriority::Normal => Style::default(),
+                        Delivery::Confirmed => Style::default().add_modifier(Modifier::BOLD),
+                        Delivery::Failed => Style::default().fg(Color::Red),
+                    };
+                    let line_style = |kind: LineKind| match kind {
+                        LineKind::Text => content_style,
+                        LineKind::CodeFence => content_style.fg(Color::DarkGray),
+                        // code keeps the sender kind apart, agents often paste generated code
+                        LineKind::Code => content_style.fg(if *agent { Color::Magenta } else { Color::Yellow }),
+                        LineKind::Link => content_style.fg(Color::Blue).add_modifier(Modifier::UNDERLINED),
+                    };
+                    let failed_mark = (*delivery == Delivery::Failed).then(|| Span::styled(" (not sent)", Style::default().fg(Color::Red)));
+                    let avatar = identicon(*avatar_seed, app.identicon_style);
+                    let avatar_len = avatar.as_ref().map_or(0, |span| span.content.chars().count());
+                    // the mark is one char but two columns wide
+                    let prefix_len = format!("[{}] {}: ", time, sender).chars().count() + avatar_len + usize::from(*agent);
+
+                    let reply_line = reply_to.as_ref().map(|target| {
+                        Line::from(Span::styled(format!("{}↳ re {}", " ".repeat(prefix_len), target), Style::default().fg(Color::DarkGray)))
+                    });
+
+                    let msg_lines: Vec<Line> = message_lines(content, parts)
+                        .into_iter()
                         .enumerate()
-                        .map(|(i, line_content)| {
+                        .map(|(i, (kind, line_content))| {
                             if i == 0 {
-                                Line::from(vec![
-                                    Span::styled(format!("[{}] ", time), Style::default().fg(Color::DarkGray)),
-                                    Span::styled(format!("{}: ", sender), Style::default().fg(Color::Cyan)),
-                                    Span::raw(line_content),
-                                ])
+                                let mut spans = vec![Span::styled(format!("[{}] ", time), Style::default().fg(Color::DarkGray))];
+                                spans.extend(avatar.clone());
+                                spans.push(Span::styled(format!("{}: ", sender), sender_style));
+                                spans.push(Span::styled(line_content, line_style(kind)));
+                                spans.extend(failed_mark.clone());
+                                Line::from(spans)
                             } else {
                                 Line::from(vec![
                                     Span::raw(" ".repeat(prefix_len)),
-                                    Span::raw(line_content),
+                                    Span::styled(line_content, line_style(kind)),
                                 ])
                             }
                         })
                         .collect();
 
-                    if msg_lines.is_empty() {
-                        vec![Line::from(vec![
-                            Span::styled(format!("[{}] ", time), Style::default().fg(Color::DarkGray)),
-                            Span::styled(format!("{}: ", sender), Style::default().fg(Color::Cyan)),
-                        ])]
+                    let msg_lines = if msg_lines.is_empty() {
+                        let mut spans = vec![Span::styled(format!("[{}] ", time), Style::default().fg(Color::DarkGray))];
+                        spans.extend(avatar);
+                        spans.push(Span::styled(format!("{}: ", sender), sender_style));
+                        spans.extend(failed_mark);
+                        vec![Line::from(spans)]
                     } else {
                         msg_lines
-                    }
+                    };
+
+                    reply_line.into_iter().chain(msg_lines).collect::<Vec<_>>()
                 }
                 TimelineItem::TimiteConnected { nick, timestamp } => {
                     let time = format_timestamp(*timestamp);
@@ -109,33 +192,46 @@ fn render_timeline(frame: &mut Frame, app: &App, area: Rect) {
                         Span::styled("joined", Style::default().fg(Color::Green)),
                     ])]
                 }
-                TimelineItem::TimiteDisconnected { nick, timestamp } => {
+                TimelineItem::TimiteDisconnected { nick, reason, timestamp } => {
                     let time = format_timestamp(*timestamp);
                     vec![Line::from(vec![
                         Span::styled(format!("[{}] ", time), Style::default().fg(Color::DarkGray)),
                         Span::styled(format!("{} ", nick), Style::default().fg(Color::Red)),
-                        Span::styled("left", Style::default().fg(Color::Red)),
+                        Span::styled(disconnect_label(*reason), Style::default().fg(Color::Red)),
                     ])]
                 }
-                TimelineItem::AbilityCall { caller, ability_name, timestamp } => {
+                TimelineItem::AbilityCall { caller, ability_name, payload, timestamp } => {
                     let time = format_timestamp(*timestamp);
-                    vec![Line::from(vec![
+                    let mut lines = vec![Line::from(vec![
                         Span::styled(format!("[{}] ", time), Style::default().fg(Color::DarkGray)),
                         Span::styled(format!("{} ", caller), Style::default().fg(Color::Magenta)),
                         Span::raw("called "),
                         Span::styled(ability_name, Style::default().fg(Color::Yellow)),
-                    ])]
+                    ])];
+                    lines.extend(payload_lines(app, payload));
+                    lines
                 }
-                TimelineItem::AbilityOutcome { ability_name, success, timestamp } => {
+                TimelineItem::AbilityOutcome { ability_name, caller, success, in_progress, detail, timestamp } => {
                     let time = format_timestamp(*timestamp);
-                    let status_color = if *success { Color::Green } else { Color::Red };
-                    let status_text = if *success { "completed" } else { "failed" };
-                    vec![Line::from(vec![
+                    let (status_text, status_color) = match (*in_progress, *success) {
+                        (true, _) => ("in progress", Color::Cyan),
+                        (false, true) => ("completed", Color::Green),
+                        (false, false) => ("failed", Color::Red),
+                    };
+                    let mut spans = vec![
                         Span::styled(format!("[{}] ", time), Style::default().fg(Color::DarkGray)),
                         Span::styled(ability_name, Style::default().fg(Color::Yellow)),
-                        Span::raw(" "),
-                        Span::styled(status_text, Style::default().fg(status_color)),
-                    ])]
+                    ];
+                    if let Some(caller) = caller {
+                        spans.push(Span::styled(format!(" (by {})", caller), Style::default().fg(Color::Magenta)));
+                    }
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(status_text, Style::default().fg(status_color)));
+                    let mut lines = vec![Line::from(spans)];
+                    if let Some(detail) = detail {
+                        lines.extend(payload_lines(app, detail));
+                    }
+                    lines
                 }
             }
         })
@@ -157,6 +253,13 @@ fn render_timeline(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(timeline, area);
 }
 
+fn payload_lines(app: &App, payload: &str) -> Vec<Line<'static>> {
+    app.payload_view(payload)
+        .into_iter()
+        .map(|line| Line::from(Span::styled(format!("    {}", line), Style::default().fg(Color::DarkGray))))
+        .collect()
+}
+
 fn render_sidebar(frame: &mut Frame, app: &App, area: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -174,10 +277,13 @@ fn render_sidebar(frame: &mut Frame, app: &App, area: Rect) {
                 Style::default().fg(Color::White)
             };
             let prefix = if t.id == app.my_timite_id { "> " } else { "  " };
-            ListItem::new(Line::from(Span::styled(
-                format!("{}{}", prefix, t.nick),
-                style,
-            )))
+            let mut spans = vec![Span::styled(prefix, style)];
+            spans.extend(identicon(seed_of(t), app.identicon_style));
+            if t.role() == TimiteRole::Agent {
+                spans.push(Span::styled(AGENT_MARK, style));
+            }
+            spans.push(Span::styled(t.nick.clone(), style));
+            ListItem::new(Line::from(spans))
         })
         .collect();
 
@@ -215,8 +321,13 @@ fn render_sidebar(frame: &mut Frame, app: &App, area: Rect) {
 }
 
 fn render_input(frame: &mut Frame, app: &App, area: Rect) {
+    if app.input_mode == InputMode::Export {
+        render_export_prompt(frame, app, area);
+        return;
+    }
+
     let input_style = match app.input_mode {
-        InputMode::Normal => Style::default(),
+        InputMode::Normal | InputMode::Export => Style::default(),
         InputMode::Insert => Style::default().fg(Color::Yellow),
     };
 
@@ -237,14 +348,19 @@ fn render_input(frame: &mut Frame, app: &App, area: Rect) {
         0
     };
 
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Message (i to type, Enter to send, Ctrl+J for new line) ");
+    if let Some(activity) = app.activity_line() {
+        block = block.title_bottom(Span::styled(
+            format!(" {} ", activity),
+            Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
+        ));
+    }
     let input = Paragraph::new(app.input.as_str())
         .style(input_style)
         .scroll((scroll_y as u16, scroll_x as u16))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(" Message (i to type, Enter to send, Ctrl+J for new line) "),
-        );
+        .block(block);
 
     frame.render_widget(input, area);
 
@@ -258,6 +374,24 @@ fn render_input(frame: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+fn render_export_prompt(frame: &mut Frame, app: &App, area: Rect) {
+    let inner_width = area.width.saturating_sub(2) as usize;
+    let path_len = app.export_path.chars().count();
+    let scroll_x = (path_len + 1).saturating_sub(inner_width);
+
+    let prompt = Paragraph::new(app.export_path.as_str())
+        .style(Style::default().fg(Color::Magenta))
+        .scroll((0, scroll_x as u16))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Export timeline to (.md for markdown, Enter to save, Esc to cancel) "),
+        );
+
+    frame.render_widget(prompt, area);
+    frame.set_cursor_position((area.x + (path_len - scroll_x) as u16 + 1, area.y + 1));
+}
+
 fn render_help_popup(frame: &mut Frame) {
     let area = centered_rect(60, 70, frame.area());
 
@@ -269,6 +403,9 @@ fn render_help_popup(frame: &mut Frame) {
         Line::from("  q/Ctrl+D    Quit"),
         Line::from("  j/k         Scroll down/up"),
         Line::from("  G           Scroll to bottom"),
+        Line::from("  e           Expand/collapse ability payloads"),
+        Line::from("  s           Export timeline to a file"),
+        Line::from("  Ctrl+L      Clear local history and reload"),
         Line::from("  F1          Toggle help"),
         Line::from(""),
         Line::from(Span::styled("Insert Mode:", Style::default().fg(Color::Cyan))),
@@ -322,3 +459,12 @@ fn format_timestamp(ts: u64) -> String {
         None => "--:--".to_string(),
     }
 }
+
+/// Events from before reasons existed carry none and read as a plain leave.
+fn disconnect_label(reason: DisconnectReason) -> &'static str {
+    match reason {
+        DisconnectReason::Unspecified | DisconnectReason::Left => "left",
+        DisconnectReason::TimedOut => "timed out",
+        DisconnectReason::Kicked => "was kicked",
+    }
+}